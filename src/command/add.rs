@@ -0,0 +1,145 @@
+use crate::ignore::IgnoreRules;
+use crate::index::{Index, IndexEntry};
+use crate::worktree;
+use std::fs;
+
+/// Add file contents to the index, staging the removal of tracked files that no longer exist
+/// https://git-scm.com/docs/git-add
+pub(crate) fn add(pathspecs: Vec<String>, all: bool, force: bool) -> std::io::Result<()> {
+    let pathspecs = match (pathspecs.is_empty(), all) {
+        (true, true) => vec![String::from(".")],
+        (true, false) => return Err(std::io::Error::other("Nothing specified, nothing added.")),
+        (false, _) => pathspecs,
+    };
+
+    let mut index = Index::load()?;
+    let mut ignore = if force {
+        IgnoreRules::none()
+    } else {
+        IgnoreRules::load()?
+    };
+
+    let files = worktree::worktree_files(&index, &mut ignore)?
+        .into_iter()
+        .filter(|path| worktree::matches_pathspec(path, &pathspecs))
+        .collect::<Vec<_>>();
+    let removed = index
+        .entries
+        .iter()
+        .filter(|entry| worktree::matches_pathspec(&entry.path, &pathspecs))
+        .filter(|entry| fs::symlink_metadata(&entry.path).is_err())
+        .map(|entry| entry.path.clone())
+        .collect::<Vec<_>>();
+
+    // Pathspecs matching nothing are an error, unless they only name ignored files
+    let mut ignored = Vec::new();
+    for pathspec in &pathspecs {
+        let spec = [pathspec.clone()];
+        let matched = files
+            .iter()
+            .chain(&removed)
+            .any(|path| worktree::matches_pathspec(path, &spec));
+        if matched {
+            continue;
+        }
+
+        let path = worktree::normalize_path(pathspec);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if ignore.is_ignored(&path, metadata.is_dir())? => ignored.push(path),
+            Ok(metadata) if metadata.is_dir() => {}
+            _ => {
+                return Err(std::io::Error::other(format!(
+                    "pathspec '{pathspec}' did not match any files"
+                )))
+            }
+        }
+    }
+
+    for path in files {
+        if let Some(entry) = index.get(&path) {
+            if !worktree::is_modified(&index, entry)? {
+                continue;
+            }
+        }
+
+        let (mode, hash, metadata) = worktree::hash_file(&path, true)?;
+        index.add(IndexEntry::from_metadata(path, mode, hash, &metadata));
+    }
+    for path in removed {
+        index.remove(&path);
+    }
+
+    index.write()?;
+
+    if !ignored.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "The following paths are ignored by one of your .gitignore files:\n{}\nhint: Use -f if you really want to add them.",
+            ignored.join("\n")
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn add_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write(".gitignore", ".hamachi\n*.log\nbuild/\n!important.log\n").unwrap();
+            fs::write("test.txt", "this is some test content\n").unwrap();
+            fs::write("debug.log", "ignored\n").unwrap();
+            fs::write("important.log", "not ignored\n").unwrap();
+            fs::create_dir_all("src/build").unwrap();
+            fs::write("src/main.rs", "fn main() {}\n").unwrap();
+            fs::write("src/build/output", "ignored\n").unwrap();
+            fs::write("run.sh", "#!/bin/sh\n").unwrap();
+            fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+
+            // Test
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            add(vec![String::from(".")], false, false).unwrap();
+
+            let expected_index = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+            let actual_index = run_git_command(
+                Command::new("git")
+                    .env("GIT_INDEX_FILE", ".hamachi/index")
+                    .arg("ls-files")
+                    .arg("--stage"),
+            )
+            .unwrap();
+            assert_eq!(expected_index, actual_index);
+
+            let error = add(vec![String::from("debug.log")], false, false).unwrap_err();
+            assert!(error.to_string().contains("ignored by one of your .gitignore files"));
+
+            fs::remove_file("test.txt").unwrap();
+            run_git_command(Command::new("git").arg("add").arg("-A")).unwrap();
+            add(Vec::new(), true, false).unwrap();
+            run_git_command(Command::new("git").arg("add").arg("-f").arg("src/build")).unwrap();
+            add(vec![String::from("src/build")], false, true).unwrap();
+
+            let expected_index = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+            let actual_index = run_git_command(
+                Command::new("git")
+                    .env("GIT_INDEX_FILE", ".hamachi/index")
+                    .arg("ls-files")
+                    .arg("--stage"),
+            )
+            .unwrap();
+            assert_eq!(expected_index, actual_index);
+
+            teardown(repo).unwrap();
+        }
+    }
+}
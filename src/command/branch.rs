@@ -0,0 +1,611 @@
+use crate::config::Config;
+use crate::graph;
+use crate::object::commit::{Commit, Signature};
+use crate::object::Hash;
+use crate::refs;
+use crate::refs::revision;
+use crate::refs::Head;
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct BranchArgs {
+    /// Show the hash and subject of each branch, and its upstream when given twice
+    #[clap(short = 'v', long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// List both local and remote-tracking branches
+    #[clap(short = 'a', long)]
+    all: bool,
+
+    /// List only remote-tracking branches
+    #[clap(short = 'r', long)]
+    remotes: bool,
+
+    /// List only the branches whose tip is reachable from the commit
+    #[clap(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    merged: Option<String>,
+
+    /// List only the branches containing the commit
+    #[clap(long, num_args = 0..=1, default_missing_value = "HEAD")]
+    contains: Option<String>,
+
+    /// Delete fully merged branches
+    #[clap(short = 'd', long)]
+    delete: bool,
+
+    /// Delete branches, even if not merged
+    #[clap(short = 'D')]
+    force_delete: bool,
+
+    /// Rename a branch
+    #[clap(short = 'm', long = "move")]
+    rename: bool,
+
+    /// Rename a branch, even if the new name already exists
+    #[clap(short = 'M')]
+    force_rename: bool,
+
+    /// Reset the branch to the start point if it already exists
+    #[clap(short = 'f', long)]
+    force: bool,
+
+    /// Set up tracking of the start point, even if it is a local branch
+    #[clap(short = 't', long, conflicts_with = "no_track")]
+    track: bool,
+
+    /// Do not set up tracking, even if the start point is a remote-tracking branch
+    #[clap(long)]
+    no_track: bool,
+
+    #[clap(short = 'u', long)]
+    set_upstream_to: Option<String>,
+
+    #[clap(long)]
+    unset_upstream: bool,
+
+    names: Vec<String>,
+}
+
+/// List, create, delete or rename branches
+/// https://git-scm.com/docs/git-branch
+pub(crate) fn branch(args: BranchArgs) -> std::io::Result<String> {
+    let names = args.names.iter().map(String::as_str).collect::<Vec<_>>();
+
+    if args.delete || args.force_delete {
+        return delete_branches(&names, args.force_delete);
+    }
+
+    if args.rename || args.force_rename {
+        let force = args.force_rename;
+        return match names.as_slice() {
+            [new] => rename_branch(&current_branch()?, new, force).map(|_| String::new()),
+            [old, new] => rename_branch(old, new, force).map(|_| String::new()),
+            _ => Err(std::io::Error::other(
+                "too many arguments for a rename operation",
+            )),
+        };
+    }
+
+    if let Some(upstream) = &args.set_upstream_to {
+        let branch = match names.as_slice() {
+            [] => current_branch()?,
+            [branch] => branch.to_string(),
+            _ => {
+                return Err(std::io::Error::other(
+                    "too many arguments to set new upstream",
+                ))
+            }
+        };
+        return set_upstream(&branch, upstream);
+    }
+
+    if args.unset_upstream {
+        let branch = match names.first() {
+            Some(branch) => branch.to_string(),
+            None => current_branch()?,
+        };
+        return unset_upstream(&branch).map(|_| String::new());
+    }
+
+    match names.as_slice() {
+        [] => list_branches(&args),
+        [name] => create_branch(name, None, args.force, tracking(&args)),
+        [name, start_point] => create_branch(name, Some(start_point), args.force, tracking(&args)),
+        _ => Err(std::io::Error::other("too many arguments")),
+    }
+}
+
+/// Whether a new branch should track its start point: `Some(true)` to always track it,
+/// `Some(false)` never, and `None` only when it is a remote-tracking branch
+fn tracking(args: &BranchArgs) -> Option<bool> {
+    match (args.track, args.no_track) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Creates a branch pointing to the start point, HEAD by default. Returns the messages to
+/// show, which describe the upstream of the branch if it tracks its start point.
+pub(crate) fn create_branch(
+    name: &str,
+    start_point: Option<&str>,
+    force: bool,
+    track: Option<bool>,
+) -> std::io::Result<String> {
+    let ref_name = validate_new_branch(name, force)?;
+
+    let start_point = start_point.unwrap_or("HEAD");
+    let start = revision::resolve_commit(start_point)
+        .map_err(|_| std::io::Error::other(format!("not a valid object name: '{start_point}'")))?;
+
+    let old = refs::read_ref(&ref_name)?;
+    let message = match old {
+        Some(_) => format!("branch: Reset to {start_point}"),
+        None => format!("branch: Created from {start_point}"),
+    };
+    refs::update_ref_with_log(&ref_name, old.as_ref(), &start, &message)?;
+
+    let remote_tracking = start_point != "HEAD"
+        && refs::ref_exists(&format!("refs/remotes/{start_point}"))?
+        && !refs::ref_exists(&format!("refs/heads/{start_point}"))?;
+    let should_track = match track {
+        Some(track) => track && start_point != "HEAD",
+        None => remote_tracking,
+    };
+
+    if should_track {
+        set_upstream(name, start_point)
+    } else {
+        Ok(String::new())
+    }
+}
+
+/// Checks that a branch can be created with the specified name, returning its full ref name
+pub(crate) fn validate_new_branch(name: &str, force: bool) -> std::io::Result<String> {
+    let ref_name = format!("refs/heads/{name}");
+    if name.starts_with('-') || name == "HEAD" || !refs::check_ref_format(&ref_name) {
+        return Err(std::io::Error::other(format!(
+            "'{name}' is not a valid branch name"
+        )));
+    }
+
+    if refs::ref_exists(&ref_name)? {
+        if !force {
+            return Err(std::io::Error::other(format!(
+                "a branch named '{name}' already exists"
+            )));
+        }
+        if refs::read_head()? == Head::Branch(ref_name.clone()) {
+            return Err(std::io::Error::other(format!(
+                "cannot force update the branch '{name}' used by the current worktree"
+            )));
+        }
+    }
+
+    Ok(ref_name)
+}
+
+fn delete_branches(names: &[&str], force: bool) -> std::io::Result<String> {
+    if names.is_empty() {
+        return Err(std::io::Error::other("branch name required"));
+    }
+
+    let head = refs::read_head()?;
+    let mut config = Config::load()?;
+    let mut output = String::new();
+
+    for name in names {
+        let ref_name = format!("refs/heads/{name}");
+        let Some(hash) = refs::read_ref(&ref_name)? else {
+            return Err(std::io::Error::other(format!("branch '{name}' not found")));
+        };
+
+        if head == Head::Branch(ref_name.clone()) {
+            return Err(std::io::Error::other(format!(
+                "cannot delete branch '{name}' checked out at '{}'",
+                std::env::current_dir()?.display()
+            )));
+        }
+
+        // A branch is safe to delete once merged into its upstream, or into HEAD without one
+        if !force {
+            let merge_target = match refs::upstream_of(&ref_name)? {
+                Some((_, upstream_ref)) => refs::read_ref(&upstream_ref)?,
+                None => refs::head_commit()?,
+            };
+            if !merge_target.is_some_and(|target| graph::is_ancestor(&hash, &target)) {
+                return Err(std::io::Error::other(format!(
+                    "the branch '{name}' is not fully merged.\nIf you are sure you want to delete it, run 'hamachi branch -D {name}'"
+                )));
+            }
+        }
+
+        refs::delete_ref(&ref_name, Some(&hash))?;
+        config.remove_section(&format!("branch.{name}"));
+        output.push_str(&format!(
+            "Deleted branch {name} (was {}).\n",
+            hash.to_short_string()
+        ));
+    }
+
+    config.write()?;
+
+    Ok(output)
+}
+
+fn rename_branch(old: &str, new: &str, force: bool) -> std::io::Result<()> {
+    let old_ref = format!("refs/heads/{old}");
+    let is_current = refs::read_head()? == Head::Branch(old_ref.clone());
+    let hash = refs::read_ref(&old_ref)?;
+    if hash.is_none() && !is_current {
+        return Err(std::io::Error::other(format!("no branch named '{old}'")));
+    }
+
+    let new_ref = if old == new {
+        old_ref.clone()
+    } else {
+        validate_new_branch(new, force)?
+    };
+
+    if let Some(hash) = &hash {
+        if new_ref != old_ref {
+            if let Some(existing) = refs::read_ref(&new_ref)? {
+                refs::delete_ref(&new_ref, Some(&existing))?;
+            }
+
+            // The reflog follows the branch to its new name
+            let logs = PathBuf::from(".hamachi/logs");
+            if logs.join(&old_ref).is_file() {
+                fs::create_dir_all(logs.join(&new_ref).parent().unwrap())?;
+                fs::rename(logs.join(&old_ref), logs.join(&new_ref))?;
+            }
+
+            refs::update_ref(&new_ref, hash)?;
+            refs::delete_ref(&old_ref, Some(hash))?;
+        }
+
+        let message = format!("Branch: renamed {old_ref} to {new_ref}");
+        refs::append_reflog(
+            &new_ref,
+            Some(hash),
+            hash,
+            &Signature::committer()?,
+            &message,
+        )?;
+    }
+
+    if is_current {
+        refs::write_head(&Head::Branch(new_ref))?;
+    }
+
+    if old != new {
+        let mut config = Config::load()?;
+        if config.get_all(&format!("branch.{old}.merge")).is_empty()
+            && config.get_all(&format!("branch.{old}.remote")).is_empty()
+        {
+            return Ok(());
+        }
+        config.remove_section(&format!("branch.{new}"));
+        config.rename_section(&format!("branch.{old}"), &format!("branch.{new}"))?;
+        config.write()?;
+    }
+
+    Ok(())
+}
+
+/// Records in the configuration that a local branch tracks a remote-tracking branch, such as
+/// `origin/master`, or another local branch
+fn set_upstream(branch: &str, upstream: &str) -> std::io::Result<String> {
+    if !refs::ref_exists(&format!("refs/heads/{branch}"))? {
+        return Err(std::io::Error::other(format!(
+            "branch '{branch}' does not exist"
+        )));
+    }
+
+    let (remote, merge) = if refs::ref_exists(&format!("refs/heads/{upstream}"))? {
+        (String::from("."), format!("refs/heads/{upstream}"))
+    } else if refs::ref_exists(&format!("refs/remotes/{upstream}"))? {
+        match upstream.split_once('/') {
+            Some((remote, remote_branch)) => {
+                (remote.to_string(), format!("refs/heads/{remote_branch}"))
+            }
+            None => {
+                return Err(std::io::Error::other(format!(
+                    "the requested upstream branch '{upstream}' does not exist"
+                )))
+            }
+        }
+    } else {
+        return Err(std::io::Error::other(format!(
+            "the requested upstream branch '{upstream}' does not exist"
+        )));
+    };
+
+    let mut config = Config::load()?;
+    config.set(&format!("branch.{branch}.remote"), &remote)?;
+    config.set(&format!("branch.{branch}.merge"), &merge)?;
+    config.write()?;
+
+    Ok(format!("branch '{branch}' set up to track '{upstream}'.\n"))
+}
+
+fn unset_upstream(branch: &str) -> std::io::Result<()> {
+    let mut config = Config::load()?;
+    if config.get(&format!("branch.{branch}.merge")).is_none() {
+        return Err(std::io::Error::other(format!(
+            "branch '{branch}' has no upstream information"
+        )));
+    }
+
+    config.unset(&format!("branch.{branch}.remote"))?;
+    config.unset(&format!("branch.{branch}.merge"))?;
+    config.write()
+}
+
+/// The name of the checked out branch
+fn current_branch() -> std::io::Result<String> {
+    match refs::read_head()? {
+        Head::Branch(name) => Ok(name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string()),
+        Head::Detached(_) => Err(std::io::Error::other("HEAD is detached")),
+    }
+}
+
+struct ListedBranch {
+    name: String,
+    ref_name: String,
+    hash: Option<Hash>,
+    is_current: bool,
+    /// Target of a symbolic ref such as `origin/HEAD`
+    symbolic_target: Option<String>,
+}
+
+fn list_branches(args: &BranchArgs) -> std::io::Result<String> {
+    let head = refs::read_head()?;
+    let mut branches = Vec::new();
+
+    if let Head::Detached(hash) = &head {
+        branches.push(ListedBranch {
+            name: format!("(HEAD detached at {})", hash.to_short_string()),
+            ref_name: String::from("HEAD"),
+            hash: Some(hash.clone()),
+            is_current: true,
+            symbolic_target: None,
+        });
+    }
+
+    if !args.remotes {
+        for (ref_name, hash) in refs::list_refs("refs/heads/")? {
+            branches.push(ListedBranch {
+                name: ref_name["refs/heads/".len()..].to_string(),
+                is_current: head == Head::Branch(ref_name.clone()),
+                ref_name,
+                hash: Some(hash),
+                symbolic_target: None,
+            });
+        }
+    }
+
+    if args.remotes || args.all {
+        for (ref_name, hash) in refs::list_refs("refs/remotes/")? {
+            let short_name = &ref_name["refs/remotes/".len()..];
+            let target = refs::symbolic_target(&ref_name)?;
+            let name = if args.all {
+                format!("remotes/{short_name}")
+            } else {
+                short_name.to_string()
+            };
+
+            branches.push(ListedBranch {
+                name,
+                symbolic_target: (target != ref_name)
+                    .then(|| target.trim_start_matches("refs/remotes/").to_string()),
+                ref_name,
+                hash: Some(hash),
+                is_current: false,
+            });
+        }
+    }
+
+    let merged = args
+        .merged
+        .as_deref()
+        .map(revision::resolve_commit)
+        .transpose()?
+        .map(|commit| graph::ancestors(&commit));
+    let contains = args
+        .contains
+        .as_deref()
+        .map(revision::resolve_commit)
+        .transpose()?;
+    branches.retain(|branch| {
+        let Some(hash) = &branch.hash else {
+            return false;
+        };
+
+        merged.as_ref().is_none_or(|merged| merged.contains(hash))
+            && contains
+                .as_ref()
+                .is_none_or(|contains| graph::is_ancestor(contains, hash))
+    });
+
+    let width = branches
+        .iter()
+        .filter(|branch| branch.symbolic_target.is_none())
+        .map(|branch| branch.name.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut output = String::new();
+    for branch in &branches {
+        let marker = if branch.is_current { '*' } else { ' ' };
+
+        if let Some(target) = &branch.symbolic_target {
+            output.push_str(&format!("{marker} {} -> {target}\n", branch.name));
+            continue;
+        }
+        if args.verbose == 0 {
+            output.push_str(&format!("{marker} {}\n", branch.name));
+            continue;
+        }
+
+        let hash = branch.hash.as_ref().unwrap();
+        let commit = Commit::from_hash(hash);
+        output.push_str(&format!(
+            "{marker} {:<width$} {} {}{}\n",
+            branch.name,
+            hash.to_short_string(),
+            tracking_summary(&branch.ref_name, hash, args.verbose > 1)?,
+            commit.subject()
+        ));
+    }
+
+    Ok(output)
+}
+
+/// The `[origin/master: ahead 1, behind 2] ` part of the verbose listing, with the upstream
+/// name only if requested
+fn tracking_summary(ref_name: &str, hash: &Hash, show_name: bool) -> std::io::Result<String> {
+    if !ref_name.starts_with("refs/heads/") {
+        return Ok(String::new());
+    }
+    let Some((name, upstream_ref)) = refs::upstream_of(ref_name)? else {
+        return Ok(String::new());
+    };
+
+    let state = match refs::read_ref(&upstream_ref)? {
+        None => String::from("gone"),
+        Some(upstream) => match graph::ahead_behind(hash, &upstream) {
+            (0, 0) => String::new(),
+            (ahead, 0) => format!("ahead {ahead}"),
+            (0, behind) => format!("behind {behind}"),
+            (ahead, behind) => format!("ahead {ahead}, behind {behind}"),
+        },
+    };
+
+    Ok(match (show_name, state.is_empty()) {
+        (true, true) => format!("[{name}] "),
+        (true, false) => format!("[{name}: {state}] "),
+        (false, true) => String::new(),
+        (false, false) => format!("[{state}] "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::branch::{branch, BranchArgs};
+    use crate::test_utils::{
+        copy_git_repository, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn branch_list_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            for i in 0..3 {
+                fs::write("test.txt", format!("version {i}\n")).unwrap();
+                run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+                run_git_command(Command::new("git").arg("commit").arg("-m").arg(format!("commit {i}"))).unwrap();
+            }
+            run_git_command(Command::new("git").arg("branch").arg("feature").arg("HEAD~1")).unwrap();
+            run_git_command(Command::new("git").arg("branch").arg("a-much-longer-name").arg("HEAD~2")).unwrap();
+            run_git_command(Command::new("git").arg("update-ref").arg("refs/remotes/origin/master").arg("HEAD~1")).unwrap();
+            run_git_command(Command::new("git").arg("symbolic-ref").arg("refs/remotes/origin/HEAD").arg("refs/remotes/origin/master")).unwrap();
+            run_git_command(Command::new("git").arg("config").arg("remote.origin.url").arg("https://example.com/repository.git")).unwrap();
+            run_git_command(Command::new("git").arg("branch").arg("--set-upstream-to").arg("origin/master")).unwrap();
+            fs::copy(".git/config", ".hamachi/config").unwrap();
+
+            copy_git_repository().unwrap();
+
+            // Test
+            for git_args in [vec!["-v"], vec!["-vv"], vec!["-a"], vec!["-r"], vec!["--merged", "feature"], vec!["--contains", "HEAD~1"]] {
+                let expected = run_git_command(Command::new("git").arg("branch").args(&git_args)).unwrap();
+                let actual = branch(BranchArgs {
+                    verbose: git_args.iter().map(|arg| match *arg { "-v" => 1, "-vv" => 2, _ => 0 }).sum(),
+                    all: git_args.contains(&"-a"),
+                    remotes: git_args.contains(&"-r"),
+                    merged: (git_args[0] == "--merged").then(|| git_args[1].to_string()),
+                    contains: (git_args[0] == "--contains").then(|| git_args[1].to_string()),
+                    ..Default::default()
+                })
+                .unwrap();
+
+                assert_eq!(expected.trim(), actual.trim(), "git branch {git_args:?}");
+            }
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn branch_create_rename_delete_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            for i in 0..2 {
+                fs::write("test.txt", format!("version {i}\n")).unwrap();
+                run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+                run_git_command(Command::new("git").arg("commit").arg("-m").arg(format!("commit {i}"))).unwrap();
+            }
+            run_git_command(Command::new("git").arg("switch").arg("-c").arg("side")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("--allow-empty").arg("-m").arg("side")).unwrap();
+            run_git_command(Command::new("git").arg("switch").arg("master")).unwrap();
+            copy_git_repository().unwrap();
+
+            // Test
+            let operations: [&[&str]; 6] = [
+                &["topic", "HEAD~1"],
+                &["merged"],
+                &["--track", "tracking", "master"],
+                &["-m", "topic", "renamed"],
+                &["-m", "main"],
+                &["-d", "merged"],
+            ];
+            for operation in operations {
+                run_git_command(Command::new("git").arg("branch").args(operation)).unwrap();
+
+                let flag = |flag: &str| operation.contains(&flag);
+                branch(BranchArgs {
+                    rename: flag("-m"),
+                    delete: flag("-d"),
+                    track: flag("--track"),
+                    names: names(&operation.iter().filter(|arg| !arg.starts_with('-')).copied().collect::<Vec<_>>()),
+                    ..Default::default()
+                })
+                .unwrap();
+            }
+
+            let expected = run_git_command(Command::new("git").arg("for-each-ref")).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("for-each-ref")).unwrap();
+            assert_eq!(expected, actual);
+
+            let expected = run_git_command(Command::new("git").arg("symbolic-ref").arg("HEAD")).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("symbolic-ref").arg("HEAD")).unwrap();
+            assert_eq!(expected, actual);
+
+            let expected = run_git_command(Command::new("git").arg("config").arg("--get-regexp").arg("^branch")).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("config").arg("--get-regexp").arg("^branch")).unwrap();
+            assert_eq!(expected, actual);
+
+            let unmerged = branch(BranchArgs { delete: true, names: names(&["side"]), ..Default::default() });
+            assert!(unmerged.is_err_and(|e| e.to_string().contains("not fully merged")));
+
+            teardown(repo).unwrap();
+        }
+    }
+}
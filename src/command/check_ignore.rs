@@ -0,0 +1,123 @@
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::worktree;
+use std::fs;
+
+/// Debug the ignore rules: lists which of the paths are ignored, along with the pattern
+/// responsible when `verbose` is set. Tracked files are never reported unless `no_index` is
+/// set. Returns the output and whether any path was ignored.
+/// https://git-scm.com/docs/git-check-ignore
+pub(crate) fn check_ignore(
+    paths: Vec<String>,
+    verbose: bool,
+    no_index: bool,
+) -> std::io::Result<(String, bool)> {
+    if paths.is_empty() {
+        return Err(std::io::Error::other("no path specified"));
+    }
+
+    let index = if no_index {
+        Index::default()
+    } else {
+        Index::load()?
+    };
+    let mut ignore = IgnoreRules::load()?;
+
+    let mut output = String::new();
+    let mut any_ignored = false;
+    for path in paths {
+        let normalized = worktree::normalize_path(&path);
+        if index.contains(&normalized) {
+            continue;
+        }
+
+        let is_directory =
+            path.ends_with('/') || fs::metadata(&normalized).is_ok_and(|m| m.is_dir());
+        let Some(pattern) = ignore.matching_pattern(&normalized, is_directory)? else {
+            continue;
+        };
+
+        if !pattern.negated {
+            any_ignored = true;
+        } else if !verbose {
+            continue;
+        }
+
+        if verbose {
+            output.push_str(&format!(
+                "{}:{}:{}\t{path}\n",
+                pattern.source, pattern.line, pattern.text
+            ));
+        } else {
+            output.push_str(&format!("{path}\n"));
+        }
+    }
+
+    Ok((output, any_ignored))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::check_ignore::check_ignore;
+    use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn check_ignore_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write(
+                ".gitignore",
+                ".hamachi\n# comment\n*.log\n!important.log\n/root-only.txt\nbuild/\ndocs/**/*.pdf\n",
+            )
+            .unwrap();
+            fs::create_dir_all("src/build").unwrap();
+            fs::create_dir_all("docs/a/b").unwrap();
+            fs::write("src/.gitignore", "*.tmp\n!keep.log\n").unwrap();
+            fs::write("src/build/output", "").unwrap();
+            fs::write("src/build.txt", "").unwrap();
+            fs::write(".hamachiignore", "").unwrap();
+
+            let paths = [
+                "debug.log",
+                "important.log",
+                "root-only.txt",
+                "src/root-only.txt",
+                "src/build",
+                "src/build/output",
+                "src/build.txt",
+                "src/file.tmp",
+                "src/keep.log",
+                "src/other.log",
+                "docs/a/b/manual.pdf",
+                "docs/manual.pdf",
+                "notes.txt",
+            ];
+
+            // Test
+            let expected = run_git_command(
+                Command::new("git")
+                    .arg("check-ignore")
+                    .arg("-v")
+                    .arg("--no-index")
+                    .args(paths),
+            )
+            .unwrap();
+            let (actual, any_ignored) = check_ignore(
+                paths.iter().map(|path| path.to_string()).collect(),
+                true,
+                true,
+            )
+            .unwrap();
+
+            assert_eq!(expected, actual.trim());
+            assert!(any_ignored);
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::command::switch::{switch_to_branch, switch_to_commit, switch_to_new_branch};
+use crate::index::Index;
+use crate::refs::revision;
+use crate::{refs, worktree};
+
+/// Switch branches or restore working tree files
+/// https://git-scm.com/docs/git-checkout
+pub(crate) fn checkout(
+    target: Option<String>,
+    paths: Vec<String>,
+    force: bool,
+    detach: bool,
+    new_branch: Option<String>,
+    reset_branch: bool,
+) -> std::io::Result<()> {
+    if let Some(new_branch) = new_branch {
+        return switch_to_new_branch(&new_branch, target.as_deref(), reset_branch, force);
+    }
+
+    if !paths.is_empty() {
+        let source = target
+            .map(|target| revision::resolve_tree(&target))
+            .transpose()?;
+
+        return worktree::checkout_paths(source.as_ref(), &paths, true, true);
+    }
+
+    let Some(target) = target else {
+        return Err(std::io::Error::other("missing branch or commit argument"));
+    };
+
+    if !detach && refs::ref_exists(&format!("refs/heads/{target}"))? {
+        return switch_to_branch(&target, force);
+    }
+
+    match revision::resolve_commit(&target) {
+        Ok(commit) => switch_to_commit(&commit, force),
+        // Like git, a lone argument that is not a revision is treated as a path
+        Err(_) if !detach && is_tracked_path(&target)? => {
+            worktree::checkout_paths(None, &[target], true, true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn is_tracked_path(path: &str) -> std::io::Result<bool> {
+    let pathspecs = [path.to_string()];
+    let index = Index::load()?;
+
+    Ok(index
+        .entries
+        .iter()
+        .any(|entry| worktree::matches_pathspec(&entry.path, &pathspecs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::checkout::checkout;
+    use crate::command::status::collect_status;
+    use crate::test_utils::{copy_git_objects, run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn checkout_commit_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("test.txt", "this is some test content\n").unwrap();
+            fs::create_dir("testdir").unwrap();
+            fs::write("testdir/test2.txt", "this is more test content\n").unwrap();
+            fs::write("run.sh", "#!/bin/sh\n").unwrap();
+            fs::set_permissions("run.sh", fs::Permissions::from_mode(0o755)).unwrap();
+            symlink("test.txt", "link").unwrap();
+
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first")).unwrap();
+            let commit_hash = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            copy_git_objects().unwrap();
+            for path in ["test.txt", "run.sh", "link"] {
+                fs::remove_file(path).unwrap();
+            }
+            fs::remove_dir_all("testdir").unwrap();
+
+            // Test
+            checkout(Some(commit_hash), Vec::new(), false, false, None, false).unwrap();
+
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "this is some test content\n");
+            assert_eq!(fs::read_to_string("testdir/test2.txt").unwrap(), "this is more test content\n");
+            assert_ne!(fs::metadata("run.sh").unwrap().permissions().mode() & 0o111, 0);
+            assert_eq!(fs::read_link("link").unwrap().to_string_lossy(), "test.txt");
+
+            let expected_index = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+            let actual_index = run_git_command(
+                Command::new("git")
+                    .env("GIT_INDEX_FILE", ".hamachi/index")
+                    .arg("ls-files")
+                    .arg("--stage"),
+            )
+            .unwrap();
+
+            assert_eq!(expected_index, actual_index);
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn checkout_refuses_to_overwrite_local_changes() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("test.txt", "first version\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first")).unwrap();
+            let first_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            fs::write("test.txt", "second version\n").unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-am").arg("second")).unwrap();
+            let second_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            copy_git_objects().unwrap();
+            fs::remove_file("test.txt").unwrap();
+            checkout(Some(first_commit), Vec::new(), false, false, None, false).unwrap();
+
+            // Test
+            fs::write("test.txt", "local changes\n").unwrap();
+
+            let error = checkout(Some(second_commit.clone()), Vec::new(), false, false, None, false).unwrap_err();
+            assert!(error.to_string().contains("would be overwritten by checkout"));
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "local changes\n");
+
+            checkout(Some(second_commit), Vec::new(), true, false, None, false).unwrap();
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "second version\n");
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn checkout_paths_from_commit_stages_them() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("test.txt", "first version\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first")).unwrap();
+            let first_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            fs::write("test.txt", "second version\n").unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-am").arg("second")).unwrap();
+            let second_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            copy_git_objects().unwrap();
+            fs::remove_file("test.txt").unwrap();
+            checkout(Some(second_commit), Vec::new(), false, false, None, false).unwrap();
+
+            // Test
+            checkout(Some(first_commit.clone()), vec!["test.txt".to_string()], false, false, None, false).unwrap();
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "first version\n");
+
+            let status = collect_status().unwrap();
+            assert_eq!(status.entries.len(), 1);
+            assert_eq!(status.entries[0].path, "test.txt");
+            assert!(status.entries[0].staged.is_some());
+            assert!(status.entries[0].unstaged.is_none());
+
+            run_git_command(Command::new("git").arg("checkout").arg(&first_commit).arg("--").arg("test.txt")).unwrap();
+            let expected_index = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+            let actual_index = run_git_command(
+                Command::new("git")
+                    .env("GIT_INDEX_FILE", ".hamachi/index")
+                    .arg("ls-files")
+                    .arg("--stage"),
+            )
+            .unwrap();
+
+            assert_eq!(expected_index, actual_index);
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::sequencer::{self, Action, SequencerOptions};
+use clap::Args;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct CherryPickArgs {
+    /// Append "(cherry picked from commit ...)" to the message of every picked commit
+    #[clap(short = 'x')]
+    record_origin: bool,
+
+    /// Append a Signed-off-by trailer with the committer to the message
+    #[clap(short = 's', long)]
+    signoff: bool,
+
+    /// The parent, counting from 1, whose changes are picked from merge commits
+    #[clap(short = 'm', long)]
+    mainline: Option<usize>,
+
+    /// Commit the resolved conflicts and go on with the remaining commits
+    #[clap(long = "continue", conflicts_with_all = ["skip", "abort"])]
+    continue_pick: bool,
+
+    /// Skip the commit that stopped the cherry-pick and go on with the remaining commits
+    #[clap(long, conflicts_with = "abort")]
+    skip: bool,
+
+    /// Cancel the cherry-pick, restoring the branch as it was before
+    #[clap(long)]
+    abort: bool,
+
+    commits: Vec<String>,
+}
+
+/// Apply the changes introduced by existing commits on top of HEAD, one new commit each
+/// https://git-scm.com/docs/git-cherry-pick
+pub(crate) fn cherry_pick(args: CherryPickArgs) -> std::io::Result<()> {
+    if args.continue_pick {
+        return sequencer::resume(Action::Pick);
+    }
+    if args.skip {
+        return sequencer::skip(Action::Pick);
+    }
+    if args.abort {
+        return sequencer::abort();
+    }
+
+    sequencer::start(
+        Action::Pick,
+        &args.commits,
+        SequencerOptions {
+            record_origin: args.record_origin,
+            signoff: args.signoff,
+            mainline: args.mainline,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::cherry_pick::{cherry_pick, CherryPickArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn cherry_pick_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_EDITOR", "true");
+
+            fs::write("file.txt", "one\ntwo\nthree\nfour\nfive\n").unwrap();
+            git(&["add", "file.txt"]);
+            git(&["commit", "-m", "base"]);
+
+            git(&["switch", "-c", "topic"]);
+            fs::write("file.txt", "one\ntwo on topic\nthree\nfour\nfive\n").unwrap();
+            git(&["commit", "-am", "first"]);
+            fs::write("added.txt", "added on topic\n").unwrap();
+            git(&["add", "added.txt"]);
+            git(&["commit", "-m", "second\n\nBody.\nSigned-off-by: Someone Else <someone@example.com>"]);
+            fs::write("file.txt", "one\ntwo on topic\nthree\nfour\nfive on topic\n").unwrap();
+            git(&["commit", "-am", "third", "--author", "Someone Else <someone@example.com>"]);
+
+            git(&["switch", "master"]);
+            fs::write("file.txt", "one\ntwo\nthree\nfour\nfive on master\n").unwrap();
+            git(&["commit", "-am", "master"]);
+            let original = git(&["rev-parse", "HEAD"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            git(&["cherry-pick", "-x", "-s", "topic~3..topic"]);
+            let expected_file = fs::read("file.txt").unwrap();
+            let expected_index = git(&["ls-files", "--stage"]);
+            fs::write("file.txt", "one\ntwo on topic\nthree\nfour\nfive resolved\n").unwrap();
+            git(&["add", "file.txt"]);
+            git(&["cherry-pick", "--continue"]);
+            let expected = git(&["log", "--format=%H %an %s", "-4"]);
+            git(&["reset", "--hard", &original]);
+
+            let args = CherryPickArgs {
+                record_origin: true,
+                signoff: true,
+                commits: vec![String::from("topic~3..topic")],
+                ..Default::default()
+            };
+            let error = cherry_pick(args).unwrap_err();
+            assert!(error.to_string().starts_with("could not apply "));
+            let actual_file = fs::read("file.txt").unwrap();
+            let actual_index = run_git_command(Command::new("git").env("GIT_INDEX_FILE", ".hamachi/index").arg("ls-files").arg("--stage")).unwrap();
+            fs::write("file.txt", "one\ntwo on topic\nthree\nfour\nfive resolved\n").unwrap();
+            add(vec![String::from("file.txt")], false, false).unwrap();
+            cherry_pick(CherryPickArgs { continue_pick: true, ..Default::default() }).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").args(["log", "--format=%H %an %s", "-4"])).unwrap();
+
+            assert_eq!(expected_file, actual_file);
+            assert_eq!(expected_index, actual_index);
+            assert_eq!(expected, actual);
+            assert!(!fs::exists(".hamachi/sequencer").unwrap());
+            assert!(!fs::exists(".hamachi/CHERRY_PICK_HEAD").unwrap());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
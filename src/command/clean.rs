@@ -0,0 +1,198 @@
+use crate::config::Config;
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::worktree;
+use std::collections::HashSet;
+use std::fs;
+
+/// Which untracked files are removed, depending on the ignore rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CleanMode {
+    /// Only files that are not ignored
+    Untracked,
+    /// Ignored files as well (`-x`)
+    All,
+    /// Only ignored files (`-X`)
+    OnlyIgnored,
+}
+
+/// Remove untracked files from the working tree, and untracked directories as well with `-d`.
+/// Returns the list of removed (or, on a dry run, removable) paths in git's output format.
+/// https://git-scm.com/docs/git-clean
+pub(crate) fn clean(
+    pathspecs: Vec<String>,
+    mode: CleanMode,
+    directories: bool,
+    dry_run: bool,
+    force: bool,
+) -> std::io::Result<String> {
+    let require_force = Config::load()?.get("clean.requireForce") != Some(String::from("false"));
+    if require_force && !force && !dry_run {
+        return Err(std::io::Error::other(
+            "clean.requireForce defaults to true and neither -n nor -f given; refusing to clean",
+        ));
+    }
+
+    let index = Index::load()?;
+    let mut ignore = IgnoreRules::load()?;
+    let context = CleanContext {
+        index: &index,
+        tracked_directories: worktree::tracked_directories(&index),
+        mode,
+        directories,
+    };
+
+    let mut removable = Vec::new();
+    context.collect(&mut ignore, "", &mut removable)?;
+    if !pathspecs.is_empty() {
+        removable.retain(|path| worktree::matches_pathspec(path.trim_end_matches('/'), &pathspecs));
+    }
+    removable.sort();
+
+    let mut output = String::new();
+    for path in removable {
+        if dry_run {
+            output.push_str(&format!("Would remove {path}\n"));
+            continue;
+        }
+
+        match path.strip_suffix('/') {
+            Some(directory) => fs::remove_dir_all(directory)?,
+            None => fs::remove_file(&path)?,
+        }
+        output.push_str(&format!("Removing {path}\n"));
+    }
+
+    Ok(output)
+}
+
+struct CleanContext<'a> {
+    index: &'a Index,
+    tracked_directories: HashSet<String>,
+    mode: CleanMode,
+    directories: bool,
+}
+
+impl CleanContext<'_> {
+    /// Collects the removable paths below a directory, returning whether everything in it is
+    /// removable so the directory can be reported as a whole instead
+    fn collect(
+        &self,
+        ignore: &mut IgnoreRules,
+        directory: &str,
+        removable: &mut Vec<String>,
+    ) -> std::io::Result<bool> {
+        let mut everything_removable = true;
+
+        for (path, metadata) in worktree::read_directory(directory)? {
+            if !metadata.is_dir() {
+                if self.index.contains(&path) {
+                    everything_removable = false;
+                } else if self.is_selected(ignore.is_ignored(&path, false)?) {
+                    removable.push(path);
+                } else {
+                    everything_removable = false;
+                }
+                continue;
+            }
+
+            if self.tracked_directories.contains(&path) {
+                self.collect(ignore, &path, removable)?;
+                everything_removable = false;
+                continue;
+            }
+
+            // Untracked directories are left alone without `-d`
+            if !self.directories {
+                everything_removable = false;
+                continue;
+            }
+
+            let ignored = ignore.is_ignored(&path, true)?;
+            match self.mode {
+                CleanMode::All => removable.push(format!("{path}/")),
+                CleanMode::OnlyIgnored if ignored => removable.push(format!("{path}/")),
+                CleanMode::Untracked if ignored => everything_removable = false,
+                _ => {
+                    let mut inner = Vec::new();
+                    if self.collect(ignore, &path, &mut inner)? {
+                        removable.push(format!("{path}/"));
+                    } else {
+                        everything_removable = false;
+                        removable.append(&mut inner);
+                    }
+                }
+            }
+        }
+
+        Ok(everything_removable)
+    }
+
+    fn is_selected(&self, ignored: bool) -> bool {
+        match self.mode {
+            CleanMode::Untracked => !ignored,
+            CleanMode::All => true,
+            CleanMode::OnlyIgnored => ignored,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::clean::{clean, CleanMode};
+    use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn clean_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write(".gitignore", ".hamachi\n*.log\n").unwrap();
+            fs::create_dir_all("src/untracked").unwrap();
+            fs::create_dir_all("logs").unwrap();
+            fs::write("tracked.txt", "tracked\n").unwrap();
+            fs::write("src/tracked.txt", "tracked\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            add(vec![String::from(".")], false, false).unwrap();
+
+            fs::write("untracked.txt", "untracked\n").unwrap();
+            fs::write("debug.log", "ignored\n").unwrap();
+            fs::write("src/untracked.txt", "untracked\n").unwrap();
+            fs::write("src/untracked/file.txt", "untracked\n").unwrap();
+            fs::write("logs/today.log", "ignored\n").unwrap();
+            fs::write("logs/notes.txt", "untracked\n").unwrap();
+
+            // Test
+            for (mode, directories, flags) in [
+                (CleanMode::Untracked, false, "-n"),
+                (CleanMode::Untracked, true, "-nd"),
+                (CleanMode::All, true, "-ndx"),
+                (CleanMode::OnlyIgnored, true, "-ndX"),
+            ] {
+                let expected = run_git_command(Command::new("git").arg("clean").arg(flags)).unwrap();
+                let expected = expected
+                    .lines()
+                    .filter(|line| !line.contains(".hamachi"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let actual = clean(Vec::new(), mode, directories, true, false).unwrap();
+
+                assert_eq!(expected, actual.trim(), "git clean {flags}");
+            }
+
+            clean(Vec::new(), CleanMode::Untracked, true, false, true).unwrap();
+            assert!(!fs::exists("untracked.txt").unwrap());
+            assert!(!fs::exists("src/untracked").unwrap());
+            assert!(fs::exists("debug.log").unwrap());
+            assert!(fs::exists("logs/today.log").unwrap());
+            assert!(fs::exists("src/tracked.txt").unwrap());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
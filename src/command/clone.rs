@@ -1,57 +1,560 @@
-use crate::object::commit::Commit;
-use crate::object::packfile::ObjectType;
-use crate::remote::http_client::HttpClient;
-use reqwest::Url;
-use std::collections::HashSet;
+use crate::config::Config;
+use crate::graph::shallow;
+use crate::object::commit::{parse_date, Commit};
+use crate::object::packfile::PackFile;
+use crate::object::Hash;
+use crate::refs::Head;
+use crate::remote::client::{FetchRequest, Ref, RemoteClient};
+use crate::remote::{promisor, transport};
+use crate::{init, refs, worktree};
+use clap::Args;
 use std::fs::File;
-use std::io::Write;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct CloneArgs {
+    pub(crate) repository: String,
+
+    pub(crate) directory: Option<String>,
+
+    /// Only fetch the specified number of commits of history
+    #[clap(long)]
+    pub(crate) depth: Option<u32>,
+
+    /// Only fetch the history more recent than the specified date
+    #[clap(long)]
+    pub(crate) shallow_since: Option<String>,
+
+    /// Only fetch the history not reachable from the specified remote branch or tag
+    #[clap(long)]
+    pub(crate) shallow_exclude: Vec<String>,
+
+    /// Make a partial clone leaving out the objects the filter excludes, such as `blob:none`,
+    /// `blob:limit=<n>` or `tree:<depth>`, which are then fetched when needed
+    #[clap(long)]
+    pub(crate) filter: Option<String>,
+
+    /// Copy the objects of a repository given by its path rather than have them sent, hard
+    /// linking them when possible, which is the default for paths
+    #[clap(short, long, overrides_with = "no_local")]
+    pub(crate) local: bool,
+
+    /// Have the objects of a repository given by its path sent as for a remote one
+    #[clap(long)]
+    pub(crate) no_local: bool,
+
+    /// Copy the objects of a local repository instead of hard linking them
+    #[clap(long)]
+    pub(crate) no_hardlinks: bool,
+}
 
 /// Clone a repository into a new directory
 /// https://git-scm.com/docs/git-clone
-pub fn clone(repository: String) {
-    let url = Url::parse(&repository).unwrap();
-    let client = HttpClient::new(url);
-
-    println!("Cloning into 'hamachi' ...");
-
-    let discover_refs_response = client.discover_refs().unwrap();
-    let packfile = client.fetch_pack(&discover_refs_response);
-
-    println!("Packfile Headers");
-    println!("- Signature {}", packfile.header.signature);
-    println!("- Version {}", packfile.header.version);
-    println!("- Entry Count {}", packfile.header.entry_count);
-
-    // Setup HEAD
-    let mut commits = HashSet::new();
-    let mut parent_commits = HashSet::new();
-    for entry in packfile.entries {
-        if entry.object_type == ObjectType::Commit {
-            let mut commit = Commit::from_hash(&entry.hash);
-
-            commits.insert(entry.hash);
-            for parent in commit.parents {
-                parent_commits.insert(parent.parent_hash);
-            }
+pub(crate) fn clone(args: CloneArgs) -> std::io::Result<()> {
+    let client = RemoteClient::connect(&args.repository)?;
+    let url = client.url().to_string();
+
+    let mut request = FetchRequest {
+        depth: args.depth,
+        deepen_since: args
+            .shallow_since
+            .as_deref()
+            .map(parse_date)
+            .transpose()?
+            .map(|(timestamp, _)| timestamp),
+        deepen_not: args.shallow_exclude,
+        filter: args.filter,
+        ..Default::default()
+    };
+    if let Some(filter) = &request.filter {
+        promisor::validate_filter(filter)?;
+    }
+    if request.depth == Some(0) {
+        return Err(std::io::Error::other("depth 0 is not a positive number"));
+    }
+
+    // Like git, repositories given by their path are copied unless told otherwise, while URLs
+    // always go through the transport
+    let mut local_repository = client
+        .local_repository()
+        .filter(|_| (args.local || !args.no_local) && !transport::is_url(&args.repository))
+        .map(Path::to_path_buf);
+    if local_repository
+        .as_ref()
+        .is_some_and(|repository| repository.join("shallow").exists())
+    {
+        eprintln!("warning: source repository is shallow, ignoring --local");
+        local_repository = None;
+    }
+    if local_repository.is_some() {
+        let ignored = [
+            ("--depth", request.depth.is_some()),
+            ("--shallow-since", request.deepen_since.is_some()),
+            ("--shallow-exclude", !request.deepen_not.is_empty()),
+            ("--filter", request.filter.is_some()),
+        ];
+        for (option, _) in ignored.iter().filter(|(_, given)| *given) {
+            eprintln!("warning: {option} is ignored in local clones; use file:// instead.");
         }
+        request = FetchRequest::default();
+    }
+    // Limiting the history only makes sense for the branch that gets checked out
+    let single_branch =
+        request.depth.is_some() || request.deepen_since.is_some() || !request.deepen_not.is_empty();
+
+    let directory = args
+        .directory
+        .unwrap_or_else(|| default_directory_name(&args.repository));
+    let path = Path::new(&directory);
+    if path.exists() && (!path.is_dir() || fs::read_dir(path)?.next().is_some()) {
+        return Err(std::io::Error::other(format!(
+            "destination path '{directory}' already exists and is not an empty directory."
+        )));
     }
+    println!("Cloning into '{directory}'...");
 
-    let commits = commits
-        .symmetric_difference(&parent_commits)
+    fs::create_dir_all(&directory)?;
+    env::set_current_dir(&directory)?;
+    init()?;
+
+    let ref_prefixes = ["HEAD", "refs/heads/", "refs/tags/"].map(String::from);
+    let discover_refs_response = client.discover_refs(&ref_prefixes)?;
+    let default_branch = discover_refs_response.default_branch();
+
+    let mut config = Config::load()?;
+    config.set("remote.origin.url", &url)?;
+    if let Some(filter) = &request.filter {
+        config.set("remote.origin.promisor", "true")?;
+        config.set("remote.origin.partialCloneFilter", filter)?;
+    }
+
+    let fetched = discover_refs_response
+        .refs
+        .iter()
+        .filter(|r| match &default_branch {
+            Some(default_branch) if single_branch => r.name == *default_branch,
+            _ => {
+                r.name.starts_with("refs/heads/")
+                    || (r.name.starts_with("refs/tags/") && !r.name.ends_with("^{}"))
+            }
+        })
         .collect::<Vec<_>>();
-    let head_commit_hash = commits.get(0).unwrap();
 
-    let mut master_ref_file = File::create(".hamachi/refs/heads/master").unwrap();
-    master_ref_file
-        .write_all(head_commit_hash.to_string().as_bytes())
-        .unwrap();
+    match &default_branch {
+        Some(default_branch) if single_branch => {
+            let name = default_branch.trim_start_matches("refs/heads/");
+            config.set(
+                "remote.origin.fetch",
+                &format!("+refs/heads/{name}:refs/remotes/origin/{name}"),
+            )?;
+        }
+        _ => config.set("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")?,
+    }
+
+    if fetched.is_empty() {
+        config.write()?;
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    }
+
+    for r in &fetched {
+        if !request.wants.contains(&r.hash) {
+            request.wants.push(r.hash.clone());
+        }
+    }
+    match &local_repository {
+        Some(repository) => copy_objects(repository, !args.no_hardlinks, args.local)?,
+        None => {
+            let response = client.fetch_pack(&discover_refs_response, &request)?;
+            shallow::update_shallow(&response.shallow, &response.unshallow)?;
+            if request.filter.is_some() {
+                response.pack.mark_promisor()?;
+            }
+        }
+    }
+
+    let message = format!("clone: from {url}");
+    for r in &fetched {
+        let local_name = match r.name.strip_prefix("refs/heads/") {
+            Some(branch) => format!("refs/remotes/origin/{branch}"),
+            None => r.name.clone(),
+        };
+        refs::update_ref_with_log(&local_name, None, &r.hash, &message)?;
+    }
+
+    let Some(default_branch) = default_branch else {
+        config.write()?;
+        return Ok(());
+    };
+    let branch = default_branch.trim_start_matches("refs/heads/");
+    refs::write_symbolic_ref(
+        "refs/remotes/origin/HEAD",
+        &format!("refs/remotes/origin/{branch}"),
+    )?;
 
-    let mut head_file = File::create(".hamachi/HEAD").unwrap();
-    head_file
-        .write_all("ref: refs/heads/master".as_bytes())
-        .unwrap();
+    config.set(&format!("branch.{branch}.remote"), "origin")?;
+    config.set(&format!("branch.{branch}.merge"), &default_branch)?;
+    config.write()?;
 
-    let head_commit = Commit::from_hash(head_commit_hash);
-    println!("Head commit hash {}", head_commit_hash.to_string());
-    println!("Head commit tree {}", head_commit.tree_hash.to_string());
+    let head = head_of(&discover_refs_response.refs, &default_branch)?;
+    refs::write_head(&Head::Branch(default_branch.clone()))?;
+    refs::update_ref_with_log(&default_branch, None, &head, &message)?;
+
+    // Materialize the files of the head commit
+    let head_commit = Commit::from_hash(&head);
+    worktree::checkout_tree(None, &head_commit.tree_hash, false)?;
+
+    Ok(())
+}
+
+fn head_of(refs: &[Ref], name: &str) -> std::io::Result<Hash> {
+    refs.iter()
+        .find(|r| r.name == name)
+        .map(|r| r.hash.clone())
+        .ok_or_else(|| {
+            std::io::Error::other(format!("remote HEAD refers to nonexistent ref {name}"))
+        })
+}
+
+/// Copies the objects of a repository on the local filesystem instead of fetching them, hard
+/// linking the loose ones unless told otherwise or they are on another filesystem, which is
+/// only an error when `--local` was asked for. As we only read loose objects, the objects of
+/// the packs are written loose, as when receiving a pack.
+fn copy_objects(repository: &Path, mut hardlinks: bool, local: bool) -> std::io::Result<()> {
+    let objects = repository.join("objects");
+    for directory in fs::read_dir(&objects)? {
+        let directory = directory?;
+        let name = directory.file_name();
+        if name.len() != 2 || !directory.file_type()?.is_dir() {
+            continue;
+        }
+
+        let destination = PathBuf::from(".hamachi/objects").join(name);
+        fs::create_dir_all(&destination)?;
+        for object in fs::read_dir(directory.path())? {
+            let source = object?.path();
+            let target = destination.join(source.file_name().unwrap_or_default());
+            if hardlinks {
+                match fs::hard_link(&source, &target) {
+                    Ok(()) => continue,
+                    Err(e) if local => {
+                        return Err(std::io::Error::other(format!(
+                            "failed to create link '{}': {e}",
+                            target.display()
+                        )))
+                    }
+                    Err(_) => hardlinks = false,
+                }
+            }
+            fs::copy(&source, &target)?;
+        }
+    }
+
+    let packs = objects.join("pack");
+    if packs.is_dir() {
+        for entry in fs::read_dir(packs)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "pack")
+            {
+                PackFile::receive(BufReader::new(File::open(&path)?), false)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory git would clone into, e.g. `repo` for `https://host/user/repo.git` or for
+/// `/path/to/repo/.git`
+fn default_directory_name(url: &str) -> String {
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix("/.git").unwrap_or(path);
+    let name = path
+        .rsplit(['/', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("hamachi");
+
+    name.strip_suffix(".git").unwrap_or(name).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::clone::{clone, CloneArgs};
+    use crate::command::fetch::{fetch, FetchArgs};
+    use crate::object::{Hash, Object};
+    use crate::refs::revision;
+    use crate::remote::promisor;
+    use crate::test_utils::{
+        fake_ssh, git, hamachi_git, run_git_command, serve_git_daemon, serve_git_http,
+        setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+    use std::process::Command;
+    use std::str::FromStr;
+
+    rusty_fork_test! {
+        #[test]
+        fn clone_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            let lines = (0..200).map(|i| format!("line {i}\n")).collect::<String>();
+            for (i, content) in ["one\n", "two\n", "three\n"].iter().enumerate() {
+                fs::write("a.txt", content).unwrap();
+                // Versions of a larger file that the remote sends as deltas
+                fs::write("b.txt", format!("{lines}{content}")).unwrap();
+                git(&["add", "a.txt", "b.txt"]);
+                git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            git(&["branch", "feature", "HEAD~1"]);
+            let url = format!("{}/.git", serve_git_http(Path::new(".")).unwrap());
+
+            git(&["clone", "--depth", "2", &url, "expected"]);
+            let expected_log = git(&["-C", "expected", "log", "--format=%H %P"]);
+            let expected_shallow = fs::read_to_string("expected/.git/shallow").unwrap();
+            let expected_fetch = git(&["-C", "expected", "config", "remote.origin.fetch"]);
+
+            // Test
+            clone(CloneArgs {
+                repository: url.clone(),
+                directory: Some(String::from("actual")),
+                depth: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+
+            assert_eq!(expected_log, hamachi_git(&["log", "--format=%H %P"]));
+            assert_eq!(expected_shallow, fs::read_to_string(".hamachi/shallow").unwrap());
+            assert_eq!(expected_fetch, hamachi_git(&["config", "remote.origin.fetch"]));
+            assert_eq!(hamachi_git(&["symbolic-ref", "refs/remotes/origin/HEAD"]), "refs/remotes/origin/master");
+            assert_eq!(hamachi_git(&["config", "branch.master.merge"]), "refs/heads/master");
+            assert_eq!(fs::read_to_string("a.txt").unwrap(), "three\n");
+            assert_eq!(hamachi_git(&["status", "--porcelain", "--untracked-files=no"]), "");
+            assert!(revision::resolve("HEAD~1").is_ok());
+            assert!(revision::resolve("HEAD~2").is_err());
+            // The pack was moved in place once received, and its deltas resolved
+            let packs = fs::read_dir(".hamachi/objects/pack").unwrap().flatten().map(|entry| entry.file_name().into_string().unwrap()).collect::<Vec<_>>();
+            assert!(packs.iter().all(|name| name.starts_with("pack-")));
+            let idx = packs.iter().find(|name| name.ends_with(".idx")).unwrap();
+            let blob = Hash::from_str(&hamachi_git(&["rev-parse", "HEAD~1:b.txt"])).unwrap();
+            assert_eq!(Object::read(&blob).unwrap().1, format!("{lines}two\n").into_bytes());
+            assert!(git(&["verify-pack", "-v", &format!(".hamachi/objects/pack/{idx}")]).contains("chain length = 1"));
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn partial_clone_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            fs::create_dir("b").unwrap();
+            fs::write("a.txt", "one\n").unwrap();
+            fs::write("b/c.txt", "two\n").unwrap();
+            git(&["add", "."]);
+            git(&["commit", "-m", "first"]);
+            git(&["switch", "-c", "feature"]);
+            fs::write("d.txt", "three\n").unwrap();
+            git(&["add", "d.txt"]);
+            git(&["commit", "-m", "second"]);
+            git(&["switch", "master"]);
+            git(&["config", "uploadpack.allowFilter", "true"]);
+            git(&["config", "uploadpack.allowAnySHA1InWant", "true"]);
+            let url = format!("{}/.git", serve_git_http(Path::new(".")).unwrap());
+
+            let missing = |args: &[&str]| {
+                let objects = run_git_command(Command::new("git").args(args).args(["rev-list", "--objects", "--all", "--missing=print"])).unwrap();
+                objects.lines().filter_map(|line| line.strip_prefix('?')).map(str::to_string).collect::<Vec<_>>()
+            };
+            git(&["clone", "--filter=blob:none", &url, "expected"]);
+            let expected_missing = missing(&["-C", "expected"]);
+            let expected_config = ["remote.origin.promisor", "remote.origin.partialclonefilter"]
+                .map(|key| git(&["-C", "expected", "config", key]));
+
+            // Test
+            clone(CloneArgs {
+                repository: url.clone(),
+                directory: Some(String::from("actual")),
+                filter: Some(String::from("blob:none")),
+                ..Default::default()
+            })
+            .unwrap();
+
+            let actual_missing = missing(&["--git-dir", ".hamachi"]);
+            assert_eq!(expected_missing, actual_missing);
+            assert_eq!(expected_config, ["remote.origin.promisor", "remote.origin.partialclonefilter"]
+                .map(|key| hamachi_git(&["config", key])));
+            assert!(fs::read_dir(".hamachi/objects/pack").unwrap().flatten().any(|entry| entry.path().extension().is_some_and(|extension| extension == "promisor")));
+            assert_eq!(fs::read_to_string("b/c.txt").unwrap(), "two\n");
+
+            // Objects left out are fetched the first time they are needed
+            let blob = Hash::from_str(&actual_missing[0]).unwrap();
+            assert_eq!(Object::read(&blob).unwrap().1, b"three\n");
+            assert!(missing(&["--git-dir", ".hamachi"]).is_empty());
+            assert!(promisor::validate_filter("blob:limit=1k").is_ok());
+            assert!(promisor::validate_filter("tree:0").is_ok());
+            assert!(promisor::validate_filter("blob:some").is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn local_clone_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            let source = |args: &[&str]| git(&[&["-C", "source", "-c", "user.name=Osamu Dazai", "-c", "user.email=osamu.dazai@gmail.com"], args].concat());
+            git(&["init", "source"]);
+            fs::write("source/a.txt", "one\n").unwrap();
+            source(&["add", "a.txt"]);
+            source(&["commit", "-m", "packed"]);
+            source(&["gc", "--quiet"]);
+            fs::write("source/a.txt", "two\n").unwrap();
+            source(&["commit", "-am", "loose"]);
+            let expected_log = source(&["log", "--format=%H %P"]);
+            let inode = |path: &str| fs::metadata(path).unwrap().ino();
+            let loose_blob = source(&["rev-parse", "HEAD:a.txt"]);
+            let loose_path = |root: &str| format!("{root}/objects/{}/{}", &loose_blob[..2], &loose_blob[2..]);
+
+            // Test
+            // By path, the loose objects are hard linked and the packed ones unpacked
+            clone(CloneArgs { repository: String::from("source"), directory: Some(String::from("linked")), ..Default::default() }).unwrap();
+            assert_eq!(hamachi_git(&["log", "--format=%H %P"]), expected_log);
+            assert_eq!(hamachi_git(&["config", "remote.origin.url"]), repo.join("source").canonicalize().unwrap().to_str().unwrap());
+            assert_eq!(fs::read_to_string("a.txt").unwrap(), "two\n");
+            std::env::set_current_dir(&repo).unwrap();
+            assert_eq!(inode(&loose_path("linked/.hamachi")), inode(&loose_path("source/.git")));
+
+            // Then fetching from it goes through upload-pack
+            fs::write("source/b.txt", "three\n").unwrap();
+            source(&["add", "b.txt"]);
+            source(&["commit", "-m", "fetched"]);
+            let head = source(&["rev-parse", "HEAD"]);
+            std::env::set_current_dir("linked").unwrap();
+            fetch(FetchArgs::default()).unwrap();
+            assert_eq!(hamachi_git(&["rev-parse", "origin/master"]), head);
+            std::env::set_current_dir(&repo).unwrap();
+
+            // Copied rather than linked when asked, and sent by upload-pack for file:// URLs
+            clone(CloneArgs { repository: String::from("source"), directory: Some(String::from("copied")), no_hardlinks: true, ..Default::default() }).unwrap();
+            std::env::set_current_dir(&repo).unwrap();
+            assert_ne!(inode(&loose_path("copied/.hamachi")), inode(&loose_path("source/.git")));
+            let url = format!("file://{}", repo.join("source").display());
+            clone(CloneArgs { repository: url.clone(), directory: Some(String::from("fetched")), depth: Some(1), ..Default::default() }).unwrap();
+            assert_eq!(hamachi_git(&["log", "--format=%H"]), head);
+            assert_eq!(hamachi_git(&["config", "remote.origin.url"]), url);
+            std::env::set_current_dir(&repo).unwrap();
+            assert_ne!(inode(&loose_path("fetched/.hamachi")), inode(&loose_path("source/.git")));
+
+            assert!(clone(CloneArgs { repository: String::from("missing"), ..Default::default() }).is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn ssh_clone_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            fake_ssh(&repo).unwrap();
+            let source = |args: &[&str]| git(&[&["-C", "source", "-c", "user.name=Osamu Dazai", "-c", "user.email=osamu.dazai@gmail.com"], args].concat());
+            git(&["init", "source"]);
+            fs::write("source/a.txt", "one\n").unwrap();
+            source(&["add", "a.txt"]);
+            source(&["commit", "-m", "first"]);
+            let first = source(&["rev-parse", "HEAD"]);
+            let path = repo.join("source").display().to_string();
+
+            // Test
+            // Over protocol version 2 with an ssh:// URL
+            let url = format!("ssh://git@localhost:2222{path}");
+            clone(CloneArgs { repository: url.clone(), directory: Some(String::from("by-url")), ..Default::default() }).unwrap();
+            assert_eq!(hamachi_git(&["log", "--format=%H"]), first);
+            assert_eq!(hamachi_git(&["config", "remote.origin.url"]), url);
+            assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+            std::env::set_current_dir(&repo).unwrap();
+            let log = fs::read_to_string("ssh.log").unwrap();
+            assert!(log.contains(&format!("-o SendEnv=GIT_PROTOCOL -p 2222 git@localhost git-upload-pack '{path}'")));
+
+            // Over the original protocol with an scp-like URL, fetching with haves
+            clone(CloneArgs { repository: format!("localhost:{path}"), directory: Some(String::from("scp-like")), ..Default::default() }).unwrap();
+            hamachi_git(&["config", "protocol.version", "0"]);
+            std::env::set_current_dir(&repo).unwrap();
+            fs::write("source/a.txt", "two\n").unwrap();
+            source(&["commit", "-am", "second"]);
+            let head = source(&["rev-parse", "HEAD"]);
+            std::env::set_current_dir("scp-like").unwrap();
+            fetch(FetchArgs::default()).unwrap();
+            assert_eq!(hamachi_git(&["rev-parse", "origin/master"]), head);
+            std::env::set_current_dir(&repo).unwrap();
+            let log = fs::read_to_string("ssh.log").unwrap();
+            assert!(log.ends_with(&format!("localhost git-upload-pack '{path}'\n")));
+            assert!(!log.lines().last().unwrap().contains("SendEnv"));
+
+            // The error of the remote end is reported
+            let error = clone(CloneArgs { repository: String::from("localhost:missing"), ..Default::default() }).unwrap_err();
+            assert!(error.to_string().contains("'missing' does not appear to be a git repository"), "{error}");
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn daemon_clone_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            let source = |args: &[&str]| git(&[&["-C", "source", "-c", "user.name=Osamu Dazai", "-c", "user.email=osamu.dazai@gmail.com"], args].concat());
+            git(&["init", "source"]);
+            fs::write("source/a.txt", "one\n").unwrap();
+            source(&["add", "a.txt"]);
+            source(&["commit", "-m", "first"]);
+            let first = source(&["rev-parse", "HEAD"]);
+            let base_url = serve_git_daemon(Path::new(".")).unwrap();
+
+            // Test
+            // Over protocol version 2
+            let url = format!("{base_url}/source");
+            clone(CloneArgs { repository: url.clone(), directory: Some(String::from("cloned")), ..Default::default() }).unwrap();
+            assert_eq!(hamachi_git(&["log", "--format=%H"]), first);
+            assert_eq!(hamachi_git(&["config", "remote.origin.url"]), url);
+            assert_eq!(fs::read_to_string("a.txt").unwrap(), "one\n");
+
+            // Over the original protocol, fetching with haves
+            hamachi_git(&["config", "protocol.version", "0"]);
+            std::env::set_current_dir(&repo).unwrap();
+            fs::write("source/a.txt", "two\n").unwrap();
+            source(&["commit", "-am", "second"]);
+            let head = source(&["rev-parse", "HEAD"]);
+            std::env::set_current_dir("cloned").unwrap();
+            fetch(FetchArgs::default()).unwrap();
+            assert_eq!(hamachi_git(&["rev-parse", "origin/master"]), head);
+            std::env::set_current_dir(&repo).unwrap();
+
+            // The error of the daemon is reported
+            let error = clone(CloneArgs { repository: format!("{base_url}/missing"), ..Default::default() }).unwrap_err();
+            assert_eq!(error.to_string(), "remote error: no such repository: /missing");
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
 }
@@ -0,0 +1,378 @@
+use crate::command::status::{status, StatusFormat};
+use crate::config::Config;
+use crate::index::{Index, IndexEntry};
+use crate::merge;
+use crate::object::commit::{now, parse_date, Commit, Signature};
+use crate::object::Hash;
+use crate::refs::Head;
+use crate::{refs, sequencer, worktree};
+use std::fs;
+use std::io::Read;
+use std::process::Command;
+
+const COMMIT_EDITMSG_PATH: &str = ".hamachi/COMMIT_EDITMSG";
+
+#[derive(Debug, Default)]
+pub(crate) struct CommitOptions {
+    /// Paragraphs of the message, one per `-m`
+    pub(crate) messages: Vec<String>,
+    /// File to read the message from, `-` for the standard input
+    pub(crate) file: Option<String>,
+    pub(crate) amend: bool,
+    /// Reuse the message of the amended commit without opening the editor
+    pub(crate) no_edit: bool,
+    pub(crate) allow_empty: bool,
+    /// Stage the modifications and deletions of tracked files first
+    pub(crate) all: bool,
+    /// Author in the `Name <email>` format
+    pub(crate) author: Option<String>,
+    pub(crate) date: Option<String>,
+}
+
+/// Record the changes staged in the index as a new commit on the current branch
+/// https://git-scm.com/docs/git-commit
+pub(crate) fn commit(options: CommitOptions) -> std::io::Result<Hash> {
+    let mut index = Index::load()?;
+    if options.all {
+        stage_tracked_changes(&mut index)?;
+    }
+    if index.has_conflicts() {
+        return Err(std::io::Error::other(
+            "Committing is not possible because you have unmerged files.",
+        ));
+    }
+    let tree_hash = index.write_tree()?;
+
+    let head_commit = refs::head_commit()?;
+    let amended = match (&head_commit, options.amend) {
+        (Some(hash), true) => Some(Commit::from_hash(hash)),
+        (None, true) => return Err(std::io::Error::other("You have nothing to amend.")),
+        (_, false) => None,
+    };
+
+    // Concluding a merge records the merged commits as additional parents
+    let merge_heads = merge::merge_heads()?;
+    if amended.is_some() && !merge_heads.is_empty() {
+        return Err(std::io::Error::other(
+            "You are in the middle of a merge -- cannot amend.",
+        ));
+    }
+
+    let parents = match &amended {
+        Some(amended) => amended
+            .parents
+            .iter()
+            .map(|parent| parent.parent_hash.clone())
+            .collect(),
+        None => head_commit
+            .iter()
+            .chain(&merge_heads)
+            .cloned()
+            .collect::<Vec<_>>(),
+    };
+
+    if amended.is_none() && merge_heads.is_empty() && !options.allow_empty {
+        let parent_tree = parents
+            .first()
+            .map(|parent| Commit::from_hash(parent).tree_hash);
+        let is_empty = match parent_tree {
+            Some(parent_tree) => parent_tree == tree_hash,
+            None => index.entries.is_empty(),
+        };
+        if is_empty {
+            return Err(std::io::Error::other(
+                "nothing to commit (use \"hamachi add\" to stage changes, or --allow-empty to record an empty commit)",
+            ));
+        }
+    }
+
+    let message = commit_message(&options, amended.as_ref())?;
+
+    let mut author = match (&options.author, &amended) {
+        (Some(author), _) => parse_author(author)?,
+        (None, Some(amended)) => amended.author(),
+        // Concluding a cherry-pick keeps the author of the picked commit
+        (None, None) => match sequencer::cherry_pick_head()? {
+            Some(picked) => Commit::from_hash(&picked).author(),
+            None => Signature::author()?,
+        },
+    };
+    if let Some(date) = &options.date {
+        (author.date, author.timezone) = parse_date(date)?;
+    }
+
+    let commit = Commit::new(tree_hash, parents, author, Signature::committer()?, message);
+    let hash = commit.write()?;
+
+    let kind = match (&amended, &head_commit) {
+        (Some(_), _) => " (amend)",
+        (None, Some(_)) if !merge_heads.is_empty() => " (merge)",
+        (None, None) => " (initial)",
+        (None, Some(_)) => "",
+    };
+    refs::update_ref_with_log(
+        "HEAD",
+        head_commit.as_ref(),
+        &hash,
+        &format!("commit{kind}: {}", commit.subject()),
+    )?;
+    merge::clear_merge_state()?;
+    sequencer::clear_pick_heads()?;
+    print_summary(&hash, &commit)?;
+
+    Ok(hash)
+}
+
+/// Reports a new commit with its branch, abbreviated hash and subject
+pub(crate) fn print_summary(hash: &Hash, commit: &Commit) -> std::io::Result<()> {
+    let branch = match refs::read_head()? {
+        Head::Branch(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(_) => String::from("detached HEAD"),
+    };
+    let root = if commit.parents.is_empty() {
+        " (root-commit)"
+    } else {
+        ""
+    };
+    println!(
+        "[{branch}{root} {}] {}",
+        hash.to_short_string(),
+        commit.subject()
+    );
+
+    Ok(())
+}
+
+/// Updates the index entries of tracked files that were modified or deleted in the working tree
+fn stage_tracked_changes(index: &mut Index) -> std::io::Result<()> {
+    let mut updates = Vec::new();
+    let mut removals = Vec::new();
+    for entry in index.entries.iter().filter(|entry| entry.stage == 0) {
+        if fs::symlink_metadata(&entry.path).is_err() {
+            removals.push(entry.path.clone());
+        } else if worktree::is_modified(index, entry)? {
+            updates.push(entry.path.clone());
+        }
+    }
+
+    for path in updates {
+        let (mode, hash, metadata) = worktree::hash_file(&path, true)?;
+        index.add(IndexEntry::from_metadata(path, mode, hash, &metadata));
+    }
+    for path in removals {
+        index.remove(&path);
+    }
+
+    index.write()
+}
+
+/// The message from `-m` or `-F`, or else from the editor, cleaned up the way git does
+fn commit_message(options: &CommitOptions, amended: Option<&Commit>) -> std::io::Result<String> {
+    let message = if !options.messages.is_empty() {
+        cleanup_message(&options.messages.join("\n\n"), false)
+    } else if let Some(file) = &options.file {
+        let mut message = String::new();
+        if file == "-" {
+            std::io::stdin().read_to_string(&mut message)?;
+        } else {
+            message = fs::read_to_string(file)?;
+        }
+        cleanup_message(&message, false)
+    } else if let (Some(amended), true) = (amended, options.no_edit) {
+        amended.commit_message.clone()
+    } else if let (Some(message), true) = (merge::merge_message()?, options.no_edit) {
+        cleanup_message(&message, true)
+    } else {
+        let initial = match amended {
+            Some(amended) => amended.commit_message.clone(),
+            None => merge::merge_message()?.unwrap_or_default(),
+        };
+        cleanup_message(&edit_message(&initial)?, true)
+    };
+
+    if message.is_empty() {
+        return Err(std::io::Error::other(
+            "Aborting commit due to empty commit message.",
+        ));
+    }
+
+    Ok(message)
+}
+
+/// Lets the user write the message in their editor, starting from the specified text followed
+/// by the status of the repository as comments
+pub(crate) fn edit_message(initial: &str) -> std::io::Result<String> {
+    let mut template = format!(
+        "{initial}\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored, and an empty message aborts the commit.\n#\n"
+    );
+    for line in status(StatusFormat::Long, false)?.lines() {
+        match line {
+            "" => template.push_str("#\n"),
+            line if line.starts_with('\t') => template.push_str(&format!("#{line}\n")),
+            line => template.push_str(&format!("# {line}\n")),
+        }
+    }
+    fs::write(COMMIT_EDITMSG_PATH, template)?;
+    launch_editor(&editor(), COMMIT_EDITMSG_PATH)?;
+
+    fs::read_to_string(COMMIT_EDITMSG_PATH)
+}
+
+/// The editor set by `GIT_EDITOR`, `core.editor`, `VISUAL` or `EDITOR`, in that order of
+/// precedence, `vi` otherwise
+pub(crate) fn editor() -> String {
+    std::env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| Config::load().ok()?.get("core.editor"))
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Opens a file in an editor, which may be a shell command with arguments, and waits for it to
+/// exit
+pub(crate) fn launch_editor(editor: &str, path: &str) -> std::io::Result<()> {
+    let exit_status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(editor)
+        .arg(path)
+        .status()?;
+    if !exit_status.success() {
+        return Err(std::io::Error::other(format!(
+            "there was a problem with the editor '{editor}'"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Strips trailing whitespace and leading and trailing blank lines, collapses consecutive blank
+/// lines and, for messages from the editor, removes comment lines
+pub(crate) fn cleanup_message(message: &str, strip_comments: bool) -> String {
+    let mut cleaned = String::new();
+    let mut pending_blank_line = false;
+
+    for line in message.lines() {
+        if strip_comments && line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            pending_blank_line = !cleaned.is_empty();
+            continue;
+        }
+
+        if pending_blank_line {
+            cleaned.push('\n');
+            pending_blank_line = false;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+
+    cleaned
+}
+
+/// Parses an author given as `Name <email>`, dated now
+fn parse_author(author: &str) -> std::io::Result<Signature> {
+    let invalid_author = || {
+        std::io::Error::other(format!(
+            "--author '{author}' is not 'Name <email>' and matches no existing author"
+        ))
+    };
+
+    let (name, rest) = author.split_once('<').ok_or_else(invalid_author)?;
+    let email = rest.strip_suffix('>').ok_or_else(invalid_author)?;
+    let (date, timezone) = now();
+
+    Ok(Signature {
+        name: name.trim().to_string(),
+        email: email.trim().to_string(),
+        date,
+        timezone,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::commit::{commit, CommitOptions};
+    use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn commit_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            fs::write("test.txt", "this is some test content\n").unwrap();
+            fs::create_dir("testdir").unwrap();
+            fs::write("testdir/test2.txt", "this is more test content\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            add(vec![String::from(".")], false, false).unwrap();
+
+            // Test
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first").arg("-m").arg("body  ")).unwrap();
+            let actual = commit(CommitOptions {
+                messages: vec![String::from("first"), String::from("body  ")],
+                ..Default::default()
+            })
+            .unwrap();
+            let expected = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+            assert_eq!(expected, actual.to_string());
+
+            fs::write("test.txt", "modified content\n").unwrap();
+            run_git_command(
+                Command::new("git")
+                    .arg("commit")
+                    .arg("-a")
+                    .arg("-m")
+                    .arg("second")
+                    .arg("--author")
+                    .arg("Someone Else <someone@example.com>")
+                    .arg("--date")
+                    .arg("2005-04-07T22:13:13+02:00"),
+            )
+            .unwrap();
+            let actual = commit(CommitOptions {
+                messages: vec![String::from("second")],
+                all: true,
+                author: Some(String::from("Someone Else <someone@example.com>")),
+                date: Some(String::from("2005-04-07T22:13:13+02:00")),
+                ..Default::default()
+            })
+            .unwrap();
+            let expected = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+            assert_eq!(expected, actual.to_string());
+
+            run_git_command(Command::new("git").arg("commit").arg("--amend").arg("-m").arg("amended")).unwrap();
+            let actual = commit(CommitOptions {
+                messages: vec![String::from("amended")],
+                amend: true,
+                ..Default::default()
+            })
+            .unwrap();
+            let expected = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+            assert_eq!(expected, actual.to_string());
+
+            assert!(commit(CommitOptions { messages: vec![String::from("empty")], ..Default::default() }).is_err());
+
+            let expected_reflog = fs::read_to_string(".git/logs/HEAD").unwrap();
+            let actual_reflog = fs::read_to_string(".hamachi/logs/HEAD").unwrap();
+            assert_eq!(expected_reflog, actual_reflog);
+            assert_eq!(expected_reflog, fs::read_to_string(".hamachi/logs/refs/heads/master").unwrap());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
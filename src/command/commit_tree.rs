@@ -1,48 +1,36 @@
-use crate::object::commit::Commit;
-use crate::object::{Hash, Object};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use sha1::{Digest, Sha1};
-use std::io::Write;
+use crate::object::commit::{Commit, Signature};
+use crate::object::Hash;
+use crate::refs::revision;
 use std::str::FromStr;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-pub fn commit_tree(hash: &str, message: &Option<String>) -> std::io::Result<Hash> {
-    let default_author_name = String::from("Osamu Dazai");
-    let default_author_email = String::from("osamu.dazai@gmail.com");
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs();
-    let default_timezone = String::from("-0500");
-
-    let commit = Commit {
-        tree_hash: Hash::from_str(&hash).expect("Invalid hash"),
-        parents: Vec::new(),
-        author_name: default_author_name.clone(),
-        author_email: default_author_email.clone(),
-        author_date: now,
-        author_date_timezone: default_timezone.clone(),
-        committer_name: default_author_name.clone(),
-        committer_email: default_author_email.clone(),
-        committer_date: now,
-        committer_date_timezone: default_timezone.clone(),
-        commit_message: message.clone().unwrap_or_default().to_string(),
-    };
-
-    let commit_file_content = commit.to_object_file_representation();
-
-    let mut hasher = Sha1::new();
-    Digest::update(&mut hasher, &commit_file_content);
-    let hash = Hash(hasher.finalize().as_slice().to_vec());
-
-    let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
-    compressor.write_all(&commit_file_content)?;
-    let compressed_bytes = compressor.finish()?;
-
-    Object::write_to_disk(&hash, &compressed_bytes)?;
-
-    Ok(hash)
+
+/// Create a new commit object from a tree, with the specified parents, several of them making
+/// a merge commit
+/// https://git-scm.com/docs/git-commit-tree
+pub fn commit_tree(
+    hash: &str,
+    parents: &[String],
+    message: &Option<String>,
+) -> std::io::Result<Hash> {
+    let tree_hash = Hash::from_str(hash)
+        .map_err(|_| std::io::Error::other(format!("not a valid object name {hash}")))?;
+
+    let mut parent_hashes: Vec<Hash> = Vec::new();
+    for parent in parents {
+        let parent = revision::resolve_commit(parent)?;
+        if !parent_hashes.contains(&parent) {
+            parent_hashes.push(parent);
+        }
+    }
+
+    let commit = Commit::new(
+        tree_hash,
+        parent_hashes,
+        Signature::author()?,
+        Signature::committer()?,
+        format!("{}\n", message.clone().unwrap_or_default()),
+    );
+
+    commit.write()
 }
 
 #[cfg(test)]
@@ -50,6 +38,7 @@ mod tests {
     use crate::command::commit_tree::commit_tree;
     use crate::object::Object;
     use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
     use std::fs::File;
     use std::process::Command;
 
@@ -74,7 +63,7 @@ mod tests {
                 .arg(commit_message),
         )
         .unwrap();
-        let actual_hash = commit_tree(&tree_hash, &Some(String::from(commit_message)))
+        let actual_hash = commit_tree(&tree_hash, &[], &Some(String::from(commit_message)))
             .unwrap()
             .to_string();
 
@@ -86,4 +75,27 @@ mod tests {
 
         teardown(repo).unwrap();
     }
+
+    rusty_fork_test! {
+        #[test]
+        fn commit_tree_timezone_test() {
+            // Setup
+            // An offset that is not a whole number of hours
+            std::env::set_var("TZ", "Asia/Kolkata");
+            let repo = setup_test_environment().unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            let tree_hash = run_git_command(Command::new("git").arg("write-tree")).unwrap();
+
+            // Test
+            let commit_message = "this is a commit message";
+            let expected_hash = run_git_command(Command::new("git").args(["commit-tree", &tree_hash, "-m", commit_message])).unwrap();
+            let actual_hash = commit_tree(&tree_hash, &[], &Some(String::from(commit_message))).unwrap().to_string();
+
+            let actual_content = Object::decompress_object(&actual_hash, false).unwrap();
+            assert!(String::from_utf8(actual_content).unwrap().contains(" +0530\n"));
+            assert_eq!(actual_hash, expected_hash);
+
+            teardown(repo).unwrap();
+        }
+    }
 }
@@ -1,23 +1,75 @@
+use crate::config::Config;
 use clap::Subcommand;
 
 #[derive(Debug, Subcommand)]
 pub(crate) enum ConfigSubcommand {
     List,
-    Get,
+    Get { name: String },
     Set { name: String, value: String },
-    Unset,
-    RenameSection,
-    RemoveSection,
+    Unset { name: String },
+    RenameSection { old_name: String, new_name: String },
+    RemoveSection { name: String },
     Edit,
 }
 
-pub fn config(subcommand: ConfigSubcommand) {
+/// Get and set repository options
+/// https://git-scm.com/docs/git-config
+pub fn config(subcommand: ConfigSubcommand) -> std::io::Result<()> {
     match subcommand {
+        ConfigSubcommand::List => config_list(),
+        ConfigSubcommand::Get { name } => config_get(name),
         ConfigSubcommand::Set { name, value } => config_set(name, value),
+        ConfigSubcommand::Unset { name } => config_unset(name),
+        ConfigSubcommand::RenameSection { old_name, new_name } => {
+            config_rename_section(old_name, new_name)
+        }
+        ConfigSubcommand::RemoveSection { name } => config_remove_section(name),
         _ => todo!(),
     }
 }
 
-fn config_set(_name: String, _value: String) {
-    todo!()
+fn config_list() -> std::io::Result<()> {
+    for (name, value) in Config::load()?.entries() {
+        println!("{name}={value}");
+    }
+
+    Ok(())
+}
+
+fn config_get(name: String) -> std::io::Result<()> {
+    match Config::load()?.get(&name) {
+        Some(value) => {
+            println!("{value}");
+            Ok(())
+        }
+        None => Err(std::io::Error::other(format!("key {name} is not set"))),
+    }
+}
+
+fn config_set(name: String, value: String) -> std::io::Result<()> {
+    let mut config = Config::load()?;
+    config.set(&name, &value)?;
+
+    config.write()
+}
+
+fn config_unset(name: String) -> std::io::Result<()> {
+    let mut config = Config::load()?;
+    config.unset(&name)?;
+
+    config.write()
+}
+
+fn config_rename_section(old_name: String, new_name: String) -> std::io::Result<()> {
+    let mut config = Config::load()?;
+    config.rename_section(&old_name, &new_name)?;
+
+    config.write()
+}
+
+fn config_remove_section(name: String) -> std::io::Result<()> {
+    let mut config = Config::load()?;
+    config.remove_section(&name);
+
+    config.write()
 }
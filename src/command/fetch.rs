@@ -0,0 +1,461 @@
+use crate::config::Config;
+use crate::graph::{self, shallow};
+use crate::object::commit::parse_date;
+use crate::object::{Hash, Object, ObjectType};
+use crate::refs;
+use crate::remote::client::{FetchRequest, RemoteClient};
+use crate::remote::refspec::Refspec;
+use clap::Args;
+
+/// The depth git asks for to get the whole history
+const INFINITE_DEPTH: u32 = 0x7fffffff;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct FetchArgs {
+    /// The remote to fetch from
+    remote: Option<String>,
+
+    /// Limit the history to the specified number of commits from the tips of the remote branches
+    #[clap(long, conflicts_with_all = ["deepen", "unshallow"])]
+    depth: Option<u32>,
+
+    /// Fetch the specified number of additional commits beyond the current shallow boundary
+    #[clap(long, conflicts_with = "unshallow")]
+    deepen: Option<u32>,
+
+    /// Deepen the history of a shallow repository to include the commits after the specified date
+    #[clap(long)]
+    shallow_since: Option<String>,
+
+    /// Deepen the history of a shallow repository to exclude the commits reachable from the
+    /// specified remote branch or tag
+    #[clap(long)]
+    shallow_exclude: Vec<String>,
+
+    /// Fetch the whole history of a shallow repository
+    #[clap(long)]
+    unshallow: bool,
+
+    /// Remove the remote-tracking refs whose branch no longer exists on the remote
+    #[clap(short, long)]
+    prune: bool,
+
+    /// Fetch every tag of the remote into `refs/tags`
+    #[clap(short, long)]
+    tags: bool,
+}
+
+/// Download the objects and refs of a remote repository, following its `fetch` refspecs. Only
+/// the objects we lack are sent, after negotiating the history we share with the remote.
+/// https://git-scm.com/docs/git-fetch
+pub(crate) fn fetch(args: FetchArgs) -> std::io::Result<()> {
+    let remote = args.remote.unwrap_or_else(|| String::from("origin"));
+    let config = Config::load()?;
+    let url = config.get(&format!("remote.{remote}.url")).ok_or_else(|| {
+        std::io::Error::other(format!("'{remote}' does not appear to be a git repository"))
+    })?;
+    let mut refspecs = config
+        .get_all(&format!("remote.{remote}.fetch"))
+        .iter()
+        .map(|refspec| Refspec::parse(refspec))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if args.tags {
+        refspecs.push(Refspec::parse("refs/tags/*:refs/tags/*")?);
+    }
+
+    if args.unshallow && !shallow::is_shallow_repository() {
+        return Err(std::io::Error::other(
+            "--unshallow on a complete repository does not make sense",
+        ));
+    }
+    if args.depth == Some(0) || args.deepen == Some(0) {
+        return Err(std::io::Error::other("depth 0 is not a positive number"));
+    }
+
+    let mut request = FetchRequest {
+        shallow: shallow::read_shallow()?.into_iter().collect(),
+        depth: match (args.depth, args.deepen, args.unshallow) {
+            (_, _, true) => Some(INFINITE_DEPTH),
+            (_, Some(deepen), _) => Some(deepen),
+            (depth, ..) => depth,
+        },
+        deepen_relative: args.deepen.is_some(),
+        deepen_since: args
+            .shallow_since
+            .as_deref()
+            .map(parse_date)
+            .transpose()?
+            .map(|(timestamp, _)| timestamp),
+        deepen_not: args.shallow_exclude,
+        // Fetches into a partial clone leave out the same objects as the clone did
+        filter: config.get(&format!("remote.{remote}.partialCloneFilter")),
+        ..Default::default()
+    };
+    let deepening =
+        request.depth.is_some() || request.deepen_since.is_some() || !request.deepen_not.is_empty();
+
+    let client = RemoteClient::connect(&url)?;
+    let ref_prefixes = refspecs
+        .iter()
+        .map(|refspec| refspec.source_prefix().to_string())
+        .collect::<Vec<_>>();
+    let discover_refs_response = client.discover_refs(&ref_prefixes)?;
+    let advertised = discover_refs_response
+        .refs
+        .iter()
+        .filter(|r| !r.name.ends_with("^{}"))
+        .collect::<Vec<_>>();
+
+    let mut updates = Vec::new();
+    for r in &advertised {
+        let Some((refspec, local_name)) = refspecs
+            .iter()
+            .find_map(|refspec| Some((refspec, refspec.map(&r.name)?)))
+        else {
+            continue;
+        };
+        let old = refs::read_ref(&local_name)?;
+        let moved = old.as_ref() != Some(&r.hash);
+        // Deepening needs the tips even when they did not move or are already present
+        let wanted = deepening || (moved && !Object::exists(&r.hash));
+        if wanted && !request.wants.contains(&r.hash) {
+            request.wants.push(r.hash.clone());
+        }
+        if moved {
+            updates.push(Update {
+                name: r.name.clone(),
+                local_name,
+                old,
+                new: r.hash.clone(),
+                force: refspec.force,
+            });
+        }
+    }
+
+    // Local refs whose remote counterpart is gone
+    let mut stale = Vec::new();
+    if args.prune {
+        for (local_name, _) in refs::list_refs("refs/")? {
+            // Symbolic refs such as `refs/remotes/origin/HEAD` follow the branches they point to
+            if refs::symbolic_target(&local_name)? != local_name {
+                continue;
+            }
+            let gone = refspecs.iter().any(|refspec| {
+                refspec.reverse_map(&local_name).is_some_and(|name| {
+                    refspec.destination_prefix().is_some()
+                        && !advertised.iter().any(|r| r.name == name)
+                })
+            });
+            if gone {
+                stale.push(local_name);
+            }
+        }
+    }
+
+    if !request.wants.is_empty() {
+        let mut tips = refs::list_refs("refs/")?
+            .into_iter()
+            .map(|(_, hash)| hash)
+            .chain(refs::head_commit()?)
+            .collect::<Vec<_>>();
+        tips.sort();
+        tips.dedup();
+        // Annotated tags are not part of the history to negotiate over
+        request.haves = tips
+            .into_iter()
+            .filter(|hash| {
+                Object::read(hash).is_ok_and(|(object_type, _)| object_type == ObjectType::COMMIT)
+            })
+            .collect();
+
+        let response = client.fetch_pack(&discover_refs_response, &request)?;
+        shallow::update_shallow(&response.shallow, &response.unshallow)?;
+        if config.get(&format!("remote.{remote}.promisor")).as_deref() == Some("true") {
+            response.pack.mark_promisor()?;
+        }
+    }
+
+    if updates.is_empty() && stale.is_empty() {
+        return Ok(());
+    }
+
+    println!("From {}", client.url());
+    let width = updates
+        .iter()
+        .map(|update| short_name(&update.name).len())
+        .chain(stale.iter().map(|_| "(none)".len()))
+        .max()
+        .unwrap_or(0);
+
+    for local_name in stale {
+        refs::delete_ref(&local_name, None)?;
+        println!(
+            " - {:<17} {:<width$} -> {}",
+            "[deleted]",
+            "(none)",
+            short_name(&local_name)
+        );
+    }
+
+    let mut rejected = false;
+    for update in updates {
+        let is_tag = update.name.starts_with("refs/tags/");
+        let (flag, summary, reason) = match &update.old {
+            None => {
+                let summary = match update.name.split('/').nth(1) {
+                    Some("tags") => "[new tag]",
+                    Some("heads") => "[new branch]",
+                    _ => "[new ref]",
+                };
+                ('*', String::from(summary), None)
+            }
+            Some(old) if !is_tag && graph::is_ancestor(old, &update.new) => (
+                ' ',
+                format!(
+                    "{}..{}",
+                    old.to_short_string(),
+                    update.new.to_short_string()
+                ),
+                None,
+            ),
+            Some(old) if update.force => (
+                '+',
+                format!(
+                    "{}...{}",
+                    old.to_short_string(),
+                    update.new.to_short_string()
+                ),
+                Some("forced update"),
+            ),
+            Some(_) if is_tag => (
+                '!',
+                String::from("[rejected]"),
+                Some("would clobber existing tag"),
+            ),
+            Some(_) => ('!', String::from("[rejected]"), Some("non-fast-forward")),
+        };
+
+        if flag == '!' {
+            rejected = true;
+        } else {
+            let message = match (&update.old, flag) {
+                (None, _) => "storing head",
+                (_, '+') => "forced-update",
+                _ => "fast-forward",
+            };
+            refs::update_ref_with_log(
+                &update.local_name,
+                update.old.as_ref(),
+                &update.new,
+                &format!("fetch {remote}: {message}"),
+            )?;
+        }
+
+        let reason = reason.map_or(String::new(), |reason| format!("  ({reason})"));
+        println!(
+            " {flag} {summary:<17} {:<width$} -> {}{reason}",
+            short_name(&update.name),
+            short_name(&update.local_name)
+        );
+    }
+
+    if rejected {
+        return Err(std::io::Error::other(format!(
+            "some local refs could not be updated; try running\n 'hamachi remote prune {remote}' to remove any old, conflicting branches"
+        )));
+    }
+
+    Ok(())
+}
+
+/// A remote-tracking ref to move to the value of the remote ref it follows
+struct Update {
+    name: String,
+    local_name: String,
+    old: Option<Hash>,
+    new: Hash,
+    /// Whether the refspec allows updates that are not fast-forwards
+    force: bool,
+}
+
+/// The name git shows for a ref in fetch reports, e.g. `origin/main` for
+/// `refs/remotes/origin/main`
+pub(crate) fn short_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::clone::{clone, CloneArgs};
+    use crate::command::fetch::{fetch, FetchArgs};
+    use crate::remote::client::{ProtocolVersion, RemoteClient};
+    use crate::test_utils::{
+        git, hamachi_git, run_git_command, serve_git_http, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn fetch_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            for i in 0..4 {
+                fs::write("a.txt", format!("{i}\n")).unwrap();
+                git(&["add", "a.txt"]);
+                git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            let url = format!("{}/.git", serve_git_http(Path::new(".")).unwrap());
+
+            git(&["clone", "--depth", "1", &url, "expected"]);
+            git(&["-C", "expected", "fetch", "--deepen", "1"]);
+            let expected_deepened = fs::read_to_string("expected/.git/shallow").unwrap();
+            git(&["-C", "expected", "fetch", "--unshallow"]);
+            let expected_log = git(&["-C", "expected", "log", "--format=%H %P"]);
+
+            // Test
+            clone(CloneArgs {
+                repository: url.clone(),
+                directory: Some(String::from("actual")),
+                depth: Some(1),
+                ..Default::default()
+            })
+            .unwrap();
+            fetch(FetchArgs { deepen: Some(1), ..Default::default() }).unwrap();
+            assert_eq!(expected_deepened, fs::read_to_string(".hamachi/shallow").unwrap());
+            fetch(FetchArgs { unshallow: true, ..Default::default() }).unwrap();
+            assert!(!Path::new(".hamachi/shallow").exists());
+            assert_eq!(expected_log, hamachi_git(&["log", "--format=%H %P"]));
+            assert!(fetch(FetchArgs { unshallow: true, ..Default::default() }).is_err());
+
+            fs::write(repo.join("a.txt"), "new\n").unwrap();
+            run_git_command(Command::new("git").current_dir(&repo).args(["commit", "-am", "new"])).unwrap();
+            let new_head = run_git_command(Command::new("git").current_dir(&repo).args(["rev-parse", "HEAD"])).unwrap();
+            fetch(FetchArgs::default()).unwrap();
+            assert_eq!(new_head, hamachi_git(&["rev-parse", "refs/remotes/origin/master"]));
+            assert_eq!(hamachi_git(&["fsck", "--connectivity-only"]), "");
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn fetch_negotiation_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            for i in 0..3 {
+                fs::write("a.txt", format!("{i}\n")).unwrap();
+                git(&["add", "a.txt"]);
+                git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            git(&["branch", "feature"]);
+            git(&["switch", "-c", "local"]);
+            for i in 0..40 {
+                git(&["commit", "--allow-empty", "-m", &format!("local {i}")]);
+            }
+            git(&["switch", "master"]);
+            let url = format!("{}/.git", serve_git_http(Path::new(".")).unwrap());
+
+            git(&["clone", &url, "expected"]);
+            clone(CloneArgs {
+                repository: url.clone(),
+                directory: Some(String::from("actual")),
+                ..Default::default()
+            })
+            .unwrap();
+            std::env::set_current_dir(&repo).unwrap();
+
+            // The remote forgets the local commits, so they must be negotiated past
+            git(&["branch", "-D", "local", "feature"]);
+            git(&["reflog", "expire", "--expire=now", "--all"]);
+            git(&["gc", "--prune=now", "--quiet"]);
+            fs::write("a.txt", "new\n").unwrap();
+            git(&["commit", "-am", "new"]);
+            git(&["tag", "-a", "v1", "-m", "first release"]);
+
+            git(&["-C", "expected", "fetch", "--prune", "--tags"]);
+            let expected_refs = git(&["-C", "expected", "for-each-ref", "--format=%(objectname) %(refname)"]);
+
+            // Test
+            std::env::set_current_dir("actual").unwrap();
+            let packs = || fs::read_dir(".hamachi/objects/pack").unwrap().flatten().map(|entry| entry.path()).filter(|path| path.extension().unwrap() == "pack").collect::<Vec<_>>();
+            let before = packs();
+            fetch(FetchArgs { prune: true, tags: true, ..Default::default() }).unwrap();
+
+            assert_eq!(expected_refs, hamachi_git(&["for-each-ref", "--format=%(objectname) %(refname)"]));
+            // Only the new commit, its tree and blob, and the tag were sent
+            let pack = packs().into_iter().find(|pack| !before.contains(pack)).unwrap();
+            assert_eq!(fs::read(pack).unwrap()[8..12], 4u32.to_be_bytes());
+            assert!(fetch(FetchArgs::default()).is_ok());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn fetch_protocol_version_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            fs::write("a.txt", "0\n").unwrap();
+            git(&["add", "a.txt"]);
+            git(&["commit", "-m", "commit 0"]);
+            git(&["notes", "add", "-m", "note"]);
+            let url = format!("{}/.git", serve_git_http(Path::new(".")).unwrap());
+            clone(CloneArgs {
+                repository: url.clone(),
+                directory: Some(String::from("actual")),
+                ..Default::default()
+            })
+            .unwrap();
+
+            // Test
+            let client = RemoteClient::connect(&url).unwrap();
+            let response = client.discover_refs(&[String::from("refs/heads/")]).unwrap();
+            assert_eq!(response.version, ProtocolVersion::V2);
+            let names = response.refs.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+            assert_eq!(names, ["refs/heads/master"]);
+
+            for version in ["0", "1", "2"] {
+                hamachi_git(&["config", "protocol.version", version]);
+                let response = client.discover_refs(&[String::from("HEAD")]).unwrap();
+                let expected = match version {
+                    "0" => ProtocolVersion::V0,
+                    "1" => ProtocolVersion::V1,
+                    _ => ProtocolVersion::V2,
+                };
+                assert_eq!(response.version, expected);
+                assert_eq!(response.default_branch().unwrap(), "refs/heads/master");
+
+                std::env::set_current_dir(&repo).unwrap();
+                fs::write("a.txt", format!("{version}\n")).unwrap();
+                git(&["commit", "-am", &format!("version {version}")]);
+                let head = git(&["rev-parse", "HEAD"]);
+                std::env::set_current_dir("actual").unwrap();
+
+                fetch(FetchArgs::default()).unwrap();
+                assert_eq!(head, hamachi_git(&["rev-parse", "refs/remotes/origin/master"]));
+            }
+            assert_eq!(hamachi_git(&["fsck", "--connectivity-only"]), "");
+
+            hamachi_git(&["config", "protocol.version", "3"]);
+            assert!(fetch(FetchArgs::default()).is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+}
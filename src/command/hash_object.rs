@@ -3,14 +3,14 @@ use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use sha1::{Digest, Sha1};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufReader, Read, Write};
 use std::path::PathBuf;
 
 /// Generates a SHA1 hash for the specified file and writes its compressed version to the disk
 /// if the w flag is used.
 /// https://git-scm.com/docs/git-hash-object
 pub(crate) fn hash_object(write: bool, file: &PathBuf) -> std::io::Result<Hash> {
-    let uncompressed_file = File::open(file).expect("Couldn't open file");
+    let uncompressed_file = File::open(file)?;
     let metadata = uncompressed_file.metadata()?;
 
     let header = format!("blob {}\0", metadata.len());
@@ -21,25 +21,29 @@ pub(crate) fn hash_object(write: bool, file: &PathBuf) -> std::io::Result<Hash>
 
     let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
     if write {
-        compressor.write(&header.as_bytes())?;
+        compressor.write_all(header.as_bytes())?;
     }
 
-    let reader = BufReader::new(uncompressed_file);
-    for line in reader.lines() {
-        let line = line?;
+    let mut reader = BufReader::new(uncompressed_file);
+    let mut chunk = [0u8; 8192];
+    loop {
+        let read_bytes = reader.read(&mut chunk)?;
+        if read_bytes == 0 {
+            break;
+        }
 
-        Digest::update(&mut hasher, &line);
+        Digest::update(&mut hasher, &chunk[..read_bytes]);
 
         // ZLib compression if the write flag is used
         if write {
-            compressor.write(&line.as_bytes())?;
+            compressor.write_all(&chunk[..read_bytes])?;
         }
     }
 
     let hash: Hash = Hash(hasher.finalize().as_slice().to_vec());
 
     // Write the compressed file to the disk if the write flag is used
-    if write {
+    if write && !Object::exists(&hash) {
         let compressed_bytes = compressor.finish()?;
 
         Object::write_to_disk(&hash, &compressed_bytes)?;
@@ -110,4 +114,23 @@ mod tests {
             teardown(repo).unwrap()
         }
     }
+
+    rusty_fork_test! {
+       #[test]
+        fn hash_object_multiline() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            let test_file_name = "test_file.txt";
+            fs::write(test_file_name, b"first line\nsecond line\r\n\n\xff\xfeno newline at the end").unwrap();
+
+            // Test
+            let expected = run_git_command(Command::new("git").arg("hash-object").arg(test_file_name)).unwrap();
+            let actual = hash_object(false, &PathBuf::from(test_file_name)).unwrap().to_string();
+
+            assert_eq!(expected, actual);
+
+            teardown(repo).unwrap();
+        }
+    }
 }
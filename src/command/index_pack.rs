@@ -0,0 +1,129 @@
+use crate::object::packfile::{idx, PackFile};
+use clap::Args;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct IndexPackArgs {
+    /// Read the pack from the standard input and store it under `objects/pack`
+    #[clap(long, conflicts_with = "pack")]
+    stdin: bool,
+
+    /// Complete a thin pack with the objects its deltas are based on, from the object database
+    #[clap(long, requires = "stdin")]
+    fix_thin: bool,
+
+    #[clap(required_unless_present = "stdin")]
+    pack: Option<String>,
+}
+
+/// Build the index of a pack, after resolving all its deltas. A pack file gets its `.idx` written
+/// next to it, while a pack read from the standard input is stored with its objects.
+/// https://git-scm.com/docs/git-index-pack
+pub(crate) fn index_pack(args: IndexPackArgs, input: impl Read) -> std::io::Result<String> {
+    let (pack, output) = match args.pack {
+        Some(path) => {
+            let Some(name) = path.strip_suffix(".pack") else {
+                return Err(std::io::Error::other(format!(
+                    "packfile name '{path}' does not end with '.pack'"
+                )));
+            };
+            let pack = PackFile::index(Path::new(&path))?;
+            idx::write(Path::new(&format!("{name}.idx")), &pack)?;
+            let output = format!("{}\n", pack.hash);
+            (pack, output)
+        }
+        None => {
+            let pack = PackFile::receive(input, args.fix_thin)?;
+            let output = format!("pack\t{}\n", pack.hash);
+            (pack, output)
+        }
+    };
+
+    let deltas = pack.entries.iter().filter(|entry| entry.depth > 0).count();
+    let mut counts = format!("Indexed {} objects ({deltas} deltas)", pack.entries.len());
+    if pack.local_objects > 0 {
+        counts.push_str(&format!(
+            ", completed with {} local objects",
+            pack.local_objects
+        ));
+    }
+    eprintln!("{counts}");
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::index_pack::{index_pack, IndexPackArgs};
+    use crate::object::{Hash, Object};
+    use crate::test_utils::{git, hamachi_git, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+    use std::str::FromStr;
+
+    /// The pack git's pack-objects makes of the revisions read from the standard input
+    fn pack_objects(revisions: &str, thin: bool) -> Vec<u8> {
+        let thin = if thin { "--thin" } else { "" };
+        let script = format!("printf '{revisions}' | git pack-objects --revs {thin} --stdout");
+        Command::new("sh")
+            .args(["-c", &script])
+            .output()
+            .unwrap()
+            .stdout
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn index_pack_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            let lines = (1..=200).map(|i| format!("line {i}\n")).collect::<String>();
+            fs::write("b.txt", &lines).unwrap();
+            git(&["add", "b.txt"]);
+            git(&["commit", "-m", "one"]);
+            fs::write("b.txt", format!("{lines}two\n")).unwrap();
+            git(&["commit", "-am", "two"]);
+            git(&["repack", "-adq"]);
+            let pack = fs::read_dir(".git/objects/pack").unwrap().flatten().map(|entry| entry.path()).find(|path| path.extension().unwrap() == "pack").unwrap();
+            fs::copy(&pack, "copy.pack").unwrap();
+
+            // Test
+            // A pack file gets the same index as git's
+            let output = index_pack(IndexPackArgs { pack: Some(String::from("copy.pack")), ..Default::default() }, std::io::empty()).unwrap();
+            let name = pack.file_stem().unwrap().to_str().unwrap();
+            assert_eq!(output, format!("{}\n", name.strip_prefix("pack-").unwrap()));
+            assert_eq!(fs::read("copy.idx").unwrap(), fs::read(pack.with_extension("idx")).unwrap());
+            assert!(index_pack(IndexPackArgs { pack: Some(String::from("copy.idx")), ..Default::default() }, std::io::empty()).is_err());
+
+            // A pack read from the standard input is stored with its objects
+            let first = pack_objects("HEAD~1\\n", false);
+            let output = index_pack(IndexPackArgs { stdin: true, ..Default::default() }, &first[..]).unwrap();
+            assert_eq!(output, format!("pack\t{}\n", hex::encode(&first[first.len() - 20..])));
+            let blob = Hash::from_str(&git(&["rev-parse", "HEAD~1:b.txt"])).unwrap();
+            assert_eq!(Object::read(&blob).unwrap().1, lines.as_bytes());
+
+            // A thin pack can only be indexed once completed with the base of its delta
+            let thin = pack_objects("HEAD\\n^HEAD~1\\n", true);
+            let error = index_pack(IndexPackArgs { stdin: true, ..Default::default() }, &thin[..]).unwrap_err();
+            assert_eq!(error.to_string(), "pack has 1 unresolved deltas");
+            let output = index_pack(IndexPackArgs { stdin: true, fix_thin: true, ..Default::default() }, &thin[..]).unwrap();
+            let hash = output.strip_prefix("pack\t").unwrap().trim_end();
+            let fixed = fs::read(format!(".hamachi/objects/pack/pack-{hash}.pack")).unwrap();
+            assert_eq!(u32::from_be_bytes(fixed[8..12].try_into().unwrap()), u32::from_be_bytes(thin[8..12].try_into().unwrap()) + 1);
+            let verified = hamachi_git(&["verify-pack", "-v", &format!(".hamachi/objects/pack/pack-{hash}.idx")]);
+            assert!(verified.contains("chain length = 1: 1 object"));
+            assert!(verified.ends_with(": ok"));
+            let blob = Hash::from_str(&git(&["rev-parse", "HEAD:b.txt"])).unwrap();
+            assert_eq!(Object::read(&blob).unwrap().1, format!("{lines}two\n").into_bytes());
+            let packs = fs::read_dir(".hamachi/objects/pack").unwrap().flatten().map(|entry| entry.file_name().into_string().unwrap()).collect::<Vec<_>>();
+            assert!(packs.iter().all(|name| name.starts_with("pack-")));
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -78,6 +78,7 @@ pub(crate) fn get_current_tree_entry(
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
     use crate::command::ls_tree::ls_tree;
     use crate::test_utils::{
@@ -0,0 +1,374 @@
+use crate::command::commit::{commit, CommitOptions};
+use crate::index::Index;
+use crate::merge::text::ConflictStyle;
+use crate::merge::{
+    checkout_merge, clear_merge_state, merge_commits, write_merge_state, MERGE_HEAD_PATH,
+};
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::Tree;
+use crate::refs::revision;
+use crate::refs::Head;
+use crate::{graph, refs, worktree};
+use clap::Args;
+use std::path::Path;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct MergeArgs {
+    /// Message of the merge commit, one paragraph per `-m`
+    #[clap(short = 'm', long = "message")]
+    messages: Vec<String>,
+
+    /// Create a merge commit even when the merge could be a fast-forward
+    #[clap(long, conflicts_with = "ff_only")]
+    no_ff: bool,
+
+    /// Refuse to merge unless the merge can be a fast-forward
+    #[clap(long)]
+    ff_only: bool,
+
+    /// Stop before creating the merge commit
+    #[clap(long)]
+    no_commit: bool,
+
+    /// Abort the merge in progress, restoring HEAD in the index and working tree
+    #[clap(long, conflicts_with = "continue_merge")]
+    abort: bool,
+
+    /// Conclude the merge in progress once its conflicts are resolved
+    #[clap(long = "continue")]
+    continue_merge: bool,
+
+    commit: Option<String>,
+}
+
+/// Join the history of another commit into the current branch, fast-forwarding when possible
+/// and creating a merge commit with both as parents otherwise
+/// https://git-scm.com/docs/git-merge
+pub(crate) fn merge(args: MergeArgs) -> std::io::Result<()> {
+    let in_progress = Path::new(MERGE_HEAD_PATH).exists();
+
+    if args.abort {
+        if !in_progress {
+            return Err(std::io::Error::other(
+                "There is no merge to abort (MERGE_HEAD missing).",
+            ));
+        }
+        let head_tree = refs::head_commit()?.map(|hash| Commit::from_hash(&hash).tree_hash);
+        if let Some(head_tree) = head_tree {
+            worktree::checkout_tree(Some(&head_tree), &head_tree, true)?;
+        }
+        return clear_merge_state();
+    }
+
+    if args.continue_merge {
+        if !in_progress {
+            return Err(std::io::Error::other(
+                "There is no merge in progress (MERGE_HEAD missing).",
+            ));
+        }
+        return commit(CommitOptions::default()).map(|_| ());
+    }
+
+    if in_progress {
+        return Err(std::io::Error::other(
+            "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+        ));
+    }
+
+    let Some(name) = args.commit else {
+        return Err(std::io::Error::other(
+            "No commit specified and merge.defaultToUpstream not set.",
+        ));
+    };
+    let theirs = revision::resolve_commit(&name)
+        .map_err(|_| std::io::Error::other(format!("{name} - not something we can merge")))?;
+    let their_tree = Commit::from_hash(&theirs).tree_hash;
+
+    let Some(head) = refs::head_commit()? else {
+        // Merging into an unborn branch simply points it to the commit
+        worktree::checkout_tree(None, &their_tree, false)?;
+        return refs::update_ref_with_log("HEAD", None, &theirs, "initial pull");
+    };
+    let head_tree = Commit::from_hash(&head).tree_hash;
+
+    if graph::is_ancestor(&theirs, &head) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+
+    if graph::is_ancestor(&head, &theirs) && !args.no_ff {
+        println!(
+            "Updating {}..{}",
+            head.to_short_string(),
+            theirs.to_short_string()
+        );
+        worktree::checkout_tree(Some(&head_tree), &their_tree, false)?;
+        refs::update_ref("ORIG_HEAD", &head)?;
+        refs::update_ref_with_log(
+            "HEAD",
+            Some(&head),
+            &theirs,
+            &format!("merge {name}: Fast-forward"),
+        )?;
+        println!("Fast-forward");
+        return Ok(());
+    }
+
+    if args.ff_only {
+        return Err(std::io::Error::other(
+            "Not possible to fast-forward, aborting.",
+        ));
+    }
+    if Index::load()?.has_conflicts() {
+        return Err(std::io::Error::other(
+            "Merging is not possible because you have unmerged files.",
+        ));
+    }
+
+    let result = merge_commits(&head, &theirs, "HEAD", &name, ConflictStyle::from_config()?)?;
+    checkout_merge(&Tree::flatten(&head_tree)?, &result)?;
+    refs::update_ref("ORIG_HEAD", &head)?;
+    for message in &result.messages {
+        println!("{message}");
+    }
+
+    let mut message = if args.messages.is_empty() {
+        default_message(&name)?
+    } else {
+        format!("{}\n", args.messages.join("\n\n"))
+    };
+
+    if !result.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in result.conflicts.keys() {
+            message.push_str(&format!("#\t{path}\n"));
+        }
+        write_merge_state(&[theirs], &message)?;
+
+        return Err(std::io::Error::other(
+            "Automatic merge failed; fix conflicts and then commit the result.",
+        ));
+    }
+
+    if args.no_commit {
+        write_merge_state(&[theirs], &message)?;
+        println!("Automatic merge went well; stopped before committing as requested");
+        return Ok(());
+    }
+
+    let tree = Index::load()?.write_tree()?;
+    let commit = Commit::new(
+        tree,
+        vec![head.clone(), theirs],
+        Signature::author()?,
+        Signature::committer()?,
+        message,
+    );
+    let hash = commit.write()?;
+    refs::update_ref_with_log(
+        "HEAD",
+        Some(&head),
+        &hash,
+        &format!("merge {name}: Merge made by the 'ort' strategy."),
+    )?;
+    println!("Merge made by the 'ort' strategy.");
+
+    Ok(())
+}
+
+/// The message git generates for merging the named commit into the current branch
+fn default_message(name: &str) -> std::io::Result<String> {
+    let kind = if refs::ref_exists(&format!("refs/heads/{name}"))? {
+        "branch"
+    } else if refs::ref_exists(&format!("refs/remotes/{name}"))? {
+        "remote-tracking branch"
+    } else if refs::ref_exists(&format!("refs/tags/{name}"))? {
+        "tag"
+    } else {
+        "commit"
+    };
+
+    // Like git, merging into the main branch is not worth mentioning
+    let destination = match refs::read_head()? {
+        Head::Branch(branch) => match branch.strip_prefix("refs/heads/").unwrap_or(&branch) {
+            "master" | "main" => String::new(),
+            branch => format!(" into {branch}"),
+        },
+        Head::Detached(_) => String::new(),
+    };
+
+    Ok(format!("Merge {kind} '{name}'{destination}\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::merge::{merge, MergeArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    fn commit_all(message: &str) {
+        run_git_command(Command::new("git").arg("add").arg("-A")).unwrap();
+        run_git_command(Command::new("git").arg("commit").arg("-m").arg(message)).unwrap();
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn merge_clean_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            fs::write("shared.txt", "one\ntwo\nthree\nfour\nfive\nsix\nseven\n").unwrap();
+            fs::write("moved.txt", "a file that is\nrenamed on one side\nand modified\non the other\n").unwrap();
+            fs::write("removed.txt", "removed on one side\n").unwrap();
+            commit_all("base");
+
+            run_git_command(Command::new("git").arg("switch").arg("-c").arg("topic")).unwrap();
+            fs::write("shared.txt", "one\ntwo\nthree\nfour\nfive\nsix\nseven changed on topic\n").unwrap();
+            fs::write("moved.txt", "a file that is\nrenamed on one side\nand modified\non the other side\n").unwrap();
+            fs::write("added.txt", "added on topic\n").unwrap();
+            commit_all("topic");
+
+            run_git_command(Command::new("git").arg("switch").arg("master")).unwrap();
+            fs::write("shared.txt", "one changed on master\ntwo\nthree\nfour\nfive\nsix\nseven\n").unwrap();
+            fs::rename("moved.txt", "renamed.txt").unwrap();
+            fs::remove_file("removed.txt").unwrap();
+            commit_all("master");
+            copy_git_repository().unwrap();
+
+            // Test
+            run_git_command(Command::new("git").arg("merge").arg("topic")).unwrap();
+            let expected = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+            let expected_files = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+            let expected_reflog = fs::read_to_string(".git/logs/HEAD").unwrap();
+            run_git_command(Command::new("git").arg("reset").arg("--hard").arg("ORIG_HEAD")).unwrap();
+
+            merge(MergeArgs { commit: Some(String::from("topic")), ..Default::default() }).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("rev-parse").arg("HEAD")).unwrap();
+            let actual_files = run_git_command(Command::new("git").env("GIT_INDEX_FILE", ".hamachi/index").arg("ls-files").arg("--stage")).unwrap();
+            let actual_reflog = fs::read_to_string(".hamachi/logs/HEAD").unwrap();
+
+            assert_eq!(expected, actual);
+            assert_eq!(expected_files, actual_files);
+            assert_eq!(expected_reflog.lines().last(), actual_reflog.lines().last());
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn merge_conflict_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("conflict.txt", "one\ntwo\nthree\n").unwrap();
+            fs::write("deleted.txt", "base\n").unwrap();
+            commit_all("base");
+
+            run_git_command(Command::new("git").arg("switch").arg("-c").arg("topic")).unwrap();
+            fs::write("conflict.txt", "one\ntwo on topic\nthree\n").unwrap();
+            fs::write("deleted.txt", "modified on topic\n").unwrap();
+            fs::write("added.txt", "added on topic\n").unwrap();
+            commit_all("topic");
+
+            run_git_command(Command::new("git").arg("switch").arg("master")).unwrap();
+            fs::write("conflict.txt", "one\ntwo on master\nthree\n").unwrap();
+            fs::remove_file("deleted.txt").unwrap();
+            fs::write("added.txt", "added on master\n").unwrap();
+            commit_all("master");
+            copy_git_repository().unwrap();
+
+            // Test
+            for style in ["merge", "diff3", "zdiff3"] {
+                run_git_command(Command::new("git").arg("config").arg("merge.conflictStyle").arg(style)).unwrap();
+                run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("config").arg("merge.conflictStyle").arg(style)).unwrap();
+
+                assert!(Command::new("git").arg("merge").arg("topic").output().unwrap().stdout.starts_with(b"Auto-merging"));
+                let expected_files = ["conflict.txt", "added.txt", "deleted.txt"].map(|path| fs::read(path).unwrap());
+                let expected_index = run_git_command(Command::new("git").arg("ls-files").arg("--stage")).unwrap();
+                run_git_command(Command::new("git").arg("merge").arg("--abort")).unwrap();
+
+                let error = merge(MergeArgs { commit: Some(String::from("topic")), ..Default::default() }).unwrap_err();
+                assert_eq!("Automatic merge failed; fix conflicts and then commit the result.", error.to_string());
+                let actual_files = ["conflict.txt", "added.txt", "deleted.txt"].map(|path| fs::read(path).unwrap());
+                let actual_index = run_git_command(Command::new("git").env("GIT_INDEX_FILE", ".hamachi/index").arg("ls-files").arg("--stage")).unwrap();
+                merge(MergeArgs { abort: true, ..Default::default() }).unwrap();
+
+                assert_eq!(expected_files, actual_files, "{style}");
+                assert_eq!(expected_index, actual_index, "{style}");
+            }
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn merge_several_bases_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            let commit = |message: &str, date: u32| {
+                std::env::set_var("GIT_AUTHOR_DATE", format!("{date} +0100"));
+                std::env::set_var("GIT_COMMITTER_DATE", format!("{date} +0100"));
+                commit_all(message);
+            };
+            let lines = |two: &str, seven: &str| format!("one\n{two}\nthree\nfour\nfive\nsix\n{seven}\neight\n");
+
+            fs::write("shared.txt", lines("two", "seven")).unwrap();
+            commit("base", 1700000000);
+            git(&["switch", "-c", "y"]);
+            fs::write("shared.txt", lines("two on y", "seven")).unwrap();
+            commit("y", 1700000100);
+            git(&["switch", "-c", "x3"]);
+            fs::write("x3.txt", "x3\n").unwrap();
+            commit("x3", 1700000200);
+            git(&["switch", "-c", "x2", "y"]);
+            fs::write("shared.txt", lines("two on x2", "seven")).unwrap();
+            commit("x2", 1700000300);
+            git(&["switch", "-c", "x1", "master"]);
+            fs::write("x1.txt", "x1\n").unwrap();
+            commit("x1", 1700000400);
+
+            // Both branches merge x1, x2 and x3, which become their merge bases. The most recent
+            // one, x1, shares no history with the others, so merging them together needs the
+            // merge bases of x3 and the virtual commit of x1 and x2, rather than of x3 and x1.
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000500 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000500 +0100");
+            git(&["switch", "-c", "topic", "master"]);
+            for branch in ["x3", "x2", "x1"] {
+                git(&["merge", "--no-ff", "--no-edit", branch]);
+            }
+            fs::write("shared.txt", lines("two on topic", "seven")).unwrap();
+            commit("topic", 1700000600);
+            git(&["switch", "master"]);
+            for branch in ["x1", "x2", "x3"] {
+                git(&["merge", "--no-ff", "--no-edit", branch]);
+            }
+            fs::write("shared.txt", lines("two on x2", "seven on master")).unwrap();
+            commit("master", 1700000700);
+            assert_eq!(git(&["merge-base", "--all", "master", "topic"]).lines().count(), 3);
+            copy_git_repository().unwrap();
+
+            // Test
+            git(&["merge", "--no-edit", "topic"]);
+            let expected = git(&["rev-parse", "HEAD"]);
+            let expected_files = git(&["ls-files", "--stage"]);
+            git(&["reset", "--hard", "ORIG_HEAD"]);
+
+            merge(MergeArgs { commit: Some(String::from("topic")), ..Default::default() }).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("rev-parse").arg("HEAD")).unwrap();
+            let actual_files = run_git_command(Command::new("git").env("GIT_INDEX_FILE", ".hamachi/index").arg("ls-files").arg("--stage")).unwrap();
+
+            assert_eq!(expected, actual);
+            assert_eq!(expected_files, actual_files);
+
+            teardown(repo).unwrap();
+        }
+    }
+}
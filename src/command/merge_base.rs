@@ -0,0 +1,210 @@
+use crate::graph::CommitGraph;
+use crate::object::Hash;
+use crate::refs;
+use crate::refs::revision;
+use clap::Args;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct MergeBaseArgs {
+    /// Output every merge base instead of only one
+    #[clap(short = 'a', long)]
+    all: bool,
+
+    /// Check whether the first commit is an ancestor of the second, answering with the exit status
+    #[clap(long, conflicts_with_all = ["all", "octopus", "fork_point"])]
+    is_ancestor: bool,
+
+    /// Compute the merge bases for a merge of all the commits at once
+    #[clap(long)]
+    octopus: bool,
+
+    /// Find where a commit forked from a ref, considering every value recorded in its reflog
+    #[clap(long, conflicts_with_all = ["all", "octopus"])]
+    fork_point: bool,
+
+    commits: Vec<String>,
+}
+
+/// Find the best common ancestors of commits, or answer whether a commit is contained in the
+/// history of another. Returns `None` when there is no answer, which git reports with exit
+/// status 1.
+/// https://git-scm.com/docs/git-merge-base
+pub(crate) fn merge_base(args: MergeBaseArgs) -> std::io::Result<Option<String>> {
+    let mut graph = CommitGraph::new();
+
+    if args.fork_point {
+        let (reference, commit) = match args.commits.as_slice() {
+            [reference] => (reference, "HEAD"),
+            [reference, commit] => (reference, commit.as_str()),
+            _ => {
+                return Err(std::io::Error::other(
+                    "--fork-point takes a ref and at most one commit",
+                ))
+            }
+        };
+        return Ok(
+            fork_point(&mut graph, reference, &revision::resolve_commit(commit)?)?
+                .map(|hash| format!("{hash}\n")),
+        );
+    }
+
+    let commits = args
+        .commits
+        .iter()
+        .map(|commit| revision::resolve_commit(commit))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    if args.is_ancestor {
+        let [ancestor, descendant] = commits.as_slice() else {
+            return Err(std::io::Error::other(
+                "--is-ancestor takes exactly two commits",
+            ));
+        };
+        return Ok(graph.is_ancestor(ancestor, descendant).then(String::new));
+    }
+
+    let bases = if args.octopus {
+        graph.octopus_merge_bases(&commits)
+    } else {
+        let Some((first, others)) = commits
+            .split_first()
+            .filter(|(_, others)| !others.is_empty())
+        else {
+            return Err(std::io::Error::other(
+                "merge-base needs at least two commits",
+            ));
+        };
+        graph.merge_bases(first, others)
+    };
+
+    let count = if args.all { bases.len() } else { 1 };
+    let output = bases
+        .iter()
+        .take(count)
+        .map(|base| format!("{base}\n"))
+        .collect::<String>();
+
+    Ok((!output.is_empty()).then_some(output))
+}
+
+/// The commit where `commit` forked from the ref: the merge base of the commit and every value the
+/// ref ever had, provided it is one of them. This finds the fork point even when the ref was
+/// rewritten since, such as an upstream branch that was rebased.
+fn fork_point(
+    graph: &mut CommitGraph,
+    reference: &str,
+    commit: &Hash,
+) -> std::io::Result<Option<Hash>> {
+    let ref_name = revision::dwim_ref(reference)?
+        .ok_or_else(|| std::io::Error::other(format!("Not a valid object name: '{reference}'")))?;
+
+    let mut values = Vec::new();
+    let entries = refs::read_reflog(&ref_name)?;
+    let history = entries
+        .into_iter()
+        .flat_map(|entry| entry.old.into_iter().chain([entry.new]));
+    for value in history.chain(refs::read_ref(&ref_name)?) {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    match graph.merge_bases(commit, &values).as_slice() {
+        [base] if values.contains(base) => Ok(Some(base.clone())),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::merge_base::{merge_base, MergeBaseArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::process::Command;
+
+    fn commit(message: &str) {
+        run_git_command(
+            Command::new("git")
+                .arg("commit")
+                .arg("--allow-empty")
+                .arg("-m")
+                .arg(message),
+        )
+        .unwrap();
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn merge_base_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            // Dates increase with every commit so the order of the merge bases is well defined
+            let mut date = 1_700_000_000;
+            let mut commit_at = |message: &str| {
+                date += 60;
+                std::env::set_var("GIT_COMMITTER_DATE", format!("{date} +0000"));
+                commit(message);
+            };
+
+            commit_at("root");
+            git(&["switch", "-c", "a"]);
+            commit_at("a1");
+            git(&["switch", "-c", "b", "master"]);
+            commit_at("b1");
+            git(&["switch", "a"]);
+            git(&["merge", "--no-edit", "b"]);
+            git(&["switch", "b"]);
+            git(&["merge", "--no-edit", "a~1"]);
+            commit_at("b2");
+            git(&["switch", "a"]);
+            commit_at("a2");
+            git(&["switch", "-c", "c", "master"]);
+            commit_at("c1");
+
+            // An upstream that is rewritten after a topic forked from it
+            git(&["switch", "-c", "upstream", "a"]);
+            commit_at("u1");
+            git(&["switch", "-c", "topic"]);
+            commit_at("t1");
+            git(&["switch", "upstream"]);
+            git(&["reset", "--hard", "HEAD~1"]);
+            commit_at("u1 rewritten");
+            git(&["switch", "topic"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            let queries: [&[&str]; 9] = [
+                &["a", "b"],
+                &["--all", "a", "b"],
+                &["a", "c"],
+                &["--all", "b", "a", "c"],
+                &["--octopus", "--all", "a", "b", "c"],
+                &["--is-ancestor", "master", "b"],
+                &["--is-ancestor", "c", "b"],
+                &["--fork-point", "upstream"],
+                &["--fork-point", "upstream", "c"],
+            ];
+            for query in queries {
+                let flag = |flag: &str| query.contains(&flag);
+                let expected = Command::new("git").arg("merge-base").args(query).output().unwrap();
+
+                let actual = merge_base(MergeBaseArgs {
+                    all: flag("--all"),
+                    is_ancestor: flag("--is-ancestor"),
+                    octopus: flag("--octopus"),
+                    fork_point: flag("--fork-point"),
+                    commits: query.iter().filter(|arg| !arg.starts_with("--")).map(|arg| arg.to_string()).collect(),
+                })
+                .unwrap();
+
+                assert_eq!(expected.status.success(), actual.is_some(), "git merge-base {query:?}");
+                assert_eq!(String::from_utf8(expected.stdout).unwrap(), actual.unwrap_or_default(), "git merge-base {query:?}");
+            }
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -1,12 +1,50 @@
+use branch::BranchArgs;
+use cherry_pick::CherryPickArgs;
 use clap::{Parser, Subcommand};
+use clone::CloneArgs;
 use config::ConfigSubcommand;
+use fetch::FetchArgs;
+use index_pack::IndexPackArgs;
+use merge::MergeArgs;
+use merge_base::MergeBaseArgs;
+use pack_objects::PackObjectsArgs;
+use push::PushArgs;
+use rebase::RebaseArgs;
+use reset::ResetArgs;
+use restore::RestoreArgs;
+use revert::RevertArgs;
+use serve::ServeArgs;
+use stash::StashSubcommand;
+use verify_pack::VerifyPackArgs;
 
+pub mod add;
+pub mod branch;
 pub mod cat_file;
+pub mod check_ignore;
+pub mod checkout;
+pub mod cherry_pick;
+pub mod clean;
 pub mod clone;
+pub mod commit;
 pub mod commit_tree;
 pub mod config;
+pub mod fetch;
 pub mod hash_object;
+pub mod index_pack;
 pub mod ls_tree;
+pub mod merge;
+pub mod merge_base;
+pub mod pack_objects;
+pub mod push;
+pub mod rebase;
+pub mod reset;
+pub mod restore;
+pub mod revert;
+pub mod serve;
+pub mod stash;
+pub mod status;
+pub mod switch;
+pub mod verify_pack;
 pub mod write_tree;
 
 #[derive(Parser, Debug)]
@@ -41,6 +79,10 @@ pub(crate) enum Command {
     CommitTree {
         hash: String,
 
+        /// Parent commit, given several times for a merge commit
+        #[clap(short = 'p')]
+        parents: Vec<String>,
+
         #[clap(short = 'm')]
         message: Option<String>,
     },
@@ -48,7 +90,135 @@ pub(crate) enum Command {
         #[clap(subcommand)]
         subcommand: ConfigSubcommand,
     },
-    Clone {
-        repository: String,
+    Clone(CloneArgs),
+    Fetch(FetchArgs),
+    IndexPack(IndexPackArgs),
+    VerifyPack(VerifyPackArgs),
+    PackObjects(PackObjectsArgs),
+    Push(PushArgs),
+    Serve(ServeArgs),
+    Checkout {
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        #[clap(long)]
+        detach: bool,
+
+        /// Create a new branch and check it out
+        #[clap(short = 'b', conflicts_with = "reset_branch")]
+        new_branch: Option<String>,
+
+        /// Create or reset a branch and check it out
+        #[clap(short = 'B')]
+        reset_branch: Option<String>,
+
+        target: Option<String>,
+
+        #[clap(last = true)]
+        paths: Vec<String>,
+    },
+    Switch {
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        #[clap(short = 'd', long)]
+        detach: bool,
+
+        /// Create a new branch and switch to it
+        #[clap(short = 'c', long, conflicts_with = "force_create")]
+        create: Option<String>,
+
+        /// Create or reset a branch and switch to it
+        #[clap(short = 'C', long)]
+        force_create: Option<String>,
+
+        branch: Option<String>,
+    },
+    Branch(BranchArgs),
+    Merge(MergeArgs),
+    MergeBase(MergeBaseArgs),
+    CherryPick(CherryPickArgs),
+    Revert(RevertArgs),
+    Rebase(RebaseArgs),
+    Reset(ResetArgs),
+    Restore(RestoreArgs),
+    Stash {
+        #[clap(subcommand)]
+        subcommand: Option<StashSubcommand>,
+    },
+    Status {
+        #[clap(short = 's', long)]
+        short: bool,
+
+        /// Machine readable output, `v1` (the default) or `v2`
+        #[clap(long, num_args = 0..=1, default_missing_value = "v1")]
+        porcelain: Option<String>,
+
+        #[clap(short = 'b', long)]
+        branch: bool,
+    },
+    Add {
+        #[clap(short = 'A', long)]
+        all: bool,
+
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        pathspecs: Vec<String>,
+    },
+    Clean {
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+
+        #[clap(short = 'f', long)]
+        force: bool,
+
+        /// Also remove untracked directories
+        #[clap(short = 'd')]
+        directories: bool,
+
+        /// Also remove ignored files
+        #[clap(short = 'x', conflicts_with = "only_ignored")]
+        ignored: bool,
+
+        /// Only remove ignored files
+        #[clap(short = 'X')]
+        only_ignored: bool,
+
+        pathspecs: Vec<String>,
+    },
+    Commit {
+        #[clap(short = 'm', long = "message")]
+        messages: Vec<String>,
+
+        #[clap(short = 'F', long)]
+        file: Option<String>,
+
+        #[clap(long)]
+        amend: bool,
+
+        #[clap(long)]
+        no_edit: bool,
+
+        #[clap(long)]
+        allow_empty: bool,
+
+        #[clap(short = 'a', long)]
+        all: bool,
+
+        #[clap(long)]
+        author: Option<String>,
+
+        #[clap(long)]
+        date: Option<String>,
+    },
+    CheckIgnore {
+        #[clap(short = 'v', long)]
+        verbose: bool,
+
+        #[clap(long)]
+        no_index: bool,
+
+        paths: Vec<String>,
     },
 }
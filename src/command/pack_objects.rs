@@ -0,0 +1,291 @@
+use crate::graph::objects::list_objects;
+use crate::object::packfile::writer::{write_pack, DeltaSearch};
+use crate::object::packfile::{idx, PackFile};
+use crate::object::{Hash, Object};
+use crate::refs::revision;
+use clap::Args;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct PackObjectsArgs {
+    /// Write the pack to the standard output instead of to files named after it
+    #[clap(long, conflicts_with = "base_name")]
+    stdout: bool,
+
+    /// Read revisions instead of object IDs, packing what they reach; `^` or `--not` excludes
+    #[clap(long)]
+    revs: bool,
+
+    /// How many of the objects sorted before each one to try as the base of a delta
+    #[clap(long, default_value_t = DeltaSearch::default().window)]
+    window: usize,
+
+    /// The longest chain of deltas to create
+    #[clap(long, default_value_t = DeltaSearch::default().depth)]
+    depth: usize,
+
+    /// Locate the base of deltas by offset rather than by hash, which makes smaller packs
+    #[clap(long)]
+    delta_base_offset: bool,
+
+    /// Write the pack and its index as `<base-name>-<checksum>.pack` and `.idx`
+    #[clap(required_unless_present = "stdout")]
+    base_name: Option<String>,
+}
+
+/// Create a pack of the objects whose IDs are read from the standard input, or of the objects
+/// the revisions read from it reach
+/// https://git-scm.com/docs/git-pack-objects
+pub(crate) fn pack_objects(
+    args: PackObjectsArgs,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    let objects = match args.revs {
+        true => read_revisions(input)?,
+        false => read_object_ids(input)?,
+    };
+    let search = DeltaSearch {
+        window: args.window,
+        depth: args.depth,
+    };
+
+    match args.base_name {
+        Some(base_name) => {
+            let temporary_path = format!("{base_name}-tmp.pack");
+            let file = BufWriter::new(File::create(&temporary_path)?);
+            let hash = match pack(&objects, file, search, args.delta_base_offset) {
+                Ok(pack) => {
+                    let path = format!("{base_name}-{}", pack.hash);
+                    idx::write(Path::new(&format!("{path}.idx")), &pack)?;
+                    fs::rename(&temporary_path, format!("{path}.pack"))?;
+                    pack.hash
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&temporary_path);
+                    return Err(e);
+                }
+            };
+            writeln!(output, "{hash}")
+        }
+        None => pack(&objects, output, search, args.delta_base_offset).map(|_| ()),
+    }
+}
+
+/// Writes the pack and reports what it holds
+fn pack(
+    objects: &[(Hash, String)],
+    output: impl Write,
+    search: DeltaSearch,
+    ofs_delta: bool,
+) -> std::io::Result<PackFile> {
+    let (_, pack) = write_pack(output, objects, search, ofs_delta)?;
+
+    let deltas = pack.entries.iter().filter(|entry| entry.depth > 0).count();
+    eprintln!("Total {} (delta {deltas})", pack.entries.len());
+
+    Ok(pack)
+}
+
+/// Object IDs, one per line, optionally followed by the path of the object
+fn read_object_ids(input: impl BufRead) -> std::io::Result<Vec<(Hash, String)>> {
+    let mut seen = HashSet::new();
+    let mut objects = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let (id, path) = line.split_once(' ').unwrap_or((&line, ""));
+        if id.is_empty() {
+            continue;
+        }
+        let hash = Hash::from_str(id).map_err(|_| {
+            std::io::Error::other(format!("expected object ID, got garbage:\n {line}"))
+        })?;
+        if !Object::exists(&hash) {
+            return Err(std::io::Error::other(format!("object {hash} not found")));
+        }
+        if seen.insert(hash.clone()) {
+            objects.push((hash, path.to_string()));
+        }
+    }
+
+    Ok(objects)
+}
+
+/// The objects reachable from the revisions, one per line, but not from those prefixed with
+/// `^` or following a `--not` line
+fn read_revisions(input: impl BufRead) -> std::io::Result<Vec<(Hash, String)>> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut negated = false;
+    for line in input.lines() {
+        let line = line?;
+        let (revision, excluded) = match line.trim() {
+            "" => continue,
+            "--not" => {
+                negated = !negated;
+                continue;
+            }
+            revision => match revision.strip_prefix('^') {
+                Some(revision) => (revision, !negated),
+                None => (revision, negated),
+            },
+        };
+
+        let hash = revision::resolve(revision)
+            .map_err(|_| std::io::Error::other(format!("bad revision '{revision}'")))?;
+        match excluded {
+            true => exclude.push(hash),
+            false => include.push(hash),
+        }
+    }
+
+    list_objects(&include, &exclude)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::pack_objects::{pack_objects, PackObjectsArgs};
+    use crate::test_utils::{git, hamachi_git, run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::collections::BTreeSet;
+    use std::fs::{self, File};
+    use std::process::Command;
+
+    /// The objects of a pack, as git lists them when verifying its index
+    fn pack_contents(idx: &str) -> BTreeSet<String> {
+        git(&["verify-pack", "-v", idx])
+            .lines()
+            .filter(|line| line.len() > 40 && line.as_bytes()[40] == b' ')
+            .map(|line| line[..40].to_string())
+            .collect()
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn pack_objects_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            fs::create_dir("src").unwrap();
+            for i in 0..3 {
+                fs::write("a.txt", format!("a {i}\n")).unwrap();
+                fs::write(format!("src/{i}.txt"), "unchanged\n").unwrap();
+                hamachi_git(&["add", "a.txt", "src"]);
+                hamachi_git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            hamachi_git(&["tag", "-a", "v1", "-m", "first release", "HEAD~1"]);
+
+            // Test
+            // The objects revisions reach, as with rev-list
+            let mut output = Vec::new();
+            let input = "HEAD\n^HEAD~2\nv1\n";
+            pack_objects(PackObjectsArgs { revs: true, base_name: Some(String::from("partial")), ..Default::default() }, input.as_bytes(), &mut output).unwrap();
+            let hash = String::from_utf8(output).unwrap().trim_end().to_string();
+            let expected = hamachi_git(&["rev-list", "--objects", "HEAD", "^HEAD~2", "v1"]).lines().map(|line| line[..40].to_string()).collect::<BTreeSet<_>>();
+            assert_eq!(pack_contents(&format!("partial-{hash}.idx")), expected);
+            assert!(fs::exists(format!("partial-{hash}.pack")).unwrap());
+            assert!(!fs::exists("partial-tmp.pack").unwrap());
+
+            let mut output = Vec::new();
+            pack_objects(PackObjectsArgs { revs: true, stdout: true, ..Default::default() }, "--not\nHEAD~1\n--not\nHEAD\n".as_bytes(), &mut output).unwrap();
+            // The commit, its two new trees and a.txt, the new file having the content of others
+            assert_eq!(u32::from_be_bytes(output[8..12].try_into().unwrap()), 4);
+
+            // Every object by ID, to the standard output
+            let ids = hamachi_git(&["rev-list", "--objects", "--all"]);
+            let mut pack = Vec::new();
+            pack_objects(PackObjectsArgs { stdout: true, ..Default::default() }, ids.as_bytes(), &mut pack).unwrap();
+            fs::write("all.pack", pack).unwrap();
+            git(&["init", "other"]);
+            run_git_command(Command::new("git").args(["-C", "other", "index-pack", "--stdin"]).stdin(File::open("all.pack").unwrap())).unwrap();
+            let objects = |git_dir: &str| run_git_command(Command::new("git").env("GIT_DIR", git_dir).args(["cat-file", "--batch-all-objects", "--batch-check"])).unwrap();
+            assert_eq!(objects("other/.git"), objects(".hamachi"));
+
+            assert!(pack_objects(PackObjectsArgs { stdout: true, ..Default::default() }, "not-a-hash\n".as_bytes(), Vec::new()).is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn pack_objects_merge_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            let commit = |message: &str, date: u32| {
+                std::env::set_var("GIT_AUTHOR_DATE", format!("{date} +0100"));
+                std::env::set_var("GIT_COMMITTER_DATE", format!("{date} +0100"));
+                fs::write(format!("{message}.txt"), format!("{message}\n")).unwrap();
+                hamachi_git(&["add", "."]);
+                hamachi_git(&["commit", "-m", message]);
+            };
+            commit("base", 1700000000);
+            hamachi_git(&["checkout", "-b", "side"]);
+            commit("side 1", 1700000100);
+            commit("side 2", 1700000200);
+            hamachi_git(&["checkout", "-"]);
+            commit("main", 1700000300);
+            hamachi_git(&["merge", "--no-edit", "side"]);
+            commit("after merge", 1700000400);
+
+            // Test
+            // The side branch is excluded, although the walk reaches it through the merge first
+            let mut output = Vec::new();
+            pack_objects(PackObjectsArgs { revs: true, base_name: Some(String::from("merge")), ..Default::default() }, "HEAD\n^side\n".as_bytes(), &mut output).unwrap();
+            let hash = String::from_utf8(output).unwrap().trim_end().to_string();
+            let expected = hamachi_git(&["rev-list", "--objects", "HEAD", "^side"]).lines().map(|line| line[..40].to_string()).collect::<BTreeSet<_>>();
+            assert_eq!(pack_contents(&format!("merge-{hash}.idx")), expected);
+
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn pack_objects_delta_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            let mut lines = (0..500).map(|i| format!("line {i} of a file that changes a little with every commit\n")).collect::<Vec<_>>();
+            for i in 0..20 {
+                lines[i * 25] = format!("line changed by commit {i}\n");
+                lines.insert(i * 20, format!("line inserted by commit {i}\n"));
+                fs::write("a.txt", lines.concat()).unwrap();
+                fs::write("b.txt", lines.iter().rev().cloned().collect::<String>()).unwrap();
+                hamachi_git(&["add", "a.txt", "b.txt"]);
+                hamachi_git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            let expected = Command::new("sh").args(["-c", "echo HEAD | GIT_DIR=.hamachi git pack-objects --revs --delta-base-offset --stdout"]).output().unwrap().stdout;
+
+            // Test
+            let pack = |window: usize, depth: usize| {
+                let mut pack = Vec::new();
+                pack_objects(PackObjectsArgs { revs: true, stdout: true, window, depth, delta_base_offset: true, ..Default::default() }, "HEAD\n".as_bytes(), &mut pack).unwrap();
+                fs::write("test.pack", &pack).unwrap();
+                let idx = git(&["index-pack", "test.pack"]);
+                assert!(!idx.is_empty());
+                (pack.len(), git(&["verify-pack", "-v", "test.idx"]))
+            };
+
+            // About as small as git's
+            let (size, verified) = pack(10, 50);
+            assert!(size < expected.len() * 5 / 4, "{size} against {}", expected.len());
+            assert!(verified.contains("chain length = 1:"));
+
+            // Chains no longer than allowed, and no deltas without a window
+            let (_, verified) = pack(10, 1);
+            assert!(verified.contains("chain length = 1:") && !verified.contains("chain length = 2:"));
+            let (whole_size, verified) = pack(0, 50);
+            assert!(!verified.contains("chain length"));
+            assert!(whole_size > size * 4);
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+}
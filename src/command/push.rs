@@ -0,0 +1,727 @@
+use crate::command::fetch::short_name;
+use crate::config::Config;
+use crate::graph::{self, objects::list_objects};
+use crate::object::{Hash, Object};
+use crate::refs::{self, revision, Head};
+use crate::remote::client::{PushCommand, PushRequest, RemoteClient};
+use crate::remote::refspec::Refspec;
+use clap::Args;
+use std::collections::HashMap;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct PushArgs {
+    /// The remote to push to, by name, URL or path
+    remote: Option<String>,
+
+    /// The remote refs to update, as `<source>:<destination>`, a leading `+` allowing an update
+    /// that is not a fast-forward and an empty source deleting the destination
+    refspecs: Vec<String>,
+
+    /// Update the remote refs even when the updates are not fast-forwards
+    #[clap(short, long)]
+    force: bool,
+
+    /// Only overwrite a remote ref that is still where we last saw it, as `<ref>:<expect>` or
+    /// `<ref>` and every ref when no value is given
+    #[clap(long, num_args = 0..=1, default_missing_value = "", require_equals = true)]
+    force_with_lease: Vec<String>,
+
+    /// Delete the remote refs named by the refspecs
+    #[clap(short, long)]
+    delete: bool,
+
+    /// Push every tag under `refs/tags`
+    #[clap(long)]
+    tags: bool,
+
+    /// Update every remote ref or none of them
+    #[clap(long)]
+    atomic: bool,
+
+    /// Pass a string on to the hooks of the remote
+    #[clap(short = 'o', long = "push-option")]
+    push_options: Vec<String>,
+}
+
+/// Update the refs of a remote repository, sending it the objects they need that it lacks.
+/// Updates that are not fast-forwards are rejected unless forced.
+/// https://git-scm.com/docs/git-push
+pub(crate) fn push(args: PushArgs) -> std::io::Result<()> {
+    let config = Config::load()?;
+    let current_branch = match refs::read_head()? {
+        Head::Branch(name) => Some(name),
+        Head::Detached(_) => None,
+    };
+    let remote = match args.remote {
+        Some(remote) => remote,
+        None => current_branch
+            .as_deref()
+            .and_then(|branch| {
+                let branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+                config.get(&format!("branch.{branch}.remote"))
+            })
+            .unwrap_or_else(|| String::from("origin")),
+    };
+    // A URL or a path may be given instead of a configured remote, which has no
+    // remote-tracking refs
+    let url = config
+        .get(&format!("remote.{remote}.url"))
+        .unwrap_or_else(|| remote.clone());
+    let tracking_refspecs = config
+        .get_all(&format!("remote.{remote}.fetch"))
+        .iter()
+        .map(|refspec| Refspec::parse(refspec))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let client = RemoteClient::connect(&url)?;
+    let discover_refs_response = client.discover_push_refs()?;
+    let remote_refs = discover_refs_response
+        .refs
+        .iter()
+        .map(|r| (r.name.clone(), r.hash.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut updates = Vec::new();
+    if args.refspecs.is_empty() && !args.tags {
+        if args.delete {
+            return Err(std::io::Error::other(
+                "--delete doesn't make sense without any refs",
+            ));
+        }
+        let branch = current_branch.ok_or_else(|| {
+            std::io::Error::other(
+                "You are not currently on a branch.\nTo push the history leading to the current (detached HEAD)\nstate now, use\n\n    hamachi push <remote> HEAD:<name-of-remote-branch>",
+            )
+        })?;
+        updates.push(parse_refspec(&branch, &remote_refs)?);
+    }
+    for refspec in &args.refspecs {
+        let update = match args.delete {
+            true if refspec.contains(':') => {
+                return Err(std::io::Error::other(
+                    "--delete only accepts plain target ref names",
+                ))
+            }
+            true => parse_refspec(&format!(":{refspec}"), &remote_refs)?,
+            false => parse_refspec(refspec, &remote_refs)?,
+        };
+        updates.push(update);
+    }
+    if args.tags {
+        for (name, hash) in refs::list_refs("refs/tags/")? {
+            // Tags already there are left out rather than reported as up to date
+            if !updates.iter().any(|update| update.name == name)
+                && remote_refs.get(&name) != Some(&hash)
+            {
+                updates.push(Update {
+                    source: name.clone(),
+                    new: Some(hash),
+                    old: remote_refs.get(&name).cloned(),
+                    name,
+                    force: false,
+                    status: Status::Pending,
+                });
+            }
+        }
+    }
+
+    let leases = args
+        .force_with_lease
+        .iter()
+        .map(|lease| Lease::parse(lease))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    for update in &mut updates {
+        let lease = leases.iter().find(|lease| lease.covers(&update.name));
+        let expected = match lease {
+            Some(Lease {
+                expected: Some(expected),
+                ..
+            }) => Some(expected.clone()),
+            // Without an expected value, the remote ref must be where its remote-tracking ref is
+            Some(_) => Some(
+                tracking_refspecs
+                    .iter()
+                    .find_map(|refspec| refspec.map(&update.name))
+                    .map(|tracking| refs::read_ref(&tracking))
+                    .transpose()?
+                    .flatten(),
+            ),
+            None => None,
+        };
+        update.status = check_update(update, args.force, expected)?;
+    }
+
+    let rejected = updates
+        .iter()
+        .any(|update| matches!(update.status, Status::Rejected(_)));
+    if rejected && args.atomic {
+        for update in &mut updates {
+            if matches!(update.status, Status::Accepted { .. }) {
+                update.status = Status::Rejected(String::from("atomic push failed"));
+            }
+        }
+    }
+
+    let accepted = updates
+        .iter()
+        .filter(|update| matches!(update.status, Status::Accepted { .. }))
+        .collect::<Vec<_>>();
+    if !accepted.is_empty() {
+        let include = accepted
+            .iter()
+            .filter_map(|update| update.new.clone())
+            .collect::<Vec<_>>();
+        // What the remote already has needs not be sent
+        let exclude = remote_refs
+            .values()
+            .filter(|hash| Object::exists(hash))
+            .cloned()
+            .collect::<Vec<_>>();
+        let request = PushRequest {
+            commands: accepted
+                .iter()
+                .map(|update| PushCommand {
+                    name: update.name.clone(),
+                    old: update.old.clone(),
+                    new: update.new.clone(),
+                })
+                .collect(),
+            objects: list_objects(&include, &exclude)?,
+            atomic: args.atomic,
+            push_options: args.push_options,
+        };
+
+        let report = client.push_pack(&discover_refs_response, &request)?;
+        if let Some(error) = &report.unpack_error {
+            eprintln!("error: remote unpack failed: {error}");
+        }
+        for update in &mut updates {
+            if let Some((_, reason)) = report
+                .rejected
+                .iter()
+                .find(|(name, _)| *name == update.name)
+            {
+                update.status = Status::RemoteRejected(reason.clone());
+            } else if report.unpack_error.is_some() {
+                update.status = Status::RemoteRejected(String::from("unpacker error"));
+            }
+        }
+    }
+
+    if updates
+        .iter()
+        .all(|update| matches!(update.status, Status::UpToDate))
+    {
+        println!("Everything up-to-date");
+        return Ok(());
+    }
+
+    println!("To {}", client.url());
+    let mut failed = false;
+    for update in &updates {
+        let destination = short_name(&update.name);
+        let source = short_name(&update.source);
+        let (flag, summary, reason) = match (&update.status, &update.old, &update.new) {
+            (Status::UpToDate | Status::Pending, ..) => continue,
+            (Status::Rejected(reason), ..) => ('!', String::from("[rejected]"), Some(reason)),
+            (Status::RemoteRejected(reason), ..) => {
+                ('!', String::from("[remote rejected]"), Some(reason))
+            }
+            (_, _, None) => {
+                println!(" - {:<17} {destination}", "[deleted]");
+                update_tracking_ref(update, &tracking_refspecs)?;
+                continue;
+            }
+            (_, None, _) => {
+                let summary = match update.name.split('/').nth(1) {
+                    Some("tags") => "[new tag]",
+                    Some("heads") => "[new branch]",
+                    _ => "[new reference]",
+                };
+                ('*', String::from(summary), None)
+            }
+            (Status::Accepted { forced: true }, Some(old), Some(new)) => (
+                '+',
+                format!("{}...{}", old.to_short_string(), new.to_short_string()),
+                None,
+            ),
+            (_, Some(old), Some(new)) => (
+                ' ',
+                format!("{}..{}", old.to_short_string(), new.to_short_string()),
+                None,
+            ),
+        };
+
+        let reason = match (flag, reason) {
+            ('+', _) => String::from(" (forced update)"),
+            (_, Some(reason)) => format!(" ({reason})"),
+            _ => String::new(),
+        };
+        println!(" {flag} {summary:<17} {source} -> {destination}{reason}");
+        match flag {
+            '!' => failed = true,
+            _ => update_tracking_ref(update, &tracking_refspecs)?,
+        }
+    }
+
+    if failed {
+        return Err(std::io::Error::other(format!(
+            "failed to push some refs to '{}'",
+            client.url()
+        )));
+    }
+
+    Ok(())
+}
+
+/// An update of a remote ref to push
+struct Update {
+    /// The local ref or revision the new value comes from, empty for a deletion
+    source: String,
+    /// The full name of the remote ref
+    name: String,
+    old: Option<Hash>,
+    new: Option<Hash>,
+    /// Whether the refspec allows an update that is not a fast-forward
+    force: bool,
+    status: Status,
+}
+
+enum Status {
+    Pending,
+    UpToDate,
+    /// The update is sent to the remote, `forced` when it is not a fast-forward
+    Accepted {
+        forced: bool,
+    },
+    /// We did not send the update, for the specified reason
+    Rejected(String),
+    /// The remote refused the update, for the specified reason
+    RemoteRejected(String),
+}
+
+/// Parses a push refspec such as `main`, `+HEAD:refs/heads/main` or `:topic`, resolving its
+/// source to a hash and its destination to the full name of a remote ref
+fn parse_refspec(refspec: &str, remote_refs: &HashMap<String, Hash>) -> std::io::Result<Update> {
+    let (force, rest) = match refspec.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, refspec),
+    };
+    let (source, destination) = rest.split_once(':').unwrap_or((rest, rest));
+
+    if source.is_empty() {
+        let name = qualify_destination(destination, None, remote_refs).ok_or_else(|| {
+            std::io::Error::other(format!(
+                "unable to delete '{destination}': remote ref does not exist"
+            ))
+        })?;
+        return Ok(Update {
+            source: String::new(),
+            old: remote_refs.get(&name).cloned(),
+            name,
+            new: None,
+            force,
+            status: Status::Pending,
+        });
+    }
+
+    let no_match = || std::io::Error::other(format!("src refspec {source} does not match any"));
+    let full_source = revision::dwim_ref(source)?;
+    let new = revision::resolve(source).map_err(|_| no_match())?;
+    let name = qualify_destination(destination, full_source.as_deref(), remote_refs)
+        .ok_or_else(|| {
+            std::io::Error::other(format!(
+                "The destination you provided is not a full refname (i.e.,\nstarting with \"refs/\"). Unable to guess a prefix for '{destination}'."
+            ))
+        })?;
+    if !refs::check_ref_format(&name) {
+        return Err(std::io::Error::other(format!(
+            "invalid refspec '{refspec}'"
+        )));
+    }
+
+    Ok(Update {
+        source: full_source.unwrap_or_else(|| source.to_string()),
+        old: remote_refs.get(&name).cloned(),
+        name,
+        new: Some(new),
+        force,
+        status: Status::Pending,
+    })
+}
+
+/// The full name of the remote ref a destination designates: the destination itself when it
+/// starts with `refs/`, a branch or tag of the remote with that name, or else a ref of the same
+/// kind as the local source ref
+fn qualify_destination(
+    destination: &str,
+    source: Option<&str>,
+    remote_refs: &HashMap<String, Hash>,
+) -> Option<String> {
+    if destination.starts_with("refs/") {
+        return Some(destination.to_string());
+    }
+
+    ["refs/heads/", "refs/tags/"]
+        .iter()
+        .map(|prefix| format!("{prefix}{destination}"))
+        .find(|name| remote_refs.contains_key(name))
+        .or_else(|| {
+            let source = source?;
+            ["refs/heads/", "refs/tags/"]
+                .iter()
+                .find(|prefix| source.starts_with(*prefix))
+                .map(|prefix| format!("{prefix}{destination}"))
+        })
+}
+
+/// Decides whether an update is sent. With a lease, the remote ref must still have the
+/// expected value, `None` meaning that it must not exist, and may then be overwritten.
+/// Otherwise an update that is not a fast-forward is rejected unless forced, as is the update of
+/// a tag or of a ref whose value we do not have to tell.
+fn check_update(
+    update: &Update,
+    force: bool,
+    lease: Option<Option<Hash>>,
+) -> std::io::Result<Status> {
+    if update.old == update.new {
+        return Ok(match update.new {
+            Some(_) => Status::UpToDate,
+            None => Status::Rejected(String::from("remote ref does not exist")),
+        });
+    }
+    let status = match (&update.old, &update.new, lease) {
+        (_, _, Some(expected)) if expected != update.old => {
+            Status::Rejected(String::from("stale info"))
+        }
+        (_, None, _) | (None, _, _) => Status::Accepted { forced: false },
+        (Some(old), Some(new), lease) => {
+            let fast_forward = !update.name.starts_with("refs/tags/")
+                && Object::exists(old)
+                && graph::is_ancestor(old, new);
+            match fast_forward {
+                true => Status::Accepted { forced: false },
+                false if force || update.force || lease.is_some() => {
+                    Status::Accepted { forced: true }
+                }
+                false if update.name.starts_with("refs/tags/") => {
+                    Status::Rejected(String::from("already exists"))
+                }
+                false if !Object::exists(old) => Status::Rejected(String::from("fetch first")),
+                false => Status::Rejected(String::from("non-fast-forward")),
+            }
+        }
+    };
+
+    Ok(status)
+}
+
+/// Moves the remote-tracking ref of an updated remote ref along, as a fetch would
+fn update_tracking_ref(update: &Update, tracking_refspecs: &[Refspec]) -> std::io::Result<()> {
+    let Some(tracking) = tracking_refspecs
+        .iter()
+        .find_map(|refspec| refspec.map(&update.name))
+    else {
+        return Ok(());
+    };
+
+    let current = refs::read_ref(&tracking)?;
+    match &update.new {
+        Some(new) => refs::update_ref_with_log(&tracking, current.as_ref(), new, "update by push"),
+        None if current.is_some() => refs::delete_ref(&tracking, None),
+        None => Ok(()),
+    }
+}
+
+/// A `--force-with-lease` value: the ref it protects, all of them when `None`, and the value
+/// the remote ref is expected to have, taken from its remote-tracking ref when not given
+struct Lease {
+    name: Option<String>,
+    expected: Option<Option<Hash>>,
+}
+
+impl Lease {
+    fn parse(lease: &str) -> std::io::Result<Self> {
+        if lease.is_empty() {
+            return Ok(Lease {
+                name: None,
+                expected: None,
+            });
+        }
+
+        let (name, expected) = match lease.split_once(':') {
+            Some((name, "")) => (name, Some(None)),
+            Some((name, expected)) => {
+                let hash = revision::resolve(expected).map_err(|_| {
+                    std::io::Error::other(format!("cannot parse expected object name '{expected}'"))
+                })?;
+                (name, Some(Some(hash)))
+            }
+            None => (lease, None),
+        };
+
+        Ok(Lease {
+            name: Some(name.to_string()),
+            expected,
+        })
+    }
+
+    /// Whether the lease protects the specified remote ref
+    fn covers(&self, name: &str) -> bool {
+        match &self.name {
+            None => true,
+            Some(lease) => {
+                name == lease
+                    || ["refs/heads/", "refs/tags/"]
+                        .iter()
+                        .any(|prefix| name.strip_prefix(prefix) == Some(lease))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::clone::{clone, CloneArgs};
+    use crate::command::push::{push, PushArgs};
+    use crate::init;
+    use crate::object::{Hash, Object};
+    use crate::refs;
+    use crate::test_utils::{
+        fake_ssh, git, hamachi_git, serve_git_daemon, serve_git_http, setup_test_environment,
+        teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::process::Command;
+    use std::str::FromStr;
+
+    /// The value of a ref of the remote, empty when it does not exist
+    fn remote_ref(name: &str) -> String {
+        git(&[
+            "--git-dir",
+            "remote.git",
+            "rev-parse",
+            "--verify",
+            "-q",
+            name,
+        ])
+    }
+
+    fn commit(file: &str, content: &str) -> String {
+        fs::write(file, content).unwrap();
+        hamachi_git(&["add", file]);
+        hamachi_git(&["commit", "-m", content]);
+        hamachi_git(&["rev-parse", "HEAD"])
+    }
+
+    /// Creates a bare repository served over HTTP that accepts pushes, as the `origin` remote
+    fn setup_remote() {
+        std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+        std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+        git(&["init", "--bare", "remote.git"]);
+        git(&[
+            "--git-dir",
+            "remote.git",
+            "config",
+            "http.receivepack",
+            "true",
+        ]);
+        let url = format!("{}/remote.git", serve_git_http(Path::new(".")).unwrap());
+        hamachi_git(&["config", "remote.origin.url", &url]);
+        hamachi_git(&[
+            "config",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        ]);
+    }
+
+    fn write_hook(name: &str, script: &str) {
+        let path = format!("remote.git/hooks/{name}");
+        fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn remote_is_valid() -> bool {
+        Command::new("git")
+            .args(["--git-dir", "remote.git", "fsck", "--strict"])
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn push_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            setup_remote();
+            fs::create_dir("src").unwrap();
+            fs::write("src/lib.txt", "library\n").unwrap();
+            hamachi_git(&["add", "src"]);
+            commit("a.txt", "first\n");
+            let head = commit("a.txt", "second\n");
+            let branch = hamachi_git(&["symbolic-ref", "HEAD"]);
+            let short_branch = branch.strip_prefix("refs/heads/").unwrap().to_string();
+
+            // Test
+            // The current branch, to a new remote branch, moving its remote-tracking ref along
+            push(PushArgs::default()).unwrap();
+            assert_eq!(remote_ref(&branch), head);
+            assert_eq!(hamachi_git(&["rev-parse", &format!("refs/remotes/origin/{short_branch}")]), head);
+            assert!(remote_is_valid());
+
+            // A fast-forward only sends what the remote lacks
+            let head = commit("b.txt", "third\n");
+            push(PushArgs::default()).unwrap();
+            assert_eq!(remote_ref(&branch), head);
+            assert!(remote_is_valid());
+            push(PushArgs::default()).unwrap();
+
+            // Rewritten history needs forcing
+            hamachi_git(&["reset", "--hard", "HEAD~1"]);
+            let rewritten = commit("b.txt", "rewritten third\n");
+            assert!(push(PushArgs::default()).is_err());
+            assert_eq!(remote_ref(&branch), head);
+            push(PushArgs { force: true, ..Default::default() }).unwrap();
+            assert_eq!(remote_ref(&branch), rewritten);
+            let older = hamachi_git(&["rev-parse", "HEAD~1"]);
+            push(PushArgs { refspecs: vec![format!("+HEAD~1:{branch}")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref(&branch), older);
+
+            // Tags, lightweight and annotated
+            hamachi_git(&["tag", "v1"]);
+            hamachi_git(&["tag", "-a", "v2", "-m", "second release", "HEAD~1"]);
+            push(PushArgs { tags: true, ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/tags/v1"), hamachi_git(&["rev-parse", "v1"]));
+            assert_eq!(remote_ref("refs/tags/v2"), hamachi_git(&["rev-parse", "v2"]));
+            assert!(remote_is_valid());
+            hamachi_git(&["tag", "-f", "v1", "HEAD~1"]);
+            assert!(push(PushArgs { refspecs: vec![String::from("v1")], ..Default::default() }).is_err());
+
+            // Another branch, then its deletion
+            push(PushArgs { refspecs: vec![String::from("HEAD:refs/heads/topic")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/topic"), rewritten);
+            assert_eq!(hamachi_git(&["rev-parse", "refs/remotes/origin/topic"]), rewritten);
+            push(PushArgs { delete: true, refspecs: vec![String::from("topic")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/topic"), "");
+            assert!(!Path::new(".hamachi/refs/remotes/origin/topic").exists());
+            assert!(push(PushArgs { delete: true, refspecs: vec![String::from("topic")], ..Default::default() }).is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn push_lease_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            setup_remote();
+            commit("a.txt", "first\n");
+            let branch = hamachi_git(&["symbolic-ref", "HEAD"]);
+            push(PushArgs::default()).unwrap();
+
+            // Someone else pushes to the remote meanwhile
+            let tree = hamachi_git(&["rev-parse", "HEAD^{tree}"]);
+            let parent = hamachi_git(&["rev-parse", "HEAD"]);
+            let theirs = git(&["--git-dir", "remote.git", "-c", "user.name=Other", "-c", "user.email=other@example.com", "commit-tree", &tree, "-p", &parent, "-m", "theirs"]);
+            git(&["--git-dir", "remote.git", "update-ref", &branch, &theirs]);
+            let ours = commit("a.txt", "ours\n");
+
+            // Test
+            // The remote-tracking ref is stale, so the lease does not hold
+            assert!(push(PushArgs { force_with_lease: vec![String::new()], ..Default::default() }).is_err());
+            assert_eq!(remote_ref(&branch), theirs);
+            push(PushArgs { force_with_lease: vec![format!("{branch}:{theirs}")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref(&branch), ours);
+
+            // Nothing is updated when an atomic push has an update rejected
+            hamachi_git(&["branch", "second"]);
+            git(&["--git-dir", "remote.git", "update-ref", &branch, &theirs]);
+            assert!(push(PushArgs { atomic: true, refspecs: vec![branch.clone(), String::from("second")], ..Default::default() }).is_err());
+            assert_eq!(remote_ref("refs/heads/second"), "");
+            push(PushArgs { atomic: true, refspecs: vec![String::from("second")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/second"), ours);
+
+            // Updates the hooks of the remote refuse
+            write_hook("update", "case \"$1\" in refs/heads/protected) echo denied >&2; exit 1;; esac\n");
+            assert!(push(PushArgs { refspecs: vec![String::from("HEAD:refs/heads/protected")], ..Default::default() }).is_err());
+            assert_eq!(remote_ref("refs/heads/protected"), "");
+
+            // Push options reach the hooks, provided the remote accepts them
+            let options = PushArgs { refspecs: vec![String::from("HEAD:refs/heads/options")], push_options: vec![String::from("ci.skip")], ..Default::default() };
+            assert!(push(options).is_err());
+            git(&["--git-dir", "remote.git", "config", "receive.advertisePushOptions", "true"]);
+            write_hook("pre-receive", "echo \"$GIT_PUSH_OPTION_COUNT $GIT_PUSH_OPTION_0\" > options.txt\n");
+            push(PushArgs { refspecs: vec![String::from("HEAD:refs/heads/options")], push_options: vec![String::from("ci.skip")], ..Default::default() }).unwrap();
+            assert_eq!(fs::read_to_string("remote.git/options.txt").unwrap(), "1 ci.skip\n");
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+
+        #[test]
+        fn push_local_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            git(&["init", "--bare", "remote.git"]);
+            fs::create_dir_all("other").unwrap();
+            std::env::set_current_dir("other").unwrap();
+            init().unwrap();
+            std::env::set_current_dir(&repo).unwrap();
+            // More objects than git would keep loose
+            fs::create_dir("files").unwrap();
+            for i in 0..150 {
+                fs::write(format!("files/{i}.txt"), format!("file {i}\n")).unwrap();
+            }
+            hamachi_git(&["add", "files"]);
+            let head = commit("a.txt", "first\n");
+
+            // Test
+            // To a bare repository by path, and to one of ours by file:// URL
+            push(PushArgs { remote: Some(String::from("remote.git")), refspecs: vec![String::from("HEAD:refs/heads/main")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/main"), head);
+            assert!(remote_is_valid());
+
+            // Our own repositories are served without git
+            let url = format!("file://{}", repo.join("other").display());
+            let path = std::env::var_os("PATH").unwrap();
+            std::env::set_var("PATH", "");
+            push(PushArgs { remote: Some(url.clone()), refspecs: vec![String::from("HEAD:refs/heads/main")], ..Default::default() }).unwrap();
+            clone(CloneArgs { repository: url, directory: Some(String::from("cloned")), ..Default::default() }).unwrap();
+            std::env::set_var("PATH", path);
+            assert_eq!(refs::read_ref("refs/remotes/origin/main").unwrap(), Some(Hash::from_str(&head).unwrap()));
+            std::env::set_current_dir(&repo).unwrap();
+            std::env::set_current_dir("other").unwrap();
+            assert_eq!(refs::read_ref("refs/heads/main").unwrap(), Some(Hash::from_str(&head).unwrap()));
+            let blob = Hash::from_str(&hamachi_git(&["rev-parse", "main:files/149.txt"])).unwrap();
+            assert_eq!(Object::read(&blob).unwrap().1, b"file 149\n");
+            std::env::set_current_dir(&repo).unwrap();
+
+            assert!(push(PushArgs { remote: Some(String::from("missing")), ..Default::default() }).is_err());
+
+            // Over ssh, with an scp-like URL
+            fake_ssh(&repo).unwrap();
+            let second = commit("a.txt", "second\n");
+            let url = format!("localhost:{}", repo.join("remote.git").display());
+            push(PushArgs { remote: Some(url), refspecs: vec![String::from("HEAD:refs/heads/main")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/main"), second);
+            assert!(remote_is_valid());
+            assert!(fs::read_to_string("ssh.log").unwrap().contains("localhost git-receive-pack '"));
+
+            // Through git daemon
+            let third = commit("a.txt", "third\n");
+            let url = format!("{}/remote.git", serve_git_daemon(Path::new(".")).unwrap());
+            push(PushArgs { remote: Some(url), refspecs: vec![String::from("HEAD:refs/heads/main")], ..Default::default() }).unwrap();
+            assert_eq!(remote_ref("refs/heads/main"), third);
+            assert!(remote_is_valid());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use crate::sequencer::rebase::{self, RebaseOptions};
+use clap::Args;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct RebaseArgs {
+    /// Replay the commits on top of this commit instead of the upstream
+    #[clap(long)]
+    onto: Option<String>,
+
+    /// Let the todo list be edited before the rebase runs
+    #[clap(short = 'i', long)]
+    interactive: bool,
+
+    /// Move fixup! and squash! commits after the commits they amend
+    #[clap(long)]
+    autosquash: bool,
+
+    /// Run a shell command after every replayed commit
+    #[clap(short = 'x', long)]
+    exec: Vec<String>,
+
+    /// Conclude the step the rebase stopped at and go on
+    #[clap(long = "continue", conflicts_with_all = ["skip", "abort"])]
+    continue_rebase: bool,
+
+    /// Drop the commit the rebase stopped at and go on
+    #[clap(long, conflicts_with = "abort")]
+    skip: bool,
+
+    /// Cancel the rebase, checking out the original branch again
+    #[clap(long)]
+    abort: bool,
+
+    upstream: Option<String>,
+
+    branch: Option<String>,
+}
+
+/// Reapply the commits of a branch on top of another base
+/// https://git-scm.com/docs/git-rebase
+pub(crate) fn rebase(args: RebaseArgs) -> std::io::Result<()> {
+    if args.continue_rebase {
+        return rebase::resume();
+    }
+    if args.skip {
+        return rebase::skip();
+    }
+    if args.abort {
+        return rebase::abort();
+    }
+
+    rebase::start(RebaseOptions {
+        upstream: args.upstream,
+        onto: args.onto,
+        branch: args.branch,
+        interactive: args.interactive,
+        autosquash: args.autosquash,
+        exec: args.exec,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::rebase::{rebase, RebaseArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    fn commit_file(path: &str, content: &str, message: &str) {
+        fs::write(path, content).unwrap();
+        git(&["add", path]);
+        git(&["commit", "-m", message]);
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn rebase_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_EDITOR", "true");
+            std::env::set_var("GIT_SEQUENCE_EDITOR", "true");
+
+            commit_file("base.txt", "base\n", "base");
+            git(&["switch", "-c", "topic"]);
+            commit_file("first.txt", "first\n", "first");
+            commit_file("first.txt", "first\nfixed\n", "fixup! first");
+            commit_file("second.txt", "second\n", "second");
+            commit_file("second.txt", "second\nsquashed\n", "squash! second\n\nMore details.");
+            commit_file("third.txt", "third\n", "third");
+            git(&["switch", "master"]);
+            commit_file("second.txt", "second on master\n", "master");
+            git(&["switch", "topic"]);
+            let original = git(&["rev-parse", "HEAD"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            git(&["rebase", "-i", "--autosquash", "master"]);
+            let expected_conflict = fs::read("second.txt").unwrap();
+            fs::write("second.txt", "second\n").unwrap();
+            git(&["add", "second.txt"]);
+            git(&["rebase", "--continue"]);
+            let expected = git(&["log", "--format=%H %an %s", "-5"]);
+            let expected_reflog = fs::read_to_string(".git/logs/HEAD").unwrap();
+            git(&["reset", "--hard", &original]);
+
+            let error = rebase(RebaseArgs {
+                interactive: true,
+                autosquash: true,
+                upstream: Some(String::from("master")),
+                ..Default::default()
+            })
+            .unwrap_err();
+            assert!(error.to_string().starts_with("could not apply "));
+            let actual_conflict = fs::read("second.txt").unwrap();
+            fs::write("second.txt", "second\n").unwrap();
+            add(vec![String::from("second.txt")], false, false).unwrap();
+            rebase(RebaseArgs { continue_rebase: true, ..Default::default() }).unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").args(["log", "--format=%H %an %s", "-5"])).unwrap();
+            let actual_reflog = fs::read_to_string(".hamachi/logs/HEAD").unwrap();
+
+            assert_eq!(expected_conflict, actual_conflict);
+            assert_eq!(expected, actual);
+            let rebase_entries = |reflog: &str| reflog.lines().filter(|line| line.contains("\trebase")).map(|line| line.to_string()).collect::<Vec<_>>();
+            assert_eq!(rebase_entries(&expected_reflog), rebase_entries(&actual_reflog));
+            assert!(!fs::exists(".hamachi/rebase-merge").unwrap());
+            assert_eq!(original, fs::read_to_string(".hamachi/ORIG_HEAD").unwrap().trim());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
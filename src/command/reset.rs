@@ -0,0 +1,210 @@
+use crate::command::status::collect_status;
+use crate::index::Index;
+use crate::merge::{self, MERGE_HEAD_PATH};
+use crate::object::commit::Commit;
+use crate::refs::revision;
+use crate::{refs, sequencer, worktree};
+use clap::Args;
+use std::path::Path;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct ResetArgs {
+    /// Only move HEAD, keeping the index and the working tree
+    #[clap(long, conflicts_with_all = ["mixed", "hard"])]
+    soft: bool,
+
+    /// Move HEAD and reset the index, keeping the working tree (the default)
+    #[clap(long, conflicts_with = "hard")]
+    mixed: bool,
+
+    /// Move HEAD and reset the index and the working tree, discarding every local change
+    #[clap(long)]
+    hard: bool,
+
+    /// The commit to reset to, followed by the paths to reset if any
+    target: Vec<String>,
+
+    #[clap(last = true)]
+    paths: Vec<String>,
+}
+
+/// Reset the current branch to the specified commit, or the index entries of the specified paths
+/// to their version in a tree-ish
+/// https://git-scm.com/docs/git-reset
+pub(crate) fn reset(args: ResetArgs) -> std::io::Result<()> {
+    // Without `--`, the first argument is the commit if it names one and otherwise a path
+    let (revision, mut paths) = match args.target.split_first() {
+        Some((first, rest)) if revision::resolve(first).is_ok() => {
+            (Some(first.clone()), rest.to_vec())
+        }
+        _ => (None, args.target),
+    };
+    paths.extend(args.paths);
+
+    if !paths.is_empty() {
+        if args.soft || args.hard {
+            let mode = if args.soft { "soft" } else { "hard" };
+            return Err(std::io::Error::other(format!(
+                "Cannot do {mode} reset with paths."
+            )));
+        }
+
+        let source = match &revision {
+            Some(revision) => Some(revision::resolve_tree(revision)?),
+            None => revision::resolve_tree("HEAD").ok(),
+        };
+        worktree::reset_paths(source.as_ref(), &paths)?;
+
+        return print_unstaged_changes();
+    }
+
+    if args.soft && Path::new(MERGE_HEAD_PATH).exists() {
+        return Err(std::io::Error::other(
+            "Cannot do a soft reset in the middle of a merge.",
+        ));
+    }
+
+    let head = refs::head_commit()?;
+    let revision = revision.unwrap_or_else(|| String::from("HEAD"));
+    let target = match (&head, revision.as_str()) {
+        // Resetting an unborn branch to itself empties the index
+        (None, "HEAD") => None,
+        _ => Some(revision::resolve_commit(&revision)?),
+    };
+    let target_tree = target
+        .as_ref()
+        .map(|hash| Commit::from_hash(hash).tree_hash);
+
+    if args.hard {
+        match &target_tree {
+            Some(tree) => {
+                let head_tree = head.as_ref().map(|hash| Commit::from_hash(hash).tree_hash);
+                worktree::checkout_tree(head_tree.as_ref(), tree, true)?;
+            }
+            None => {
+                for entry in Index::load()?.entries {
+                    worktree::remove_file(&entry.path)?;
+                }
+                worktree::reset_paths(None, &[String::from(".")])?;
+            }
+        }
+    } else if !args.soft {
+        worktree::reset_paths(target_tree.as_ref(), &[String::from(".")])?;
+    }
+
+    if let Some(target) = &target {
+        if let Some(head) = &head {
+            refs::update_ref("ORIG_HEAD", head)?;
+        }
+        refs::update_ref_with_log(
+            "HEAD",
+            head.as_ref(),
+            target,
+            &format!("reset: moving to {revision}"),
+        )?;
+    }
+    merge::clear_merge_state()?;
+    sequencer::clear_pick_heads()?;
+
+    match &target {
+        Some(target) if args.hard => {
+            let commit = Commit::from_hash(target);
+            println!(
+                "HEAD is now at {} {}",
+                target.to_short_string(),
+                commit.subject()
+            );
+            Ok(())
+        }
+        _ if !args.soft => print_unstaged_changes(),
+        _ => Ok(()),
+    }
+}
+
+/// Lists the tracked files whose working tree version differs from the index, as git does after
+/// resetting the index
+fn print_unstaged_changes() -> std::io::Result<()> {
+    let status = collect_status()?;
+    let changes = status
+        .entries
+        .iter()
+        .filter_map(|entry| match (&entry.conflict, &entry.unstaged) {
+            (Some(_), _) => Some(format!("U\t{}", entry.path)),
+            (None, Some(kind)) => Some(format!("{}\t{}", kind.letter(), entry.path)),
+            (None, None) => None,
+        })
+        .collect::<Vec<_>>();
+
+    if !changes.is_empty() {
+        println!("Unstaged changes after reset:");
+        for change in changes {
+            println!("{change}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::reset::{reset, ResetArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, hamachi_git, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+
+    fn commit_file(path: &str, content: &str, message: &str) {
+        fs::write(path, content).unwrap();
+        git(&["add", path]);
+        git(&["commit", "-m", message]);
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn reset_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            commit_file("a.txt", "one\n", "first");
+            commit_file("a.txt", "two\n", "second");
+            commit_file("b.txt", "three\n", "third");
+            let original = git(&["rev-parse", "HEAD"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            fs::write("a.txt", "local\n").unwrap();
+            git(&["add", "a.txt"]);
+            git(&["reset", "--soft", "HEAD~1"]);
+            let expected_soft = git(&["ls-files", "--stage"]);
+            git(&["reset", "HEAD~1"]);
+            let expected_mixed = git(&["ls-files", "--stage"]);
+            git(&["reset", &original, "--", "b.txt"]);
+            let expected_paths = git(&["ls-files", "--stage"]);
+            git(&["reset", "--hard", "HEAD"]);
+            let expected_reflog = fs::read_to_string(".git/logs/HEAD").unwrap();
+            let expected_files = (fs::read_to_string("a.txt").unwrap(), fs::exists("b.txt").unwrap());
+
+            fs::write("a.txt", "local\n").unwrap();
+            hamachi_git(&["add", "a.txt"]);
+            reset(ResetArgs { soft: true, target: vec![String::from("HEAD~1")], ..Default::default() }).unwrap();
+            assert_eq!(expected_soft, hamachi_git(&["ls-files", "--stage"]));
+            assert_eq!(original, fs::read_to_string(".hamachi/ORIG_HEAD").unwrap().trim());
+            reset(ResetArgs { target: vec![String::from("HEAD~1")], ..Default::default() }).unwrap();
+            assert_eq!(expected_mixed, hamachi_git(&["ls-files", "--stage"]));
+            reset(ResetArgs { target: vec![original.clone()], paths: vec![String::from("b.txt")], ..Default::default() }).unwrap();
+            assert_eq!(expected_paths, hamachi_git(&["ls-files", "--stage"]));
+            reset(ResetArgs { hard: true, target: vec![String::from("HEAD")], ..Default::default() }).unwrap();
+            let actual_reflog = fs::read_to_string(".hamachi/logs/HEAD").unwrap();
+
+            let reset_entries = |reflog: &str| reflog.lines().filter(|line| line.contains("\treset")).map(|line| line.to_string()).collect::<Vec<_>>();
+            assert_eq!(reset_entries(&expected_reflog), reset_entries(&actual_reflog));
+            assert_eq!(expected_files, (fs::read_to_string("a.txt").unwrap(), fs::exists("b.txt").unwrap()));
+            assert_eq!(hamachi_git(&["status", "--porcelain", "--untracked-files=no"]), "");
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::refs::revision;
+use crate::worktree;
+use clap::Args;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct RestoreArgs {
+    /// Restore from this tree-ish instead of the index, or instead of HEAD with `--staged`
+    #[clap(short = 's', long)]
+    source: Option<String>,
+
+    /// Restore the index
+    #[clap(short = 'S', long)]
+    staged: bool,
+
+    /// Restore the working tree, the default unless `--staged` is given
+    #[clap(short = 'W', long)]
+    worktree: bool,
+
+    paths: Vec<String>,
+}
+
+/// Restore working tree files from the index, or index entries from HEAD, or either from the
+/// specified tree-ish
+/// https://git-scm.com/docs/git-restore
+pub(crate) fn restore(args: RestoreArgs) -> std::io::Result<()> {
+    if args.paths.is_empty() {
+        return Err(std::io::Error::other("you must specify path(s) to restore"));
+    }
+
+    let restore_worktree = args.worktree || !args.staged;
+    let source = match (&args.source, args.staged) {
+        (Some(source), _) => Some(revision::resolve_tree(source)?),
+        (None, true) => revision::resolve_tree("HEAD").ok(),
+        (None, false) => None,
+    };
+
+    if args.staged {
+        worktree::reset_paths(source.as_ref(), &args.paths)?;
+    }
+    if restore_worktree {
+        // Once the index is restored from the source, the working tree can follow the index
+        let source = if args.staged { None } else { source };
+        worktree::checkout_paths(source.as_ref(), &args.paths, false, false)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::checkout::checkout;
+    use crate::command::restore::{restore, RestoreArgs};
+    use crate::test_utils::{copy_git_objects, run_git_command, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn restore_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("test.txt", "first version\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first")).unwrap();
+            let first_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            fs::write("test.txt", "second version\n").unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-am").arg("second")).unwrap();
+            let second_commit = run_git_command(Command::new("git").arg("rev-parse").arg("HEAD")).unwrap();
+
+            copy_git_objects().unwrap();
+            fs::remove_file("test.txt").unwrap();
+            checkout(Some(second_commit), Vec::new(), false, false, None, false).unwrap();
+
+            // Test
+            fs::write("test.txt", "local changes\n").unwrap();
+            restore(RestoreArgs {
+                paths: vec![String::from("test.txt")],
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "second version\n");
+
+            restore(RestoreArgs {
+                source: Some(first_commit),
+                paths: vec![String::from(".")],
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "first version\n");
+
+            fs::write("test.txt", "staged version\n").unwrap();
+            add(vec![String::from("test.txt")], false, false).unwrap();
+            restore(RestoreArgs {
+                staged: true,
+                paths: vec![String::from("test.txt")],
+                ..Default::default()
+            })
+            .unwrap();
+            let index = run_git_command(
+                Command::new("git")
+                    .arg("ls-files")
+                    .arg("--stage")
+                    .env("GIT_INDEX_FILE", ".hamachi/index"),
+            )
+            .unwrap();
+            assert!(index.contains(&run_git_command(Command::new("git").arg("rev-parse").arg("HEAD:test.txt")).unwrap()));
+            assert_eq!(fs::read_to_string("test.txt").unwrap(), "staged version\n");
+
+            teardown(repo).unwrap();
+        }
+    }
+}
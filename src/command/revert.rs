@@ -0,0 +1,115 @@
+use crate::sequencer::{self, Action, SequencerOptions};
+use clap::Args;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct RevertArgs {
+    /// Append a Signed-off-by trailer with the committer to the message
+    #[clap(short = 's', long)]
+    signoff: bool,
+
+    /// The parent, counting from 1, against which merge commits are reverted
+    #[clap(short = 'm', long)]
+    mainline: Option<usize>,
+
+    /// Commit the resolved conflicts and go on with the remaining commits
+    #[clap(long = "continue", conflicts_with_all = ["skip", "abort"])]
+    continue_revert: bool,
+
+    /// Skip the commit that stopped the revert and go on with the remaining commits
+    #[clap(long, conflicts_with = "abort")]
+    skip: bool,
+
+    /// Cancel the revert, restoring the branch as it was before
+    #[clap(long)]
+    abort: bool,
+
+    commits: Vec<String>,
+}
+
+/// Record new commits undoing the changes introduced by existing commits
+/// https://git-scm.com/docs/git-revert
+pub(crate) fn revert(args: RevertArgs) -> std::io::Result<()> {
+    if args.continue_revert {
+        return sequencer::resume(Action::Revert);
+    }
+    if args.skip {
+        return sequencer::skip(Action::Revert);
+    }
+    if args.abort {
+        return sequencer::abort();
+    }
+
+    sequencer::start(
+        Action::Revert,
+        &args.commits,
+        SequencerOptions {
+            signoff: args.signoff,
+            mainline: args.mainline,
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::revert::{revert, RevertArgs};
+    use crate::test_utils::{
+        copy_git_repository, git, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn revert_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            fs::write("file.txt", "one\ntwo\nthree\nfour\nfive\n").unwrap();
+            git(&["add", "file.txt"]);
+            git(&["commit", "-m", "base"]);
+            fs::write("file.txt", "one\ntwo changed\nthree\nfour\nfive\n").unwrap();
+            git(&["commit", "-am", "first"]);
+            fs::write("added.txt", "added\n").unwrap();
+            git(&["add", "added.txt"]);
+            git(&["commit", "-m", "second"]);
+            fs::write("file.txt", "one\ntwo changed again\nthree\nfour\nfive\n").unwrap();
+            git(&["commit", "-am", "third"]);
+            let original = git(&["rev-parse", "HEAD"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            // Reverting the first change conflicts with the third, and aborting restores the
+            // branch from before the revert
+            let error = revert(RevertArgs { commits: vec![String::from("HEAD~1"), String::from("HEAD~2")], ..Default::default() }).unwrap_err();
+            assert!(error.to_string().starts_with("could not revert "));
+            assert!(fs::exists(".hamachi/REVERT_HEAD").unwrap());
+            revert(RevertArgs { abort: true, ..Default::default() }).unwrap();
+
+            let head = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").args(["rev-parse", "HEAD"])).unwrap();
+            let status = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").env("GIT_WORK_TREE", ".").args(["status", "--porcelain", "--untracked-files=no"])).unwrap();
+            assert_eq!(original, head);
+            assert_eq!("", status);
+            assert!(!fs::exists(".hamachi/sequencer").unwrap());
+            assert!(!fs::exists(".hamachi/REVERT_HEAD").unwrap());
+
+            git(&["revert", "--no-edit", "-s", "HEAD~2..HEAD"]);
+            let expected = git(&["log", "--format=%H %s", "-2"]);
+            git(&["reset", "--hard", &original]);
+
+            revert(RevertArgs {
+                signoff: true,
+                commits: vec![String::from("HEAD~2..HEAD")],
+                ..Default::default()
+            })
+            .unwrap();
+            let actual = run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").args(["log", "--format=%H %s", "-2"])).unwrap();
+            assert_eq!(expected, actual);
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use crate::remote::client::ProtocolVersion;
+use crate::remote::pkt_line::PktLineWriter;
+use crate::remote::receive_pack::{self, receive_pack};
+use crate::remote::transport::Service;
+use crate::remote::upload_pack::{self, upload_pack};
+use clap::Args;
+use flate2::read::GzDecoder;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// The largest request body accepted, pushes being read whole before they are answered
+const MAX_BODY_SIZE: usize = 512 * 1024 * 1024;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct ServeArgs {
+    /// The directory holding the repositories, each served at its path below it
+    directory: Option<String>,
+
+    /// The address to listen on
+    #[clap(long, default_value = "127.0.0.1")]
+    listen: String,
+
+    /// The port to listen on, any free one for 0
+    #[clap(long, default_value_t = 8080)]
+    port: u16,
+
+    /// Refuse pushes, serving only fetches and clones
+    #[clap(long)]
+    read_only: bool,
+
+    /// Seconds to wait for a client to send or receive more data before dropping it, 0 for no
+    /// limit
+    #[clap(long, default_value_t = 60)]
+    timeout: u64,
+}
+
+/// An HTTP request, its body read whole
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP response, its body built whole
+struct Response {
+    status: u16,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, message: &str) -> Self {
+        Response {
+            status,
+            content_type: String::from("text/plain"),
+            body: format!("{message}\n").into_bytes(),
+        }
+    }
+}
+
+/// Serve the repositories below a directory over git's smart HTTP protocol, in versions 0 to 2,
+/// to git and hamachi clients: `<repository>/info/refs` advertises the refs of a service, and
+/// `<repository>/git-upload-pack` and `<repository>/git-receive-pack` answer its requests.
+/// Requests are served one at a time, each in the repository it is for, as hamachi works in the
+/// current directory. A client that stops sending is dropped after the timeout, and a request
+/// that fails is dropped alone.
+/// https://git-scm.com/docs/http-protocol
+pub(crate) fn serve(args: ServeArgs) -> std::io::Result<()> {
+    let directory = args.directory.as_deref().unwrap_or(".");
+    let root = Path::new(directory)
+        .canonicalize()
+        .map_err(|e| std::io::Error::other(format!("cannot serve '{directory}': {e}")))?;
+    let listener = TcpListener::bind((args.listen.as_str(), args.port)).map_err(|e| {
+        std::io::Error::other(format!(
+            "unable to listen on {}:{}: {e}",
+            args.listen, args.port
+        ))
+    })?;
+    println!(
+        "Serving {} on http://{}",
+        root.display(),
+        listener.local_addr()?
+    );
+
+    let timeout = (args.timeout > 0).then(|| Duration::from_secs(args.timeout));
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let handled = stream
+            .set_read_timeout(timeout)
+            .and_then(|_| stream.set_write_timeout(timeout))
+            .and_then(|_| {
+                panic::catch_unwind(AssertUnwindSafe(|| {
+                    handle_connection(stream, &root, args.read_only)
+                }))
+                .unwrap_or_else(|_| Err(std::io::Error::other("request handling panicked")))
+            });
+        if let Err(e) = handled {
+            eprintln!("error: {e}");
+        }
+        std::env::set_current_dir(&root)?;
+    }
+
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, root: &Path, read_only: bool) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let response = match read_request(&mut reader, &mut writer)? {
+        Ok(request) => handle_request(&request, root, read_only),
+        Err(refusal) => refusal,
+    };
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        _ => "Internal Server Error",
+    };
+    write!(
+        writer,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache, max-age=0, must-revalidate\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+/// Reads a request along with its body, which is sent in chunks for large pushes and fetches.
+/// Clients waiting for the go-ahead before sending the body are given it. Requests whose body
+/// exceeds `MAX_BODY_SIZE` are refused with the response to send instead.
+fn read_request(
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut TcpStream,
+) -> std::io::Result<Result<Request, Response>> {
+    let bad_request = || std::io::Error::other("bad HTTP request");
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad_request());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad_request());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(bad_request)?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body: Vec::new(),
+    };
+    let too_large = || Response::text(413, "request body too large");
+    let chunked = request
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let length = match request.header("Content-Length") {
+        Some(_) if chunked => None,
+        Some(length) => Some(length.parse::<u64>().map_err(|_| bad_request())?),
+        None => None,
+    };
+    if length.is_some_and(|length| length > MAX_BODY_SIZE as u64) {
+        return Ok(Err(too_large()));
+    }
+
+    if request
+        .header("Expect")
+        .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    if chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = size.split(';').next().unwrap_or_default().trim();
+            let size = u64::from_str_radix(size, 16).map_err(|_| bad_request())?;
+            if size == 0 {
+                // The trailer, up to an empty line
+                let mut line = String::new();
+                while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+                    line.clear();
+                }
+                break;
+            }
+            if size > (MAX_BODY_SIZE - request.body.len()) as u64 {
+                return Ok(Err(too_large()));
+            }
+
+            read_body(reader, size, &mut request.body)?;
+            let mut end = [0; 2];
+            reader.read_exact(&mut end)?;
+        }
+    } else if let Some(length) = length {
+        read_body(reader, length, &mut request.body)?;
+    }
+
+    Ok(Ok(request))
+}
+
+/// Appends exactly `length` bytes of the body, the buffer growing as they arrive rather than
+/// being sized from what the client announced
+fn read_body(
+    reader: &mut BufReader<TcpStream>,
+    length: u64,
+    body: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    if reader.take(length).read_to_end(body)? as u64 != length {
+        return Err(std::io::Error::other("unexpected end of request body"));
+    }
+
+    Ok(())
+}
+
+fn handle_request(request: &Request, root: &Path, read_only: bool) -> Response {
+    let (repository, endpoint) = request.path.rsplit_once('/').unwrap_or(("", ""));
+    let (repository, service, method) = match endpoint {
+        "refs" => match repository.strip_suffix("/info") {
+            Some(repository) => {
+                let service = request
+                    .query
+                    .split('&')
+                    .find_map(|parameter| parameter.strip_prefix("service="))
+                    .and_then(Service::from_name);
+                match service {
+                    Some(service) => (repository, service, "GET"),
+                    None => return Response::text(403, "only the smart HTTP protocol is served"),
+                }
+            }
+            None => return Response::text(404, "not found"),
+        },
+        name => match Service::from_name(name) {
+            Some(service) => (repository, service, "POST"),
+            None => return Response::text(404, "not found"),
+        },
+    };
+
+    if request.method != method {
+        return Response::text(405, "method not allowed");
+    }
+    if service == Service::ReceivePack && read_only {
+        return Response::text(403, "pushes are not allowed by this server");
+    }
+    let Some(repository) = find_repository(root, repository) else {
+        return Response::text(404, "repository not found");
+    };
+    if let Err(e) = std::env::set_current_dir(&repository) {
+        return Response::text(500, &e.to_string());
+    }
+
+    // Pushes are always spoken in the original version
+    let version = match request.header("Git-Protocol") {
+        _ if service == Service::ReceivePack => ProtocolVersion::V0,
+        Some(header) if header.split(':').any(|parameter| parameter == "version=2") => {
+            ProtocolVersion::V2
+        }
+        Some(header) if header.split(':').any(|parameter| parameter == "version=1") => {
+            ProtocolVersion::V1
+        }
+        _ => ProtocolVersion::V0,
+    };
+
+    match method {
+        "GET" => advertise(service, version),
+        _ => answer(request, service, version),
+    }
+}
+
+/// The advertisement of a service, which over HTTP starts with a line naming the service in the
+/// original protocol
+fn advertise(service: Service, version: ProtocolVersion) -> Response {
+    let mut writer = PktLineWriter::new(Vec::new());
+    let advertised = match version {
+        ProtocolVersion::V2 => Ok(()),
+        ProtocolVersion::V0 | ProtocolVersion::V1 => writer
+            .write_line(&format!("# service={}", service.name()))
+            .and_then(|_| writer.write_flush()),
+    };
+    let mut body = writer.into_inner();
+    let advertised = advertised.and_then(|_| match service {
+        Service::UploadPack => upload_pack::advertise(version, &mut body),
+        Service::ReceivePack => receive_pack::advertise(&mut body),
+    });
+
+    match advertised {
+        Ok(()) => Response {
+            status: 200,
+            content_type: format!("application/x-{}-advertisement", service.name()),
+            body,
+        },
+        Err(e) => Response::text(500, &e.to_string()),
+    }
+}
+
+/// The response of a service to a request, whose body may be compressed. Errors of upload-pack
+/// are sent as an `ERR` line, which clients show, and those of receive-pack as a failure.
+fn answer(request: &Request, service: Service, version: ProtocolVersion) -> Response {
+    let content_type = format!("application/x-{}-request", service.name());
+    if request
+        .header("Content-Type")
+        .is_some_and(|header| header != content_type)
+    {
+        return Response::text(415, "unsupported media type");
+    }
+    let body: Box<dyn Read> = match request.header("Content-Encoding") {
+        Some("gzip" | "x-gzip") => Box::new(GzDecoder::new(&request.body[..])),
+        Some(encoding) if encoding != "identity" => {
+            return Response::text(415, &format!("unsupported content encoding '{encoding}'"))
+        }
+        _ => Box::new(&request.body[..]),
+    };
+
+    let mut output = Vec::new();
+    let answered = match service {
+        Service::UploadPack => upload_pack(body, version, &mut output),
+        Service::ReceivePack => receive_pack(body, &mut output),
+    };
+    let body = match (answered, service) {
+        (Ok(()), _) => output,
+        (Err(e), Service::UploadPack) => {
+            let mut writer = PktLineWriter::new(Vec::new());
+            if let Err(e) = writer.write_line(&format!("ERR {e}")) {
+                return Response::text(500, &e.to_string());
+            }
+            writer.into_inner()
+        }
+        (Err(e), Service::ReceivePack) => return Response::text(500, &e.to_string()),
+    };
+
+    Response {
+        status: 200,
+        content_type: format!("application/x-{}-result", service.name()),
+        body,
+    }
+}
+
+/// The repository at a path below the root, the `.git` suffix clients add to bare repositories
+/// being optional. Paths leaving the root are never served.
+fn find_repository(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.trim_matches('/');
+    if !Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    [Some(path), path.strip_suffix(".git")]
+        .into_iter()
+        .flatten()
+        .map(|path| root.join(path))
+        .find(|repository| repository.join(".hamachi").is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::clone::{clone, CloneArgs};
+    use crate::command::push::{push, PushArgs};
+    use crate::command::serve::{serve, ServeArgs};
+    use crate::init;
+    use crate::test_utils::{git, hamachi_git, setup_test_environment, teardown};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+
+    /// A server run by `serve_process` in a process of its own, as serving changes the current
+    /// directory. It is stopped when dropped.
+    struct Server {
+        child: Child,
+        url: String,
+    }
+
+    impl Server {
+        fn start(root: &Path, read_only: bool) -> Server {
+            let mut child = Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "command::serve::tests::serve_process",
+                    "--exact",
+                    "--ignored",
+                ])
+                .args(["--nocapture", "--test-threads=1"])
+                .env("HAMACHI_SERVE_ROOT", root)
+                .env("HAMACHI_SERVE_READ_ONLY", read_only.to_string())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let url = BufReader::new(child.stdout.take().unwrap())
+                .lines()
+                .find_map(|line| Some(line.ok()?.split_once(" on ")?.1.to_string()))
+                .unwrap();
+
+            Server { child, url }
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    #[test]
+    #[ignore = "serves until killed, started by the other tests"]
+    fn serve_process() {
+        let Ok(root) = std::env::var("HAMACHI_SERVE_ROOT") else {
+            return;
+        };
+        let read_only = std::env::var("HAMACHI_SERVE_READ_ONLY").as_deref() == Ok("true");
+        serve(ServeArgs {
+            directory: Some(root),
+            listen: String::from("127.0.0.1"),
+            port: 0,
+            read_only,
+            timeout: 2,
+        })
+        .unwrap();
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn serve_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            for variable in ["GIT_AUTHOR", "GIT_COMMITTER"] {
+                std::env::set_var(format!("{variable}_NAME"), "Osamu Dazai");
+                std::env::set_var(format!("{variable}_EMAIL"), "osamu.dazai@gmail.com");
+            }
+            fs::create_dir("project").unwrap();
+            std::env::set_current_dir("project").unwrap();
+            init().unwrap();
+            for i in 0..3 {
+                fs::write("a.txt", format!("version {i}\n")).unwrap();
+                hamachi_git(&["add", "a.txt"]);
+                hamachi_git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            hamachi_git(&["tag", "-a", "v1", "-m", "v1"]);
+            let expected_log = hamachi_git(&["log", "--format=%H %s"]);
+            std::env::set_current_dir(&repo).unwrap();
+            let server = Server::start(&repo, false);
+            let url = format!("{}/project", server.url);
+            let project = |args: &[&str]| git(&[&["--git-dir", "project/.hamachi"], args].concat());
+
+            // Test
+            // Stock git clones over version 2 and the original protocol, the tag included
+            git(&["clone", "-q", &url, "v2"]);
+            assert_eq!(git(&["-C", "v2", "log", "--format=%H %s"]), expected_log);
+            assert_eq!(git(&["-C", "v2", "rev-parse", "v1"]), project(&["rev-parse", "v1"]));
+            git(&["-c", "protocol.version=0", "clone", "-q", &format!("{url}.git"), "v0"]);
+            assert_eq!(git(&["-C", "v0", "log", "--format=%H %s"]), expected_log);
+
+            // And pushes, except to the checked out branch
+            fs::write("v2/b.txt", "pushed\n").unwrap();
+            git(&["-C", "v2", "add", "b.txt"]);
+            git(&["-C", "v2", "commit", "-q", "-m", "pushed"]);
+            git(&["-C", "v2", "push", "-q", "origin", "HEAD:refs/heads/feature"]);
+            assert_eq!(project(&["rev-parse", "feature"]), git(&["-C", "v2", "rev-parse", "HEAD"]));
+            assert!(Command::new("git").args(["--git-dir", "project/.hamachi", "fsck", "--strict"]).status().unwrap().success());
+            let output = Command::new("git").args(["-C", "v2", "push", "origin", "HEAD:master"]).output().unwrap();
+            assert!(!output.status.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("[remote rejected] HEAD -> master (branch is currently checked out)"));
+
+            // Negotiating with more haves than fit in an uncompressed request
+            for i in 0..40 {
+                git(&["-C", "v0", "commit", "-q", "--allow-empty", "-m", &format!("local {i}")]);
+            }
+            std::env::set_current_dir("project").unwrap();
+            fs::write("a.txt", "version 3\n").unwrap();
+            hamachi_git(&["commit", "-am", "commit 3"]);
+            std::env::set_current_dir(&repo).unwrap();
+            git(&["-C", "v0", "-c", "protocol.version=0", "fetch", "-q"]);
+            assert_eq!(git(&["-C", "v0", "rev-parse", "origin/master"]), project(&["rev-parse", "master"]));
+            git(&["-C", "v2", "fetch", "-q"]);
+            assert_eq!(git(&["-C", "v2", "rev-parse", "origin/master"]), project(&["rev-parse", "master"]));
+
+            // A compressed request body
+            let mut body = GzEncoder::new(Vec::new(), Compression::default());
+            body.write_all(b"0014command=ls-refs\n00010015ref-prefix refs/\n0000").unwrap();
+            let response = reqwest::blocking::Client::new()
+                .post(format!("{url}/git-upload-pack"))
+                .header("Content-Type", "application/x-git-upload-pack-request")
+                .header("Content-Encoding", "gzip")
+                .header("Git-Protocol", "version=2")
+                .body(body.finish().unwrap())
+                .send()
+                .unwrap();
+            assert_eq!(response.headers()["Content-Type"], "application/x-git-upload-pack-result");
+            let listed = response.text().unwrap();
+            assert!(listed.contains(&format!("{} refs/heads/feature\n", project(&["rev-parse", "feature"]))));
+
+            // Hamachi clones and pushes too
+            clone(CloneArgs { repository: url.clone(), directory: Some(String::from("cloned")), ..Default::default() }).unwrap();
+            assert_eq!(hamachi_git(&["log", "--format=%H %s"]), project(&["-C", "..", "log", "--format=%H %s"]));
+            fs::write("c.txt", "from hamachi\n").unwrap();
+            hamachi_git(&["add", "c.txt"]);
+            hamachi_git(&["commit", "-m", "from hamachi"]);
+            assert!(push(PushArgs::default()).is_err());
+            hamachi_git(&["checkout", "-q", "-b", "hamachi"]);
+            push(PushArgs::default()).unwrap();
+            let head = hamachi_git(&["rev-parse", "HEAD"]);
+            std::env::set_current_dir(&repo).unwrap();
+            assert_eq!(project(&["rev-parse", "hamachi"]), head);
+
+            // Read-only servers refuse pushes, and missing repositories are not found
+            let read_only = Server::start(&repo, true);
+            let output = Command::new("git").args(["-C", "v2", "push", &format!("{}/project", read_only.url), "HEAD:refs/heads/other"]).output().unwrap();
+            assert!(!output.status.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("403"));
+            git(&["clone", "-q", &format!("{}/project", read_only.url), "read-only"]);
+            assert!(Command::new("git").args(["clone", &format!("{}/missing", server.url)]).status().unwrap().code() != Some(0));
+
+            // Oversized bodies are refused, and a stalled client holds the server up only until
+            // it times out
+            let address = server.url.strip_prefix("http://").unwrap();
+            for (headers, body) in [("Content-Length: 18446744073709551615", ""), ("Transfer-Encoding: chunked", "ffffffffffffffff\r\n")] {
+                let mut stream = TcpStream::connect(address).unwrap();
+                write!(stream, "POST /project/git-upload-pack HTTP/1.1\r\n{headers}\r\n\r\n{body}").unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                assert!(response.starts_with("HTTP/1.1 413 "), "{response}");
+            }
+            let _stalled = TcpStream::connect(address).unwrap();
+            assert!(git(&["ls-remote", &url]).contains("refs/heads/master"));
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn serve_without_identity_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            for variable in ["GIT_AUTHOR", "GIT_COMMITTER"] {
+                std::env::remove_var(format!("{variable}_NAME"));
+                std::env::remove_var(format!("{variable}_EMAIL"));
+            }
+            // Only git is told who commits, neither hamachi repository knows
+            let commit = |message: &str| {
+                hamachi_git(&["-c", "user.name=Osamu Dazai", "-c", "user.email=osamu.dazai@gmail.com", "commit", "-m", message]);
+            };
+            fs::create_dir("project").unwrap();
+            std::env::set_current_dir("project").unwrap();
+            init().unwrap();
+            fs::write("a.txt", "served\n").unwrap();
+            hamachi_git(&["add", "a.txt"]);
+            commit("served");
+            std::env::set_current_dir(&repo).unwrap();
+            let server = Server::start(&repo, false);
+            let url = format!("{}/project", server.url);
+
+            // Test
+            // Ref updates are logged all the same, in clones and in the repository pushed to
+            clone(CloneArgs { repository: String::from("project"), directory: Some(String::from("local")), ..Default::default() }).unwrap();
+            assert!(fs::read_to_string(".hamachi/logs/HEAD").unwrap().contains("clone: from"));
+            std::env::set_current_dir(&repo).unwrap();
+
+            clone(CloneArgs { repository: url, directory: Some(String::from("cloned")), ..Default::default() }).unwrap();
+            fs::write("b.txt", "pushed\n").unwrap();
+            hamachi_git(&["add", "b.txt"]);
+            commit("pushed");
+            hamachi_git(&["checkout", "-q", "-b", "feature"]);
+            push(PushArgs::default()).unwrap();
+            let head = hamachi_git(&["rev-parse", "HEAD"]);
+            std::env::set_current_dir(&repo).unwrap();
+
+            assert_eq!(git(&["--git-dir", "project/.hamachi", "rev-parse", "feature"]), head);
+            assert!(fs::read_to_string("project/.hamachi/logs/refs/heads/feature").unwrap().contains(&head));
+
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use crate::command::status::{collect_status, status, StatusFormat};
+use crate::diff::{self, FileMap};
+use crate::index::{Index, IndexEntry};
+use crate::merge::text::ConflictStyle;
+use crate::merge::{checkout_merge, merge_trees, MergeLabels};
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::Tree;
+use crate::object::Hash;
+use crate::refs::revision;
+use crate::refs::Head;
+use crate::{refs, worktree};
+use clap::Subcommand;
+use std::collections::BTreeSet;
+use std::fs;
+
+const STASH_REF: &str = "refs/stash";
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum StashSubcommand {
+    /// Save the local changes as a new stash entry and revert them, the default
+    Push {
+        #[clap(short = 'm', long)]
+        message: Option<String>,
+
+        pathspecs: Vec<String>,
+    },
+    List,
+    /// Show the changes recorded in a stash entry as a diffstat
+    Show {
+        stash: Option<String>,
+    },
+    /// Apply the changes of a stash entry on top of the working tree
+    Apply {
+        /// Also restore the changes that were staged
+        #[clap(long)]
+        index: bool,
+
+        stash: Option<String>,
+    },
+    /// Apply a stash entry and remove it from the list
+    Pop {
+        #[clap(long)]
+        index: bool,
+
+        stash: Option<String>,
+    },
+    Drop {
+        stash: Option<String>,
+    },
+}
+
+/// Stash the changes in a dirty working directory away. Every entry is a commit whose first
+/// parent is HEAD at the time, whose second parent records the index and whose tree holds the
+/// working tree, so git can read the stashes and the other way around.
+/// https://git-scm.com/docs/git-stash
+pub(crate) fn stash(subcommand: Option<StashSubcommand>) -> std::io::Result<()> {
+    match subcommand.unwrap_or(StashSubcommand::Push {
+        message: None,
+        pathspecs: Vec::new(),
+    }) {
+        StashSubcommand::Push { message, pathspecs } => push(message, &pathspecs),
+        StashSubcommand::List => {
+            print!("{}", list()?);
+            Ok(())
+        }
+        StashSubcommand::Show { stash } => {
+            print!("{}", show(&stash_revision(stash)?)?);
+            Ok(())
+        }
+        StashSubcommand::Apply { index, stash } => apply(&stash_revision(stash)?, index),
+        StashSubcommand::Pop { index, stash } => {
+            let revision = stash_revision(stash)?;
+            let n = stash_index(&revision)?;
+            apply(&revision, index)?;
+            drop_entry(&revision, n)
+        }
+        StashSubcommand::Drop { stash } => {
+            let revision = stash_revision(stash)?;
+            let n = stash_index(&revision)?;
+            drop_entry(&revision, n)
+        }
+    }
+}
+
+fn push(message: Option<String>, pathspecs: &[String]) -> std::io::Result<()> {
+    let Some(head) = refs::head_commit()? else {
+        return Err(std::io::Error::other(
+            "You do not have the initial commit yet",
+        ));
+    };
+    let head_commit = Commit::from_hash(&head);
+    let pathspecs = if pathspecs.is_empty() {
+        vec![String::from(".")]
+    } else {
+        pathspecs.to_vec()
+    };
+
+    // The paths the entry records and that are reverted afterwards, renames counting for both
+    let mut changed = BTreeSet::new();
+    for entry in collect_status()?.entries {
+        if let Some(diff::ChangeKind::Renamed { from, .. }) = &entry.staged {
+            changed.insert(from.clone());
+        }
+        changed.insert(entry.path);
+    }
+    changed.retain(|path| worktree::matches_pathspec(path, &pathspecs));
+    if changed.is_empty() {
+        println!("No local changes to save");
+        return Ok(());
+    }
+
+    let index = Index::load()?;
+    if index.has_conflicts() {
+        return Err(std::io::Error::other(
+            "could not save index tree\nCannot save the current index state",
+        ));
+    }
+    let index_tree = index.write_tree()?;
+
+    let mut files = index
+        .entries
+        .iter()
+        .map(|entry| (entry.path.clone(), (entry.mode, entry.hash.clone())))
+        .collect::<FileMap>();
+    for entry in &index.entries {
+        if !worktree::matches_pathspec(&entry.path, &pathspecs)
+            || !worktree::is_modified(&index, entry)?
+        {
+            continue;
+        }
+        match fs::symlink_metadata(&entry.path) {
+            Ok(_) => {
+                let (mode, hash, _) = worktree::hash_file(&entry.path, true)?;
+                files.insert(entry.path.clone(), (mode, hash));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                files.remove(&entry.path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let worktree_tree = Tree::write_from_files(&files)?;
+
+    let branch = match refs::read_head()? {
+        Head::Branch(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(_) => String::from("(no branch)"),
+    };
+    let description = format!(
+        "{branch}: {} {}",
+        head.to_short_string(),
+        head_commit.subject()
+    );
+
+    let index_commit = Commit::new(
+        index_tree,
+        vec![head.clone()],
+        Signature::author()?,
+        Signature::committer()?,
+        format!("index on {description}\n"),
+    )
+    .write()?;
+
+    // Unlike other commits, git records the message of the stash itself without a final newline
+    let message = match message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {description}"),
+    };
+    let stash = Commit::new(
+        worktree_tree,
+        vec![head.clone(), index_commit],
+        Signature::author()?,
+        Signature::committer()?,
+        message.clone(),
+    )
+    .write()?;
+
+    let previous = refs::read_ref(STASH_REF)?;
+    refs::update_ref_with_log(STASH_REF, previous.as_ref(), &stash, &message)?;
+    println!("Saved working directory and index state {message}");
+
+    let head_tree = head_commit.tree_hash;
+    if pathspecs == ["."] {
+        return worktree::checkout_tree(Some(&head_tree), &head_tree, true);
+    }
+
+    // Only the selected paths go back to their version in HEAD, in the index and working tree
+    let head_files = Tree::flatten(&head_tree)?;
+    let mut index = Index::load()?;
+    for path in changed {
+        match head_files.get(&path) {
+            Some((mode, hash)) => {
+                let metadata = worktree::write_file(&path, *mode, hash)?;
+                index.add(IndexEntry::from_metadata(
+                    path,
+                    *mode,
+                    hash.clone(),
+                    &metadata,
+                ));
+            }
+            None => {
+                worktree::remove_file(&path)?;
+                index.remove(&path);
+            }
+        }
+    }
+
+    index.write()
+}
+
+/// The entries of the stash, most recent first
+fn list() -> std::io::Result<String> {
+    Ok(refs::read_reflog(STASH_REF)?
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, entry)| format!("stash@{{{i}}}: {}\n", entry.message))
+        .collect())
+}
+
+fn show(revision: &str) -> std::io::Result<String> {
+    let (stash, base, _) = resolve_stash(revision)?;
+
+    diff::diff_stat(
+        &Tree::flatten(&Commit::from_hash(&base).tree_hash)?,
+        &Tree::flatten(&Commit::from_hash(&stash).tree_hash)?,
+    )
+}
+
+/// Merges the changes of a stash entry into the index and working tree, with the tree the entry
+/// was based on as the merge base. The changes end up unstaged, except for new files and unless
+/// `restore_index` asks for the staged changes to be staged again.
+fn apply(revision: &str, restore_index: bool) -> std::io::Result<()> {
+    let (stash, base, index_commit) = resolve_stash(revision)?;
+    let base_tree = Commit::from_hash(&base).tree_hash;
+    let index_tree = Commit::from_hash(&index_commit).tree_hash;
+
+    let index = Index::load()?;
+    if index.has_conflicts() {
+        return Err(std::io::Error::other(
+            "Cannot apply a stash in the middle of a merge",
+        ));
+    }
+    let current_tree = index.write_tree()?;
+
+    let base_files = Tree::flatten(&base_tree)?;
+    let current_files = Tree::flatten(&current_tree)?;
+    let style = ConflictStyle::from_config()?;
+
+    let restored_index = match restore_index && base_tree != index_tree {
+        true => {
+            let labels = MergeLabels {
+                base: String::from("Stash base"),
+                ours: String::from("Updated upstream"),
+                theirs: String::from("Stashed index"),
+            };
+            let merge = merge_trees(
+                &base_files,
+                &current_files,
+                &Tree::flatten(&index_tree)?,
+                &labels,
+                style,
+            )?;
+            if !merge.is_clean() {
+                return Err(std::io::Error::other(
+                    "conflicts in index. Try without --index.",
+                ));
+            }
+            Some(Tree::write_from_files(&merge.files)?)
+        }
+        false => None,
+    };
+
+    let labels = MergeLabels {
+        base: String::from("Stash base"),
+        ours: String::from(if base_tree == current_tree {
+            "Version stash was based on"
+        } else {
+            "Updated upstream"
+        }),
+        theirs: String::from("Stashed changes"),
+    };
+    let merge = merge_trees(
+        &base_files,
+        &current_files,
+        &Tree::flatten(&Commit::from_hash(&stash).tree_hash)?,
+        &labels,
+        style,
+    )?;
+    checkout_merge(&current_files, &merge)?;
+    for message in &merge.messages {
+        println!("{message}");
+    }
+
+    if !merge.is_clean() {
+        if restore_index {
+            eprintln!("Index was not unstashed.");
+        }
+        return Err(std::io::Error::other(
+            "The stash entry is kept in case you need it again.",
+        ));
+    }
+
+    match restored_index {
+        Some(tree) => worktree::reset_paths(Some(&tree), &[String::from(".")])?,
+        None => {
+            let index = Index::load()?;
+            let unstaged = current_files
+                .iter()
+                .filter(|(path, version)| {
+                    index
+                        .get(path)
+                        .map(|entry| (entry.mode, entry.hash.clone()))
+                        .as_ref()
+                        != Some(version)
+                })
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            worktree::reset_paths(Some(&current_tree), &unstaged)?;
+        }
+    }
+
+    print!("{}", status(StatusFormat::Long, false)?);
+
+    Ok(())
+}
+
+fn drop_entry(revision: &str, n: usize) -> std::io::Result<()> {
+    let (stash, _, _) = resolve_stash(revision)?;
+    refs::drop_reflog_entry(STASH_REF, n)?;
+    println!("Dropped {revision} ({stash})");
+
+    Ok(())
+}
+
+/// The revision designating the requested entry, `stash@{n}` being accepted as just `n` and the
+/// most recent entry being the default
+fn stash_revision(stash: Option<String>) -> std::io::Result<String> {
+    match stash {
+        None if refs::read_ref(STASH_REF)?.is_none() => {
+            Err(std::io::Error::other("No stash entries found."))
+        }
+        None => Ok(format!("{STASH_REF}@{{0}}")),
+        Some(n) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => {
+            Ok(format!("{STASH_REF}@{{{n}}}"))
+        }
+        Some(stash) => Ok(stash),
+    }
+}
+
+/// The position in the stash reflog of the entry a revision designates, which must be of the
+/// `stash@{n}` form to be removed from the list
+fn stash_index(revision: &str) -> std::io::Result<usize> {
+    let not_a_stash_ref =
+        || std::io::Error::other(format!("'{revision}' is not a stash reference"));
+    let (name, n) = revision
+        .strip_suffix('}')
+        .and_then(|revision| revision.rsplit_once("@{"))
+        .ok_or_else(not_a_stash_ref)?;
+
+    let name = if name.is_empty() { "HEAD" } else { name };
+    if revision::dwim_ref(name)?.as_deref() != Some(STASH_REF) {
+        return Err(not_a_stash_ref());
+    }
+
+    n.parse().map_err(|_| not_a_stash_ref())
+}
+
+/// The stash commit a revision designates along with its base and index commits
+fn resolve_stash(revision: &str) -> std::io::Result<(Hash, Hash, Hash)> {
+    let stash = revision::resolve_commit(revision)
+        .map_err(|_| std::io::Error::other(format!("{revision} is not a valid reference")))?;
+
+    match Commit::from_hash(&stash).parents.as_slice() {
+        [base, index, ..] => Ok((
+            stash.clone(),
+            base.parent_hash.clone(),
+            index.parent_hash.clone(),
+        )),
+        _ => Err(std::io::Error::other(format!(
+            "'{revision}' is not a stash-like commit"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::add::add;
+    use crate::command::stash::{list, show, stash, StashSubcommand};
+    use crate::test_utils::{
+        copy_git_repository, git, hamachi_git, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+
+    fn commit_file(path: &str, content: &str, message: &str) {
+        fs::write(path, content).unwrap();
+        git(&["add", path]);
+        git(&["commit", "-m", message]);
+    }
+
+    fn make_changes() {
+        fs::write("a.txt", "a changed\n").unwrap();
+        fs::write("b.txt", "b staged\n").unwrap();
+        fs::write("c.txt", "new\n").unwrap();
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn stash_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+
+            commit_file("a.txt", "a\n", "first");
+            commit_file("b.txt", "b\n", "second");
+            let original = git(&["rev-parse", "HEAD"]);
+            copy_git_repository().unwrap();
+
+            // Test
+            make_changes();
+            git(&["add", "b.txt", "c.txt"]);
+            git(&["stash"]);
+            fs::write("a.txt", "a again\n").unwrap();
+            git(&["stash", "push", "-m", "only a", "--", "a.txt"]);
+            let expected_list = git(&["stash", "list", "--format=%H %gd: %gs"]);
+            let expected_show = git(&["stash", "show", "stash@{1}"]);
+            git(&["stash", "pop", "--index", "stash@{1}"]);
+            let expected_status = git(&["status", "--porcelain"]);
+            let expected_index = git(&["ls-files", "--stage"]);
+            let expected_reflog = fs::read_to_string(".git/logs/refs/stash").unwrap();
+            git(&["reset", "--hard", &original]);
+
+            make_changes();
+            add(vec![String::from("b.txt"), String::from("c.txt")], false, false).unwrap();
+            stash(None).unwrap();
+            fs::write("a.txt", "a again\n").unwrap();
+            stash(Some(StashSubcommand::Push { message: Some(String::from("only a")), pathspecs: vec![String::from("a.txt")] })).unwrap();
+            assert_eq!(expected_list, hamachi_git(&["stash", "list", "--format=%H %gd: %gs"]));
+            assert_eq!(hamachi_git(&["stash", "list"]), list().unwrap().trim_end());
+            assert_eq!(expected_show, show("stash@{1}").unwrap().trim());
+            stash(Some(StashSubcommand::Pop { index: true, stash: Some(String::from("stash@{1}")) })).unwrap();
+
+            assert_eq!(expected_status, hamachi_git(&["status", "--porcelain"]));
+            assert_eq!(expected_index, hamachi_git(&["ls-files", "--stage"]));
+            assert_eq!(expected_reflog, fs::read_to_string(".hamachi/logs/refs/stash").unwrap());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
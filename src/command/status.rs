@@ -0,0 +1,625 @@
+use crate::diff::{detect_renames, diff_files, ChangeKind, FileMap};
+use crate::ignore::IgnoreRules;
+use crate::index::Index;
+use crate::object::commit::Commit;
+use crate::object::tree::{Mode, Tree};
+use crate::object::Hash;
+use crate::refs::Head;
+use crate::{graph, refs, worktree};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// Output format of the status command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StatusFormat {
+    Long,
+    Short,
+    PorcelainV1,
+    PorcelainV2,
+}
+
+/// The differences between HEAD, the index and the working tree
+pub(crate) struct Status {
+    pub(crate) head: Head,
+    pub(crate) head_commit: Option<Hash>,
+    pub(crate) upstream: Option<Upstream>,
+    /// Tracked paths with staged, unstaged or conflicting changes, sorted by path
+    pub(crate) entries: Vec<StatusEntry>,
+    pub(crate) untracked: Vec<String>,
+}
+
+/// The branch the current branch tracks, configured by `branch.<name>.remote` and
+/// `branch.<name>.merge`
+pub(crate) struct Upstream {
+    /// Short name, e.g. `origin/master`
+    pub(crate) name: String,
+    /// Commits only on the local branch and only on the upstream, or `None` if the upstream
+    /// branch no longer exists
+    pub(crate) ahead_behind: Option<(usize, usize)>,
+}
+
+pub(crate) struct StatusEntry {
+    pub(crate) path: String,
+    /// Change between HEAD and the index
+    pub(crate) staged: Option<ChangeKind>,
+    /// Change between the index and the working tree
+    pub(crate) unstaged: Option<ChangeKind>,
+    pub(crate) head: Option<(Mode, Hash)>,
+    pub(crate) index: Option<(Mode, Hash)>,
+    pub(crate) worktree_mode: Option<Mode>,
+    /// The base, ours and theirs stages of an unmerged path
+    pub(crate) conflict: Option<[Option<(Mode, Hash)>; 3]>,
+}
+
+impl Status {
+    /// Whether committing now would record any change
+    pub(crate) fn has_staged_changes(&self) -> bool {
+        self.entries.iter().any(|entry| entry.staged.is_some())
+    }
+
+    pub(crate) fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.conflict.is_some())
+    }
+}
+
+impl StatusEntry {
+    fn new(path: String) -> Self {
+        StatusEntry {
+            path,
+            staged: None,
+            unstaged: None,
+            head: None,
+            index: None,
+            worktree_mode: None,
+            conflict: None,
+        }
+    }
+
+    /// The two letter status code of the short format, e.g. `M ` or `UU`
+    fn short_code(&self) -> String {
+        if let Some(stages) = &self.conflict {
+            return match stages.each_ref().map(Option::is_some) {
+                [true, false, false] => "DD",
+                [false, true, false] => "AU",
+                [true, true, false] => "UD",
+                [false, false, true] => "UA",
+                [true, false, true] => "DU",
+                [false, true, true] => "AA",
+                _ => "UU",
+            }
+            .to_string();
+        }
+
+        let letter = |kind: &Option<ChangeKind>| kind.as_ref().map_or(' ', ChangeKind::letter);
+
+        format!("{}{}", letter(&self.staged), letter(&self.unstaged))
+    }
+
+    fn original_path(&self) -> Option<(&str, u8)> {
+        match &self.staged {
+            Some(ChangeKind::Renamed { from, score }) => Some((from, *score)),
+            _ => None,
+        }
+    }
+}
+
+/// Show the working tree status
+/// https://git-scm.com/docs/git-status
+pub(crate) fn status(format: StatusFormat, show_branch: bool) -> std::io::Result<String> {
+    let status = collect_status()?;
+
+    Ok(match format {
+        StatusFormat::Long => format_long(&status),
+        StatusFormat::Short | StatusFormat::PorcelainV1 => format_short(&status, show_branch),
+        StatusFormat::PorcelainV2 => format_porcelain_v2(&status, show_branch),
+    })
+}
+
+/// Compares HEAD, the index and the working tree. Index entries whose file is unchanged but
+/// whose stat information is outdated are refreshed so the next run does not rehash them.
+pub(crate) fn collect_status() -> std::io::Result<Status> {
+    let head = refs::read_head()?;
+    let head_commit = refs::head_commit()?;
+    let mut index = Index::load()?;
+
+    let conflicted = index
+        .entries
+        .iter()
+        .filter(|entry| entry.stage != 0)
+        .map(|entry| entry.path.clone())
+        .collect::<BTreeSet<_>>();
+
+    let mut head_files = match &head_commit {
+        Some(hash) => Tree::flatten(&Commit::from_hash(hash).tree_hash)?,
+        None => BTreeMap::new(),
+    };
+    head_files.retain(|path, _| !conflicted.contains(path));
+    let index_files: FileMap = index
+        .entries
+        .iter()
+        .filter(|entry| entry.stage == 0)
+        .map(|entry| (entry.path.clone(), (entry.mode, entry.hash.clone())))
+        .collect();
+
+    let mut entries = BTreeMap::new();
+    for change in detect_renames(diff_files(&head_files, &index_files))? {
+        let entry = entries
+            .entry(change.path.clone())
+            .or_insert_with(|| StatusEntry::new(change.path));
+        entry.staged = Some(change.kind);
+        entry.head = change.old;
+        entry.index = change.new;
+    }
+
+    let mut refreshed = Vec::new();
+    for (i, index_entry) in index.entries.iter().enumerate() {
+        let metadata = match fs::symlink_metadata(&index_entry.path) {
+            Ok(metadata) if !metadata.is_dir() => Some(metadata),
+            Ok(_) => None,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let worktree_mode = metadata.as_ref().map(Mode::from_metadata);
+
+        if index_entry.stage != 0 {
+            let entry = entries
+                .entry(index_entry.path.clone())
+                .or_insert_with(|| StatusEntry::new(index_entry.path.clone()));
+            entry.worktree_mode = worktree_mode;
+            entry.conflict.get_or_insert([None, None, None])[index_entry.stage as usize - 1] =
+                Some((index_entry.mode, index_entry.hash.clone()));
+            continue;
+        }
+
+        let unstaged = match &metadata {
+            None => Some(ChangeKind::Deleted),
+            Some(metadata) if worktree::is_modified(&index, index_entry)? => {
+                let was_symlink = index_entry.mode == Mode::SYMBOLIC;
+                if (Mode::from_metadata(metadata) == Mode::SYMBOLIC) != was_symlink {
+                    Some(ChangeKind::TypeChanged)
+                } else {
+                    Some(ChangeKind::Modified)
+                }
+            }
+            Some(metadata) => {
+                if !index_entry.matches_stat(metadata) {
+                    refreshed.push((i, metadata.clone()));
+                }
+                None
+            }
+        };
+
+        let has_changes = unstaged.is_some() || entries.contains_key(&index_entry.path);
+        if has_changes {
+            let entry = entries
+                .entry(index_entry.path.clone())
+                .or_insert_with(|| StatusEntry::new(index_entry.path.clone()));
+            entry.unstaged = unstaged;
+            entry.worktree_mode = worktree_mode;
+            entry.index = Some((index_entry.mode, index_entry.hash.clone()));
+            if entry.staged.is_none() {
+                entry.head = entry.index.clone();
+            }
+        }
+    }
+
+    if !refreshed.is_empty() {
+        for (i, metadata) in refreshed {
+            index.entries[i].update_stat(&metadata);
+        }
+
+        // Like git, refreshing is opportunistic and skipped while another process holds the index
+        if let Err(e) = index.write() {
+            if e.kind() != std::io::ErrorKind::AlreadyExists {
+                return Err(e);
+            }
+        }
+    }
+
+    let upstream = match &head {
+        Head::Branch(name) => upstream(name, head_commit.as_ref())?,
+        Head::Detached(_) => None,
+    };
+
+    Ok(Status {
+        head,
+        head_commit,
+        upstream,
+        entries: entries.into_values().collect(),
+        untracked: worktree::untracked_files(&index, &mut IgnoreRules::load()?)?,
+    })
+}
+
+fn upstream(branch_ref: &str, local: Option<&Hash>) -> std::io::Result<Option<Upstream>> {
+    let Some((name, upstream_ref)) = refs::upstream_of(branch_ref)? else {
+        return Ok(None);
+    };
+
+    let ahead_behind = match (local, refs::read_ref(&upstream_ref)?) {
+        (Some(local), Some(upstream)) => Some(graph::ahead_behind(local, &upstream)),
+        (None, Some(upstream)) => Some((0, graph::ancestors(&upstream).len())),
+        (_, None) => None,
+    };
+
+    Ok(Some(Upstream { name, ahead_behind }))
+}
+
+fn format_short(status: &Status, show_branch: bool) -> String {
+    let mut output = String::new();
+
+    if show_branch {
+        output.push_str("## ");
+        match (&status.head, &status.head_commit) {
+            (Head::Detached(_), _) => output.push_str("HEAD (no branch)"),
+            (Head::Branch(name), None) => {
+                output.push_str(&format!("No commits yet on {}", short_branch_name(name)))
+            }
+            (Head::Branch(name), Some(_)) => output.push_str(short_branch_name(name)),
+        }
+
+        if let Some(upstream) = &status.upstream {
+            output.push_str(&format!("...{}", upstream.name));
+            match upstream.ahead_behind {
+                None => output.push_str(" [gone]"),
+                Some((0, 0)) => {}
+                Some((ahead, 0)) => output.push_str(&format!(" [ahead {ahead}]")),
+                Some((0, behind)) => output.push_str(&format!(" [behind {behind}]")),
+                Some((ahead, behind)) => {
+                    output.push_str(&format!(" [ahead {ahead}, behind {behind}]"))
+                }
+            }
+        }
+        output.push('\n');
+    }
+
+    for entry in &status.entries {
+        match entry.original_path() {
+            Some((from, _)) => output.push_str(&format!(
+                "{} {} -> {}\n",
+                entry.short_code(),
+                quote_path(from, true),
+                quote_path(&entry.path, true)
+            )),
+            None => output.push_str(&format!(
+                "{} {}\n",
+                entry.short_code(),
+                quote_path(&entry.path, true)
+            )),
+        }
+    }
+    for path in &status.untracked {
+        output.push_str(&format!("?? {}\n", quote_path(path, true)));
+    }
+
+    output
+}
+
+/// The porcelain v2 format, which also includes the modes and hashes of every changed path
+/// https://git-scm.com/docs/git-status#_porcelain_format_version_2
+fn format_porcelain_v2(status: &Status, show_branch: bool) -> String {
+    let mut output = String::new();
+
+    if show_branch {
+        match &status.head_commit {
+            Some(hash) => output.push_str(&format!("# branch.oid {hash}\n")),
+            None => output.push_str("# branch.oid (initial)\n"),
+        }
+        match &status.head {
+            Head::Branch(name) => {
+                output.push_str(&format!("# branch.head {}\n", short_branch_name(name)))
+            }
+            Head::Detached(_) => output.push_str("# branch.head (detached)\n"),
+        }
+        if let Some(upstream) = &status.upstream {
+            output.push_str(&format!("# branch.upstream {}\n", upstream.name));
+            if let Some((ahead, behind)) = upstream.ahead_behind {
+                output.push_str(&format!("# branch.ab +{ahead} -{behind}\n"));
+            }
+        }
+    }
+
+    let mode = |mode: Option<Mode>| format!("{:06o}", mode.map_or(0, Mode::to_octal));
+    let hash = |entry: &Option<(Mode, Hash)>| match entry {
+        Some((_, hash)) => hash.to_string(),
+        None => "0".repeat(40),
+    };
+
+    for entry in &status.entries {
+        let code = entry.short_code().replace(' ', ".");
+
+        if let Some(stages) = &entry.conflict {
+            output.push_str(&format!(
+                "u {code} N... {} {} {} {} {} {} {} {}\n",
+                mode(stages[0].as_ref().map(|stage| stage.0)),
+                mode(stages[1].as_ref().map(|stage| stage.0)),
+                mode(stages[2].as_ref().map(|stage| stage.0)),
+                mode(entry.worktree_mode),
+                hash(&stages[0]),
+                hash(&stages[1]),
+                hash(&stages[2]),
+                quote_path(&entry.path, false)
+            ));
+            continue;
+        }
+
+        let fields = format!(
+            "{code} N... {} {} {} {} {}",
+            mode(entry.head.as_ref().map(|head| head.0)),
+            mode(entry.index.as_ref().map(|index| index.0)),
+            mode(entry.worktree_mode),
+            hash(&entry.head),
+            hash(&entry.index)
+        );
+        match entry.original_path() {
+            Some((from, score)) => output.push_str(&format!(
+                "2 {fields} R{score} {}\t{}\n",
+                quote_path(&entry.path, false),
+                quote_path(from, false)
+            )),
+            None => output.push_str(&format!("1 {fields} {}\n", quote_path(&entry.path, false))),
+        }
+    }
+    for path in &status.untracked {
+        output.push_str(&format!("? {}\n", quote_path(path, false)));
+    }
+
+    output
+}
+
+fn format_long(status: &Status) -> String {
+    let mut output = String::new();
+
+    match &status.head {
+        Head::Branch(name) => output.push_str(&format!("On branch {}\n", short_branch_name(name))),
+        Head::Detached(hash) => {
+            output.push_str(&format!("HEAD detached at {}\n", hash.to_short_string()))
+        }
+    }
+
+    if let Some(upstream) = &status.upstream {
+        let name = &upstream.name;
+        let plural = |count: usize| if count == 1 { "commit" } else { "commits" };
+
+        match upstream.ahead_behind {
+            None => output.push_str(&format!(
+                "Your branch is based on '{name}', but the upstream is gone.\n"
+            )),
+            Some((0, 0)) => {
+                output.push_str(&format!("Your branch is up to date with '{name}'.\n"))
+            }
+            Some((ahead, 0)) => output.push_str(&format!(
+                "Your branch is ahead of '{name}' by {ahead} {}.\n  (use \"hamachi push\" to publish your local commits)\n",
+                plural(ahead)
+            )),
+            Some((0, behind)) => output.push_str(&format!(
+                "Your branch is behind '{name}' by {behind} {}, and can be fast-forwarded.\n  (use \"hamachi pull\" to update your local branch)\n",
+                plural(behind)
+            )),
+            Some((ahead, behind)) => output.push_str(&format!(
+                "Your branch and '{name}' have diverged,\nand have {ahead} and {behind} different commits each, respectively.\n  (use \"hamachi pull\" to merge the remote branch into yours)\n"
+            )),
+        }
+        output.push('\n');
+    }
+
+    if status.head_commit.is_none() {
+        output.push_str("\nNo commits yet\n\n");
+    }
+
+    let staged = status
+        .entries
+        .iter()
+        .filter_map(|entry| Some((entry, entry.staged.as_ref()?)))
+        .collect::<Vec<_>>();
+    if !staged.is_empty() {
+        output.push_str("Changes to be committed:\n");
+        output.push_str("  (use \"hamachi restore --staged <file>...\" to unstage)\n");
+        for (entry, kind) in staged {
+            let path = match entry.original_path() {
+                Some((from, _)) => format!("{from} -> {}", entry.path),
+                None => entry.path.clone(),
+            };
+            output.push_str(&format!("\t{:<12}{path}\n", change_label(kind)));
+        }
+        output.push('\n');
+    }
+
+    if status.has_conflicts() {
+        output.push_str("Unmerged paths:\n");
+        output.push_str("  (use \"hamachi add <file>...\" to mark resolution)\n");
+        for entry in status
+            .entries
+            .iter()
+            .filter(|entry| entry.conflict.is_some())
+        {
+            let label = match entry.short_code().as_str() {
+                "DD" => "both deleted:",
+                "AU" => "added by us:",
+                "UD" => "deleted by them:",
+                "UA" => "added by them:",
+                "DU" => "deleted by us:",
+                "AA" => "both added:",
+                _ => "both modified:",
+            };
+            output.push_str(&format!("\t{label:<17}{}\n", entry.path));
+        }
+        output.push('\n');
+    }
+
+    let unstaged = status
+        .entries
+        .iter()
+        .filter_map(|entry| Some((entry, entry.unstaged.as_ref()?)))
+        .collect::<Vec<_>>();
+    if !unstaged.is_empty() {
+        output.push_str("Changes not staged for commit:\n");
+        output.push_str("  (use \"hamachi add <file>...\" to update what will be committed)\n");
+        output.push_str(
+            "  (use \"hamachi restore <file>...\" to discard changes in working directory)\n",
+        );
+        for (entry, kind) in &unstaged {
+            output.push_str(&format!("\t{:<12}{}\n", change_label(kind), entry.path));
+        }
+        output.push('\n');
+    }
+
+    if !status.untracked.is_empty() {
+        output.push_str("Untracked files:\n");
+        output.push_str("  (use \"hamachi add <file>...\" to include in what will be committed)\n");
+        for path in &status.untracked {
+            output.push_str(&format!("\t{path}\n"));
+        }
+        output.push('\n');
+    }
+
+    if !status.has_staged_changes() && !status.has_conflicts() {
+        if !unstaged.is_empty() {
+            output.push_str(
+                "no changes added to commit (use \"hamachi add\" and/or \"hamachi commit -a\")\n",
+            );
+        } else if !status.untracked.is_empty() {
+            output.push_str(
+                "nothing added to commit but untracked files present (use \"hamachi add\" to track)\n",
+            );
+        } else if status.head_commit.is_none() {
+            output.push_str(
+                "nothing to commit (create/copy files and use \"hamachi add\" to track)\n",
+            );
+        } else {
+            output.push_str("nothing to commit, working tree clean\n");
+        }
+    }
+
+    output
+}
+
+fn change_label(kind: &ChangeKind) -> &'static str {
+    match kind {
+        ChangeKind::Added => "new file:",
+        ChangeKind::Deleted => "deleted:",
+        ChangeKind::Modified => "modified:",
+        ChangeKind::TypeChanged => "typechange:",
+        ChangeKind::Renamed { .. } => "renamed:",
+    }
+}
+
+fn short_branch_name(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+/// Quotes a path the way git does in its machine readable output, as a C string literal if it
+/// contains quotes, backslashes, control or non ASCII characters. Formats that separate fields
+/// with spaces also quote paths containing spaces.
+pub(crate) fn quote_path(path: &str, quote_spaces: bool) -> String {
+    let needs_quoting = path.bytes().any(|c| {
+        c == b'"' || c == b'\\' || (c == b' ' && quote_spaces) || !(0x20..0x7f).contains(&c)
+    });
+    if !needs_quoting {
+        return path.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in path.bytes() {
+        match c {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            c if !(0x20..0x7f).contains(&c) => quoted.push_str(&format!("\\{c:03o}")),
+            c => quoted.push(c as char),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::status::{status, StatusFormat};
+    use crate::test_utils::{
+        copy_git_repository, run_git_command, setup_test_environment, teardown,
+    };
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::process::Command;
+
+    rusty_fork_test! {
+        #[test]
+        fn status_porcelain_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            fs::write("unchanged.txt", "unchanged\n").unwrap();
+            fs::write("staged.txt", "first version\n").unwrap();
+            fs::write("unstaged.txt", "first version\n").unwrap();
+            fs::write("deleted.txt", "to be deleted\n").unwrap();
+            fs::write("renamed.txt", "a file\nwith a few\nlines in it\nto be renamed\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+            run_git_command(Command::new("git").arg("commit").arg("-m").arg("first")).unwrap();
+
+            fs::write("staged.txt", "second version\n").unwrap();
+            fs::write("new.txt", "new file\n").unwrap();
+            run_git_command(Command::new("git").arg("add").arg("staged.txt").arg("new.txt")).unwrap();
+            run_git_command(Command::new("git").arg("mv").arg("renamed.txt").arg("moved file.txt")).unwrap();
+            fs::write("unstaged.txt", "second version\n").unwrap();
+            fs::remove_file("deleted.txt").unwrap();
+            fs::create_dir("untracked").unwrap();
+            fs::write("untracked/file.txt", "untracked\n").unwrap();
+            fs::write("untracked.txt", "untracked\n").unwrap();
+
+            copy_git_repository().unwrap();
+
+            // Test
+            for (format, git_format) in [
+                (StatusFormat::PorcelainV1, "--porcelain=v1"),
+                (StatusFormat::PorcelainV2, "--porcelain=v2"),
+            ] {
+                let expected = run_git_command(Command::new("git").arg("status").arg(git_format)).unwrap();
+                let actual = status(format, false).unwrap();
+
+                assert_eq!(expected, actual.trim());
+            }
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn status_branch_tracking_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            for i in 0..3 {
+                fs::write("test.txt", format!("version {i}\n")).unwrap();
+                run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+                run_git_command(Command::new("git").arg("commit").arg("-m").arg(format!("commit {i}"))).unwrap();
+            }
+            run_git_command(Command::new("git").arg("update-ref").arg("refs/remotes/origin/master").arg("HEAD~2")).unwrap();
+            let config = [
+                ("remote.origin.url", "https://example.com/repository.git"),
+                ("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*"),
+                ("branch.master.remote", "origin"),
+                ("branch.master.merge", "refs/heads/master"),
+            ];
+            for (key, value) in config {
+                run_git_command(Command::new("git").arg("config").arg(key).arg(value)).unwrap();
+                run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").arg("config").arg(key).arg(value)).unwrap();
+            }
+
+            copy_git_repository().unwrap();
+
+            // Test
+            let expected = run_git_command(Command::new("git").arg("status").arg("--porcelain=v2").arg("--branch")).unwrap();
+            let actual = status(StatusFormat::PorcelainV2, true).unwrap();
+            assert_eq!(expected, actual.trim());
+
+            let expected = run_git_command(Command::new("git").arg("status").arg("--porcelain").arg("--branch")).unwrap();
+            let actual = status(StatusFormat::PorcelainV1, true).unwrap();
+            assert_eq!(expected, actual.trim());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
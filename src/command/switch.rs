@@ -0,0 +1,104 @@
+use crate::command::branch::{create_branch, validate_new_branch};
+use crate::object::commit::Commit;
+use crate::object::Hash;
+use crate::refs::revision;
+use crate::refs::Head;
+use crate::{refs, worktree};
+
+/// Switch to a branch, or to a detached commit
+/// https://git-scm.com/docs/git-switch
+pub(crate) fn switch(
+    branch: Option<String>,
+    detach: bool,
+    force: bool,
+    new_branch: Option<String>,
+    reset_branch: bool,
+) -> std::io::Result<()> {
+    if let Some(new_branch) = new_branch {
+        return switch_to_new_branch(&new_branch, branch.as_deref(), reset_branch, force);
+    }
+
+    match (branch, detach) {
+        (Some(target), true) => switch_to_commit(&revision::resolve_commit(&target)?, force),
+        (None, true) => switch_to_commit(&revision::resolve_commit("HEAD")?, force),
+        (Some(branch), false) => {
+            if !refs::ref_exists(&format!("refs/heads/{branch}"))? {
+                return Err(std::io::Error::other(format!(
+                    "invalid reference: {branch}"
+                )));
+            }
+
+            switch_to_branch(&branch, force)
+        }
+        (None, false) => Err(std::io::Error::other("missing branch or commit argument")),
+    }
+}
+
+/// Checks out the tip of a local branch and attaches HEAD to it
+pub(crate) fn switch_to_branch(branch: &str, force: bool) -> std::io::Result<()> {
+    let ref_name = format!("refs/heads/{branch}");
+    if refs::read_head()? == Head::Branch(ref_name.clone()) {
+        println!("Already on '{branch}'");
+        return Ok(());
+    }
+
+    let commit = refs::read_ref(&ref_name)?
+        .ok_or_else(|| std::io::Error::other(format!("invalid reference: {branch}")))?;
+
+    move_head(Head::Branch(ref_name), &commit, force)?;
+    println!("Switched to branch '{branch}'");
+
+    Ok(())
+}
+
+/// Creates a branch at the start point, HEAD by default, then checks it out. With `reset`, an
+/// existing branch is moved to the start point instead.
+pub(crate) fn switch_to_new_branch(
+    branch: &str,
+    start_point: Option<&str>,
+    reset: bool,
+    force: bool,
+) -> std::io::Result<()> {
+    let ref_name = validate_new_branch(branch, reset)?;
+    let existed = refs::ref_exists(&ref_name)?;
+
+    let start = revision::resolve_commit(start_point.unwrap_or("HEAD"))?;
+    let current_tree = refs::head_commit()?.map(|hash| Commit::from_hash(&hash).tree_hash);
+    let target_tree = Commit::from_hash(&start).tree_hash;
+    worktree::checkout_tree(current_tree.as_ref(), &target_tree, force)?;
+
+    print!("{}", create_branch(branch, start_point, reset, None)?);
+    refs::write_head(&Head::Branch(ref_name))?;
+
+    if existed {
+        println!("Switched to and reset branch '{branch}'");
+    } else {
+        println!("Switched to a new branch '{branch}'");
+    }
+
+    Ok(())
+}
+
+/// Checks out a commit and detaches HEAD at it
+pub(crate) fn switch_to_commit(commit: &Hash, force: bool) -> std::io::Result<()> {
+    move_head(Head::Detached(commit.clone()), commit, force)?;
+
+    println!(
+        "HEAD is now at {} {}",
+        commit.to_short_string(),
+        Commit::from_hash(commit).subject()
+    );
+
+    Ok(())
+}
+
+/// Updates the index and working tree from the current HEAD commit to the target commit,
+/// then points HEAD to its new value
+fn move_head(target_head: Head, commit: &Hash, force: bool) -> std::io::Result<()> {
+    let current_tree = refs::head_commit()?.map(|hash| Commit::from_hash(&hash).tree_hash);
+    let target_tree = Commit::from_hash(commit).tree_hash;
+
+    worktree::checkout_tree(current_tree.as_ref(), &target_tree, force)?;
+
+    refs::write_head(&target_head)
+}
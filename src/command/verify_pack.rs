@@ -0,0 +1,130 @@
+use crate::object::packfile::idx::{self, IndexEntry};
+use crate::object::packfile::PackFile;
+use clap::Args;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Args, Debug, Default)]
+pub(crate) struct VerifyPackArgs {
+    /// Show the objects of the pack, and a histogram of the length of its delta chains
+    #[clap(short = 'v', long)]
+    verbose: bool,
+
+    /// The packs to verify, given by their `.idx` or `.pack` file
+    #[clap(required = true)]
+    packs: Vec<String>,
+}
+
+/// Validate packs against their index: the checksums of both files, and the hash, offset and
+/// CRC32 of every object, whose deltas are all resolved
+/// https://git-scm.com/docs/git-verify-pack
+pub(crate) fn verify_pack(args: VerifyPackArgs) -> std::io::Result<String> {
+    let mut output = String::new();
+    for path in args.packs {
+        let name = path
+            .strip_suffix(".idx")
+            .or_else(|| path.strip_suffix(".pack"))
+            .unwrap_or(&path);
+        let pack_path = format!("{name}.pack");
+
+        let index = idx::read(Path::new(&format!("{name}.idx")))?;
+        let pack = PackFile::index(Path::new(&pack_path))?;
+        let mut entries = pack
+            .entries
+            .iter()
+            .map(|entry| IndexEntry {
+                hash: entry.hash.clone(),
+                crc32: entry.crc32,
+                offset: entry.offset,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+        if index.pack_hash != pack.hash || index.entries != entries {
+            return Err(std::io::Error::other(format!(
+                "packfile {pack_path} does not match index"
+            )));
+        }
+
+        if args.verbose {
+            let mut whole_objects = 0;
+            let mut chain_lengths = BTreeMap::new();
+            for entry in &pack.entries {
+                output.push_str(&format!(
+                    "{} {:<6} {} {} {}",
+                    entry.hash,
+                    entry.object_type.to_string(),
+                    entry.size,
+                    entry.packed_size,
+                    entry.offset
+                ));
+                match &entry.base {
+                    Some(base) => {
+                        output.push_str(&format!(" {} {base}", entry.depth));
+                        *chain_lengths.entry(entry.depth).or_insert(0) += 1;
+                    }
+                    None => whole_objects += 1,
+                }
+                output.push('\n');
+            }
+
+            output.push_str(&format!("non delta: {}\n", objects(whole_objects)));
+            for (length, count) in chain_lengths {
+                output.push_str(&format!("chain length = {length}: {}\n", objects(count)));
+            }
+            output.push_str(&format!("{pack_path}: ok\n"));
+        }
+    }
+
+    Ok(output)
+}
+
+fn objects(count: usize) -> String {
+    match count {
+        1 => String::from("1 object"),
+        count => format!("{count} objects"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::verify_pack::{verify_pack, VerifyPackArgs};
+    use crate::test_utils::{git, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+
+    rusty_fork_test! {
+        #[test]
+        fn verify_pack_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            std::env::set_var("GIT_AUTHOR_DATE", "1700000000 +0100");
+            std::env::set_var("GIT_COMMITTER_DATE", "1700000000 +0100");
+            let lines = (1..=200).map(|i| format!("line {i}\n")).collect::<String>();
+            for i in 0..3 {
+                fs::write("b.txt", format!("{lines}{}", "more\n".repeat(i))).unwrap();
+                git(&["add", "b.txt"]);
+                git(&["commit", "-m", &format!("commit {i}")]);
+            }
+            git(&["repack", "-adq"]);
+            let pack = fs::read_dir(".git/objects/pack").unwrap().flatten().map(|entry| entry.path()).find(|path| path.extension().unwrap() == "pack").unwrap();
+            let idx = pack.with_extension("idx").to_str().unwrap().to_string();
+
+            // Test
+            let expected = git(&["verify-pack", "-v", &idx]);
+            let actual = verify_pack(VerifyPackArgs { verbose: true, packs: vec![idx.clone()] }).unwrap();
+            assert_eq!(format!("{expected}\n"), actual);
+            assert_eq!(verify_pack(VerifyPackArgs { verbose: false, packs: vec![idx.clone()] }).unwrap(), "");
+
+            // A corrupted object fails the verification
+            let mut data = fs::read(&pack).unwrap();
+            let middle = data.len() / 2;
+            data[middle] ^= 0xff;
+            fs::write("copy.pack", data).unwrap();
+            fs::write("copy.idx", fs::read(&idx).unwrap()).unwrap();
+            assert!(verify_pack(VerifyPackArgs { verbose: false, packs: vec![String::from("copy.pack")] }).is_err());
+
+            std::env::set_current_dir(&repo).unwrap();
+            teardown(repo).unwrap();
+        }
+    }
+}
@@ -1,46 +1,47 @@
-use crate::command::hash_object::hash_object;
-use crate::object::tree::{Entry, Mode};
+use crate::ignore::IgnoreRules;
+use crate::object::tree::{Entry, Mode, Tree};
 use crate::object::{Hash, Object, ObjectType};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use sha1::{Digest, Sha1};
-use std::fs;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use crate::worktree;
+use std::path::PathBuf;
 
 pub(crate) fn write_tree(path_buf: Option<PathBuf>) -> std::io::Result<Hash> {
-    let path = path_buf.unwrap_or(PathBuf::from("."));
+    let directory = worktree::normalize_path(&path_buf.unwrap_or_default().to_string_lossy());
 
-    let paths_iter = fs::read_dir(&path)?;
-    let paths = paths_iter
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .collect::<Vec<_>>();
+    write_directory(&directory, &mut IgnoreRules::load()?)
+}
 
+fn write_directory(directory: &str, ignore: &mut IgnoreRules) -> std::io::Result<Hash> {
     let mut entries = Vec::new();
-    for path in paths {
-        let metadata = fs::metadata(&path)?;
+    for (path, metadata) in worktree::read_directory(directory)? {
+        if ignore.is_ignored(&path, metadata.is_dir())? {
+            continue;
+        }
+        let filename = path
+            .rsplit_once('/')
+            .map_or(path.as_str(), |(_, name)| name)
+            .to_string();
 
-        if metadata.is_file() {
-            let hash: Hash = hash_object(true, &path)?;
+        if !metadata.is_dir() {
+            let (mode, hash, _) = worktree::hash_file(&path, true)?;
 
             let entry = Entry {
-                mode: Mode::REGULAR,
-                filename: path.file_name().unwrap().to_string_lossy().to_string(),
+                mode,
+                filename,
                 object_type: ObjectType::BLOB,
                 hash,
             };
             entries.push(entry);
         } else {
-            if &path == &PathBuf::from("./.git") || &path == Path::new("./.hamachi") {
+            let hash = write_directory(&path, ignore)?;
+
+            // Git does not track empty directories
+            if hash == Object::hash(ObjectType::TREE, &[]) {
                 continue;
             }
 
-            let hash = write_tree(Some(PathBuf::from(&path)))?;
-
             let entry = Entry {
                 mode: Mode::DIRECTORY,
-                filename: path.file_name().unwrap().to_string_lossy().to_string(),
+                filename,
                 object_type: ObjectType::TREE,
                 hash,
             };
@@ -48,39 +49,11 @@ pub(crate) fn write_tree(path_buf: Option<PathBuf>) -> std::io::Result<Hash> {
         }
     }
 
-    let mut entry_byte_vectors = Vec::new();
-    for mut entry in entries {
-        let mut entry_bytes = format!("{} {}\0", entry.mode as u32, entry.filename)
-            .as_bytes()
-            .to_vec();
-        entry_bytes.append(&mut entry.hash.0);
-
-        entry_byte_vectors.push(entry_bytes);
-    }
-
-    let entries_section = entry_byte_vectors
-        .into_iter()
-        .flatten()
-        .collect::<Vec<u8>>();
-    let header = format!("tree {}\0", entries_section.len())
-        .as_bytes()
-        .to_vec();
-
-    let mut hasher = Sha1::new();
-    Digest::update(&mut hasher, &header);
-    Digest::update(&mut hasher, &entries_section);
-    let hash = Hash(hasher.finalize().as_slice().to_vec());
-
-    let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
-    compressor.write_all(&header)?;
-    compressor.write_all(&entries_section)?;
-    let compressed_bytes = compressor.finish()?;
-
-    Object::write_to_disk(&hash, &compressed_bytes)?;
-
-    Ok(hash)
+    Tree { entries }.write()
 }
+
 #[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
     use crate::command::write_tree::write_tree;
     use crate::object::Object;
@@ -152,4 +125,28 @@ mod tests {
             teardown(repo).unwrap();
         }
     }
+
+    rusty_fork_test! {
+        #[test]
+        fn write_tree_order_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+
+            // A directory sorts as if its name ended with a slash, after "foo.txt" and "foo-bar"
+            for file in ["foo.txt", "foo-bar", "Zeta", "foo/a.txt", "foo/b/c.txt"] {
+                fs::create_dir_all(PathBuf::from(file).parent().unwrap()).ok();
+                fs::write(file, file).unwrap();
+            }
+
+            run_git_command(Command::new("git").arg("add").arg(".")).unwrap();
+
+            // Test
+            let expected_tree_hash = run_git_command(Command::new("git").arg("write-tree")).unwrap();
+            let actual_tree_hash = write_tree(None).unwrap().to_string();
+
+            assert_eq!(expected_tree_hash, actual_tree_hash);
+
+            teardown(repo).unwrap();
+        }
+    }
 }
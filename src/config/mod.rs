@@ -0,0 +1,170 @@
+use ini::{EscapePolicy, Ini, ParseOption, WriteOption};
+use std::path::PathBuf;
+
+const CONFIG_PATH: &str = ".hamachi/config";
+
+/// The repository configuration, stored in `.hamachi/config` in the git config format.
+/// Keys are written the way git spells them on the command line, e.g. `core.excludesFile` or
+/// `branch.master.remote`, where the middle part is the subsection.
+/// https://git-scm.com/docs/git-config
+pub(crate) struct Config {
+    ini: Ini,
+    path: PathBuf,
+}
+
+impl Config {
+    pub(crate) fn load() -> std::io::Result<Self> {
+        Self::load_from(PathBuf::from(CONFIG_PATH))
+    }
+
+    fn load_from(path: PathBuf) -> std::io::Result<Self> {
+        let ini = if path.is_file() {
+            let options = ParseOption {
+                enabled_quote: true,
+                enabled_escape: false,
+            };
+            Ini::load_from_file_opt(&path, options)
+                .map_err(|e| std::io::Error::other(format!("bad config file: {e}")))?
+        } else {
+            Ini::new()
+        };
+
+        Ok(Config { ini, path })
+    }
+
+    /// The last value of a key, as git returns it when a key is set several times
+    pub(crate) fn get(&self, key: &str) -> Option<String> {
+        self.get_all(key).pop()
+    }
+
+    /// Every value of a multi-valued key such as `remote.origin.fetch`
+    pub(crate) fn get_all(&self, key: &str) -> Vec<String> {
+        let Some((section, name)) = parse_key(key) else {
+            return Vec::new();
+        };
+
+        self.ini
+            .section_all(Some(section.as_str()))
+            .flat_map(|properties| {
+                properties
+                    .iter()
+                    .filter(|(property, _)| property.eq_ignore_ascii_case(&name))
+                    .map(|(_, value)| value.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Sets a key, replacing all of its current values
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> std::io::Result<()> {
+        let (section, name) = parse_key(key).ok_or_else(|| invalid_key(key))?;
+
+        self.unset(key)?;
+        self.ini.with_section(Some(section)).set(name, value);
+
+        Ok(())
+    }
+
+    pub(crate) fn unset(&mut self, key: &str) -> std::io::Result<()> {
+        let (section, name) = parse_key(key).ok_or_else(|| invalid_key(key))?;
+
+        for properties in self.ini.section_all_mut(Some(section.as_str())) {
+            let matching = properties
+                .iter()
+                .map(|(property, _)| property.to_string())
+                .filter(|property| property.eq_ignore_ascii_case(&name))
+                .collect::<Vec<_>>();
+            for property in matching {
+                properties.remove_all(property).for_each(drop);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes a whole section, e.g. `branch.master`
+    pub(crate) fn remove_section(&mut self, name: &str) {
+        let section = section_header(name);
+
+        while self.ini.delete(Some(section.as_str())).is_some() {}
+    }
+
+    /// Moves every key of a section to another section, e.g. from `branch.old` to `branch.new`
+    pub(crate) fn rename_section(&mut self, old: &str, new: &str) -> std::io::Result<()> {
+        let old = section_header(old);
+        let new = section_header(new);
+
+        let properties = self
+            .ini
+            .section_all(Some(old.as_str()))
+            .flat_map(|properties| properties.iter())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        if self.ini.delete(Some(old.as_str())).is_none() {
+            return Err(std::io::Error::other("no such section"));
+        }
+
+        for (name, value) in properties {
+            self.ini.with_section(Some(new.as_str())).add(name, value);
+        }
+
+        Ok(())
+    }
+
+    /// Every key and value, in the `section.subsection.name=value` form of `git config --list`
+    pub(crate) fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        for (section, properties) in self.ini.iter() {
+            let Some(section) = section else {
+                continue;
+            };
+            let prefix = match section.split_once(' ') {
+                Some((name, subsection)) => format!("{name}.{}", subsection.trim_matches('"')),
+                None => section.to_string(),
+            };
+
+            for (name, value) in properties.iter() {
+                entries.push((
+                    format!("{prefix}.{}", name.to_ascii_lowercase()),
+                    value.to_string(),
+                ));
+            }
+        }
+
+        entries
+    }
+
+    pub(crate) fn write(&self) -> std::io::Result<()> {
+        let options = WriteOption {
+            escape_policy: EscapePolicy::Nothing,
+            ..WriteOption::default()
+        };
+
+        self.ini.write_to_file_opt(&self.path, options)
+    }
+}
+
+/// Splits `section.subsection.name` into the ini section header and the variable name
+fn parse_key(key: &str) -> Option<(String, String)> {
+    let (section, rest) = key.split_once('.')?;
+    let section = section.to_ascii_lowercase();
+
+    match rest.rsplit_once('.') {
+        Some((subsection, name)) => Some((format!("{section} \"{subsection}\""), name.to_string())),
+        None => Some((section, rest.to_string())),
+    }
+}
+
+/// The ini section header of a section name such as `branch.master`
+fn section_header(name: &str) -> String {
+    match name.split_once('.') {
+        Some((section, subsection)) => {
+            format!("{} \"{subsection}\"", section.to_ascii_lowercase())
+        }
+        None => name.to_ascii_lowercase(),
+    }
+}
+
+fn invalid_key(key: &str) -> std::io::Error {
+    std::io::Error::other(format!("invalid key: {key}"))
+}
@@ -0,0 +1,466 @@
+use crate::merge::text::is_binary;
+use crate::object::tree::Mode;
+use crate::object::{Hash, Object};
+use std::collections::{BTreeMap, HashMap};
+
+/// A flat listing of files, keyed by repository relative path
+pub(crate) type FileMap = BTreeMap<String, (Mode, Hash)>;
+
+/// Minimum similarity, in percent, for a deleted and an added file to be paired as a rename
+pub(crate) const RENAME_THRESHOLD: u8 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    /// The file changed between a regular file and a symbolic link
+    TypeChanged,
+    Renamed {
+        from: String,
+        score: u8,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+    pub(crate) path: String,
+    pub(crate) kind: ChangeKind,
+    pub(crate) old: Option<(Mode, Hash)>,
+    pub(crate) new: Option<(Mode, Hash)>,
+}
+
+impl ChangeKind {
+    /// The single letter status git uses for the change
+    pub(crate) fn letter(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Deleted => 'D',
+            ChangeKind::Modified => 'M',
+            ChangeKind::TypeChanged => 'T',
+            ChangeKind::Renamed { .. } => 'R',
+        }
+    }
+}
+
+/// Lists the files that differ between two file listings, sorted by path
+pub(crate) fn diff_files(old: &FileMap, new: &FileMap) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (path, old_entry) in old {
+        match new.get(path) {
+            None => changes.push(Change {
+                path: path.clone(),
+                kind: ChangeKind::Deleted,
+                old: Some(old_entry.clone()),
+                new: None,
+            }),
+            Some(new_entry) if new_entry != old_entry => changes.push(Change {
+                path: path.clone(),
+                kind: if is_symlink(old_entry.0) != is_symlink(new_entry.0) {
+                    ChangeKind::TypeChanged
+                } else {
+                    ChangeKind::Modified
+                },
+                old: Some(old_entry.clone()),
+                new: Some(new_entry.clone()),
+            }),
+            _ => {}
+        }
+    }
+
+    for (path, new_entry) in new {
+        if !old.contains_key(path) {
+            changes.push(Change {
+                path: path.clone(),
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(new_entry.clone()),
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    changes
+}
+
+/// Pairs deleted and added files into renames, exact matches first and then by content
+/// similarity, keeping the best scoring source for every destination
+pub(crate) fn detect_renames(changes: Vec<Change>) -> std::io::Result<Vec<Change>> {
+    let (mut deleted, mut others): (Vec<_>, Vec<_>) = changes
+        .into_iter()
+        .partition(|change| change.kind == ChangeKind::Deleted);
+    let mut added = Vec::new();
+    others.retain(|change| {
+        if change.kind == ChangeKind::Added {
+            added.push(change.clone());
+            false
+        } else {
+            true
+        }
+    });
+
+    let mut renames = Vec::new();
+
+    // Exact renames only need the hashes
+    added.retain(|destination| {
+        let new = destination.new.as_ref().unwrap();
+        let source = deleted
+            .iter()
+            .position(|source| source.old.as_ref().unwrap().1 == new.1);
+
+        match source {
+            Some(i) => {
+                let source = deleted.remove(i);
+                renames.push(rename(source, destination.clone(), 100));
+                false
+            }
+            None => true,
+        }
+    });
+
+    // Inexact renames compare the content of every remaining pair
+    if !deleted.is_empty() && !added.is_empty() {
+        let mut sources = Vec::new();
+        for source in &deleted {
+            let hash = &source.old.as_ref().unwrap().1;
+            sources.push(Object::read(hash)?.1);
+        }
+
+        let mut candidates = Vec::new();
+        for (destination_index, destination) in added.iter().enumerate() {
+            let content = Object::read(&destination.new.as_ref().unwrap().1)?.1;
+            for (source_index, source) in sources.iter().enumerate() {
+                let score = similarity(source, &content);
+                if score >= RENAME_THRESHOLD {
+                    candidates.push((score, source_index, destination_index));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut used_sources = vec![false; deleted.len()];
+        let mut used_destinations = vec![false; added.len()];
+        for (score, source_index, destination_index) in candidates {
+            if used_sources[source_index] || used_destinations[destination_index] {
+                continue;
+            }
+            used_sources[source_index] = true;
+            used_destinations[destination_index] = true;
+
+            renames.push(rename(
+                deleted[source_index].clone(),
+                added[destination_index].clone(),
+                score,
+            ));
+        }
+
+        deleted = deleted
+            .into_iter()
+            .zip(used_sources)
+            .filter(|(_, used)| !used)
+            .map(|(change, _)| change)
+            .collect();
+        added = added
+            .into_iter()
+            .zip(used_destinations)
+            .filter(|(_, used)| !used)
+            .map(|(change, _)| change)
+            .collect();
+    }
+
+    let mut changes = others;
+    changes.append(&mut deleted);
+    changes.append(&mut added);
+    changes.append(&mut renames);
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(changes)
+}
+
+fn rename(source: Change, destination: Change, score: u8) -> Change {
+    Change {
+        path: destination.path,
+        kind: ChangeKind::Renamed {
+            from: source.path,
+            score,
+        },
+        old: source.old,
+        new: destination.new,
+    }
+}
+
+/// Estimates, in percent, how much of the content of two files is shared, counting the bytes of
+/// the lines they have in common relative to the larger file
+pub(crate) fn similarity(a: &[u8], b: &[u8]) -> u8 {
+    if a.is_empty() && b.is_empty() {
+        return 100;
+    }
+
+    let mut lines: HashMap<&[u8], isize> = HashMap::new();
+    for line in a.split_inclusive(|&c| c == b'\n') {
+        *lines.entry(line).or_default() += 1;
+    }
+
+    let mut common = 0;
+    for line in b.split_inclusive(|&c| c == b'\n') {
+        if let Some(count) = lines.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += line.len();
+            }
+        }
+    }
+
+    (common * 100 / a.len().max(b.len())) as u8
+}
+
+/// Splits content into lines, each keeping its line terminator
+pub(crate) fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&c| c == b'\n').collect()
+}
+
+/// The pairs of line numbers that two files have in common, in increasing order, as found by
+/// Myers' shortest edit script algorithm after setting aside the common prefix and suffix
+pub(crate) fn matching_lines(a: &[&[u8]], b: &[&[u8]]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut matches = (0..prefix).map(|i| (i, i)).collect::<Vec<_>>();
+    let middle = myers(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    matches.extend(middle.into_iter().map(|(i, j)| (i + prefix, j + prefix)));
+    matches.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+
+    matches
+}
+
+fn myers(a: &[&[u8]], b: &[&[u8]]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    if max == 0 {
+        return Vec::new();
+    }
+
+    // `v[k + max]` is the furthest x reached on diagonal k, and `trace[d]` the part of v that
+    // step d started from, indexed by `k + d`
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(v[(max - d) as usize..=(max + d) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let i = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let at = |k: isize| v[(k + d) as usize];
+
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = if d == 0 { 0 } else { at(previous_k) };
+        let previous_y = if d == 0 { 0 } else { previous_x - previous_k };
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    matches.reverse();
+
+    matches
+}
+
+/// Summarizes the changes between two file listings the way `git diff --stat` does: a line per
+/// file with its number of changed lines and a graph of insertions and deletions scaled to fit
+/// the terminal, then the totals
+pub(crate) fn diff_stat(old: &FileMap, new: &FileMap) -> std::io::Result<String> {
+    enum FileStat {
+        Text { added: usize, deleted: usize },
+        Binary { old_size: usize, new_size: usize },
+    }
+
+    let mut stats = Vec::new();
+    for change in diff_files(old, new) {
+        let old_content = read_content(change.old.as_ref())?;
+        let new_content = read_content(change.new.as_ref())?;
+
+        let stat = if is_binary(&old_content) || is_binary(&new_content) {
+            FileStat::Binary {
+                old_size: old_content.len(),
+                new_size: new_content.len(),
+            }
+        } else {
+            let (old_lines, new_lines) = (split_lines(&old_content), split_lines(&new_content));
+            let common = matching_lines(&old_lines, &new_lines).len();
+            FileStat::Text {
+                added: new_lines.len() - common,
+                deleted: old_lines.len() - common,
+            }
+        };
+        stats.push((change.path, stat));
+    }
+
+    let width = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .unwrap_or(80);
+
+    let mut max_change = 0;
+    let mut bin_width = 0;
+    let mut number_width = 0;
+    for (_, stat) in &stats {
+        match stat {
+            FileStat::Text { added, deleted } => max_change = max_change.max(added + deleted),
+            FileStat::Binary { old_size, new_size } => {
+                bin_width = bin_width.max(14 + decimal_width(*old_size) + decimal_width(*new_size));
+                // Counts are aligned with the "Bin" of binary files
+                number_width = 3;
+            }
+        }
+    }
+    let number_width = number_width.max(decimal_width(max_change));
+    let width = width.max(16 + 6 + number_width);
+
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = stats
+        .iter()
+        .map(|(path, _)| path.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    if name_width + number_width + 6 + graph_width > width {
+        if graph_width > (width * 3 / 8).saturating_sub(number_width + 6) {
+            graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let mut output = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for (path, stat) in &stats {
+        // Names too long for their column keep their end, cut at a directory boundary
+        let mut name = path.as_str();
+        let mut prefix = "";
+        if name.chars().count() > name_width {
+            prefix = "...";
+            let keep = name_width.saturating_sub(3);
+            let start = name.char_indices().nth(name.chars().count() - keep);
+            name = &name[start.map_or(name.len(), |(i, _)| i)..];
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = name_width.saturating_sub(prefix.len() + name.chars().count());
+        output.push_str(&format!(" {prefix}{name}{} | ", " ".repeat(padding)));
+
+        match *stat {
+            FileStat::Binary { old_size, new_size } => {
+                output.push_str(&format!("{:>number_width$}", "Bin"));
+                if old_size != 0 || new_size != 0 {
+                    output.push_str(&format!(" {old_size} -> {new_size} bytes"));
+                }
+            }
+            FileStat::Text { added, deleted } => {
+                insertions += added;
+                deletions += deleted;
+
+                let (mut plus, mut minus) = (added, deleted);
+                if graph_width <= max_change {
+                    let scale = |count: usize| match count {
+                        0 => 0,
+                        count => 1 + count * (graph_width - 1) / max_change,
+                    };
+                    let mut total = scale(added + deleted);
+                    if total < 2 && added != 0 && deleted != 0 {
+                        total = 2;
+                    }
+                    if added < deleted {
+                        plus = scale(added);
+                        minus = total - plus;
+                    } else {
+                        minus = scale(deleted);
+                        plus = total - minus;
+                    }
+                }
+
+                output.push_str(&format!("{:>number_width$}", added + deleted));
+                if added + deleted != 0 {
+                    output.push(' ');
+                }
+                output.push_str(&"+".repeat(plus));
+                output.push_str(&"-".repeat(minus));
+            }
+        }
+        output.push('\n');
+    }
+
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let files = stats.len();
+    output.push_str(&format!(" {files} file{} changed", plural(files)));
+    if insertions != 0 || deletions == 0 {
+        output.push_str(&format!(
+            ", {insertions} insertion{}(+)",
+            plural(insertions)
+        ));
+    }
+    if deletions != 0 || insertions == 0 {
+        output.push_str(&format!(", {deletions} deletion{}(-)", plural(deletions)));
+    }
+    output.push('\n');
+
+    Ok(output)
+}
+
+fn read_content(entry: Option<&(Mode, Hash)>) -> std::io::Result<Vec<u8>> {
+    match entry {
+        Some((_, hash)) => Ok(Object::read(hash)?.1),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn decimal_width(number: usize) -> usize {
+    number.to_string().len()
+}
+
+fn is_symlink(mode: Mode) -> bool {
+    mode == Mode::SYMBOLIC
+}
@@ -0,0 +1,294 @@
+pub(crate) mod objects;
+pub(crate) mod shallow;
+
+use crate::object::commit::Commit;
+use crate::object::Hash;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Reachable from the first commit of a query
+const PARENT1: u8 = 1 << 0;
+/// Reachable from the second commit(s) of a query
+const PARENT2: u8 = 1 << 1;
+/// Reachable from a common ancestor, so of no further interest
+const STALE: u8 = 1 << 2;
+/// Already recorded as a merge base
+const RESULT: u8 = 1 << 3;
+
+/// Answers reachability queries over the history, reading every commit at most once so that
+/// repeated queries on large histories stay cheap. Walks visit the most recent commits first,
+/// which lets them stop as soon as what remains to visit cannot change the answer.
+/// In a shallow repository, the commits of the shallow boundary have no parents.
+pub(crate) struct CommitGraph {
+    commits: HashMap<Hash, CommitNode>,
+    shallow: BTreeSet<Hash>,
+}
+
+struct CommitNode {
+    parents: Vec<Hash>,
+    date: u64,
+}
+
+impl CommitGraph {
+    pub(crate) fn new() -> Self {
+        CommitGraph {
+            commits: HashMap::new(),
+            shallow: shallow::read_shallow().unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn parents(&mut self, hash: &Hash) -> Vec<Hash> {
+        self.node(hash).parents.clone()
+    }
+
+    pub(crate) fn date(&mut self, hash: &Hash) -> u64 {
+        self.node(hash).date
+    }
+
+    fn node(&mut self, hash: &Hash) -> &CommitNode {
+        let shallow = &self.shallow;
+        self.commits.entry(hash.clone()).or_insert_with(|| {
+            let commit = Commit::from_hash(hash);
+            CommitNode {
+                parents: commit
+                    .parents
+                    .into_iter()
+                    .map(|parent| parent.parent_hash)
+                    .filter(|_| !shallow.contains(hash))
+                    .collect(),
+                date: commit.committer_date,
+            }
+        })
+    }
+
+    /// Every commit reachable from the specified commit, including itself
+    pub(crate) fn ancestors(&mut self, commit: &Hash) -> HashSet<Hash> {
+        let mut seen = HashSet::from([commit.clone()]);
+        let mut queue = VecDeque::from([commit.clone()]);
+
+        while let Some(hash) = queue.pop_front() {
+            for parent in self.parents(&hash) {
+                if seen.insert(parent.clone()) {
+                    queue.push_back(parent);
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// The commits reachable from `include` but not from `exclude`, as in `exclude..include`,
+    /// most recent first. Both sides are painted in the same walk, which stops once what remains
+    /// to visit is reachable from `exclude`.
+    pub(crate) fn range(&mut self, exclude: &Hash, include: &Hash) -> Vec<Hash> {
+        let (_, flags) = self.paint_down_to_common(include, std::slice::from_ref(exclude));
+        // Commits the walk stopped before reaching are all reachable from `exclude`
+        let excluded = |hash: &Hash| {
+            flags
+                .get(hash)
+                .is_none_or(|flag| flag & (PARENT2 | STALE) != 0)
+        };
+
+        let mut seen = HashSet::from([include.clone()]);
+        let mut queue = BinaryHeap::from([(self.date(include), include.clone())]);
+
+        let mut commits = Vec::new();
+        while let Some((_, hash)) = queue.pop() {
+            if excluded(&hash) {
+                continue;
+            }
+            for parent in self.parents(&hash) {
+                if seen.insert(parent.clone()) {
+                    queue.push((self.date(&parent), parent));
+                }
+            }
+            commits.push(hash);
+        }
+
+        commits
+    }
+
+    /// Whether `ancestor` is reachable from `descendant`, a commit being its own ancestor
+    pub(crate) fn is_ancestor(&mut self, ancestor: &Hash, descendant: &Hash) -> bool {
+        if ancestor == descendant {
+            return true;
+        }
+
+        self.paint_down_to_common(ancestor, std::slice::from_ref(descendant))
+            .0
+            .contains(ancestor)
+    }
+
+    /// The best common ancestors of a commit and any of the others: the commits reachable from
+    /// both that are not ancestors of another such commit. Criss-cross histories have several,
+    /// which are returned most recent first.
+    pub(crate) fn merge_bases(&mut self, one: &Hash, others: &[Hash]) -> Vec<Hash> {
+        if others.contains(one) {
+            return vec![one.clone()];
+        }
+
+        let (candidates, _) = self.paint_down_to_common(one, others);
+        let mut bases = self.remove_redundant(candidates);
+        bases.sort_by_key(|hash| std::cmp::Reverse(self.date(hash)));
+
+        bases
+    }
+
+    /// The best common ancestors of all the commits, as needed by a merge of all of them at once
+    pub(crate) fn octopus_merge_bases(&mut self, commits: &[Hash]) -> Vec<Hash> {
+        let Some((first, others)) = commits.split_first() else {
+            return Vec::new();
+        };
+
+        let mut bases = vec![first.clone()];
+        for other in others {
+            let mut next = Vec::new();
+            for base in &bases {
+                for merge_base in self.merge_bases(base, std::slice::from_ref(other)) {
+                    if !next.contains(&merge_base) {
+                        next.push(merge_base);
+                    }
+                }
+            }
+            bases = next;
+        }
+
+        bases
+    }
+
+    /// Counts the commits reachable only from `local` and only from `upstream`
+    pub(crate) fn ahead_behind(&mut self, local: &Hash, upstream: &Hash) -> (usize, usize) {
+        let (_, flags) = self.paint_down_to_common(local, std::slice::from_ref(upstream));
+
+        flags.values().fold((0, 0), |(ahead, behind), flag| {
+            match flag & (PARENT1 | PARENT2 | STALE) {
+                PARENT1 => (ahead + 1, behind),
+                PARENT2 => (ahead, behind + 1),
+                _ => (ahead, behind),
+            }
+        })
+    }
+
+    /// Walks down from `one` and `others` at once, most recent commit first, marking which side
+    /// reaches every commit. Commits reached from both sides are merge base candidates, and
+    /// their ancestors are marked stale. The walk ends once only stale commits remain to visit.
+    /// Returns the candidates, most recent first, and the marks of the visited commits.
+    fn paint_down_to_common(
+        &mut self,
+        one: &Hash,
+        others: &[Hash],
+    ) -> (Vec<Hash>, HashMap<Hash, u8>) {
+        let mut flags = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        // How many times each commit is queued, and how many queued entries are not stale, so
+        // that the end of the walk is known without scanning the queue
+        let mut queued = HashMap::<Hash, usize>::new();
+        let mut nonstale = 0;
+
+        flags.insert(one.clone(), PARENT1);
+        for other in others {
+            *flags.entry(other.clone()).or_default() |= PARENT2;
+        }
+        for hash in std::iter::once(one).chain(others) {
+            *queued.entry(hash.clone()).or_default() += 1;
+            nonstale += 1;
+            queue.push((self.date(hash), hash.clone()));
+        }
+
+        let mut candidates = Vec::new();
+        while nonstale > 0 {
+            let (_, hash) = queue.pop().unwrap();
+            *queued.get_mut(&hash).unwrap() -= 1;
+            let flag = flags.get_mut(&hash).unwrap();
+            if *flag & STALE == 0 {
+                nonstale -= 1;
+            }
+            let mut inherited = *flag & (PARENT1 | PARENT2 | STALE);
+
+            if inherited == PARENT1 | PARENT2 {
+                if *flag & RESULT == 0 {
+                    *flag |= RESULT;
+                    candidates.push(hash.clone());
+                }
+                inherited |= STALE;
+            }
+
+            for parent in self.parents(&hash) {
+                let parent_flag = flags.entry(parent.clone()).or_default();
+                if *parent_flag & inherited == inherited {
+                    continue;
+                }
+                let parent_queued = queued.entry(parent.clone()).or_default();
+                if *parent_flag & STALE == 0 && inherited & STALE != 0 {
+                    // The entries already queued for the parent just became stale
+                    nonstale -= *parent_queued;
+                }
+                *parent_flag |= inherited;
+                if *parent_flag & STALE == 0 {
+                    nonstale += 1;
+                }
+                *parent_queued += 1;
+                queue.push((self.date(&parent), parent));
+            }
+        }
+
+        // Candidates that turned out to be reachable from another candidate are not needed
+        candidates.retain(|hash| flags[hash] & STALE == 0);
+
+        (candidates, flags)
+    }
+
+    /// Drops the commits that are ancestors of another one in the list
+    fn remove_redundant(&mut self, commits: Vec<Hash>) -> Vec<Hash> {
+        if commits.len() < 2 {
+            return commits;
+        }
+
+        let mut redundant = HashSet::new();
+        for (i, commit) in commits.iter().enumerate() {
+            if redundant.contains(commit) {
+                continue;
+            }
+            let others = commits
+                .iter()
+                .enumerate()
+                .filter(|(j, other)| *j != i && !redundant.contains(*other))
+                .map(|(_, other)| other.clone())
+                .collect::<Vec<_>>();
+
+            let (_, flags) = self.paint_down_to_common(commit, &others);
+            for other in &others {
+                if flags.get(other).is_some_and(|flag| flag & PARENT1 != 0) {
+                    redundant.insert(other.clone());
+                }
+            }
+            if flags[commit] & PARENT2 != 0 {
+                redundant.insert(commit.clone());
+            }
+        }
+
+        commits
+            .into_iter()
+            .filter(|commit| !redundant.contains(commit))
+            .collect()
+    }
+}
+
+/// Every commit reachable from the specified commit, including itself
+pub(crate) fn ancestors(commit: &Hash) -> HashSet<Hash> {
+    CommitGraph::new().ancestors(commit)
+}
+
+/// Whether `ancestor` is reachable from `descendant`, a commit being its own ancestor
+pub(crate) fn is_ancestor(ancestor: &Hash, descendant: &Hash) -> bool {
+    CommitGraph::new().is_ancestor(ancestor, descendant)
+}
+
+/// Counts the commits reachable only from `local` and only from `upstream`
+pub(crate) fn ahead_behind(local: &Hash, upstream: &Hash) -> (usize, usize) {
+    CommitGraph::new().ahead_behind(local, upstream)
+}
+
+/// The best common ancestors of two commits, most recent first
+pub(crate) fn merge_bases(a: &Hash, b: &Hash) -> Vec<Hash> {
+    CommitGraph::new().merge_bases(a, std::slice::from_ref(b))
+}
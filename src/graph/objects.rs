@@ -0,0 +1,217 @@
+use crate::graph::CommitGraph;
+use crate::object::commit::Commit;
+use crate::object::tree::Tree;
+use crate::object::{Hash, Object, ObjectType};
+use std::collections::{BinaryHeap, HashSet};
+use std::str::FromStr;
+
+/// Lists the objects reachable from `include` but not from `exclude`, the way
+/// `git rev-list --objects` does: the commits, most recent first, then the annotated tags, then
+/// the trees and blobs along with their path. Tips may be tags, commits, trees or blobs, tags
+/// being peeled.
+/// The excluded history is only walked as far as it may still hide included commits, and only
+/// the trees of the excluded commits bordering the included ones are walked to find the objects
+/// to leave out, as a full walk of the excluded history would be far too costly.
+/// https://git-scm.com/docs/git-rev-list#_object_traversal
+pub(crate) fn list_objects(
+    include: &[Hash],
+    exclude: &[Hash],
+) -> std::io::Result<Vec<(Hash, String)>> {
+    let mut graph = CommitGraph::new();
+    let mut objects = ObjectList::default();
+
+    let mut excluded_commits = Vec::new();
+    let mut excluded_trees = Vec::new();
+    for tip in exclude {
+        let mut excluded_tags = Vec::new();
+        let (object_type, hash) = peel(tip, &mut excluded_tags)?;
+        objects.seen.extend(excluded_tags);
+        match object_type {
+            ObjectType::COMMIT => excluded_commits.push(hash),
+            ObjectType::TREE => excluded_trees.push(hash),
+            _ => _ = objects.seen.insert(hash),
+        }
+    }
+    let mut commits = Vec::new();
+    let mut tags = Vec::new();
+    let mut roots = Vec::new();
+    for tip in include {
+        let (object_type, hash) = peel(tip, &mut tags)?;
+        match object_type {
+            ObjectType::COMMIT => commits.push(hash),
+            _ => roots.push((object_type, hash)),
+        }
+    }
+
+    // The included and excluded commits are walked together, most recent first, the ancestors
+    // of excluded commits being uninteresting. The walk stops once only uninteresting commits
+    // remain to visit.
+    let mut uninteresting = excluded_commits.iter().cloned().collect::<HashSet<_>>();
+    let mut seen = commits
+        .iter()
+        .chain(&excluded_commits)
+        .cloned()
+        .collect::<HashSet<_>>();
+    let mut queued = seen.clone();
+    let mut queue = seen
+        .iter()
+        .map(|commit| (graph.date(commit), commit.clone()))
+        .collect::<BinaryHeap<_>>();
+    let mut interesting = seen.difference(&uninteresting).count();
+    let mut visited = Vec::new();
+    while interesting > 0 {
+        let (_, hash) = queue.pop().unwrap();
+        queued.remove(&hash);
+        let is_uninteresting = uninteresting.contains(&hash);
+        if !is_uninteresting {
+            interesting -= 1;
+        }
+
+        for parent in graph.parents(&hash) {
+            if is_uninteresting {
+                // Commits already visited pass the mark on to their own parents
+                let mut marks = vec![parent.clone()];
+                while let Some(commit) = marks.pop() {
+                    if !uninteresting.insert(commit.clone()) {
+                        continue;
+                    }
+                    if queued.contains(&commit) {
+                        interesting -= 1;
+                    } else if seen.contains(&commit) {
+                        marks.extend(graph.parents(&commit));
+                    }
+                }
+            }
+            if seen.insert(parent.clone()) {
+                if !uninteresting.contains(&parent) {
+                    interesting += 1;
+                }
+                queued.insert(parent.clone());
+                queue.push((graph.date(&parent), parent));
+            }
+        }
+        visited.push(hash);
+    }
+
+    // The included commits, and the excluded ones they border
+    let included = visited
+        .into_iter()
+        .filter(|commit| !uninteresting.contains(commit))
+        .collect::<Vec<_>>();
+    let mut border = HashSet::new();
+    for commit in &included {
+        for parent in graph.parents(commit) {
+            if uninteresting.contains(&parent) && border.insert(parent.clone()) {
+                excluded_trees.push(Commit::from_hash(&parent).tree_hash);
+            }
+        }
+    }
+    excluded_trees.extend(
+        excluded_commits
+            .iter()
+            .map(|commit| Commit::from_hash(commit).tree_hash),
+    );
+
+    for tree in excluded_trees {
+        objects.mark_tree_seen(&tree)?;
+    }
+
+    for commit in &included {
+        objects.add_tree(&Commit::from_hash(commit).tree_hash, "")?;
+    }
+    for (object_type, hash) in roots {
+        match object_type {
+            ObjectType::TREE => objects.add_tree(&hash, "")?,
+            _ => _ = objects.add(&hash, ""),
+        }
+    }
+
+    let mut list = included
+        .into_iter()
+        .map(|commit| (commit, String::new()))
+        .collect::<Vec<_>>();
+    for tag in tags {
+        if objects.seen.insert(tag.clone()) {
+            list.push((tag, String::new()));
+        }
+    }
+    list.extend(objects.list);
+
+    Ok(list)
+}
+
+/// Peels a tip down to an object that is not a tag, collecting the tags met along the way
+pub(crate) fn peel(hash: &Hash, tags: &mut Vec<Hash>) -> std::io::Result<(ObjectType, Hash)> {
+    let mut hash = hash.clone();
+    loop {
+        let (object_type, content) = Object::read(&hash)?;
+        if object_type != ObjectType::TAG {
+            return Ok((object_type, hash));
+        }
+
+        tags.push(hash.clone());
+        hash = String::from_utf8_lossy(&content)
+            .lines()
+            .find_map(|line| line.strip_prefix("object "))
+            .and_then(|target| Hash::from_str(target).ok())
+            .ok_or_else(|| std::io::Error::other(format!("bad tag object {hash}")))?;
+    }
+}
+
+/// The trees and blobs found so far, with those seen, listed or left out, to walk each tree once
+#[derive(Default)]
+struct ObjectList {
+    seen: HashSet<Hash>,
+    list: Vec<(Hash, String)>,
+}
+
+impl ObjectList {
+    /// Lists an object not seen yet, returning whether it was
+    fn add(&mut self, hash: &Hash, path: &str) -> bool {
+        let added = self.seen.insert(hash.clone());
+        if added {
+            self.list.push((hash.clone(), path.to_string()));
+        }
+
+        added
+    }
+
+    /// Lists a tree and what it holds that was not seen yet
+    fn add_tree(&mut self, hash: &Hash, path: &str) -> std::io::Result<()> {
+        if !self.add(hash, path) {
+            return Ok(());
+        }
+
+        for entry in Tree::from_hash(hash)?.entries {
+            let path = match path {
+                "" => entry.filename,
+                _ => format!("{path}/{}", entry.filename),
+            };
+            match entry.object_type {
+                ObjectType::TREE => self.add_tree(&entry.hash, &path)?,
+                ObjectType::BLOB => _ = self.add(&entry.hash, &path),
+                // Submodule commits live in another repository
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks a tree and what it holds as seen, so that they are left out
+    fn mark_tree_seen(&mut self, hash: &Hash) -> std::io::Result<()> {
+        if !self.seen.insert(hash.clone()) {
+            return Ok(());
+        }
+
+        for entry in Tree::from_hash(hash)?.entries {
+            match entry.object_type {
+                ObjectType::TREE => self.mark_tree_seen(&entry.hash)?,
+                ObjectType::BLOB => _ = self.seen.insert(entry.hash),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use crate::lockfile::LockFile;
+use crate::object::Hash;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const SHALLOW_PATH: &str = ".hamachi/shallow";
+
+/// The commits whose parents a shallow clone lacks, listed in `.hamachi/shallow`. History walks
+/// treat them as root commits.
+/// https://git-scm.com/docs/shallow
+pub(crate) fn read_shallow() -> std::io::Result<BTreeSet<Hash>> {
+    if !Path::new(SHALLOW_PATH).is_file() {
+        return Ok(BTreeSet::new());
+    }
+
+    fs::read_to_string(SHALLOW_PATH)?
+        .lines()
+        .map(|line| {
+            Hash::from_str(line.trim())
+                .map_err(|_| std::io::Error::other(format!("bad shallow line: {line}")))
+        })
+        .collect()
+}
+
+pub(crate) fn is_shallow_repository() -> bool {
+    Path::new(SHALLOW_PATH).is_file()
+}
+
+/// Replaces the shallow boundary, removing the file once the history is complete
+pub(crate) fn write_shallow(commits: &BTreeSet<Hash>) -> std::io::Result<()> {
+    if commits.is_empty() {
+        return match fs::remove_file(SHALLOW_PATH) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let content = commits
+        .iter()
+        .map(|hash| format!("{hash}\n"))
+        .collect::<String>();
+
+    let mut lock = LockFile::acquire(SHALLOW_PATH)?;
+    lock.write_all(content.as_bytes())?;
+    lock.commit()
+}
+
+/// Applies the boundary changes announced by a remote: commits that became shallow are added,
+/// and commits whose parents were fetched are removed
+pub(crate) fn update_shallow(shallow: &[Hash], unshallow: &[Hash]) -> std::io::Result<()> {
+    let mut commits = read_shallow()?;
+    commits.extend(shallow.iter().cloned());
+    for hash in unshallow {
+        commits.remove(hash);
+    }
+
+    write_shallow(&commits)
+}
@@ -0,0 +1,333 @@
+use crate::config::Config;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const PER_DIRECTORY_FILES: [&str; 2] = [".gitignore", ".hamachiignore"];
+const INFO_EXCLUDE_PATH: &str = ".hamachi/info/exclude";
+
+/// The ignore rules of the repository, read from the `.gitignore` and `.hamachiignore` files of
+/// every directory, `.hamachi/info/exclude` and the file configured by `core.excludesFile`.
+/// Per-directory files are only read when a path below them is first checked.
+/// https://git-scm.com/docs/gitignore
+pub(crate) struct IgnoreRules {
+    /// `info/exclude` then `core.excludesFile`, in decreasing precedence
+    global: Vec<Vec<Pattern>>,
+    per_directory_files: &'static [&'static str],
+    per_directory: HashMap<String, Vec<Pattern>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    /// The pattern as written in its file
+    pub(crate) text: String,
+    /// The file the pattern was read from and its line number there
+    pub(crate) source: String,
+    pub(crate) line: usize,
+    pub(crate) negated: bool,
+    glob: String,
+    directory_only: bool,
+    /// Whether the pattern is matched against the whole path relative to `base`, instead of
+    /// only the file name
+    anchored: bool,
+    /// The directory of the ignore file, patterns only apply to paths below it
+    base: String,
+}
+
+impl IgnoreRules {
+    pub(crate) fn load() -> std::io::Result<Self> {
+        let mut global = vec![read_pattern_file(INFO_EXCLUDE_PATH, "")?];
+
+        if let Some(path) = Config::load()?.get("core.excludesFile") {
+            let path = match path.strip_prefix("~/") {
+                Some(rest) => match std::env::var("HOME") {
+                    Ok(home) => format!("{home}/{rest}"),
+                    Err(_) => path,
+                },
+                None => path,
+            };
+            global.push(read_pattern_file(&path, "")?);
+        }
+
+        Ok(IgnoreRules {
+            global,
+            per_directory_files: &PER_DIRECTORY_FILES,
+            per_directory: HashMap::new(),
+        })
+    }
+
+    /// Rules that ignore nothing, for commands asked to disregard the ignore files
+    pub(crate) fn none() -> Self {
+        IgnoreRules {
+            global: Vec::new(),
+            per_directory_files: &[],
+            per_directory: HashMap::new(),
+        }
+    }
+
+    /// Whether a repository relative path, or one of its parent directories, is ignored
+    pub(crate) fn is_ignored(&mut self, path: &str, is_directory: bool) -> std::io::Result<bool> {
+        Ok(self
+            .matching_pattern(path, is_directory)?
+            .is_some_and(|pattern| !pattern.negated))
+    }
+
+    /// The pattern deciding whether a path is ignored, which is negated if the path is explicitly
+    /// re-included. A path inside an ignored directory is reported with the directory's pattern,
+    /// since git never looks inside excluded directories.
+    pub(crate) fn matching_pattern(
+        &mut self,
+        path: &str,
+        is_directory: bool,
+    ) -> std::io::Result<Option<Pattern>> {
+        let mut parent_end = 0;
+        while let Some(i) = path[parent_end..].find('/') {
+            let parent = &path[..parent_end + i];
+            if let Some(pattern) = self.match_path(parent, true)? {
+                if !pattern.negated {
+                    return Ok(Some(pattern));
+                }
+            }
+            parent_end += i + 1;
+        }
+
+        self.match_path(path, is_directory)
+    }
+
+    /// The last matching pattern of the highest precedence source: the ignore files of the
+    /// closest directory first, then those of its parents, then the global files
+    fn match_path(&mut self, path: &str, is_directory: bool) -> std::io::Result<Option<Pattern>> {
+        let mut directory = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        loop {
+            let patterns = self.directory_patterns(directory)?;
+            if let Some(pattern) = last_match(patterns, path, is_directory) {
+                return Ok(Some(pattern.clone()));
+            }
+
+            if directory.is_empty() {
+                break;
+            }
+            directory = directory.rsplit_once('/').map_or("", |(parent, _)| parent);
+        }
+
+        Ok(self
+            .global
+            .iter()
+            .find_map(|patterns| last_match(patterns, path, is_directory))
+            .cloned())
+    }
+
+    fn directory_patterns(&mut self, directory: &str) -> std::io::Result<&[Pattern]> {
+        if !self.per_directory.contains_key(directory) {
+            let mut patterns = Vec::new();
+            for file_name in self.per_directory_files {
+                let path = if directory.is_empty() {
+                    file_name.to_string()
+                } else {
+                    format!("{directory}/{file_name}")
+                };
+                patterns.append(&mut read_pattern_file(&path, directory)?);
+            }
+            self.per_directory.insert(directory.to_string(), patterns);
+        }
+
+        Ok(&self.per_directory[directory])
+    }
+}
+
+fn last_match<'a>(patterns: &'a [Pattern], path: &str, is_directory: bool) -> Option<&'a Pattern> {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.matches(path, is_directory))
+}
+
+fn read_pattern_file(path: &str, base: &str) -> std::io::Result<Vec<Pattern>> {
+    let content = match fs::read(PathBuf::from(path)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(String::from_utf8_lossy(&content)
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Pattern::parse(line, path, i + 1, base))
+        .collect())
+}
+
+impl Pattern {
+    /// Parses a line of an ignore file, returning `None` for blank lines and comments
+    fn parse(line: &str, source: &str, line_number: usize, base: &str) -> Option<Self> {
+        let text = trim_trailing_spaces(line);
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+
+        let (negated, mut glob) = match text.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+
+        let directory_only = glob.ends_with('/') && !glob.ends_with("\\/");
+        if directory_only {
+            glob = &glob[..glob.len() - 1];
+        }
+
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            text: text.to_string(),
+            source: source.to_string(),
+            line: line_number,
+            negated,
+            glob: glob.to_string(),
+            directory_only,
+            anchored,
+            base: base.to_string(),
+        })
+    }
+
+    fn matches(&self, path: &str, is_directory: bool) -> bool {
+        if self.directory_only && !is_directory {
+            return false;
+        }
+
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+
+        if self.anchored {
+            wildmatch(self.glob.as_bytes(), relative.as_bytes(), true)
+        } else {
+            let file_name = relative.rsplit_once('/').map_or(relative, |(_, name)| name);
+            wildmatch(self.glob.as_bytes(), file_name.as_bytes(), true)
+        }
+    }
+}
+
+/// Trailing spaces are ignored unless escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end].ends_with("\\ ") {
+        end -= 1;
+    }
+
+    &line[..end]
+}
+
+/// Matches a path against a glob where `*` and `?` do not match `/`, and `**` between slashes
+/// matches any number of directories
+fn wildmatch(pattern: &[u8], text: &[u8], segment_start: bool) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] if segment_start && (rest.is_empty() || rest[0] == b'/') => {
+            match rest {
+                // A trailing `**` matches everything inside
+                [] => true,
+                // `**/` matches zero or more leading directories
+                [_, rest @ ..] => {
+                    wildmatch(rest, text, true)
+                        || text
+                            .iter()
+                            .enumerate()
+                            .any(|(i, &c)| c == b'/' && wildmatch(rest, &text[i + 1..], true))
+                }
+            }
+        }
+        [b'*', rest @ ..] => {
+            let rest = trim_leading_stars(rest);
+            (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != b'/')
+                .any(|i| wildmatch(rest, &text[i..], false))
+        }
+        [b'?', rest @ ..] => match text {
+            [c, text @ ..] if *c != b'/' => wildmatch(rest, text, false),
+            _ => false,
+        },
+        [b'[', class @ ..] => {
+            let Some((&c, text)) = text.split_first() else {
+                return false;
+            };
+            match match_class(class, c) {
+                Some((matched, rest)) => matched && c != b'/' && wildmatch(rest, text, false),
+                // An unterminated bracket is matched literally
+                None => c == b'[' && wildmatch(class, text, false),
+            }
+        }
+        [b'\\', escaped, rest @ ..] => match text {
+            [c, text @ ..] if c == escaped => wildmatch(rest, text, *c == b'/'),
+            _ => false,
+        },
+        [literal, rest @ ..] => match text {
+            [c, text @ ..] if c == literal => wildmatch(rest, text, *c == b'/'),
+            _ => false,
+        },
+    }
+}
+
+fn trim_leading_stars(mut pattern: &[u8]) -> &[u8] {
+    while let [b'*', rest @ ..] = pattern {
+        pattern = rest;
+    }
+
+    pattern
+}
+
+/// Matches a character against a bracket expression such as `a-z]` or `!0-9]`, returning
+/// whether it matched and the rest of the pattern after the closing bracket
+fn match_class(class: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] if !first => return Some((matched != negated, rest)),
+            [b'[', b':', rest @ ..] => {
+                let end = rest.windows(2).position(|window| window == b":]")?;
+                matched |= match &rest[..end] {
+                    b"alpha" => c.is_ascii_alphabetic(),
+                    b"digit" => c.is_ascii_digit(),
+                    b"alnum" => c.is_ascii_alphanumeric(),
+                    b"upper" => c.is_ascii_uppercase(),
+                    b"lower" => c.is_ascii_lowercase(),
+                    b"space" => c.is_ascii_whitespace(),
+                    b"punct" => c.is_ascii_punctuation(),
+                    b"xdigit" => c.is_ascii_hexdigit(),
+                    _ => false,
+                };
+                class = &rest[end + 2..];
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= c == *escaped;
+                class = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                matched |= (*start..=*end).contains(&c);
+                class = rest;
+            }
+            [literal, rest @ ..] => {
+                matched |= c == *literal;
+                class = rest;
+            }
+        }
+        first = false;
+    }
+}
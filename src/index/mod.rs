@@ -0,0 +1,334 @@
+use crate::lockfile::LockFile;
+use crate::object::tree::{Mode, Tree};
+use crate::object::Hash;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+const INDEX_PATH: &str = ".hamachi/index";
+const SIGNATURE: &[u8; 4] = b"DIRC";
+
+/// The staging area, stored in the same binary format git uses so either tool can read it
+/// https://git-scm.com/docs/index-format
+#[derive(Debug, Default)]
+pub(crate) struct Index {
+    /// Sorted by path, then by stage
+    pub(crate) entries: Vec<IndexEntry>,
+    /// Modification time of the index file when it was loaded, used to detect racily clean entries
+    pub(crate) timestamp: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IndexEntry {
+    pub(crate) ctime_seconds: u32,
+    pub(crate) ctime_nanoseconds: u32,
+    pub(crate) mtime_seconds: u32,
+    pub(crate) mtime_nanoseconds: u32,
+    pub(crate) dev: u32,
+    pub(crate) ino: u32,
+    pub(crate) mode: Mode,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u32,
+    pub(crate) hash: Hash,
+    /// 0 for a regular entry, 1 to 3 for the base, ours and theirs sides of a conflict
+    pub(crate) stage: u8,
+    pub(crate) path: String,
+}
+
+impl Index {
+    /// Loads the index from the disk, or returns an empty index if there is none yet
+    pub(crate) fn load() -> std::io::Result<Self> {
+        let path = PathBuf::from(INDEX_PATH);
+        if !path.exists() {
+            return Ok(Index::default());
+        }
+
+        let data = fs::read(&path)?;
+        let metadata = fs::metadata(&path)?;
+
+        let mut index = Self::parse(&data)?;
+        index.timestamp = Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+
+        Ok(index)
+    }
+
+    fn parse(data: &[u8]) -> std::io::Result<Self> {
+        let corrupted = || std::io::Error::other("index file corrupt");
+
+        if data.len() < 32 || &data[..4] != SIGNATURE {
+            return Err(corrupted());
+        }
+
+        let (content, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(content).as_slice() != checksum {
+            return Err(std::io::Error::other("index file corrupt: bad checksum"));
+        }
+
+        let version = read_u32(content, 4);
+        if !(2..=3).contains(&version) {
+            return Err(std::io::Error::other(format!(
+                "index file version {version} is not supported"
+            )));
+        }
+
+        let entry_count = read_u32(content, 8);
+        let mut read_pointer = 12;
+        let mut entries = Vec::with_capacity(entry_count as usize);
+
+        for _ in 0..entry_count {
+            let entry_start = read_pointer;
+            let fixed = content
+                .get(read_pointer..read_pointer + 62)
+                .ok_or_else(corrupted)?;
+
+            let flags = u16::from_be_bytes([fixed[60], fixed[61]]);
+            read_pointer += 62;
+
+            // Version 3 entries with the extended flag carry two more bytes of flags
+            if flags & 0x4000 != 0 {
+                read_pointer += 2;
+            }
+
+            let name_end = content[read_pointer..]
+                .iter()
+                .position(|&c| c == b'\0')
+                .ok_or_else(corrupted)?;
+            let path = String::from_utf8(content[read_pointer..read_pointer + name_end].to_vec())
+                .map_err(|_| corrupted())?;
+            read_pointer += name_end;
+
+            // Entries are padded with 1 to 8 null bytes to a multiple of 8
+            let entry_length = read_pointer - entry_start;
+            read_pointer = entry_start + (entry_length + 8) / 8 * 8;
+
+            entries.push(IndexEntry {
+                ctime_seconds: read_u32(fixed, 0),
+                ctime_nanoseconds: read_u32(fixed, 4),
+                mtime_seconds: read_u32(fixed, 8),
+                mtime_nanoseconds: read_u32(fixed, 12),
+                dev: read_u32(fixed, 16),
+                ino: read_u32(fixed, 20),
+                mode: Mode::from_octal(read_u32(fixed, 24)).map_err(|_| corrupted())?,
+                uid: read_u32(fixed, 28),
+                gid: read_u32(fixed, 32),
+                size: read_u32(fixed, 36),
+                hash: Hash(fixed[40..60].to_vec()),
+                stage: ((flags >> 12) & 0b11) as u8,
+                path,
+            });
+        }
+
+        // Extensions are optional caches, except the ones with a lowercase signature
+        while read_pointer + 8 <= content.len() {
+            let signature = &content[read_pointer..read_pointer + 4];
+            if signature[0].is_ascii_lowercase() {
+                return Err(std::io::Error::other(format!(
+                    "index uses the unsupported {} extension",
+                    String::from_utf8_lossy(signature)
+                )));
+            }
+
+            let size = read_u32(content, read_pointer + 4) as usize;
+            read_pointer += 8 + size;
+        }
+
+        Ok(Index {
+            entries,
+            timestamp: None,
+        })
+    }
+
+    /// Atomically writes the index to the disk
+    pub(crate) fn write(&mut self) -> std::io::Result<()> {
+        self.sort();
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&2u32.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        for entry in &self.entries {
+            let entry_start = data.len();
+            for value in [
+                entry.ctime_seconds,
+                entry.ctime_nanoseconds,
+                entry.mtime_seconds,
+                entry.mtime_nanoseconds,
+                entry.dev,
+                entry.ino,
+                entry.mode.to_octal(),
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&entry.hash.0);
+
+            let flags = ((entry.stage as u16 & 0b11) << 12) | entry.path.len().min(0xFFF) as u16;
+            data.extend_from_slice(&flags.to_be_bytes());
+            data.extend_from_slice(entry.path.as_bytes());
+
+            let entry_length = data.len() - entry_start;
+            data.resize(entry_start + (entry_length + 8) / 8 * 8, 0);
+        }
+
+        let checksum = Sha1::digest(&data);
+        data.extend_from_slice(&checksum);
+
+        let mut lock = LockFile::acquire(INDEX_PATH)?;
+        lock.write_all(&data)?;
+        lock.commit()?;
+
+        let metadata = fs::metadata(INDEX_PATH)?;
+        self.timestamp = Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+
+        Ok(())
+    }
+
+    /// The regular (stage 0) entry for the specified path
+    pub(crate) fn get(&self, path: &str) -> Option<&IndexEntry> {
+        self.position(path, 0).ok().map(|i| &self.entries[i])
+    }
+
+    /// Writes the index as a hierarchy of tree objects and returns the root tree, which is
+    /// only possible once every conflict is resolved
+    pub(crate) fn write_tree(&self) -> std::io::Result<Hash> {
+        if self.has_conflicts() {
+            return Err(std::io::Error::other(
+                "cannot write a tree from an index with unresolved conflicts",
+            ));
+        }
+
+        let files = self
+            .entries
+            .iter()
+            .map(|entry| (entry.path.clone(), (entry.mode, entry.hash.clone())))
+            .collect();
+
+        Tree::write_from_files(&files)
+    }
+
+    /// Whether the path is tracked, at any stage
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        let i = self
+            .entries
+            .partition_point(|entry| entry.path.as_bytes() < path.as_bytes());
+
+        self.entries.get(i).is_some_and(|entry| entry.path == path)
+    }
+
+    /// Adds or replaces an entry, dropping any conflict stages recorded for its path
+    pub(crate) fn add(&mut self, entry: IndexEntry) {
+        self.remove(&entry.path);
+
+        match self.position(&entry.path, entry.stage) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Replaces the entries of a path with its unmerged stages: the base (1), ours (2) and
+    /// theirs (3), each only if that side has the file
+    pub(crate) fn add_conflict(&mut self, path: &str, stages: &[Option<(Mode, Hash)>; 3]) {
+        self.remove(path);
+
+        for (stage, version) in stages.iter().enumerate() {
+            if let Some((mode, hash)) = version {
+                let mut entry = IndexEntry::new(path.to_string(), *mode, hash.clone());
+                entry.stage = stage as u8 + 1;
+
+                let i = self.position(path, entry.stage).unwrap_err();
+                self.entries.insert(i, entry);
+            }
+        }
+    }
+
+    /// Removes every stage of the specified path
+    pub(crate) fn remove(&mut self, path: &str) {
+        self.entries.retain(|entry| entry.path != path);
+    }
+
+    /// Whether any path has unresolved conflict stages
+    pub(crate) fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage != 0)
+    }
+
+    fn position(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries.binary_search_by(|entry| {
+            entry
+                .path
+                .as_bytes()
+                .cmp(path.as_bytes())
+                .then(entry.stage.cmp(&stage))
+        })
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| {
+            a.path
+                .as_bytes()
+                .cmp(b.path.as_bytes())
+                .then(a.stage.cmp(&b.stage))
+        });
+    }
+}
+
+impl IndexEntry {
+    /// An entry with no stat information, which is always considered in need of a content check
+    pub(crate) fn new(path: String, mode: Mode, hash: Hash) -> Self {
+        IndexEntry {
+            ctime_seconds: 0,
+            ctime_nanoseconds: 0,
+            mtime_seconds: 0,
+            mtime_nanoseconds: 0,
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash,
+            stage: 0,
+            path,
+        }
+    }
+
+    pub(crate) fn from_metadata(path: String, mode: Mode, hash: Hash, metadata: &Metadata) -> Self {
+        let mut entry = Self::new(path, mode, hash);
+        entry.update_stat(metadata);
+
+        entry
+    }
+
+    /// Records the stat information of the working tree file so later checks can skip rehashing it
+    pub(crate) fn update_stat(&mut self, metadata: &Metadata) {
+        self.ctime_seconds = metadata.ctime() as u32;
+        self.ctime_nanoseconds = metadata.ctime_nsec() as u32;
+        self.mtime_seconds = metadata.mtime() as u32;
+        self.mtime_nanoseconds = metadata.mtime_nsec() as u32;
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.size() as u32;
+    }
+
+    /// Whether the stat information recorded in the entry matches the working tree file
+    pub(crate) fn matches_stat(&self, metadata: &Metadata) -> bool {
+        self.mtime_seconds == metadata.mtime() as u32
+            && self.mtime_nanoseconds == metadata.mtime_nsec() as u32
+            && self.ctime_seconds == metadata.ctime() as u32
+            && self.ctime_nanoseconds == metadata.ctime_nsec() as u32
+            && self.ino == metadata.ino() as u32
+            && self.size == metadata.size() as u32
+            && self.mode == Mode::from_metadata(metadata)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
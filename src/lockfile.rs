@@ -0,0 +1,67 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Exclusive `<path>.lock` file used to atomically replace a file, the same way git updates
+/// refs and the index. The new content is written to the lock file and renamed over the
+/// target on commit; dropping an uncommitted lock removes it and leaves the target untouched.
+pub(crate) struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub(crate) fn acquire(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Unable to create '{}': {e}", lock_path.display()),
+                )
+            })?;
+
+        Ok(LockFile {
+            path,
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.file
+            .as_mut()
+            .expect("Lock file already committed")
+            .write_all(data)
+    }
+
+    /// Flushes the lock file and renames it over the target path
+    pub(crate) fn commit(mut self) -> std::io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+
+        fs::rename(&self.lock_path, &self.path)
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
@@ -1,15 +1,48 @@
 mod command;
+mod config;
+mod diff;
+mod graph;
+mod ignore;
+mod index;
+mod lockfile;
+mod merge;
 mod object;
+mod progress;
+mod refs;
 mod remote;
+mod sequencer;
+#[cfg(test)]
 mod test_utils;
+mod worktree;
 
+use crate::command::add::add;
+use crate::command::branch::branch;
 use crate::command::cat_file::cat_file;
+use crate::command::check_ignore::check_ignore;
+use crate::command::checkout::checkout;
+use crate::command::cherry_pick::cherry_pick;
+use crate::command::clean::{clean, CleanMode};
 use crate::command::clone::clone;
+use crate::command::commit::{commit, CommitOptions};
 use crate::command::commit_tree::commit_tree;
+use crate::command::fetch::fetch;
 use crate::command::hash_object::hash_object;
+use crate::command::index_pack::index_pack;
 use crate::command::ls_tree::ls_tree;
+use crate::command::merge::merge;
+use crate::command::merge_base::merge_base;
+use crate::command::pack_objects::pack_objects;
+use crate::command::push::push;
+use crate::command::rebase::rebase;
+use crate::command::reset::reset;
+use crate::command::restore::restore;
+use crate::command::revert::revert;
+use crate::command::serve::serve;
+use crate::command::stash::stash;
+use crate::command::status::{status, StatusFormat};
+use crate::command::switch::switch;
+use crate::command::verify_pack::verify_pack;
 use crate::command::write_tree::write_tree;
-use crate::object::Object;
 use clap::Parser;
 use command::config::config;
 use command::{Args, Command};
@@ -56,16 +89,183 @@ fn main() {
 
             println!("{tree_hash}");
         }
-        Command::CommitTree { hash, message } => {
-            let commit_hash = commit_tree(&hash, &message).unwrap().to_string();
+        Command::CommitTree {
+            hash,
+            parents,
+            message,
+        } => {
+            let commit_hash = exit_on_error(commit_tree(&hash, &parents, &message)).to_string();
 
             println!("{commit_hash}");
         }
         Command::Config { subcommand } => {
-            config(subcommand);
+            exit_on_error(config(subcommand));
         }
-        Command::Clone { repository } => {
-            clone(repository);
+        Command::Clone(args) => {
+            exit_on_error(clone(args));
+        }
+        Command::Fetch(args) => {
+            exit_on_error(fetch(args));
+        }
+        Command::IndexPack(args) => {
+            print!(
+                "{}",
+                exit_on_error(index_pack(args, std::io::stdin().lock()))
+            );
+        }
+        Command::VerifyPack(args) => {
+            print!("{}", exit_on_error(verify_pack(args)));
+        }
+        Command::PackObjects(args) => {
+            exit_on_error(pack_objects(
+                args,
+                std::io::stdin().lock(),
+                std::io::stdout().lock(),
+            ));
+        }
+        Command::Push(args) => {
+            exit_on_error(push(args));
+        }
+        Command::Serve(args) => {
+            exit_on_error(serve(args));
+        }
+        Command::Checkout {
+            force,
+            detach,
+            new_branch,
+            reset_branch,
+            target,
+            paths,
+        } => {
+            let reset = reset_branch.is_some();
+            let new_branch = new_branch.or(reset_branch);
+            exit_on_error(checkout(target, paths, force, detach, new_branch, reset));
+        }
+        Command::Switch {
+            force,
+            detach,
+            create,
+            force_create,
+            branch,
+        } => {
+            let reset = force_create.is_some();
+            let new_branch = create.or(force_create);
+            exit_on_error(switch(branch, detach, force, new_branch, reset));
+        }
+        Command::Branch(args) => {
+            print!("{}", exit_on_error(branch(args)));
+        }
+        Command::Merge(args) => {
+            exit_on_error(merge(args));
+        }
+        Command::MergeBase(args) => match exit_on_error(merge_base(args)) {
+            Some(output) => print!("{output}"),
+            None => std::process::exit(1),
+        },
+        Command::CherryPick(args) => {
+            exit_on_error(cherry_pick(args));
+        }
+        Command::Revert(args) => {
+            exit_on_error(revert(args));
+        }
+        Command::Rebase(args) => {
+            exit_on_error(rebase(args));
+        }
+        Command::Reset(args) => {
+            exit_on_error(reset(args));
+        }
+        Command::Restore(args) => {
+            exit_on_error(restore(args));
+        }
+        Command::Stash { subcommand } => {
+            exit_on_error(stash(subcommand));
+        }
+        Command::Status {
+            short,
+            porcelain,
+            branch,
+        } => {
+            let format = match porcelain.as_deref() {
+                Some("v1" | "1") => StatusFormat::PorcelainV1,
+                Some("v2" | "2") => StatusFormat::PorcelainV2,
+                Some(version) => exit_on_error(Err(std::io::Error::other(format!(
+                    "unsupported porcelain version '{version}'"
+                )))),
+                None if short => StatusFormat::Short,
+                None => StatusFormat::Long,
+            };
+
+            print!("{}", exit_on_error(status(format, branch)));
+        }
+        Command::Add {
+            all,
+            force,
+            pathspecs,
+        } => {
+            exit_on_error(add(pathspecs, all, force));
+        }
+        Command::Clean {
+            dry_run,
+            force,
+            directories,
+            ignored,
+            only_ignored,
+            pathspecs,
+        } => {
+            let mode = match (ignored, only_ignored) {
+                (true, _) => CleanMode::All,
+                (_, true) => CleanMode::OnlyIgnored,
+                _ => CleanMode::Untracked,
+            };
+
+            print!(
+                "{}",
+                exit_on_error(clean(pathspecs, mode, directories, dry_run, force))
+            );
+        }
+        Command::Commit {
+            messages,
+            file,
+            amend,
+            no_edit,
+            allow_empty,
+            all,
+            author,
+            date,
+        } => {
+            exit_on_error(commit(CommitOptions {
+                messages,
+                file,
+                amend,
+                no_edit,
+                allow_empty,
+                all,
+                author,
+                date,
+            }));
+        }
+        Command::CheckIgnore {
+            verbose,
+            no_index,
+            paths,
+        } => {
+            let (output, any_ignored) = exit_on_error(check_ignore(paths, verbose, no_index));
+
+            print!("{output}");
+            if !any_ignored {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Reports a failed command the way git does instead of panicking
+fn exit_on_error<T>(result: std::io::Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
         }
     }
 }
@@ -75,11 +275,11 @@ fn main() {
 fn init() -> std::io::Result<()> {
     fs::create_dir(Path::new(".hamachi"))?;
     fs::create_dir(Path::new(".hamachi/objects"))?;
-    fs::create_dir(Path::new(".hamachi/objects/pack"))?;
     fs::create_dir_all(Path::new(".hamachi/refs/heads"))?;
     fs::create_dir(Path::new(".hamachi/refs/tags"))?;
 
     File::create(".hamachi/config")?;
+    fs::write(".hamachi/HEAD", "ref: refs/heads/master\n")?;
 
     Ok(())
 }
@@ -0,0 +1,596 @@
+pub(crate) mod text;
+
+use crate::diff::{self, ChangeKind, FileMap};
+use crate::graph::{self, CommitGraph};
+use crate::index::{Index, IndexEntry};
+use crate::merge::text::{is_binary, merge_text, ConflictStyle};
+use crate::object::commit::Commit;
+use crate::object::tree::{Mode, Tree};
+use crate::object::{Hash, Object, ObjectType};
+use crate::worktree;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::str::FromStr;
+
+pub(crate) const MERGE_HEAD_PATH: &str = ".hamachi/MERGE_HEAD";
+pub(crate) const MERGE_MSG_PATH: &str = ".hamachi/MERGE_MSG";
+pub(crate) const MERGE_MODE_PATH: &str = ".hamachi/MERGE_MODE";
+
+/// The base, ours and theirs versions of a conflicted path, as recorded in index stages 1 to 3
+pub(crate) type Stages = [Option<(Mode, Hash)>; 3];
+
+/// Names of the sides of a merge, shown in conflict markers and messages
+#[derive(Debug, Clone)]
+pub(crate) struct MergeLabels {
+    pub(crate) base: String,
+    pub(crate) ours: String,
+    pub(crate) theirs: String,
+}
+
+impl MergeLabels {
+    /// The labels with the path of every side appended, used when a file was renamed
+    fn with_paths(&self, paths: &[String; 3]) -> Self {
+        MergeLabels {
+            base: format!("{}:{}", self.base, paths[0]),
+            ours: format!("{}:{}", self.ours, paths[1]),
+            theirs: format!("{}:{}", self.theirs, paths[2]),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TreeMerge {
+    /// The merged files. Conflicted paths hold what is left in the working tree for the user to
+    /// resolve, such as content with conflict markers.
+    pub(crate) files: FileMap,
+    pub(crate) conflicts: BTreeMap<String, Stages>,
+    /// Messages about the merge of every path, in git's wording, sorted by path
+    pub(crate) messages: Vec<String>,
+}
+
+impl TreeMerge {
+    pub(crate) fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merges the trees of two commits, starting from their merge base. Criss-cross histories have
+/// several merge bases, which are first merged into a single virtual one.
+pub(crate) fn merge_commits(
+    ours: &Hash,
+    theirs: &Hash,
+    ours_label: &str,
+    theirs_label: &str,
+    style: ConflictStyle,
+) -> std::io::Result<TreeMerge> {
+    let bases = graph::merge_bases(ours, theirs);
+    let labels = MergeLabels {
+        base: match bases.as_slice() {
+            [base] => base.to_short_string(),
+            [] => String::from("empty tree"),
+            _ => String::from("merged common ancestors"),
+        },
+        ours: ours_label.to_string(),
+        theirs: theirs_label.to_string(),
+    };
+
+    merge_trees(
+        &virtual_base(&bases, style)?,
+        &Tree::flatten(&Commit::from_hash(ours).tree_hash)?,
+        &Tree::flatten(&Commit::from_hash(theirs).tree_hash)?,
+        &labels,
+        style,
+    )
+}
+
+/// The files of the merge bases merged together, conflicts included with their markers. As in
+/// git's recursive strategy, each base is merged into the virtual commit of the previous ones,
+/// against the merge bases of that commit and the next base.
+fn virtual_base(bases: &[Hash], style: ConflictStyle) -> std::io::Result<FileMap> {
+    let Some((first, others)) = bases.split_first() else {
+        return Ok(FileMap::new());
+    };
+
+    let mut graph = CommitGraph::new();
+    // The parents of the virtual commit, whose ancestors are those of all of them
+    let mut merged = vec![first.clone()];
+    let mut files = Tree::flatten(&Commit::from_hash(first).tree_hash)?;
+    for other in others {
+        let labels = MergeLabels {
+            base: String::from("merged common ancestors"),
+            ours: String::from("Temporary merge branch 1"),
+            theirs: String::from("Temporary merge branch 2"),
+        };
+        let base = virtual_base(&graph.merge_bases(other, &merged), style)?;
+        let other_files = Tree::flatten(&Commit::from_hash(other).tree_hash)?;
+
+        files = merge_trees(&base, &files, &other_files, &labels, style)?.files;
+        merged.push(other.clone());
+    }
+
+    Ok(files)
+}
+
+/// Three-way merge of file listings. Changes made on only one side are taken as they are, files
+/// renamed on one side receive the changes made to them on the other, and contents changed on
+/// both sides are merged line by line.
+pub(crate) fn merge_trees(
+    base: &FileMap,
+    ours: &FileMap,
+    theirs: &FileMap,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> std::io::Result<TreeMerge> {
+    let mut merge = TreeMerger {
+        base: base.clone(),
+        ours: ours.clone(),
+        theirs: theirs.clone(),
+        labels,
+        style,
+        sources: BTreeMap::new(),
+        result: TreeMerge::default(),
+        messages: Vec::new(),
+    };
+
+    merge.follow_renames()?;
+
+    let paths = merge
+        .base
+        .keys()
+        .chain(merge.ours.keys())
+        .chain(merge.theirs.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    for path in paths {
+        merge.merge_path(&path)?;
+    }
+
+    merge.resolve_directory_conflicts();
+
+    let mut result = merge.result;
+    merge.messages.sort_by(|a, b| a.0.cmp(&b.0));
+    result.messages = merge
+        .messages
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect();
+
+    Ok(result)
+}
+
+struct TreeMerger<'a> {
+    base: FileMap,
+    ours: FileMap,
+    theirs: FileMap,
+    labels: &'a MergeLabels,
+    style: ConflictStyle,
+    /// The base, ours and theirs paths of renamed files, by merged path
+    sources: BTreeMap<String, [String; 3]>,
+    result: TreeMerge,
+    messages: Vec<(String, String)>,
+}
+
+impl TreeMerger<'_> {
+    /// Moves the files renamed on one side to their new path in the base and on the other side,
+    /// so they are merged together. Files renamed differently on both sides, or renamed on one
+    /// side and deleted on the other, are conflicts.
+    fn follow_renames(&mut self) -> std::io::Result<()> {
+        let ours_renames = renames(&self.base, &self.ours)?;
+        let theirs_renames = renames(&self.base, &self.theirs)?;
+
+        for (from, to) in &ours_renames {
+            let base_entry = self.base.remove(from);
+
+            match theirs_renames.get(from) {
+                Some(their_to) if their_to == to => {
+                    self.base
+                        .extend(base_entry.map(|entry| (to.clone(), entry)));
+                    self.sources
+                        .insert(to.clone(), [from.clone(), to.clone(), to.clone()]);
+                }
+                Some(their_to) => {
+                    let ours_entry = self.ours.remove(to);
+                    let theirs_entry = self.theirs.remove(their_to);
+                    self.conflict(
+                        to,
+                        [base_entry.clone(), ours_entry.clone(), None],
+                        ours_entry,
+                    );
+                    self.conflict(
+                        their_to,
+                        [base_entry, None, theirs_entry.clone()],
+                        theirs_entry,
+                    );
+                    self.message(
+                        from,
+                        format!(
+                            "CONFLICT (rename/rename): {from} renamed to {to} in {} and to {their_to} in {}.",
+                            self.labels.ours, self.labels.theirs
+                        ),
+                    );
+                }
+                None => match self.theirs.remove(from) {
+                    Some(theirs_entry) if !self.theirs.contains_key(to) => {
+                        self.base
+                            .extend(base_entry.map(|entry| (to.clone(), entry)));
+                        self.theirs.insert(to.clone(), theirs_entry);
+                        self.sources
+                            .insert(to.clone(), [from.clone(), to.clone(), from.clone()]);
+                    }
+                    Some(theirs_entry) => {
+                        self.base
+                            .extend(base_entry.map(|entry| (from.clone(), entry)));
+                        self.theirs.insert(from.clone(), theirs_entry);
+                    }
+                    None => {
+                        let ours_entry = self.ours.remove(to);
+                        self.conflict(to, [base_entry, ours_entry.clone(), None], ours_entry);
+                        self.message(
+                            to,
+                            format!(
+                                "CONFLICT (rename/delete): {from} renamed to {to} in {}, but deleted in {}.",
+                                self.labels.ours, self.labels.theirs
+                            ),
+                        );
+                    }
+                },
+            }
+        }
+
+        for (from, to) in &theirs_renames {
+            if ours_renames.contains_key(from) {
+                continue;
+            }
+            let base_entry = self.base.remove(from);
+
+            match self.ours.remove(from) {
+                Some(ours_entry) if !self.ours.contains_key(to) => {
+                    self.base
+                        .extend(base_entry.map(|entry| (to.clone(), entry)));
+                    self.ours.insert(to.clone(), ours_entry);
+                    self.sources
+                        .insert(to.clone(), [from.clone(), from.clone(), to.clone()]);
+                }
+                Some(ours_entry) => {
+                    self.base
+                        .extend(base_entry.map(|entry| (from.clone(), entry)));
+                    self.ours.insert(from.clone(), ours_entry);
+                }
+                None => {
+                    let theirs_entry = self.theirs.remove(to);
+                    self.conflict(to, [base_entry, None, theirs_entry.clone()], theirs_entry);
+                    self.message(
+                        to,
+                        format!(
+                            "CONFLICT (rename/delete): {from} renamed to {to} in {}, but deleted in {}.",
+                            self.labels.theirs, self.labels.ours
+                        ),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn merge_path(&mut self, path: &str) -> std::io::Result<()> {
+        let base = self.base.get(path).cloned();
+        let ours = self.ours.get(path).cloned();
+        let theirs = self.theirs.get(path).cloned();
+
+        if ours == theirs || base == ours {
+            self.result
+                .files
+                .extend(theirs.map(|entry| (path.to_string(), entry)));
+            return Ok(());
+        }
+        if base == theirs {
+            self.result
+                .files
+                .extend(ours.map(|entry| (path.to_string(), entry)));
+            return Ok(());
+        }
+
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) => self.merge_contents(path, base, ours, theirs),
+            (Some(ours), None) => {
+                self.conflict(path, [base, Some(ours.clone()), None], Some(ours));
+                self.message(
+                    path,
+                    format!(
+                        "CONFLICT (modify/delete): {path} deleted in {} and modified in {}.  Version {} of {path} left in tree.",
+                        self.labels.theirs, self.labels.ours, self.labels.ours
+                    ),
+                );
+                Ok(())
+            }
+            (None, Some(theirs)) => {
+                self.conflict(path, [base, None, Some(theirs.clone())], Some(theirs));
+                self.message(
+                    path,
+                    format!(
+                        "CONFLICT (modify/delete): {path} deleted in {} and modified in {}.  Version {} of {path} left in tree.",
+                        self.labels.ours, self.labels.theirs, self.labels.theirs
+                    ),
+                );
+                Ok(())
+            }
+            (None, None) => unreachable!("both sides deleting a path is not a change"),
+        }
+    }
+
+    /// Merges a file changed on both sides: its mode, then its content if both sides changed it
+    fn merge_contents(
+        &mut self,
+        path: &str,
+        base: Option<(Mode, Hash)>,
+        ours: (Mode, Hash),
+        theirs: (Mode, Hash),
+    ) -> std::io::Result<()> {
+        let stages = [base.clone(), Some(ours.clone()), Some(theirs.clone())];
+        let base_mode = base.as_ref().map(|entry| entry.0);
+        let base_hash = base.as_ref().map(|entry| &entry.1);
+
+        let mode = if ours.0 == theirs.0 || base_mode == Some(ours.0) {
+            theirs.0
+        } else if base_mode == Some(theirs.0) {
+            ours.0
+        } else {
+            self.conflict(path, stages, Some(ours));
+            self.message(
+                path,
+                format!("CONFLICT (content): Merge conflict in {path}"),
+            );
+            return Ok(());
+        };
+
+        if ours.1 == theirs.1 || base_hash == Some(&ours.1) {
+            self.result.files.insert(path.to_string(), (mode, theirs.1));
+            return Ok(());
+        }
+        if base_hash == Some(&theirs.1) {
+            self.result.files.insert(path.to_string(), (mode, ours.1));
+            return Ok(());
+        }
+
+        let kind = if base.is_some() { "content" } else { "add/add" };
+        if ours.0 == Mode::SYMBOLIC || theirs.0 == Mode::SYMBOLIC {
+            self.conflict(path, stages, Some(ours));
+            self.message(path, format!("CONFLICT ({kind}): Merge conflict in {path}"));
+            return Ok(());
+        }
+
+        let base_content = match &base {
+            Some((_, hash)) => Object::read(hash)?.1,
+            None => Vec::new(),
+        };
+        let ours_content = Object::read(&ours.1)?.1;
+        let theirs_content = Object::read(&theirs.1)?.1;
+        self.message(path, format!("Auto-merging {path}"));
+
+        if is_binary(&base_content) || is_binary(&ours_content) || is_binary(&theirs_content) {
+            self.conflict(path, stages, Some(ours));
+            self.message(
+                path,
+                format!(
+                    "warning: Cannot merge binary files: {path} ({} vs. {})",
+                    self.labels.ours, self.labels.theirs
+                ),
+            );
+            self.message(path, format!("CONFLICT ({kind}): Merge conflict in {path}"));
+            return Ok(());
+        }
+
+        let labels = match self.sources.get(path) {
+            Some(paths) => self.labels.with_paths(paths),
+            None => self.labels.clone(),
+        };
+        let merged = merge_text(
+            &base_content,
+            &ours_content,
+            &theirs_content,
+            &labels,
+            self.style,
+        );
+        let hash = Object::write(ObjectType::BLOB, &merged.content)?;
+
+        if merged.conflicts > 0 {
+            self.conflict(path, stages, Some((mode, hash)));
+            self.message(path, format!("CONFLICT ({kind}): Merge conflict in {path}"));
+        } else {
+            self.result.files.insert(path.to_string(), (mode, hash));
+        }
+
+        Ok(())
+    }
+
+    /// Moves files that ended up where the other side has a directory next to it, as
+    /// `path~label` where the label names the side the file comes from
+    fn resolve_directory_conflicts(&mut self) {
+        let files = self.result.files.keys().cloned().collect::<Vec<_>>();
+        for path in files {
+            let prefix = format!("{path}/");
+            let has_directory = self
+                .result
+                .files
+                .range(prefix.clone()..)
+                .next()
+                .is_some_and(|(other, _)| other.starts_with(&prefix));
+            if !has_directory {
+                continue;
+            }
+
+            let entry = self.result.files.remove(&path).unwrap();
+            let mut stages = self
+                .result
+                .conflicts
+                .remove(&path)
+                .unwrap_or([None, None, None]);
+            let label = if self.ours.get(&path) == Some(&entry) {
+                stages[1] = Some(entry.clone());
+                &self.labels.ours
+            } else {
+                stages[2] = Some(entry.clone());
+                &self.labels.theirs
+            };
+            let new_path = format!("{path}~{}", label.replace('/', "_"));
+
+            self.message(
+                &path,
+                format!(
+                    "CONFLICT (file/directory): directory in the way of {path} from {label}; moving it to {new_path} instead."
+                ),
+            );
+            self.conflict(&new_path, stages, Some(entry));
+        }
+    }
+
+    /// Records a conflict, along with the version of the file left in the working tree if any
+    fn conflict(&mut self, path: &str, stages: Stages, worktree: Option<(Mode, Hash)>) {
+        self.result.conflicts.insert(path.to_string(), stages);
+        if let Some(entry) = worktree {
+            self.result.files.insert(path.to_string(), entry);
+        }
+    }
+
+    fn message(&mut self, path: &str, message: String) {
+        self.messages.push((path.to_string(), message));
+    }
+}
+
+/// The files renamed between two listings, from their old path to their new one
+fn renames(old: &FileMap, new: &FileMap) -> std::io::Result<BTreeMap<String, String>> {
+    Ok(diff::detect_renames(diff::diff_files(old, new))?
+        .into_iter()
+        .filter_map(|change| match change.kind {
+            ChangeKind::Renamed { from, .. } => Some((from, change.path)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Updates the index and working tree from our side of a merge to its result, recording the
+/// conflicted paths as unmerged index stages. Paths the merge touches must not have local
+/// changes, and untracked files in the way are not overwritten.
+pub(crate) fn checkout_merge(ours: &FileMap, merge: &TreeMerge) -> std::io::Result<()> {
+    let mut index = Index::load()?;
+
+    let changed = ours
+        .keys()
+        .chain(merge.files.keys())
+        .chain(merge.conflicts.keys())
+        .filter(|path| {
+            ours.get(*path) != merge.files.get(*path) || merge.conflicts.contains_key(*path)
+        })
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut local_changes = Vec::new();
+    let mut untracked = Vec::new();
+    for path in &changed {
+        match index.get(path) {
+            Some(entry)
+                if Some(&(entry.mode, entry.hash.clone())) != ours.get(path)
+                    || worktree::is_modified(&index, entry)? =>
+            {
+                local_changes.push(path.clone());
+            }
+            Some(_) => {}
+            None if ours.contains_key(path) => local_changes.push(path.clone()),
+            None if fs::symlink_metadata(path).is_ok() => {
+                let (mode, hash, _) = worktree::hash_file(path, false)?;
+                if merge.files.get(path) != Some(&(mode, hash)) {
+                    untracked.push(path.clone());
+                }
+            }
+            None => {}
+        }
+    }
+
+    if !local_changes.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "Your local changes to the following files would be overwritten by merge:\n\t{}\nPlease commit your changes or stash them before you merge.",
+            local_changes.join("\n\t")
+        )));
+    }
+    if !untracked.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "The following untracked working tree files would be overwritten by merge:\n\t{}\nPlease move or remove them before you merge.",
+            untracked.join("\n\t")
+        )));
+    }
+
+    // Removals go first so files can replace directories and the other way around
+    for path in &changed {
+        if !merge.files.contains_key(path) {
+            worktree::remove_file(path)?;
+            index.remove(path);
+        }
+    }
+    for path in changed {
+        if let Some((mode, hash)) = merge.files.get(&path) {
+            let metadata = worktree::write_file(&path, *mode, hash)?;
+            index.add(IndexEntry::from_metadata(
+                path.clone(),
+                *mode,
+                hash.clone(),
+                &metadata,
+            ));
+        }
+        if let Some(stages) = merge.conflicts.get(&path) {
+            index.add_conflict(&path, stages);
+        }
+    }
+
+    index.write()
+}
+
+/// The commits being merged into HEAD by a merge waiting to be committed
+pub(crate) fn merge_heads() -> std::io::Result<Vec<Hash>> {
+    let content = match fs::read_to_string(MERGE_HEAD_PATH) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    content
+        .lines()
+        .map(|line| {
+            Hash::from_str(line.trim())
+                .map_err(|_| std::io::Error::other("could not parse MERGE_HEAD"))
+        })
+        .collect()
+}
+
+/// The message prepared for the commit concluding the merge in progress
+pub(crate) fn merge_message() -> std::io::Result<Option<String>> {
+    match fs::read_to_string(MERGE_MSG_PATH) {
+        Ok(message) => Ok(Some(message)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Records a merge to be concluded by the next commit
+pub(crate) fn write_merge_state(heads: &[Hash], message: &str) -> std::io::Result<()> {
+    let heads = heads
+        .iter()
+        .map(|head| format!("{head}\n"))
+        .collect::<String>();
+    fs::write(MERGE_HEAD_PATH, heads)?;
+    fs::write(MERGE_MSG_PATH, message)?;
+    fs::write(MERGE_MODE_PATH, "")
+}
+
+/// Forgets the merge in progress, once committed or aborted
+pub(crate) fn clear_merge_state() -> std::io::Result<()> {
+    for path in [MERGE_HEAD_PATH, MERGE_MSG_PATH, MERGE_MODE_PATH] {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::config::Config;
+use crate::diff;
+use crate::merge::MergeLabels;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Size of the `<<<<<<<`, `|||||||`, `=======` and `>>>>>>>` conflict markers
+const MARKER_SIZE: usize = 7;
+
+/// How far git looks into a file to decide whether it is binary
+const BINARY_CHECK_SIZE: usize = 8000;
+
+/// How conflicting hunks are presented in the merged content, set by `merge.conflictStyle`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) enum ConflictStyle {
+    /// Our and their versions, without the lines both sides agree on
+    #[default]
+    Merge,
+    /// Our, the base and their versions, as they are
+    Diff3,
+    /// Our, the base and their versions, without the lines both sides agree on
+    ZDiff3,
+}
+
+impl FromStr for ConflictStyle {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(ConflictStyle::Merge),
+            "diff3" => Ok(ConflictStyle::Diff3),
+            "zdiff3" => Ok(ConflictStyle::ZDiff3),
+            _ => Err(std::io::Error::other(format!(
+                "unknown style '{s}' given for 'merge.conflictstyle'"
+            ))),
+        }
+    }
+}
+
+impl ConflictStyle {
+    pub(crate) fn from_config() -> std::io::Result<Self> {
+        match Config::load()?.get("merge.conflictStyle") {
+            Some(style) => style.parse(),
+            None => Ok(ConflictStyle::default()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TextMerge {
+    pub(crate) content: Vec<u8>,
+    /// Number of conflicting hunks written with conflict markers
+    pub(crate) conflicts: usize,
+}
+
+/// Whether content should be treated as binary, which is the case when it contains a NUL byte
+/// near its start
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_SIZE)].contains(&0)
+}
+
+/// Merges the changes made to the base on both sides, line by line. Regions that only one side
+/// changed take its version, and regions both sides changed differently are written between
+/// conflict markers.
+pub(crate) fn merge_text(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> TextMerge {
+    let base = diff::split_lines(base);
+    let ours = diff::split_lines(ours);
+    let theirs = diff::split_lines(theirs);
+
+    let ours_matches = diff::matching_lines(&base, &ours)
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let theirs_matches = diff::matching_lines(&base, &theirs)
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut merge = TextMerge {
+        content: Vec::new(),
+        conflicts: 0,
+    };
+
+    // Base lines kept by both sides split the files into chunks that are merged separately
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        let stable = (i..base.len())
+            .find(|line| ours_matches.contains_key(line) && theirs_matches.contains_key(line));
+        let (end_i, end_j, end_k) = match stable {
+            Some(line) => (line, ours_matches[&line], theirs_matches[&line]),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+
+        merge.merge_chunk(
+            &base[i..end_i],
+            &ours[j..end_j],
+            &theirs[k..end_k],
+            labels,
+            style,
+        );
+
+        if stable.is_none() {
+            break;
+        }
+        merge.content.extend_from_slice(base[end_i]);
+        (i, j, k) = (end_i + 1, end_j + 1, end_k + 1);
+    }
+
+    merge
+}
+
+impl TextMerge {
+    fn merge_chunk(
+        &mut self,
+        base: &[&[u8]],
+        ours: &[&[u8]],
+        theirs: &[&[u8]],
+        labels: &MergeLabels,
+        style: ConflictStyle,
+    ) {
+        if ours == base || ours == theirs {
+            self.content.extend(theirs.concat());
+            return;
+        }
+        if theirs == base {
+            self.content.extend(ours.concat());
+            return;
+        }
+
+        // Lines both sides added alike at the edges of the chunk are not part of the conflict
+        let (prefix, suffix) = if style == ConflictStyle::Diff3 {
+            (0, 0)
+        } else {
+            let prefix = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+            let suffix = ours[prefix..]
+                .iter()
+                .rev()
+                .zip(theirs[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            (prefix, suffix)
+        };
+
+        self.content.extend(ours[..prefix].concat());
+
+        self.push_marker('<', &labels.ours);
+        self.push_lines(&ours[prefix..ours.len() - suffix]);
+        if style != ConflictStyle::Merge {
+            self.push_marker('|', &labels.base);
+            self.push_lines(base);
+        }
+        self.push_marker('=', "");
+        self.push_lines(&theirs[prefix..theirs.len() - suffix]);
+        self.push_marker('>', &labels.theirs);
+        self.conflicts += 1;
+
+        self.content.extend(ours[ours.len() - suffix..].concat());
+    }
+
+    fn push_marker(&mut self, marker: char, label: &str) {
+        self.content
+            .extend(marker.to_string().repeat(MARKER_SIZE).into_bytes());
+        if !label.is_empty() {
+            self.content.push(b' ');
+            self.content.extend(label.as_bytes());
+        }
+        self.content.push(b'\n');
+    }
+
+    /// Appends lines, terminating the last one so the following marker starts its own line
+    fn push_lines(&mut self, lines: &[&[u8]]) {
+        for line in lines {
+            self.content.extend_from_slice(line);
+        }
+        if self.content.last().is_some_and(|&c| c != b'\n') {
+            self.content.push(b'\n');
+        }
+    }
+}
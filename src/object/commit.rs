@@ -1,10 +1,10 @@
-use crate::object::{Hash, Object};
-use flate2::read::{ZlibDecoder, ZlibEncoder};
+use crate::config::Config;
+use crate::object::{Hash, Object, ObjectType};
+use chrono::{DateTime, Local, NaiveDateTime};
 use std::fmt::Display;
-use std::fs::File;
 use std::io::Read;
-use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub(crate) struct Commit {
@@ -18,6 +18,10 @@ pub(crate) struct Commit {
     pub(crate) committer_email: String,
     pub(crate) committer_date: u64,
     pub(crate) committer_date_timezone: String,
+    /// Headers hamachi does not interpret, such as `gpgsig`, kept so the commit can be
+    /// written back unchanged
+    pub(crate) extra_headers: Vec<(String, String)>,
+    /// The full message, including its trailing newline
     pub(crate) commit_message: String,
 }
 
@@ -35,79 +39,41 @@ impl Commit {
         Self::parse_commit_content(content)
     }
 
-    pub(crate) fn from_packfile_compressed_data(data: &[u8]) -> (Self, usize) {
-        let mut decompressor = ZlibDecoder::new(data);
-        let mut decompressed_data = String::new();
-        decompressor.read_to_string(&mut decompressed_data).unwrap();
+    fn parse_commit_content(data: String) -> Self {
+        let (headers, commit_message) = data.split_once("\n\n").unwrap_or((&data, ""));
 
-        (
-            Self::parse_commit_content(decompressed_data),
-            decompressor.total_in() as usize,
-        )
-    }
+        let mut tree_hash = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
 
-    fn parse_commit_content(data: String) -> Self {
-        let mut lines = data.lines();
-
-        // Tree hash
-        let (line_type, tree_hash) = lines.next().unwrap().split_once(' ').unwrap();
-        let tree_hash = Hash::from_str(tree_hash).unwrap();
-        assert_eq!(line_type, "tree");
-
-        // Parent hash
-        // TODO: Support multiple parents
-        let parent_line = lines.next().unwrap();
-        let parent_hash = if parent_line.starts_with("parent") {
-            let parent_hash = parent_line.split_once(' ').unwrap().1;
-
-            Some(Hash::from_str(parent_hash).unwrap())
-        } else {
-            None
-        };
-        let parents = if parent_hash.is_some() {
-            vec![Parent::new(parent_hash.unwrap())]
-        } else {
-            Vec::new()
-        };
+        for line in headers.lines() {
+            // Continuation lines of multi-line headers such as signatures start with a space
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = extra_headers.last_mut() {
+                    value.push('\n');
+                    value.push_str(continuation);
+                }
+                continue;
+            }
 
-        // Author
-        let mut author_line = if parents.len() > 0 {
-            lines.next().unwrap()
-        } else {
-            parent_line
-        };
-        assert!(author_line.starts_with("author"));
-        let email_start = author_line.find("<").unwrap();
-        let email_end = author_line.find(">").unwrap();
-        let author_name = author_line["author ".len()..email_start].trim().to_string();
-        let author_email = author_line[email_start + 1..email_end].trim().to_string();
-
-        let mut remaining = author_line[email_end + 1..].trim().split_whitespace();
-        let author_date = remaining.next().unwrap();
-        let author_date = author_date.parse::<u64>().unwrap();
-        let author_date_timezone = remaining.next().unwrap().to_string();
-
-        // Committer
-        let mut committer_line = lines.next().unwrap();
-        assert!(committer_line.starts_with("committer"));
-        let email_start = committer_line.find("<").unwrap();
-        let email_end = committer_line.find(">").unwrap();
-        let committer_name = committer_line["committer ".len()..email_start]
-            .trim()
-            .to_string();
-        let committer_email = committer_line[email_start + 1..email_end]
-            .trim()
-            .to_string();
-
-        let mut remaining = committer_line[email_end + 1..].trim().split_whitespace();
-        let committer_date = remaining.next().unwrap();
-        let committer_date = committer_date.parse::<u64>().unwrap();
-        let committer_date_timezone = remaining.next().unwrap().to_string();
-
-        let commit_message = lines.skip(1).next().unwrap().to_string();
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            match name {
+                "tree" => tree_hash = Some(Hash::from_str(value).unwrap()),
+                "parent" => parents.push(Parent::new(Hash::from_str(value).unwrap())),
+                "author" => author = Some(parse_signature(value)),
+                "committer" => committer = Some(parse_signature(value)),
+                _ => extra_headers.push((name.to_string(), value.to_string())),
+            }
+        }
+
+        let (author_name, author_email, author_date, author_date_timezone) = author.unwrap();
+        let (committer_name, committer_email, committer_date, committer_date_timezone) =
+            committer.unwrap();
 
         Self {
-            tree_hash,
+            tree_hash: tree_hash.unwrap(),
             parents,
             author_name,
             author_email,
@@ -117,19 +83,56 @@ impl Commit {
             committer_email,
             committer_date,
             committer_date_timezone,
+            extra_headers,
+            commit_message: commit_message.to_string(),
+        }
+    }
+
+    pub(crate) fn new(
+        tree_hash: Hash,
+        parents: Vec<Hash>,
+        author: Signature,
+        committer: Signature,
+        commit_message: String,
+    ) -> Self {
+        Commit {
+            tree_hash,
+            parents: parents.into_iter().map(Parent::new).collect(),
+            author_name: author.name,
+            author_email: author.email,
+            author_date: author.date,
+            author_date_timezone: author.timezone,
+            committer_name: committer.name,
+            committer_email: committer.email,
+            committer_date: committer.date,
+            committer_date_timezone: committer.timezone,
+            extra_headers: Vec::new(),
             commit_message,
         }
     }
 
-    pub(crate) fn to_object_file_representation(&self) -> Vec<u8> {
-        let content = format!(
-            "tree {}{}\nauthor {} <{}> {} {}\ncommitter {} <{}> {} {}\n\n{}\n",
-            self.tree_hash.to_string(),
-            self.parents
-                .iter()
-                .map(|p| format!("\nparent {}", p.parent_hash.to_string()))
-                .collect::<Vec<String>>()
-                .join(""),
+    pub(crate) fn author(&self) -> Signature {
+        Signature {
+            name: self.author_name.clone(),
+            email: self.author_email.clone(),
+            date: self.author_date,
+            timezone: self.author_date_timezone.clone(),
+        }
+    }
+
+    /// The first line of the commit message
+    pub(crate) fn subject(&self) -> &str {
+        self.commit_message.lines().next().unwrap_or_default()
+    }
+
+    /// The commit content, without the object header
+    pub(crate) fn content(&self) -> Vec<u8> {
+        let mut content = format!("tree {}\n", self.tree_hash);
+        for parent in &self.parents {
+            content.push_str(&format!("{parent}\n"));
+        }
+        content.push_str(&format!(
+            "author {} <{}> {} {}\ncommitter {} <{}> {} {}\n",
             self.author_name,
             self.author_email,
             self.author_date,
@@ -138,13 +141,204 @@ impl Commit {
             self.committer_email,
             self.committer_date,
             self.committer_date_timezone,
-            self.commit_message
-        );
-        let header = format!("commit {}", content.len());
-        let commit_string = format!("{}\0{}", header, content);
+        ));
+        for (name, value) in &self.extra_headers {
+            content.push_str(&format!("{name} {}\n", value.replace('\n', "\n ")));
+        }
+        content.push('\n');
+        content.push_str(&self.commit_message);
+
+        content.into_bytes()
+    }
+
+    /// Writes the commit to the object database
+    pub(crate) fn write(&self) -> std::io::Result<Hash> {
+        Object::write(ObjectType::COMMIT, &self.content())
+    }
+}
+
+/// Splits `Name <email> timestamp timezone`
+fn parse_signature(signature: &str) -> (String, String, u64, String) {
+    let email_start = signature.find('<').unwrap();
+    let email_end = signature.rfind('>').unwrap();
+    let name = signature[..email_start].trim().to_string();
+    let email = signature[email_start + 1..email_end].trim().to_string();
+
+    let mut remaining = signature[email_end + 1..].split_whitespace();
+    let date = remaining.next().unwrap_or("0").parse::<u64>().unwrap_or(0);
+    let timezone = remaining.next().unwrap_or("+0000").to_string();
+
+    (name, email, date, timezone)
+}
+
+/// The offset of the local timezone in the `+HHMM` format used in commit objects
+pub(crate) fn local_timezone() -> String {
+    let offset = chrono::Local::now().offset().local_minus_utc();
+
+    format_timezone(offset)
+}
+
+fn format_timezone(offset_seconds: i32) -> String {
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let offset = offset_seconds.abs() / 60;
+
+    format!("{sign}{:02}{:02}", offset / 60, offset % 60)
+}
+
+/// An identity and a point in time, as recorded in the author and committer lines of a commit
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Signature {
+    pub(crate) name: String,
+    pub(crate) email: String,
+    pub(crate) date: u64,
+    pub(crate) timezone: String,
+}
 
-        commit_string.as_bytes().to_vec()
+impl Signature {
+    /// The author from `GIT_AUTHOR_NAME`, `GIT_AUTHOR_EMAIL` and `GIT_AUTHOR_DATE`, falling back
+    /// to `user.name`, `user.email` and the current time
+    pub(crate) fn author() -> std::io::Result<Self> {
+        Self::from_environment("AUTHOR")
     }
+
+    /// The committer from `GIT_COMMITTER_NAME`, `GIT_COMMITTER_EMAIL` and `GIT_COMMITTER_DATE`,
+    /// falling back to `user.name`, `user.email` and the current time
+    pub(crate) fn committer() -> std::io::Result<Self> {
+        Self::from_environment("COMMITTER")
+    }
+
+    /// The committer recorded in reflogs. Unlike commits, ref updates never fail for want of an
+    /// identity: as git does in a fresh clone, the login name stands in for what is not
+    /// configured, and the current time for a date that cannot be parsed.
+    pub(crate) fn reflog_committer() -> Self {
+        let config = Config::load().ok();
+        let variable = |name: &str| std::env::var(format!("GIT_COMMITTER_{name}")).ok();
+        let configured = |key: &str| config.as_ref().and_then(|config| config.get(key));
+        let login = || {
+            ["USER", "USERNAME", "LOGNAME"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .unwrap_or_else(|| String::from("unknown"))
+        };
+        let host = || {
+            ["HOSTNAME", "COMPUTERNAME"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .unwrap_or_else(|| String::from("localhost"))
+        };
+
+        let name = variable("NAME")
+            .or_else(|| configured("user.name"))
+            .unwrap_or_else(login);
+        let email = variable("EMAIL")
+            .or_else(|| configured("user.email"))
+            .unwrap_or_else(|| format!("{}@{}", login(), host()));
+        let (date, timezone) = variable("DATE")
+            .and_then(|date| parse_date(&date).ok())
+            .unwrap_or_else(now);
+
+        Signature {
+            name,
+            email,
+            date,
+            timezone,
+        }
+    }
+
+    fn from_environment(role: &str) -> std::io::Result<Self> {
+        let config = Config::load()?;
+        let variable = |name: &str| std::env::var(format!("GIT_{role}_{name}")).ok();
+
+        let name = variable("NAME").or_else(|| config.get("user.name"));
+        let email = variable("EMAIL").or_else(|| config.get("user.email"));
+        let (Some(name), Some(email)) = (name, email) else {
+            return Err(std::io::Error::other(
+                "Author identity unknown\n\nPlease tell me who you are by running\n\n  hamachi config set user.email \"you@example.com\"\n  hamachi config set user.name \"Your Name\"",
+            ));
+        };
+
+        let (date, timezone) = match variable("DATE") {
+            Some(date) => parse_date(&date)?,
+            None => now(),
+        };
+
+        Ok(Signature {
+            name,
+            email,
+            date,
+            timezone,
+        })
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name, self.email, self.date, self.timezone
+        )
+    }
+}
+
+/// The current time as a timestamp and the local timezone
+pub(crate) fn now() -> (u64, String) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    (now, local_timezone())
+}
+
+/// Parses a date in one of the formats git accepts for `--date` and `GIT_AUTHOR_DATE`: its
+/// internal `<timestamp> <timezone>` format (optionally prefixed with `@`), RFC 2822 and
+/// ISO 8601. Dates without a timezone are in the local timezone.
+pub(crate) fn parse_date(date: &str) -> std::io::Result<(u64, String)> {
+    let date = date.trim();
+    let invalid_date = || std::io::Error::other(format!("invalid date format: {date}"));
+
+    let internal = date.strip_prefix('@').unwrap_or(date);
+    let mut parts = internal.split_whitespace();
+    if let Some(Ok(timestamp)) = parts.next().map(str::parse::<u64>) {
+        let timezone = match parts.next() {
+            Some(timezone) if is_timezone(timezone) => timezone.to_string(),
+            Some(_) => return Err(invalid_date()),
+            None => String::from("+0000"),
+        };
+
+        return Ok((timestamp, timezone));
+    }
+
+    let with_offset = DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z"))
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%z"));
+    if let Ok(parsed) = with_offset {
+        return Ok((
+            parsed.timestamp().max(0) as u64,
+            format_timezone(parsed.offset().local_minus_utc()),
+        ));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S"))
+        .map_err(|_| invalid_date())?;
+    let local = naive
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(invalid_date)?;
+
+    Ok((
+        local.timestamp().max(0) as u64,
+        format_timezone(local.offset().local_minus_utc()),
+    ))
+}
+
+fn is_timezone(timezone: &str) -> bool {
+    timezone.len() == 5
+        && timezone.starts_with(['+', '-'])
+        && timezone[1..].chars().all(|c| c.is_ascii_digit())
 }
 
 #[derive(Debug)]
@@ -160,6 +354,6 @@ impl Parent {
 
 impl Display for Parent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", format!("parent {}", self.parent_hash.to_string()))
+        write!(f, "parent {}", self.parent_hash)
     }
 }
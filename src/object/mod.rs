@@ -1,5 +1,9 @@
 use crate::object::tree::Mode;
+use crate::remote::promisor;
 use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
 use std::ffi::CStr;
 use std::fmt::{Display, Formatter};
 use std::fs;
@@ -8,7 +12,6 @@ use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-pub mod blob;
 pub mod commit;
 pub mod packfile;
 pub mod tree;
@@ -25,11 +28,13 @@ pub struct Header {
     pub(crate) size: usize,
 }
 
-#[derive(Debug, PartialOrd, Eq, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialOrd, Eq, PartialEq)]
 pub(crate) enum ObjectType {
     BLOB,
     TREE,
     COMMIT,
+    TAG,
 }
 
 impl Object {
@@ -37,7 +42,18 @@ impl Object {
         let (subdirectory, file_name) = Self::get_path_from_hash(hash).expect("Invalid hash");
         let file_path = format!(".hamachi/objects/{}/{}", subdirectory, file_name);
 
-        let compressed_file = File::open(file_path).expect("Can't open object file");
+        let compressed_file = match File::open(&file_path) {
+            Ok(file) => file,
+            // Partial clones fetch the objects they left out the first time they are needed
+            Err(_) => {
+                if let Ok(missing) = Hash::from_str(hash) {
+                    if let Err(e) = promisor::fetch_missing(&[missing]) {
+                        eprintln!("error: could not fetch {hash} from promisor remote: {e}");
+                    }
+                }
+                File::open(file_path).map_err(|_| "Object not found")?
+            }
+        };
 
         let decompressor = ZlibDecoder::new(compressed_file);
         let mut file_buffer_reader = BufReader::new(decompressor);
@@ -72,7 +88,61 @@ impl Object {
         })
     }
 
-    pub(crate) fn write_to_disk(hash: &Hash, content: &Vec<u8>) -> std::io::Result<()> {
+    /// Reads the type and the full decompressed content of the object with the specified hash
+    pub(crate) fn read(hash: &Hash) -> std::io::Result<(ObjectType, Vec<u8>)> {
+        let mut object = Self::from_hash(&hash.to_string()).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, format!("{e} {hash}"))
+        })?;
+
+        let mut content = Vec::with_capacity(object.header.size);
+        object.content_buffer_reader.read_to_end(&mut content)?;
+
+        Ok((object.header.object_type, content))
+    }
+
+    /// Computes the hash an object of the specified type and content would be stored under
+    pub(crate) fn hash(object_type: ObjectType, content: &[u8]) -> Hash {
+        let header = format!("{} {}\0", object_type, content.len());
+
+        let mut hasher = Sha1::new();
+        Digest::update(&mut hasher, header.as_bytes());
+        Digest::update(&mut hasher, content);
+
+        Hash(hasher.finalize().to_vec())
+    }
+
+    /// Hashes, compresses and writes an object of the specified type and content to the disk
+    pub(crate) fn write(object_type: ObjectType, content: &[u8]) -> std::io::Result<Hash> {
+        let hash = Self::hash(object_type, content);
+        if Self::exists(&hash) {
+            return Ok(hash);
+        }
+
+        let header = format!("{} {}\0", object_type, content.len());
+
+        let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressor.write_all(header.as_bytes())?;
+        compressor.write_all(content)?;
+        let compressed_bytes = compressor.finish()?;
+
+        Self::write_to_disk(&hash, &compressed_bytes)?;
+
+        Ok(hash)
+    }
+
+    /// Whether an object with the specified hash is present in the object database
+    pub(crate) fn exists(hash: &Hash) -> bool {
+        let hash_string = hash.to_string();
+        let (subdirectory, file_name) =
+            Self::get_path_from_hash(&hash_string).expect("Invalid hash");
+
+        PathBuf::from(".hamachi/objects")
+            .join(subdirectory)
+            .join(file_name)
+            .exists()
+    }
+
+    pub(crate) fn write_to_disk(hash: &Hash, content: &[u8]) -> std::io::Result<()> {
         let string_hash = hash.to_string();
         let (subdirectory, file_name) =
             Self::get_path_from_hash(&string_hash).expect("Invalid hash");
@@ -82,9 +152,10 @@ impl Object {
         fs::create_dir_all(format!(".hamachi/objects/{}", subdirectory))?;
 
         if file_path.exists() {
-            let mut perms = fs::metadata(&file_path)?.permissions();
+            let mut perms = fs::metadata(file_path)?.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
             perms.set_readonly(false);
-            fs::set_permissions(&file_path, perms)?;
+            fs::set_permissions(file_path, perms)?;
         }
 
         let mut file = OpenOptions::new()
@@ -93,11 +164,11 @@ impl Object {
             .truncate(true)
             .open(file_path)?;
 
-        file.write_all(&content)?;
+        file.write_all(content)?;
 
-        let mut perms = fs::metadata(&file_path)?.permissions();
+        let mut perms = fs::metadata(file_path)?.permissions();
         perms.set_readonly(true);
-        fs::set_permissions(&file_path, perms)?;
+        fs::set_permissions(file_path, perms)?;
 
         Ok(())
     }
@@ -109,6 +180,7 @@ impl Object {
         Ok((subdirectory, file_name))
     }
 
+    #[cfg(test)]
     pub fn decompress_object(hash: &str, is_git: bool) -> std::io::Result<Vec<u8>> {
         let (subdirectory, file_name) = Self::get_path_from_hash(hash).expect("Invalid hash");
         let path = PathBuf::from(if is_git {
@@ -116,8 +188,8 @@ impl Object {
         } else {
             ".hamachi/objects"
         })
-        .join(&subdirectory)
-        .join(&file_name);
+        .join(subdirectory)
+        .join(file_name);
         let file = File::open(path)?;
 
         let mut decompressed = Vec::new();
@@ -144,7 +216,9 @@ impl FromStr for ObjectType {
         match s {
             "blob" => Ok(ObjectType::BLOB),
             "tree" => Ok(ObjectType::TREE),
-            _ => Ok(ObjectType::TREE),
+            "commit" => Ok(ObjectType::COMMIT),
+            "tag" => Ok(ObjectType::TAG),
+            _ => Err(()),
         }
     }
 }
@@ -158,12 +232,13 @@ impl Display for ObjectType {
                 ObjectType::BLOB => "blob",
                 ObjectType::TREE => "tree",
                 ObjectType::COMMIT => "commit",
+                ObjectType::TAG => "tag",
             }
         )
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Hash(pub Vec<u8>);
 
 impl Display for Hash {
@@ -172,10 +247,37 @@ impl Display for Hash {
     }
 }
 
+impl Hash {
+    /// The abbreviated form of the hash used in human readable output
+    pub(crate) fn to_short_string(&self) -> String {
+        self.to_string()[..7].to_string()
+    }
+}
+
 impl FromStr for Hash {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        hex::decode(s).map_err(|_| ()).map(|v| Hash(v))
+        hex::decode(s)
+            .map_err(|_| ())
+            .and_then(|v| if v.len() == 20 { Ok(Hash(v)) } else { Err(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::Hash;
+    use std::str::FromStr;
+
+    #[test]
+    fn hash_from_str_test() {
+        let hash = Hash::from_str("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391").unwrap();
+        assert_eq!(hash.to_string(), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+
+        // Abbreviated, overlong and non hexadecimal hashes are not object names
+        assert!(Hash::from_str("e69de29").is_err());
+        assert!(Hash::from_str("e69de29bb2d1d6434b8b29ae775ad8c2e48c539100").is_err());
+        assert!(Hash::from_str("master").is_err());
+        assert!(Hash::from_str("").is_err());
     }
 }
@@ -1,133 +1,339 @@
-use crate::object::{Hash, Object, ObjectType};
-use std::io::Read;
+use std::collections::HashMap;
 
-pub fn parse_ref_delta_file(data: Vec<u8>, base_hash: &Hash) -> Vec<u8> {
-    let mut read_pointer = 0;
+/// The size of the blocks of the base that are indexed, and so the shortest copy looked for
+const BLOCK_SIZE: usize = 16;
+/// The multiplier of the rolling hash of a block
+const HASH_MULTIPLIER: u32 = 0x01000193;
+/// How many blocks of the base sharing a hash are kept, as repetitive content would otherwise
+/// make every lookup slow
+const MAX_BUCKET_SIZE: usize = 64;
+/// The most a single copy instruction copies
+const MAX_COPY_SIZE: usize = 0x10000;
+/// The most a single insert instruction inserts
+const MAX_INSERT_SIZE: usize = 0x7f;
 
-    // Base object
-    let mut base_object = Object::from_hash(&base_hash.to_string()).unwrap();
-    let mut base_object_content = Vec::new();
-    base_object
-        .content_buffer_reader
-        .read_to_end(&mut base_object_content)
-        .expect("TODO: panic message");
+/// Rebuilds an object from its base and a delta in git's format: the sizes of the base and of
+/// the result, followed by instructions that either copy a range of the base or insert new data
+/// https://git-scm.com/docs/pack-format#_deltified_representation
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> std::io::Result<Vec<u8>> {
+    let corrupted = || std::io::Error::other("delta data is corrupted");
+    let mut read_pointer = 0;
 
-    // Parse source size
-    let (source_size, read_bytes) = parse_varint(&data[read_pointer..]);
+    let (source_size, read_bytes) = parse_varint(&delta[read_pointer..]).ok_or_else(corrupted)?;
     read_pointer += read_bytes;
+    if source_size != base.len() {
+        return Err(std::io::Error::other("delta base size does not match"));
+    }
 
-    assert_eq!(base_object_content.len(), source_size);
-
-    // Parse target source size
-    let (target_size, read_bytes) = parse_varint(&data[read_pointer..]);
+    let (target_size, read_bytes) = parse_varint(&delta[read_pointer..]).ok_or_else(corrupted)?;
     read_pointer += read_bytes;
 
-    let mut undeltified_data = Vec::with_capacity(target_size);
-    while read_pointer < data.len() {
-        let (mut parsed_data, read_bytes) =
-            parse_instruction(&data[read_pointer..], &base_object_content);
-        read_pointer += read_bytes;
+    // The announced size is only checked, the result growing as instructions add to it
+    let mut target = Vec::new();
+    while read_pointer < delta.len() {
+        let instruction = delta[read_pointer];
+        read_pointer += 1;
 
-        undeltified_data.append(&mut parsed_data);
-    }
+        if instruction & 0x80 != 0 {
+            let (offset, size, read_bytes) =
+                parse_copy_instruction(instruction, &delta[read_pointer..])
+                    .ok_or_else(corrupted)?;
+            read_pointer += read_bytes;
 
-    // TODO: Move this out of here
-    match base_object.header.object_type {
-        ObjectType::BLOB => {
-            todo!()
+            let end = offset.checked_add(size).ok_or_else(corrupted)?;
+            let copied = base.get(offset..end).ok_or_else(corrupted)?;
+            target.extend_from_slice(copied);
+        } else if instruction != 0 {
+            let size = instruction as usize;
+            let inserted = delta
+                .get(read_pointer..read_pointer + size)
+                .ok_or_else(corrupted)?;
+            target.extend_from_slice(inserted);
+            read_pointer += size;
+        } else {
+            return Err(corrupted());
         }
-        ObjectType::TREE => {
-            let mut header = format!("tree {}\0", target_size).as_bytes().to_vec();
-            header.append(&mut undeltified_data);
 
-            header
-        }
-        ObjectType::COMMIT => {
-            todo!()
+        if target.len() > target_size {
+            return Err(std::io::Error::other("delta result size does not match"));
         }
     }
-}
 
-fn parse_varint(data: &[u8]) -> (usize, usize) {
-    let mut byte = 0x80;
-    let mut val = 0usize;
-    let mut shift = 0usize;
+    if target.len() != target_size {
+        return Err(std::io::Error::other("delta result size does not match"));
+    }
+
+    Ok(target)
+}
 
-    let mut read_bytes = 0;
+/// Parses the little-endian base 128 sizes at the start of a delta, returning the value and the
+/// number of bytes read
+fn parse_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    let mut shift = 0;
 
-    while (byte & 0x80) > 0 {
-        byte = *data.get(read_bytes).unwrap();
-        val += ((byte & 127) as usize) << shift;
+    for (i, byte) in data.iter().enumerate() {
+        if shift >= usize::BITS {
+            return None;
+        }
+        value |= ((byte & 0x7f) as usize) << shift;
         shift += 7;
 
-        read_bytes += 1;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
     }
 
-    (val, read_bytes)
+    None
 }
 
-fn parse_variable_size_integer(data: &[u8]) -> Vec<u8> {
+/// The bits 0 to 3 of a copy instruction tell which bytes of the offset follow and the bits 4
+/// to 6 which bytes of the size, least significant first. A size of 0 stands for 0x10000.
+fn parse_copy_instruction(instruction: u8, data: &[u8]) -> Option<(usize, usize, usize)> {
     let mut read_pointer = 0;
-    let mut is_final_byte = false;
-    let mut source_size_bits = Vec::new();
 
-    while !is_final_byte {
-        let byte = data.get(read_pointer).unwrap();
+    let mut offset = 0usize;
+    for i in 0..4 {
+        if instruction & (1 << i) != 0 {
+            offset |= (*data.get(read_pointer)? as usize) << (8 * i);
+            read_pointer += 1;
+        }
+    }
 
-        is_final_byte = *byte < 128u8;
-        source_size_bits.push(byte & 0b1111111);
-        read_pointer += 1;
+    let mut size = 0usize;
+    for i in 0..3 {
+        if instruction & (1 << (4 + i)) != 0 {
+            size |= (*data.get(read_pointer)? as usize) << (8 * i);
+            read_pointer += 1;
+        }
+    }
+    if size == 0 {
+        size = 0x10000;
     }
 
-    source_size_bits
+    Some((offset, size, read_pointer))
 }
 
-fn parse_instruction(data: &[u8], base_object: &Vec<u8>) -> (Vec<u8>, usize) {
-    let msb = data.get(0).unwrap() >> 7;
-    match msb {
-        0b0 => {
-            let parsed_data = parse_insert_instruction(data);
-            let len = parsed_data.len() + 1;
-            (parsed_data, len)
-        }
-        0b1 => parse_copy_instruction(data, base_object),
-        _ => {
-            panic!(
-                "Something catastrophic happened the universe is probably on the verge of collapse"
-            )
+/// An index of the blocks of a base, to compute deltas of several objects against it
+pub(crate) struct DeltaIndex {
+    base: Vec<u8>,
+    /// The offsets of the blocks of the base, by their hash
+    blocks: HashMap<u32, Vec<usize>>,
+}
+
+impl DeltaIndex {
+    pub(crate) fn new(base: Vec<u8>) -> Self {
+        let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+        for offset in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE) {
+            let bucket = blocks.entry(block_hash(&base[offset..])).or_default();
+            if bucket.len() < MAX_BUCKET_SIZE {
+                bucket.push(offset);
+            }
         }
+
+        DeltaIndex { base, blocks }
     }
-}
 
-fn parse_copy_instruction(data: &[u8], base_object: &Vec<u8>) -> (Vec<u8>, usize) {
-    let mut read_pointer = 1;
+    pub(crate) fn base_size(&self) -> usize {
+        self.base.len()
+    }
 
-    let mut offset_bytes: [u8; 4] = [0, 0, 0, 0];
-    for i in 0..4 {
-        if data.get(0).unwrap() & (2u8.pow(i)) > 0 {
-            offset_bytes[i as usize] = *data.get(read_pointer).unwrap();
-            read_pointer += 1;
+    /// Computes a delta rebuilding the target from the base, the way git's diff-delta does: a
+    /// rolling hash of every block of the target is looked up in the index, a match is
+    /// extended as far as the contents agree in both directions and becomes a copy, and what
+    /// no match covers is inserted. Gives up, returning `None`, once the delta outgrows
+    /// `max_size`.
+    pub(crate) fn create_delta(&self, target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+        let mut delta = encode_varint(self.base.len());
+        delta.extend(encode_varint(target.len()));
+
+        let mut insert_start = 0;
+        let mut position = 0;
+        let mut hash = None;
+        while position + BLOCK_SIZE <= target.len() {
+            let block_hash = *hash.get_or_insert_with(|| block_hash(&target[position..]));
+            let (base_offset, length) = self.longest_match(target, position, block_hash);
+            if length < BLOCK_SIZE {
+                if position + BLOCK_SIZE < target.len() {
+                    hash = Some(roll_hash(
+                        block_hash,
+                        target[position],
+                        target[position + BLOCK_SIZE],
+                    ));
+                }
+                position += 1;
+                continue;
+            }
+
+            // Take back what matches right before from the pending insert
+            let mut start = position;
+            let mut base_start = base_offset;
+            while start > insert_start
+                && base_start > 0
+                && target[start - 1] == self.base[base_start - 1]
+            {
+                start -= 1;
+                base_start -= 1;
+            }
+
+            write_inserts(&mut delta, &target[insert_start..start]);
+            write_copies(&mut delta, base_start, length + position - start);
+            if delta.len() > max_size {
+                return None;
+            }
+            position += length;
+            insert_start = position;
+            hash = None;
         }
+        write_inserts(&mut delta, &target[insert_start..]);
+
+        (delta.len() <= max_size).then_some(delta)
     }
 
-    let mut length_bytes: [u8; 4] = [0, 0, 0, 0];
-    for i in 0..3 {
-        if ((data.get(0).unwrap() >> 4) & (2u8.pow(2 - i))) > 0u8 {
-            length_bytes[i as usize + 1] = *data.get(read_pointer).unwrap();
-            read_pointer += 1;
+    /// The longest run of the base matching the target from the specified position, among the
+    /// blocks sharing its hash
+    fn longest_match(&self, target: &[u8], position: usize, hash: u32) -> (usize, usize) {
+        let mut best = (0, 0);
+        for &offset in self.blocks.get(&hash).into_iter().flatten() {
+            let length = self.base[offset..]
+                .iter()
+                .zip(&target[position..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.1 {
+                best = (offset, length);
+            }
         }
+
+        best
     }
+}
 
-    let offset = u32::from_be_bytes(offset_bytes);
-    let length = u32::from_be_bytes(length_bytes);
+/// The hash of the block starting the data, rolled by `roll_hash` along the target
+fn block_hash(data: &[u8]) -> u32 {
+    data[..BLOCK_SIZE].iter().fold(0u32, |hash, &byte| {
+        hash.wrapping_mul(HASH_MULTIPLIER).wrapping_add(byte as u32)
+    })
+}
 
-    let copy_content = &base_object[offset as usize..(offset + length) as usize];
+/// The hash of the block one byte further, from the hash of a block, its first byte and the
+/// byte following it
+fn roll_hash(hash: u32, removed: u8, added: u8) -> u32 {
+    let removed_weight = HASH_MULTIPLIER.wrapping_pow(BLOCK_SIZE as u32 - 1);
+    hash.wrapping_sub((removed as u32).wrapping_mul(removed_weight))
+        .wrapping_mul(HASH_MULTIPLIER)
+        .wrapping_add(added as u32)
+}
 
-    (copy_content.to_vec(), read_pointer)
+fn encode_varint(mut value: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+
+    bytes
 }
 
-fn parse_insert_instruction(data: &[u8]) -> Vec<u8> {
-    let size = data.get(0).unwrap() & 0b1111111;
+fn write_inserts(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
 
-    data[1..size as usize + 1].to_vec()
+/// Writes copy instructions, whose offset and size bytes are only present when not zero
+fn write_copies(delta: &mut Vec<u8>, mut offset: usize, mut size: usize) {
+    while size > 0 {
+        let copied = size.min(MAX_COPY_SIZE);
+        let position = delta.len();
+        let mut instruction = 0x80;
+        delta.push(0);
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                delta.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = (copied >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 1 << (4 + i);
+                delta.push(byte);
+            }
+        }
+        delta[position] = instruction;
+
+        offset += copied;
+        size -= copied;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_delta, DeltaIndex};
+
+    #[test]
+    fn delta_round_trip_test() {
+        let base = (0..2000)
+            .map(|i| format!("line {i}\n"))
+            .collect::<String>()
+            .into_bytes();
+        let mut target = b"a new first line\n".to_vec();
+        target.extend_from_slice(&base[..5000]);
+        target.extend_from_slice(b"something inserted in the middle\n");
+        target.extend_from_slice(&base[7000..]);
+        target.extend_from_slice(&base[100..300]);
+
+        let index = DeltaIndex::new(base.clone());
+        let delta = index.create_delta(&target, usize::MAX).unwrap();
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        assert!(delta.len() < 200);
+        assert!(index.create_delta(&target, 50).is_none());
+
+        // Copies larger than a single instruction allows, and contents shorter than a block
+        let base = vec![7; 3 * 0x10000 + 5];
+        let delta = DeltaIndex::new(base.clone())
+            .create_delta(&base, usize::MAX)
+            .unwrap();
+        assert_eq!(apply_delta(&base, &delta).unwrap(), base);
+        for (base, target) in [
+            (&b""[..], &b"short"[..]),
+            (b"short", b""),
+            (b"short", b"shorter"),
+        ] {
+            let delta = DeltaIndex::new(base.to_vec())
+                .create_delta(target, usize::MAX)
+                .unwrap();
+            assert_eq!(apply_delta(base, &delta).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn corrupt_delta_test() {
+        let base = b"some base content\n";
+        let delta = DeltaIndex::new(base.to_vec())
+            .create_delta(b"some base content, and more\n", usize::MAX)
+            .unwrap();
+        let error = |base: &[u8], delta: &[u8]| apply_delta(base, delta).unwrap_err().to_string();
+
+        // Truncated instructions, a result larger or smaller than announced, a base of another
+        // size, and sizes that do not fit in usize
+        assert_eq!(
+            error(base, &delta[..delta.len() - 1]),
+            "delta data is corrupted"
+        );
+        assert_eq!(
+            error(base, &[&delta[..], b"\x01!"].concat()),
+            "delta result size does not match"
+        );
+        assert_eq!(
+            error(base, &[18, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x91, 0, 18]),
+            "delta result size does not match"
+        );
+        assert_eq!(error(&base[1..], &delta), "delta base size does not match");
+        assert_eq!(error(base, &[0xff; 12]), "delta data is corrupted");
+    }
 }
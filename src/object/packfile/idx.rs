@@ -1 +1,146 @@
+use crate::object::packfile::PackFile;
+use crate::object::Hash;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::Path;
 
+/// The signature opening a version 2 index, `\377tOc`
+const SIGNATURE: [u8; 4] = [0xff, 0x74, 0x4f, 0x63];
+const VERSION: u32 = 2;
+/// The bit of a 4 bytes offset telling that it is the position of an 8 bytes offset instead
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+/// An object of a pack as its index records it
+#[derive(Debug, PartialEq)]
+pub(crate) struct IndexEntry {
+    pub(crate) hash: Hash,
+    pub(crate) crc32: u32,
+    pub(crate) offset: u64,
+}
+
+/// The index of a pack: its objects sorted by hash, and the checksum of the pack it indexes
+#[derive(Debug)]
+pub(crate) struct PackIndex {
+    pub(crate) entries: Vec<IndexEntry>,
+    pub(crate) pack_hash: Hash,
+}
+
+/// Writes a version 2 index for a pack: a fan-out table counting the objects by first byte of
+/// their hash, then their sorted hashes, CRC32s and offsets, offsets that do not fit in 31 bits
+/// being kept in a table of 8 bytes offsets, and last the checksums of the pack and the index
+/// https://git-scm.com/docs/pack-format#_version_2_pack_idx_files_support_packs_larger_than_4_gib_and
+pub(crate) fn write(path: &Path, pack: &PackFile) -> std::io::Result<()> {
+    let mut entries = pack.entries.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+    let mut data = Vec::with_capacity(1072 + entries.len() * 28);
+    data.extend_from_slice(&SIGNATURE);
+    data.extend_from_slice(&VERSION.to_be_bytes());
+    for byte in 0..=u8::MAX {
+        let count = entries.partition_point(|entry| entry.hash.0[0] <= byte);
+        data.extend_from_slice(&(count as u32).to_be_bytes());
+    }
+    for entry in &entries {
+        data.extend_from_slice(&entry.hash.0);
+    }
+    for entry in &entries {
+        data.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for entry in &entries {
+        let offset = match u32::try_from(entry.offset) {
+            Ok(offset) if offset < LARGE_OFFSET => offset,
+            _ => {
+                large_offsets.push(entry.offset);
+                LARGE_OFFSET | (large_offsets.len() as u32 - 1)
+            }
+        };
+        data.extend_from_slice(&offset.to_be_bytes());
+    }
+    for offset in large_offsets {
+        data.extend_from_slice(&offset.to_be_bytes());
+    }
+    data.extend_from_slice(&pack.hash.0);
+    let checksum = Sha1::digest(&data);
+    data.extend_from_slice(&checksum);
+
+    fs::write(path, data)
+}
+
+/// Reads a version 2 index, checking its structure and its checksum
+pub(crate) fn read(path: &Path) -> std::io::Result<PackIndex> {
+    let data = fs::read(path)?;
+    let name = path.display();
+    let corrupt = || std::io::Error::other(format!("index file {name} is corrupt"));
+
+    if data.len() < 8 + 256 * 4 + 40 {
+        return Err(std::io::Error::other(format!(
+            "index file {name} is too small"
+        )));
+    }
+    if data[..4] != SIGNATURE {
+        return Err(std::io::Error::other(format!(
+            "index file {name} is version 1 and is not supported"
+        )));
+    }
+    let version = read_u32(&data, 4);
+    if version != VERSION {
+        return Err(std::io::Error::other(format!(
+            "index file {name} is version {version} and is not supported"
+        )));
+    }
+    let (content, checksum) = data.split_at(data.len() - 20);
+    if Sha1::digest(content).as_slice() != checksum {
+        return Err(std::io::Error::other(format!(
+            "Packfile index for {name} SHA1 mismatch"
+        )));
+    }
+
+    let fanout = (0..256)
+        .map(|i| read_u32(&data, 8 + i * 4))
+        .collect::<Vec<_>>();
+    if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(std::io::Error::other(format!("non-monotonic index {name}")));
+    }
+    let count = fanout[255] as usize;
+    let hashes = 8 + 256 * 4;
+    let crcs = hashes + count * 20;
+    let offsets = crcs + count * 4;
+    let large_offsets = offsets + count * 4;
+    if content.len() < large_offsets + 20 {
+        return Err(corrupt());
+    }
+    let large_offset_count = (content.len() - 20 - large_offsets) / 8;
+
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = match read_u32(&data, offsets + i * 4) {
+            offset if offset & LARGE_OFFSET == 0 => offset as u64,
+            offset => {
+                let position = (offset & !LARGE_OFFSET) as usize;
+                if position >= large_offset_count {
+                    return Err(corrupt());
+                }
+                let start = large_offsets + position * 8;
+                u64::from_be_bytes(data[start..start + 8].try_into().unwrap())
+            }
+        };
+        entries.push(IndexEntry {
+            hash: Hash(data[hashes + i * 20..hashes + (i + 1) * 20].to_vec()),
+            crc32: read_u32(&data, crcs + i * 4),
+            offset,
+        });
+    }
+    if entries.windows(2).any(|pair| pair[0].hash >= pair[1].hash) {
+        return Err(corrupt());
+    }
+
+    Ok(PackIndex {
+        entries,
+        pack_hash: Hash(content[content.len() - 20..].to_vec()),
+    })
+}
+
+fn read_u32(data: &[u8], position: usize) -> u32 {
+    u32::from_be_bytes(data[position..position + 4].try_into().unwrap())
+}
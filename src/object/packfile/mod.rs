@@ -1,258 +1,789 @@
 mod delta;
-mod idx;
-
-use crate::object;
-use crate::object::commit::Commit;
-use crate::object::packfile::delta::parse_ref_delta_file;
-use crate::object::tree::Tree;
-use crate::object::{Hash, Object};
-use flate2::read::ZlibDecoder;
+pub(crate) mod idx;
+pub(crate) mod writer;
+
+use crate::object::packfile::delta::apply_delta;
+use crate::object::{Hash, Object, ObjectType};
+use crate::progress::Progress;
+use flate2::bufread::ZlibDecoder;
 use flate2::write::ZlibEncoder;
-use flate2::Compression;
+use flate2::{Compression, Crc};
+use rand::distr::{Alphanumeric, SampleString};
 use sha1::{Digest, Sha1};
-use std::fs;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::str::FromStr;
-
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const PACK_DIR: &str = ".hamachi/objects/pack";
+/// The type of an entry holding a delta against an earlier entry, found by offset
+const OFS_DELTA: u8 = 6;
+/// The type of an entry holding a delta against an object given by its hash
+const REF_DELTA: u8 = 7;
+
+/// A pack whose entries have been indexed and whose deltas have been resolved
+/// https://git-scm.com/docs/pack-format
 #[derive(Debug)]
-pub struct PackFile {
-    pub(crate) header: PackFileHeader,
-    pub(crate) entries: Vec<PackFileEntry>,
+pub(crate) struct PackFile {
+    /// The checksum closing the pack, which also names its file
+    pub(crate) hash: Hash,
+    /// The entries of the pack, in the order they appear in it
+    pub(crate) entries: Vec<PackEntry>,
+    /// The number of objects a thin pack lacked, appended to it from the object database
+    pub(crate) local_objects: usize,
 }
 
-#[derive(Debug)]
-pub struct PackFileHeader {
-    pub signature: String,
-    pub hash: String,
-    pub version: u32,
-    pub entry_count: u32,
+/// An entry of an indexed pack and the object it holds
+#[derive(Debug, Clone)]
+pub(crate) struct PackEntry {
+    pub(crate) hash: Hash,
+    pub(crate) object_type: ObjectType,
+    /// The size the entry header gives: the size of the object, or of the delta for deltas
+    pub(crate) size: usize,
+    /// The size of the entry in the pack, header included
+    pub(crate) packed_size: u64,
+    pub(crate) offset: u64,
+    /// The CRC32 of the entry in the pack, which the index records
+    pub(crate) crc32: u32,
+    /// The length of the chain of deltas leading to the object, 0 for a whole object
+    pub(crate) depth: usize,
+    /// The object a delta applies to
+    pub(crate) base: Option<Hash>,
 }
 
+/// How an entry is stored in the pack
 #[derive(Debug)]
-pub struct PackFileEntry {
-    pub hash: Hash,
-    pub object_type: ObjectType,
-    pub size: usize,
+enum EntryData {
+    Whole(ObjectType),
+    /// A delta against the entry starting at the specified offset
+    OfsDelta(u64),
+    /// A delta against the object with the specified hash, which thin packs leave out
+    RefDelta(Hash),
 }
 
-impl PackFile {
-    pub fn new(data: Vec<u8>, hash: String) -> Self {
-        // Write the file to disk
-        fs::write(format!(".hamachi/objects/pack/pack-{}.pack", hash), &data).unwrap();
+/// An entry of the pack as indexed while it is read: where it starts, where its compressed data
+/// starts and, once known, the object it holds
+struct Entry {
+    offset: u64,
+    data: EntryData,
+    data_offset: u64,
+    size: usize,
+    packed_size: u64,
+    crc32: u32,
+    resolved: Option<ResolvedObject>,
+}
 
-        let (header, mut read_bytes) = Self::parse_header(&data, hash);
+/// The object an entry turned out to hold
+#[derive(Clone)]
+struct ResolvedObject {
+    hash: Hash,
+    object_type: ObjectType,
+    depth: usize,
+}
 
-        // Objects
-        let mut entries = Vec::new();
-        for _ in 0..header.entry_count {
-            let (entry, object_size) = Self::parse_object(&data[read_bytes..]);
-            read_bytes += object_size;
-            entries.push(entry);
-        }
+/// The file a pack is received into under `objects/pack`, removed unless it is persisted
+struct TemporaryPack {
+    path: PathBuf,
+    persisted: bool,
+}
 
-        PackFile { header, entries }
+impl TemporaryPack {
+    fn create() -> std::io::Result<(Self, File)> {
+        fs::create_dir_all(PACK_DIR)?;
+        let suffix = Alphanumeric.sample_string(&mut rand::rng(), 6);
+        let path = PathBuf::from(format!("{PACK_DIR}/tmp_pack_{suffix}"));
+        let file = File::options().write(true).create_new(true).open(&path)?;
+
+        Ok((
+            TemporaryPack {
+                path,
+                persisted: false,
+            },
+            file,
+        ))
     }
 
-    fn parse_header(data: &Vec<u8>, hash: String) -> (PackFileHeader, usize) {
-        let mut read_pointer: usize = 0;
-
-        let _ = &data[read_pointer..read_pointer + 8];
-        read_pointer += 8;
-
-        // 4 byte signature PACK
-        let signature = String::from_utf8(data[read_pointer..read_pointer + 4].to_vec()).unwrap();
-        read_pointer += 4;
-        assert_eq!(signature, "PACK");
-
-        // Version number
-        let version = u32::from_be_bytes(
-            data[read_pointer..read_pointer + 4]
-                .to_vec()
-                .try_into()
-                .unwrap(),
-        );
-        read_pointer += 4;
-
-        // Number of objects
-        let entry_count = u32::from_be_bytes(
-            data[read_pointer..read_pointer + 4]
-                .to_vec()
-                .try_into()
-                .unwrap(),
-        );
-        read_pointer += 4;
-
-        (
-            PackFileHeader {
-                signature,
-                hash,
-                version,
-                entry_count,
-            },
-            read_pointer,
-        )
+    /// Atomically moves the pack to its final name
+    fn persist(mut self, hash: &Hash) -> std::io::Result<()> {
+        fs::rename(&self.path, format!("{PACK_DIR}/pack-{hash}.pack"))?;
+        self.persisted = true;
+
+        Ok(())
     }
+}
 
-    fn parse_object(data: &[u8]) -> (PackFileEntry, usize) {
-        let mut read_pointer = 0;
+impl Drop for TemporaryPack {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
 
-        // Parse the header
-        let mut is_final_byte = *data.get(read_pointer).unwrap() < 128u8;
-        let object_type =
-            ObjectType::from_u8(*data.get(read_pointer).unwrap() >> 4 & 0b111).unwrap();
-        read_pointer += 1;
+/// The stream a pack is read from. The bytes consumed are hashed along the way, and copied to
+/// the temporary pack when the pack is being received, and entries are inflated straight from
+/// its buffer, so that reading one stops exactly where it ends.
+struct PackStream<R> {
+    inner: BufReader<R>,
+    file: Option<BufWriter<File>>,
+    hasher: Sha1,
+    /// The CRC32 of the entry being read
+    crc: Crc,
+    offset: u64,
+    /// The first error writing to the temporary pack, which `consume` cannot return
+    error: Option<std::io::Error>,
+}
+
+impl<R: Read> PackStream<R> {
+    fn new(reader: R, file: Option<File>) -> Self {
+        PackStream {
+            inner: BufReader::new(reader),
+            file: file.map(BufWriter::new),
+            hasher: Sha1::new(),
+            crc: Crc::new(),
+            offset: 0,
+            error: None,
+        }
+    }
+}
 
-        let mut size_bits = Vec::new();
-        size_bits.push(*data.get(read_pointer).unwrap() & 0b1111);
+impl<R: Read> Read for PackStream<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = buffer.len().min(available.len());
+        buffer[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
 
-        while !is_final_byte {
-            let byte = *data.get(read_pointer).unwrap();
+        Ok(count)
+    }
+}
 
-            is_final_byte = byte < 128u8;
-            size_bits.push(byte & 0b1111111);
-            read_pointer += 1;
+impl<R: Read> BufRead for PackStream<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
         }
+        self.inner.fill_buf()
+    }
 
-        // Parse the object data
-        let (compressed_size, hash) = match object_type {
-            ObjectType::Commit => Self::handle_commit(&data[read_pointer..], 0),
-            ObjectType::Blob => Self::handle_blob(&data[read_pointer..], 0),
-            ObjectType::Tree => Self::handle_tree(&data[read_pointer..], 0),
-            ObjectType::RefDelta => Self::handle_ref_delta(&data[read_pointer..], 0),
-            ObjectType::OfsDelta => Self::handle_ofs_delta(&data[read_pointer..], 0),
-            _ => {
-                println!("Unimplemented type {:?}", object_type);
-                Self::handle_any(&data[read_pointer..], 0)
-            }
+    fn consume(&mut self, count: usize) {
+        let consumed = &self.inner.buffer()[..count];
+        self.hasher.update(consumed);
+        self.crc.update(consumed);
+        if let Some(Err(error)) = self.file.as_mut().map(|file| file.write_all(consumed)) {
+            self.error.get_or_insert(error);
+        }
+        self.offset += count as u64;
+        self.inner.consume(count);
+    }
+}
+
+impl PackFile {
+    /// Receives a pack from a stream the way git's index-pack does. The pack is written to a
+    /// temporary file as it arrives, while its checksum is computed and its entries indexed:
+    /// whole objects are written to the object database right away, and deltas are resolved
+    /// once the pack is complete, by reading them back from the file. Only then is the file
+    /// moved under `objects/pack`, named after the checksum closing the pack, next to its index.
+    /// With `fix_thin`, deltas against objects the pack lacks but which are present locally
+    /// are resolved too, and those objects appended to the pack so that it stands on its own.
+    pub(crate) fn receive(reader: impl Read, fix_thin: bool) -> std::io::Result<Self> {
+        let (temporary_pack, file) = TemporaryPack::create()?;
+        let mut stream = PackStream::new(reader, Some(file));
+        let (mut entries, mut checksum) =
+            Self::read_entries(&mut stream, "Receiving objects", true)?;
+        if let Some(file) = stream.file.take() {
+            file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+
+        let local_objects =
+            Self::resolve_deltas(&temporary_pack.path, &mut entries, true, fix_thin)?;
+        if !local_objects.is_empty() {
+            checksum = Self::append_objects(&temporary_pack.path, &mut entries, &local_objects)?;
+        }
+
+        let pack = PackFile {
+            hash: Hash(checksum.to_vec()),
+            entries: Self::into_pack_entries(entries),
+            local_objects: local_objects.len(),
         };
+        idx::write(
+            Path::new(&format!("{PACK_DIR}/pack-{}.idx", pack.hash)),
+            &pack,
+        )?;
+        temporary_pack.persist(&pack.hash)?;
 
-        (
-            PackFileEntry {
-                hash,
-                object_type,
-                size: 0,
-            },
-            read_pointer + compressed_size,
-        )
+        Ok(pack)
+    }
+
+    /// Indexes a pack file and resolves its deltas without writing anything, to write its index
+    /// or to check it against one
+    pub(crate) fn index(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path).map_err(|e| {
+            std::io::Error::other(format!("cannot open packfile '{}': {e}", path.display()))
+        })?;
+        let mut stream = PackStream::new(file, None);
+        let (mut entries, checksum) = Self::read_entries(&mut stream, "Indexing objects", false)?;
+        Self::resolve_deltas(path, &mut entries, false, false)?;
+
+        Ok(PackFile {
+            hash: Hash(checksum.to_vec()),
+            entries: Self::into_pack_entries(entries),
+            local_objects: 0,
+        })
+    }
+
+    /// Whether the pack holds the object with the specified hash
+    pub(crate) fn contains(&self, hash: &Hash) -> bool {
+        self.entries.iter().any(|entry| &entry.hash == hash)
+    }
+
+    /// Marks the pack as coming from a promisor remote with a `.promisor` file next to it, which
+    /// tells that the objects it references but lacks are left out on purpose
+    pub(crate) fn mark_promisor(&self) -> std::io::Result<()> {
+        fs::write(format!("{PACK_DIR}/pack-{}.promisor", self.hash), "")
+    }
+
+    /// Reads the header, every entry and the checksum closing the pack, which is verified
+    fn read_entries(
+        stream: &mut PackStream<impl Read>,
+        title: &'static str,
+        store: bool,
+    ) -> std::io::Result<(Vec<Entry>, [u8; 20])> {
+        let mut header = [0; 12];
+        stream
+            .read_exact(&mut header)
+            .map_err(|_| std::io::Error::other("protocol error: bad pack header"))?;
+        let entry_count = Self::parse_header(&header)?;
+
+        let mut progress = Progress::new(title, entry_count as u64);
+        // The count is not trusted for an allocation, the entries being read one by one
+        let mut entries = Vec::new();
+        for i in 0..entry_count {
+            entries.push(Self::parse_entry(stream, store)?);
+            progress.update_with_bytes(i as u64 + 1, stream.offset);
+        }
+
+        let expected = std::mem::take(&mut stream.hasher).finalize();
+        let mut checksum = [0; 20];
+        stream
+            .read_exact(&mut checksum)
+            .map_err(|_| std::io::Error::other("pack is truncated"))?;
+        if let Some(error) = stream.error.take() {
+            return Err(error);
+        }
+        if expected.as_slice() != checksum {
+            return Err(std::io::Error::other("pack is corrupted (SHA1 mismatch)"));
+        }
+        if stream.inner.read(&mut [0])? != 0 {
+            return Err(std::io::Error::other("pack has junk at the end"));
+        }
+        progress.update_with_bytes(entry_count as u64, stream.offset);
+        progress.finish();
+
+        Ok((entries, checksum))
     }
 
-    fn handle_commit(data: &[u8], uncompressed_size: usize) -> (usize, Hash) {
-        let (commit, read_bytes) = Commit::from_packfile_compressed_data(data);
-        let commit_object_file_content = commit.to_object_file_representation();
+    /// Checks the `PACK` signature and version, and returns the number of objects
+    fn parse_header(data: &[u8; 12]) -> std::io::Result<u32> {
+        if &data[..4] != b"PACK" {
+            return Err(std::io::Error::other("protocol error: bad pack header"));
+        }
 
-        let object_hash = Self::hash_and_write_object(commit_object_file_content);
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if version != 2 && version != 3 {
+            return Err(std::io::Error::other(format!(
+                "pack version {version} unsupported"
+            )));
+        }
+        let entry_count = u32::from_be_bytes(data[8..12].try_into().unwrap());
 
-        (read_bytes, object_hash)
+        Ok(entry_count)
     }
 
-    fn handle_blob(data: &[u8], _uncompressed_size: usize) -> (usize, Hash) {
-        let (blob, read_bytes) = object::blob::Blob::from_packfile_compressed_data(data);
-        let blob_object_file_content = blob.to_object_file_representation();
+    /// Parses the next entry: its header, the base of deltas and its zlib compressed data.
+    /// A whole object is hashed, and written to the object database when storing, while the
+    /// data of a delta is only checked, to be read again once its base is known.
+    fn parse_entry(stream: &mut PackStream<impl Read>, store: bool) -> std::io::Result<Entry> {
+        let offset = stream.offset;
+        stream.crc.reset();
+        let mut read_byte = || -> std::io::Result<u8> {
+            let mut byte = [0];
+            stream
+                .read_exact(&mut byte)
+                .map_err(|_| std::io::Error::other("pack is truncated"))?;
+            Ok(byte[0])
+        };
 
-        let object_hash = Self::hash_and_write_object(blob_object_file_content);
+        // Type and size: 3 bits of type and 4 bits of size, then 7 more bits of size per byte
+        let mut byte = read_byte()?;
+        let type_number = (byte >> 4) & 0b111;
+        let mut size = (byte & 0b1111) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            if shift >= usize::BITS {
+                return Err(std::io::Error::other("bad object header"));
+            }
+            byte = read_byte()?;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let data = match type_number {
+            1 => EntryData::Whole(ObjectType::COMMIT),
+            2 => EntryData::Whole(ObjectType::TREE),
+            3 => EntryData::Whole(ObjectType::BLOB),
+            4 => EntryData::Whole(ObjectType::TAG),
+            OFS_DELTA => {
+                // The distance back to the base, in a big-endian encoding where every
+                // continuation also adds one
+                let mut byte = read_byte()?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    if distance >> (u64::BITS - 7) != 0 {
+                        return Err(std::io::Error::other("offset value overflow"));
+                    }
+                    byte = read_byte()?;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| std::io::Error::other("delta base offset is out of bound"))?;
+                EntryData::OfsDelta(base_offset)
+            }
+            REF_DELTA => {
+                let mut base = vec![0; 20];
+                for byte in base.iter_mut() {
+                    *byte = read_byte()?;
+                }
+                EntryData::RefDelta(Hash(base))
+            }
+            _ => {
+                return Err(std::io::Error::other(format!(
+                    "unknown object type {type_number}"
+                )))
+            }
+        };
 
-        (read_bytes, object_hash)
+        // The data is inflated no further than one byte past the size the header announces, the
+        // buffer growing as it arrives
+        let data_offset = stream.offset;
+        let mut decompressor = ZlibDecoder::new(&mut *stream).take(size as u64 + 1);
+        let (inflated_size, resolved) = match &data {
+            EntryData::Whole(object_type) => {
+                let mut content = Vec::new();
+                decompressor.read_to_end(&mut content)?;
+                let hash = match store {
+                    true => Object::write(*object_type, &content)?,
+                    false => Object::hash(*object_type, &content),
+                };
+                let resolved = ResolvedObject {
+                    hash,
+                    object_type: *object_type,
+                    depth: 0,
+                };
+                (content.len(), Some(resolved))
+            }
+            _ => (
+                std::io::copy(&mut decompressor, &mut std::io::sink())? as usize,
+                None,
+            ),
+        };
+        if inflated_size != size {
+            return Err(std::io::Error::other("inflate returned an unexpected size"));
+        }
+
+        Ok(Entry {
+            offset,
+            data,
+            data_offset,
+            size,
+            packed_size: stream.offset - offset,
+            crc32: stream.crc.sum(),
+            resolved,
+        })
     }
 
-    fn handle_tree(data: &[u8], uncompressed_size: usize) -> (usize, Hash) {
-        let (mut tree, read_bytes) = Tree::from_packfile_compressed_data(data);
-        let tree_object_file_content = tree.generate_object_file_representation();
+    /// Resolves the objects the deltas of the pack hold. Starting from every object whose
+    /// content is known, the deltas against it are applied in turn, then the deltas against
+    /// those, so that only the chain of deltas being worked on is held in memory.
+    /// Returns the local objects the deltas of a thin pack were resolved against.
+    fn resolve_deltas(
+        path: &Path,
+        entries: &mut [Entry],
+        store: bool,
+        fix_thin: bool,
+    ) -> std::io::Result<Vec<Hash>> {
+        let positions = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.offset, i))
+            .collect::<HashMap<_, _>>();
+        let mut offset_deltas: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut ref_deltas: HashMap<Hash, Vec<usize>> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            match &entry.data {
+                EntryData::Whole(_) => {}
+                EntryData::OfsDelta(base_offset) => {
+                    let base = positions
+                        .get(base_offset)
+                        .ok_or_else(|| std::io::Error::other("delta base is not an entry"))?;
+                    offset_deltas.entry(*base).or_default().push(i);
+                }
+                EntryData::RefDelta(base) => ref_deltas.entry(base.clone()).or_default().push(i),
+            }
+        }
+
+        let delta_count = entries
+            .iter()
+            .filter(|entry| entry.resolved.is_none())
+            .count();
+        let mut resolver = DeltaResolver {
+            pack: BufReader::new(File::open(path)?),
+            entries,
+            offset_deltas,
+            ref_deltas,
+            store,
+            progress: Progress::new("Resolving deltas", delta_count as u64),
+            resolved: 0,
+        };
 
-        let object_hash = Self::hash_and_write_object(tree_object_file_content);
+        for i in 0..resolver.entries.len() {
+            let Some(base) = resolver.entries[i].resolved.clone() else {
+                continue;
+            };
+            if resolver.has_deltas(&base.hash, Some(i)) {
+                let content = resolver.read_data(i)?;
+                resolver.resolve_from(&base, &content, Some(i))?;
+            }
+        }
+        // The bases a thin pack leaves out, which we already have
+        let mut local_objects = Vec::new();
+        if fix_thin {
+            local_objects = resolver
+                .ref_deltas
+                .keys()
+                .filter(|base| Object::exists(base))
+                .cloned()
+                .collect::<Vec<_>>();
+            for hash in &local_objects {
+                let (object_type, content) = Object::read(hash)?;
+                let base = ResolvedObject {
+                    hash: hash.clone(),
+                    object_type,
+                    depth: 0,
+                };
+                resolver.resolve_from(&base, &content, None)?;
+            }
+        }
+        resolver.progress.finish();
 
-        (read_bytes, object_hash)
+        let unresolved = delta_count - resolver.resolved;
+        if unresolved > 0 {
+            return Err(std::io::Error::other(format!(
+                "pack has {unresolved} unresolved deltas"
+            )));
+        }
+
+        Ok(local_objects)
     }
 
-    fn handle_ref_delta(data: &[u8], _uncompressed_size: usize) -> (usize, Hash) {
-        let base_hash = Hash(data[..20].to_vec());
+    /// Completes a thin pack with the local objects its deltas were resolved against, appended
+    /// as whole entries, then updates the number of objects in the header and the checksum
+    /// closing the pack, which is returned
+    fn append_objects(
+        path: &Path,
+        entries: &mut Vec<Entry>,
+        objects: &[Hash],
+    ) -> std::io::Result<[u8; 20]> {
+        let mut file = File::options().read(true).write(true).open(path)?;
+        let mut offset = file.metadata()?.len() - 20;
+        file.set_len(offset)?;
+        file.seek(SeekFrom::End(0))?;
+
+        let mut writer = BufWriter::new(&mut file);
+        for hash in objects {
+            let (object_type, content) = Object::read(hash)?;
+            let header = entry_header(type_number(object_type), content.len());
+            let mut compressor = ZlibEncoder::new(header.clone(), Compression::default());
+            compressor.write_all(&content)?;
+            let entry = compressor.finish()?;
+            writer.write_all(&entry)?;
+
+            let mut crc = Crc::new();
+            crc.update(&entry);
+            entries.push(Entry {
+                offset,
+                data: EntryData::Whole(object_type),
+                data_offset: offset + header.len() as u64,
+                size: content.len(),
+                packed_size: entry.len() as u64,
+                crc32: crc.sum(),
+                resolved: Some(ResolvedObject {
+                    hash: hash.clone(),
+                    object_type,
+                    depth: 0,
+                }),
+            });
+            offset += entry.len() as u64;
+        }
+        writer.flush()?;
+        drop(writer);
 
-        let mut decompressor = ZlibDecoder::new(&data[20..]);
-        let mut decompressed_data = Vec::new();
-        decompressor.read_to_end(&mut decompressed_data).unwrap();
+        file.seek(SeekFrom::Start(8))?;
+        file.write_all(&(entries.len() as u32).to_be_bytes())?;
+        file.seek(SeekFrom::Start(0))?;
+        let mut hasher = Sha1::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        let checksum: [u8; 20] = hasher.finalize().into();
+        file.write_all(&checksum)?;
+        file.sync_all()?;
 
-        let undeltified = parse_ref_delta_file(decompressed_data, &base_hash);
-        let _hash = Self::hash_and_write_object(undeltified);
+        Ok(checksum)
+    }
 
-        println!("HASH IS {_hash}");
+    /// The indexed entries, whose deltas all have been resolved
+    fn into_pack_entries(entries: Vec<Entry>) -> Vec<PackEntry> {
+        let hashes = entries
+            .iter()
+            .filter_map(|entry| Some((entry.offset, entry.resolved.as_ref()?.hash.clone())))
+            .collect::<HashMap<_, _>>();
+
+        entries
+            .into_iter()
+            .filter_map(|entry| {
+                let base = match &entry.data {
+                    EntryData::Whole(_) => None,
+                    EntryData::OfsDelta(base_offset) => hashes.get(base_offset).cloned(),
+                    EntryData::RefDelta(base) => Some(base.clone()),
+                };
+                let resolved = entry.resolved?;
+
+                Some(PackEntry {
+                    hash: resolved.hash,
+                    object_type: resolved.object_type,
+                    size: entry.size,
+                    packed_size: entry.packed_size,
+                    offset: entry.offset,
+                    crc32: entry.crc32,
+                    depth: resolved.depth,
+                    base,
+                })
+            })
+            .collect()
+    }
+}
 
-        (
-            decompressor.total_in() as usize + 20,
-            Hash::from_str("95e0993a2b6f9d4c4b64286de1d4fec569e9cfc2").unwrap(),
-        )
+/// The number a pack gives to the type of a whole object
+fn type_number(object_type: ObjectType) -> u8 {
+    match object_type {
+        ObjectType::COMMIT => 1,
+        ObjectType::TREE => 2,
+        ObjectType::BLOB => 3,
+        ObjectType::TAG => 4,
     }
+}
 
-    fn handle_ofs_delta(data: &[u8], _uncompressed_size: usize) -> (usize, Hash) {
-        let _hash = &data[..20];
+/// The header of an entry: 3 bits of type and the size, 4 bits in the first byte and 7 bits in
+/// each of the next ones, the most significant bit telling whether another byte follows
+fn entry_header(type_number: u8, size: usize) -> Vec<u8> {
+    let mut header = vec![(type_number << 4) | (size & 0b1111) as u8];
+    let mut size = size >> 4;
+    while size > 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
 
-        println!("OFS DELTA");
+    header
+}
 
-        let mut decompressor = ZlibDecoder::new(&data[20..]);
-        let mut decompressed_data = Vec::new();
-        decompressor.read_to_end(&mut decompressed_data).unwrap();
+/// The state of delta resolution: the deltas waiting for each base, by the position of the base
+/// in the pack or by its hash
+struct DeltaResolver<'a> {
+    pack: BufReader<File>,
+    entries: &'a mut [Entry],
+    offset_deltas: HashMap<usize, Vec<usize>>,
+    ref_deltas: HashMap<Hash, Vec<usize>>,
+    /// Whether the objects resolved are written to the object database
+    store: bool,
+    progress: Progress,
+    resolved: usize,
+}
 
-        (
-            decompressor.total_in() as usize + 20,
-            Hash::from_str("95e0993a2b6f9d4c4b64286de1d4fec569e9cfc2").unwrap(),
-        )
+impl DeltaResolver<'_> {
+    /// Whether deltas wait for the specified object, found at the specified position when it is
+    /// part of the pack
+    fn has_deltas(&self, hash: &Hash, position: Option<usize>) -> bool {
+        position.is_some_and(|position| self.offset_deltas.contains_key(&position))
+            || self.ref_deltas.contains_key(hash)
     }
 
-    fn handle_any(data: &[u8], _uncompressed_size: usize) -> (usize, Hash) {
-        let mut decompressor = ZlibDecoder::new(data);
-        let mut decompressed_data = Vec::new();
-        decompressor.read_to_end(&mut decompressed_data).unwrap();
+    /// Applies every delta waiting for the specified object, found at the specified position
+    /// when it is part of the pack, then the deltas against the results
+    fn resolve_from(
+        &mut self,
+        base: &ResolvedObject,
+        content: &[u8],
+        position: Option<usize>,
+    ) -> std::io::Result<()> {
+        let mut deltas = position
+            .and_then(|position| self.offset_deltas.remove(&position))
+            .unwrap_or_default();
+        deltas.extend(self.ref_deltas.remove(&base.hash).unwrap_or_default());
+
+        for delta in deltas {
+            if self.entries[delta].resolved.is_some() {
+                continue;
+            }
+            let delta_content = apply_delta(content, &self.read_data(delta)?)?;
+            let object = ResolvedObject {
+                hash: match self.store {
+                    true => Object::write(base.object_type, &delta_content)?,
+                    false => Object::hash(base.object_type, &delta_content),
+                },
+                object_type: base.object_type,
+                depth: base.depth + 1,
+            };
+
+            self.entries[delta].resolved = Some(object.clone());
+            self.resolved += 1;
+            self.progress.update(self.resolved as u64);
+            self.resolve_from(&object, &delta_content, Some(delta))?;
+        }
 
-        (
-            decompressor.total_in() as usize,
-            Hash::from_str("95e0993a2b6f9d4c4b64286de1d4fec569e9cfc2").unwrap(),
-        )
+        Ok(())
     }
 
-    fn hash_and_write_object(data: Vec<u8>) -> Hash {
-        // TODO: Move this to object struct
-        let mut hasher = Sha1::new();
-        Digest::update(&mut hasher, &data);
-        let hash = Hash(hasher.finalize().to_vec());
-        let hash_string = hash.to_string();
-
-        let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
-        compressor.write_all(&data).unwrap();
-        let compressed_data = compressor.finish().unwrap();
-
-        let (subdirectory, file_name) = Object::get_path_from_hash(&hash_string).unwrap();
-        let path = PathBuf::from(".hamachi/objects")
-            .join(subdirectory)
-            .join(file_name);
-        if !fs::exists(&path).unwrap() {
-            fs::create_dir_all(PathBuf::from(".hamachi/objects").join(subdirectory)).unwrap();
-            let mut file = File::create(&path).unwrap();
-            file.write(compressed_data.as_slice()).unwrap();
+    /// Inflates the data of an entry back from the pack
+    fn read_data(&mut self, position: usize) -> std::io::Result<Vec<u8>> {
+        let entry = &self.entries[position];
+        self.pack.seek(SeekFrom::Start(entry.data_offset))?;
+
+        let mut data = Vec::new();
+        ZlibDecoder::new(&mut self.pack)
+            .take(entry.size as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() != entry.size {
+            return Err(std::io::Error::other("inflate returned an unexpected size"));
         }
 
-        hash
+        Ok(data)
     }
 }
 
-#[derive(Debug, PartialOrd, PartialEq)]
-pub enum ObjectType {
-    Commit = 1,
-    Tree = 2,
-    Blob = 3,
-    Tag = 4,
-    OfsDelta = 6,
-    RefDelta = 7,
-}
-impl ObjectType {
-    fn from_u8(value: u8) -> Result<ObjectType, &'static str> {
-        match value {
-            1 => Ok(ObjectType::Commit),
-            2 => Ok(ObjectType::Tree),
-            3 => Ok(ObjectType::Blob),
-            4 => Ok(ObjectType::Tag),
-            6 => Ok(ObjectType::OfsDelta),
-            7 => Ok(ObjectType::RefDelta),
-            _ => Err("Unknown object type"),
+#[cfg(test)]
+mod tests {
+    use crate::object::packfile::PackFile;
+    use crate::object::Object;
+    use crate::test_utils::{run_git_command, setup_test_environment, teardown};
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use rusty_fork::rusty_fork_test;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    rusty_fork_test! {
+        #[test]
+        fn store_first_pack_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            fs::write("test.txt", "hello\n").unwrap();
+            let hash = run_git_command(Command::new("git").arg("hash-object").arg("-w").arg("test.txt")).unwrap();
+            let mut pack_objects = Command::new("git")
+                .args(["pack-objects", "--stdout"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            pack_objects.stdin.take().unwrap().write_all(format!("{hash}\n").as_bytes()).unwrap();
+            let mut pack = Vec::new();
+            pack_objects.stdout.take().unwrap().read_to_end(&mut pack).unwrap();
+            pack_objects.wait().unwrap();
+
+            // Test
+            assert!(!Path::new(".hamachi/objects/pack").exists());
+            let pack_file = PackFile::receive(pack.as_slice(), false).unwrap();
+
+            assert_eq!(pack_file.entries.len(), 1);
+            assert_eq!(pack_file.entries[0].hash.to_string(), hash);
+            assert!(Path::new(&format!(".hamachi/objects/pack/pack-{}.pack", pack_file.hash)).is_file());
+            assert_eq!(
+                Object::decompress_object(&hash, false).unwrap(),
+                Object::decompress_object(&hash, true).unwrap()
+            );
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn resolve_deltas_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            let mut hashes = Vec::new();
+            for version in 0..3 {
+                // Versions close enough for git to store them as deltas of each other
+                let content = (0..200)
+                    .map(|line| match line % 50 {
+                        0 => format!("line {line} of version {version}\n"),
+                        _ => format!("line {line}\n"),
+                    })
+                    .collect::<String>();
+                fs::write("test.txt", content).unwrap();
+                hashes.push(run_git_command(Command::new("git").arg("hash-object").arg("-w").arg("test.txt")).unwrap());
+            }
+            let mut pack_objects = Command::new("git")
+                .args(["pack-objects", "--stdout"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            pack_objects.stdin.take().unwrap().write_all(hashes.join("\n").as_bytes()).unwrap();
+            let mut pack = Vec::new();
+            pack_objects.stdout.take().unwrap().read_to_end(&mut pack).unwrap();
+            pack_objects.wait().unwrap();
+
+            // Test
+            let pack_file = PackFile::receive(pack.as_slice(), false).unwrap();
+
+            assert_eq!(pack_file.entries.len(), 3);
+            for hash in &hashes {
+                assert_eq!(
+                    Object::decompress_object(hash, false).unwrap(),
+                    Object::decompress_object(hash, true).unwrap()
+                );
+            }
+
+            teardown(repo).unwrap();
+        }
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn corrupt_pack_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            let pack = |entry: &[u8]| [&b"PACK\0\0\0\x02\0\0\0\x01"[..], entry].concat();
+
+            // Test
+            // Sizes and delta base offsets too large to be represented
+            let error = PackFile::receive(pack(&[0xff; 16]).as_slice(), false).unwrap_err();
+            assert_eq!(error.to_string(), "bad object header");
+            let error = PackFile::receive(pack(&[[0x60].as_slice(), &[0xff; 12]].concat()).as_slice(), false).unwrap_err();
+            assert_eq!(error.to_string(), "offset value overflow");
+
+            // A size far beyond the data, which is not allocated up front
+            let mut entry = vec![0xbf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0f];
+            let mut encoder = ZlibEncoder::new(&mut entry, Compression::default());
+            encoder.write_all(b"hello\n").unwrap();
+            encoder.finish().unwrap();
+            let error = PackFile::receive(pack(&entry).as_slice(), false).unwrap_err();
+            assert_eq!(error.to_string(), "inflate returned an unexpected size");
+
+            teardown(repo).unwrap();
         }
     }
 }
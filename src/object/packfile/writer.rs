@@ -0,0 +1,417 @@
+use crate::object::packfile::delta::DeltaIndex;
+use crate::object::packfile::{
+    entry_header, type_number, PackEntry, PackFile, OFS_DELTA, REF_DELTA,
+};
+use crate::object::{Hash, Object, ObjectType};
+use crate::progress::Progress;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+
+/// How hard to look for deltas: against how many of the objects sorted before each one, and
+/// how long chains of deltas may grow
+/// https://git-scm.com/docs/git-pack-objects#Documentation/git-pack-objects.txt---windowltngt
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DeltaSearch {
+    pub(crate) window: usize,
+    pub(crate) depth: usize,
+}
+
+impl Default for DeltaSearch {
+    fn default() -> Self {
+        DeltaSearch {
+            window: 10,
+            depth: 50,
+        }
+    }
+}
+
+/// Writes a pack of the specified objects, given with the path they were found at, in their
+/// order except that the base of a delta comes before it. Deltas are searched first.
+pub(crate) fn write_pack<W: Write>(
+    output: W,
+    objects: &[(Hash, String)],
+    search: DeltaSearch,
+    ofs_delta: bool,
+) -> std::io::Result<(W, PackFile)> {
+    let mut deltas = find_deltas(objects, search)?;
+
+    let mut writer = PackWriter::new(output, objects.len() as u32, ofs_delta)?;
+    let mut progress = Progress::new("Writing objects", objects.len() as u64);
+    for (hash, _) in objects {
+        writer.write_with_base(hash, &mut deltas)?;
+        progress.update(writer.entries.len() as u64);
+    }
+    progress.finish();
+
+    writer.finish()
+}
+
+/// The delta found for an object
+struct FoundDelta {
+    base: Hash,
+    object_type: ObjectType,
+    data: Vec<u8>,
+}
+
+/// An object taking part in the delta search
+struct Candidate<'a> {
+    hash: &'a Hash,
+    object_type: ObjectType,
+    name_hash: u32,
+    size: usize,
+}
+
+/// An object of the window, which the next objects are tried against
+struct WindowEntry {
+    hash: Hash,
+    object_type: ObjectType,
+    index: DeltaIndex,
+    depth: usize,
+}
+
+/// Finds a delta for as many objects as possible, the way git does. Objects are sorted by type,
+/// then by a hash of their path, then largest first, so that versions of the same file end up
+/// next to each other; each object is then tried against the ones within the window before it,
+/// keeping the smallest delta, which must be small enough to be worth it.
+/// https://github.com/git/git/blob/master/Documentation/technical/pack-heuristics.adoc
+fn find_deltas(
+    objects: &[(Hash, String)],
+    search: DeltaSearch,
+) -> std::io::Result<HashMap<Hash, FoundDelta>> {
+    let mut deltas = HashMap::new();
+    if search.window == 0 || search.depth == 0 {
+        return Ok(deltas);
+    }
+
+    let mut candidates = objects
+        .iter()
+        .map(|(hash, name)| {
+            let header = Object::from_hash(&hash.to_string())
+                .map_err(|e| std::io::Error::other(format!("{e} {hash}")))?
+                .header;
+            Ok(Candidate {
+                hash,
+                object_type: header.object_type,
+                name_hash: name_hash(name),
+                size: header.size,
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Stable, so that equal objects stay most recent first
+    candidates.sort_by(|a, b| {
+        type_number(b.object_type)
+            .cmp(&type_number(a.object_type))
+            .then(b.name_hash.cmp(&a.name_hash))
+            .then(b.size.cmp(&a.size))
+    });
+
+    let mut progress = Progress::new("Compressing objects", candidates.len() as u64);
+    let mut window: VecDeque<WindowEntry> = VecDeque::with_capacity(search.window);
+    for (i, candidate) in candidates.iter().enumerate() {
+        let (_, content) = Object::read(candidate.hash)?;
+
+        // A delta must at least halve the object, and more so against a long chain
+        let max_size = (content.len() / 2).saturating_sub(20);
+        let mut best: Option<(&WindowEntry, Vec<u8>)> = None;
+        for base in window.iter().rev() {
+            if base.object_type != candidate.object_type || base.depth >= search.depth {
+                continue;
+            }
+            let mut limit = max_size * search.depth / (search.depth - base.depth + 1);
+            if let Some((_, delta)) = &best {
+                limit = limit.min(delta.len() - 1);
+            }
+            if limit == 0 || content.len().abs_diff(base.index.base_size()) >= limit {
+                continue;
+            }
+            if let Some(delta) = base.index.create_delta(&content, limit) {
+                best = Some((base, delta));
+            }
+        }
+
+        let depth = match best {
+            Some((base, delta)) => {
+                let found = FoundDelta {
+                    base: base.hash.clone(),
+                    object_type: candidate.object_type,
+                    data: delta,
+                };
+                deltas.insert(candidate.hash.clone(), found);
+                base.depth + 1
+            }
+            None => 0,
+        };
+        if window.len() == search.window {
+            window.pop_front();
+        }
+        window.push_back(WindowEntry {
+            hash: candidate.hash.clone(),
+            object_type: candidate.object_type,
+            index: DeltaIndex::new(content),
+            depth,
+        });
+        progress.update(i as u64 + 1);
+    }
+    progress.finish();
+
+    Ok(deltas)
+}
+
+/// The hash git sorts objects by to find deltas, mostly made of the last characters of the path
+/// so that files with the same name or extension sort together
+fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .fold(0u32, |hash, byte| {
+            (hash >> 2).wrapping_add((byte as u32) << 24)
+        })
+}
+
+/// Writes a version 2 pack: the `PACK` header announcing the number of entries, each entry
+/// with its type and size header followed by its zlib compressed data, and the checksum of all
+/// that. Deltas are written against an entry of the pack by its offset when allowed, and by the
+/// hash of their base otherwise, which lets thin packs refer to objects they leave out.
+/// https://git-scm.com/docs/pack-format
+pub(crate) struct PackWriter<W> {
+    inner: W,
+    hasher: Sha1,
+    offset: u64,
+    object_count: u32,
+    entries: Vec<PackEntry>,
+    /// The position in `entries` of every object written so far
+    positions: HashMap<Hash, usize>,
+    ofs_delta: bool,
+}
+
+impl<W: Write> PackWriter<W> {
+    /// Starts a pack of the specified number of entries, whose deltas are written against
+    /// their base in the pack by offset when `ofs_delta` is set
+    pub(crate) fn new(inner: W, object_count: u32, ofs_delta: bool) -> std::io::Result<Self> {
+        let mut writer = PackWriter {
+            inner,
+            hasher: Sha1::new(),
+            offset: 0,
+            object_count,
+            entries: Vec::with_capacity(object_count as usize),
+            positions: HashMap::new(),
+            ofs_delta,
+        };
+
+        let mut header = Vec::with_capacity(12);
+        header.extend_from_slice(b"PACK");
+        header.extend_from_slice(&2u32.to_be_bytes());
+        header.extend_from_slice(&object_count.to_be_bytes());
+        writer.write_bytes(&header)?;
+
+        Ok(writer)
+    }
+
+    /// Writes an object whole
+    pub(crate) fn write_object(
+        &mut self,
+        hash: &Hash,
+        object_type: ObjectType,
+        content: &[u8],
+    ) -> std::io::Result<()> {
+        let header = entry_header(type_number(object_type), content.len());
+        self.write_entry(hash, object_type, header, content, None)
+    }
+
+    /// Writes an object as a delta against another, which must come first in the pack unless
+    /// the pack is thin
+    pub(crate) fn write_delta(
+        &mut self,
+        hash: &Hash,
+        object_type: ObjectType,
+        base: &Hash,
+        delta: &[u8],
+    ) -> std::io::Result<()> {
+        let mut header;
+        match self.positions.get(base) {
+            Some(&position) if self.ofs_delta => {
+                header = entry_header(OFS_DELTA, delta.len());
+                header.extend(encode_distance(self.offset - self.entries[position].offset));
+            }
+            _ => {
+                header = entry_header(REF_DELTA, delta.len());
+                header.extend_from_slice(&base.0);
+            }
+        }
+
+        self.write_entry(hash, object_type, header, delta, Some(base))
+    }
+
+    /// Writes an object found by the delta search, as a delta when one was found, after its
+    /// base if that is part of the pack and not written yet
+    fn write_with_base(
+        &mut self,
+        hash: &Hash,
+        deltas: &mut HashMap<Hash, FoundDelta>,
+    ) -> std::io::Result<()> {
+        if self.positions.contains_key(hash) {
+            return Ok(());
+        }
+
+        match deltas.remove(hash) {
+            Some(delta) => {
+                self.write_with_base(&delta.base, deltas)?;
+                self.write_delta(hash, delta.object_type, &delta.base, &delta.data)
+            }
+            None => {
+                let (object_type, content) = Object::read(hash)?;
+                self.write_object(hash, object_type, &content)
+            }
+        }
+    }
+
+    /// Closes the pack with its checksum, and returns the stream it was written to along with
+    /// the entries written, to index them
+    pub(crate) fn finish(mut self) -> std::io::Result<(W, PackFile)> {
+        if self.entries.len() != self.object_count as usize {
+            return Err(std::io::Error::other(format!(
+                "wrote {} objects while expecting {}",
+                self.entries.len(),
+                self.object_count
+            )));
+        }
+
+        let checksum = std::mem::take(&mut self.hasher).finalize();
+        self.inner.write_all(&checksum)?;
+        self.inner.flush()?;
+
+        Ok((
+            self.inner,
+            PackFile {
+                hash: Hash(checksum.to_vec()),
+                entries: self.entries,
+                local_objects: 0,
+            },
+        ))
+    }
+
+    fn write_entry(
+        &mut self,
+        hash: &Hash,
+        object_type: ObjectType,
+        mut entry: Vec<u8>,
+        data: &[u8],
+        base: Option<&Hash>,
+    ) -> std::io::Result<()> {
+        if self.entries.len() == self.object_count as usize {
+            return Err(std::io::Error::other(format!(
+                "wrote more objects than the {} expected",
+                self.object_count
+            )));
+        }
+
+        let mut compressor = ZlibEncoder::new(entry, Compression::default());
+        compressor.write_all(data)?;
+        entry = compressor.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&entry);
+
+        let depth = base
+            .and_then(|base| self.positions.get(base))
+            .map_or(0, |&position| self.entries[position].depth)
+            + base.is_some() as usize;
+        self.positions.insert(hash.clone(), self.entries.len());
+        self.entries.push(PackEntry {
+            hash: hash.clone(),
+            object_type,
+            size: data.len(),
+            packed_size: entry.len() as u64,
+            offset: self.offset,
+            crc32: crc.sum(),
+            depth,
+            base: base.cloned(),
+        });
+
+        self.write_bytes(&entry)
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+        self.inner.write_all(data)
+    }
+}
+
+/// The distance back from a delta to its base, in the big-endian encoding where every
+/// continuation also adds one
+fn encode_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PackWriter;
+    use crate::object::packfile::PackFile;
+    use crate::object::{Object, ObjectType};
+
+    /// A delta inserting the whole of a short content, whatever its base
+    fn insert_delta(base_size: u8, content: &[u8]) -> Vec<u8> {
+        let mut delta = vec![base_size, content.len() as u8, content.len() as u8];
+        delta.extend_from_slice(content);
+        delta
+    }
+
+    #[test]
+    fn pack_writer_test() {
+        let base = b"base content\n";
+        let target = b"new content\n";
+        let (base_hash, target_hash) = (
+            Object::hash(ObjectType::BLOB, base),
+            Object::hash(ObjectType::BLOB, target),
+        );
+
+        for ofs_delta in [true, false] {
+            let mut writer = PackWriter::new(Vec::new(), 2, ofs_delta).unwrap();
+            writer
+                .write_object(&base_hash, ObjectType::BLOB, base)
+                .unwrap();
+            let delta = insert_delta(base.len() as u8, target);
+            writer
+                .write_delta(&target_hash, ObjectType::BLOB, &base_hash, &delta)
+                .unwrap();
+            assert!(writer
+                .write_object(&target_hash, ObjectType::BLOB, target)
+                .is_err());
+            let (data, written) = writer.finish().unwrap();
+
+            // Deltas by offset take 1 byte to locate their base, by hash 20 bytes
+            let delta_type = if ofs_delta { 6 } else { 7 };
+            assert_eq!(data[written.entries[1].offset as usize] >> 4, delta_type);
+
+            let path = std::env::temp_dir().join(format!(
+                "pack-writer-{}-{ofs_delta}.pack",
+                std::process::id()
+            ));
+            std::fs::write(&path, &data).unwrap();
+            let pack = PackFile::index(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(pack.hash, written.hash);
+            for (read, written) in pack.entries.iter().zip(&written.entries) {
+                assert_eq!(read.hash, written.hash);
+                assert_eq!(read.offset, written.offset);
+                assert_eq!(read.crc32, written.crc32);
+                assert_eq!(read.packed_size, written.packed_size);
+                assert_eq!(read.depth, written.depth);
+                assert_eq!(read.base, written.base);
+            }
+            assert_eq!(pack.entries[1].depth, 1);
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use crate::command::ls_tree::ls_tree;
-use crate::object::{Hash, ObjectType};
-use flate2::read::ZlibDecoder;
-use std::cmp::PartialEq;
+use crate::object::{Hash, Object, ObjectType};
+use std::cmp::{Ordering, PartialEq};
+use std::collections::BTreeMap;
 use std::fmt::Display;
-use std::io::Read;
+use std::fs::Metadata;
+use std::os::unix::fs::PermissionsExt;
 use std::str::FromStr;
 
 pub(crate) struct Tree {
@@ -11,32 +11,44 @@ pub(crate) struct Tree {
 }
 
 impl Tree {
-    pub(crate) fn from_packfile_compressed_data(data: &[u8]) -> (Self, usize) {
-        let mut decompressor = ZlibDecoder::new(data);
-        let mut decompressed_data = Vec::new();
-        decompressor.read_to_end(&mut decompressed_data).unwrap();
+    /// Reads the tree object with the specified hash from the object database
+    pub(crate) fn from_hash(hash: &Hash) -> std::io::Result<Self> {
+        let (object_type, content) = Object::read(hash)?;
+        if object_type != ObjectType::TREE {
+            return Err(std::io::Error::other(format!("{hash} is not a tree")));
+        }
+
+        let entries = Self::parse_entries(&content)
+            .map_err(|_| std::io::Error::other(format!("Malformed tree {hash}")))?;
 
+        Ok(Tree { entries })
+    }
+
+    fn parse_entries(data: &[u8]) -> Result<Vec<Entry>, ()> {
         let mut entries = Vec::new();
         let mut read_pointer = 0;
 
-        while read_pointer < decompressed_data.len() {
-            let null_separator = decompressed_data[read_pointer..]
+        while read_pointer < data.len() {
+            let null_separator = data[read_pointer..]
                 .iter()
                 .position(|&c| c == b'\0')
-                .unwrap();
+                .ok_or(())?;
 
-            let mode_name = &decompressed_data[read_pointer..read_pointer + null_separator];
-            let mode_name_str = String::from_utf8(mode_name.to_vec()).unwrap();
-            let mut parts = mode_name_str.split_whitespace();
+            let mode_name = &data[read_pointer..read_pointer + null_separator];
+            let space = mode_name.iter().position(|&c| c == b' ').ok_or(())?;
 
-            let mode = Mode::from_str(parts.next().unwrap()).unwrap();
-            let filename = parts.next().unwrap().to_string();
+            let mode = Mode::from_bytes(&mode_name[..space])?;
+            let filename = String::from_utf8_lossy(&mode_name[space + 1..]).to_string();
 
             let object_type = ObjectType::from_file_mode(mode);
 
             read_pointer += null_separator + 1;
 
-            let hash = Hash(decompressed_data[read_pointer..read_pointer + 20].to_vec());
+            let hash = Hash(
+                data.get(read_pointer..read_pointer + 20)
+                    .ok_or(())?
+                    .to_vec(),
+            );
 
             read_pointer += 20;
 
@@ -48,35 +60,110 @@ impl Tree {
             })
         }
 
-        (Tree { entries }, decompressor.total_in() as usize)
+        Ok(entries)
     }
 
-    pub(crate) fn generate_object_file_representation(&mut self) -> Vec<u8> {
-        let mut entry_byte_vectors = Vec::new();
-        for entry in &mut self.entries {
-            let mut entry_bytes = format!("{} {}\0", entry.mode as u32, entry.filename)
-                .as_bytes()
-                .to_vec();
-            entry_bytes.append(&mut entry.hash.0.clone());
+    /// Serializes the entries of the tree, without the object header
+    fn generate_content(&mut self) -> Vec<u8> {
+        self.entries.sort_by(Entry::tree_order);
 
-            entry_byte_vectors.push(entry_bytes);
+        let mut entries_section = Vec::new();
+        for entry in &self.entries {
+            entries_section.extend_from_slice(
+                format!("{} {}\0", entry.mode as u32, entry.filename).as_bytes(),
+            );
+            entries_section.extend_from_slice(&entry.hash.0);
         }
 
-        let entries_section = entry_byte_vectors
-            .into_iter()
-            .flatten()
-            .collect::<Vec<u8>>();
-        let header = format!("tree {}\0", entries_section.len())
-            .as_bytes()
-            .to_vec();
+        entries_section
+    }
+
+    /// Writes the tree to the object database, sorting its entries the way git expects
+    pub(crate) fn write(&mut self) -> std::io::Result<Hash> {
+        let content = self.generate_content();
 
-        vec![header, entries_section].concat()
+        Object::write(ObjectType::TREE, &content)
     }
 
-    pub(crate) fn read_from_file(hash: Hash) -> Self {
-        let tree = ls_tree(false, &hash.to_string()).1;
+    /// Recursively lists every non-directory entry reachable from the tree with the specified hash,
+    /// keyed by its slash separated path relative to the tree root
+    pub(crate) fn flatten(hash: &Hash) -> std::io::Result<BTreeMap<String, (Mode, Hash)>> {
+        let mut files = BTreeMap::new();
+        Self::flatten_into(hash, "", &mut files)?;
+
+        Ok(files)
+    }
+
+    /// Writes a hierarchy of trees holding the specified files, the inverse of `flatten`,
+    /// and returns the hash of the root tree
+    pub(crate) fn write_from_files(
+        files: &BTreeMap<String, (Mode, Hash)>,
+    ) -> std::io::Result<Hash> {
+        let files = files
+            .iter()
+            .map(|(path, (mode, hash))| (path.as_str(), *mode, hash))
+            .collect::<Vec<_>>();
+
+        Self::write_level(&files)
+    }
+
+    /// Writes the tree of a single directory from its files, sorted by path relative to it
+    fn write_level(files: &[(&str, Mode, &Hash)]) -> std::io::Result<Hash> {
+        let mut entries = Vec::new();
+
+        let mut i = 0;
+        while i < files.len() {
+            let (path, mode, hash) = files[i];
+            let Some((directory, _)) = path.split_once('/') else {
+                entries.push(Entry {
+                    mode,
+                    filename: path.to_string(),
+                    object_type: ObjectType::BLOB,
+                    hash: hash.clone(),
+                });
+                i += 1;
+                continue;
+            };
+
+            // Files of the same directory are contiguous since they share a prefix
+            let children = files[i..]
+                .iter()
+                .map_while(|(path, mode, hash)| match path.split_once('/') {
+                    Some((child_directory, rest)) if child_directory == directory => {
+                        Some((rest, *mode, *hash))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            i += children.len();
+
+            entries.push(Entry {
+                mode: Mode::DIRECTORY,
+                filename: directory.to_string(),
+                object_type: ObjectType::TREE,
+                hash: Self::write_level(&children)?,
+            });
+        }
 
-        tree
+        Tree { entries }.write()
+    }
+
+    fn flatten_into(
+        hash: &Hash,
+        prefix: &str,
+        files: &mut BTreeMap<String, (Mode, Hash)>,
+    ) -> std::io::Result<()> {
+        let tree = Self::from_hash(hash)?;
+        for entry in tree.entries {
+            let path = format!("{prefix}{}", entry.filename);
+            if entry.mode == Mode::DIRECTORY {
+                Self::flatten_into(&entry.hash, &format!("{path}/"), files)?;
+            } else {
+                files.insert(path, (entry.mode, entry.hash));
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -88,19 +175,32 @@ pub(crate) struct Entry {
     pub(crate) hash: Hash,
 }
 
+impl Entry {
+    /// Git sorts tree entries by name, comparing directories as if their name ended with a slash
+    pub(crate) fn tree_order(a: &Entry, b: &Entry) -> Ordering {
+        let key = |entry: &Entry| {
+            let mut name = entry.filename.as_bytes().to_vec();
+            if entry.mode == Mode::DIRECTORY {
+                name.push(b'/');
+            }
+            name
+        };
+
+        key(a).cmp(&key(b))
+    }
+}
+
 impl Display for Entry {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
             "{:0>6} {} {}\t{}",
-            self.mode as u32,
-            self.object_type,
-            &self.hash.to_string(),
-            self.filename
+            self.mode as u32, self.object_type, &self.hash, self.filename
         )
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, PartialOrd, PartialEq, Debug)]
 pub(crate) enum Mode {
     REGULAR = 100644,
@@ -128,4 +228,38 @@ impl Mode {
 
         Self::from_str(string.as_str())
     }
+
+    /// The mode git would record for a file with the specified metadata
+    /// (obtained with `symlink_metadata` so links are not followed)
+    pub(crate) fn from_metadata(metadata: &Metadata) -> Self {
+        if metadata.file_type().is_symlink() {
+            Mode::SYMBOLIC
+        } else if metadata.is_dir() {
+            Mode::DIRECTORY
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            Mode::EXECUTABLE
+        } else {
+            Mode::REGULAR
+        }
+    }
+
+    /// The octal value git uses for the mode in the index and in tree objects
+    pub(crate) fn to_octal(self) -> u32 {
+        match self {
+            Mode::REGULAR => 0o100644,
+            Mode::EXECUTABLE => 0o100755,
+            Mode::SYMBOLIC => 0o120000,
+            Mode::DIRECTORY => 0o040000,
+        }
+    }
+
+    pub(crate) fn from_octal(value: u32) -> Result<Self, ()> {
+        match value {
+            0o100644 => Ok(Mode::REGULAR),
+            0o100755 => Ok(Mode::EXECUTABLE),
+            0o120000 => Ok(Mode::SYMBOLIC),
+            0o040000 => Ok(Mode::DIRECTORY),
+            _ => Err(()),
+        }
+    }
 }
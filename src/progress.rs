@@ -0,0 +1,95 @@
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// How often a meter whose percentage did not change is redrawn, for its byte rate
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A progress meter on stderr such as `Receiving objects:  45% (9/20), 1.20 KiB | 1.00 MiB/s`,
+/// redrawn in place and only shown when stderr is a terminal
+pub(crate) struct Progress {
+    title: &'static str,
+    total: u64,
+    count: u64,
+    /// The bytes transferred so far, for meters that show a throughput
+    bytes: Option<u64>,
+    start: Instant,
+    last_draw: Option<(Instant, u64)>,
+    enabled: bool,
+}
+
+impl Progress {
+    pub(crate) fn new(title: &'static str, total: u64) -> Self {
+        Progress {
+            title,
+            total,
+            count: 0,
+            bytes: None,
+            start: Instant::now(),
+            last_draw: None,
+            enabled: std::io::stderr().is_terminal(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, count: u64) {
+        self.count = count;
+        self.draw(false);
+    }
+
+    pub(crate) fn update_with_bytes(&mut self, count: u64, bytes: u64) {
+        self.bytes = Some(bytes);
+        self.update(count);
+    }
+
+    /// Draws the meter a last time, ended by `done`
+    pub(crate) fn finish(&mut self) {
+        self.draw(true);
+    }
+
+    fn draw(&mut self, done: bool) {
+        if !self.enabled {
+            return;
+        }
+        let percent = match self.total {
+            0 => 100,
+            total => self.count * 100 / total,
+        };
+        let stale = self.last_draw.is_none_or(|(time, last_percent)| {
+            last_percent != percent || time.elapsed() >= REDRAW_INTERVAL
+        });
+        if !done && !stale {
+            return;
+        }
+        self.last_draw = Some((Instant::now(), percent));
+
+        let mut line = format!(
+            "{}: {percent:>3}% ({}/{})",
+            self.title, self.count, self.total
+        );
+        if let Some(bytes) = self.bytes {
+            let seconds = self.start.elapsed().as_secs_f64().max(0.001);
+            line.push_str(&format!(
+                ", {} | {}/s",
+                humanise_bytes(bytes),
+                humanise_bytes((bytes as f64 / seconds) as u64)
+            ));
+        }
+        let end = if done { ", done.\n" } else { "\r" };
+
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "{line}{end}");
+        let _ = stderr.flush();
+    }
+}
+
+/// A size the way git shows it in progress meters, such as `512 bytes` or `1.20 KiB`
+fn humanise_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["GiB", "MiB", "KiB"];
+    for (i, unit) in UNITS.iter().enumerate() {
+        let scale = 1u64 << (10 * (UNITS.len() - i));
+        if bytes >= scale {
+            return format!("{:.2} {unit}", bytes as f64 / scale as f64);
+        }
+    }
+
+    format!("{bytes} bytes")
+}
@@ -0,0 +1,432 @@
+use crate::config::Config;
+use crate::lockfile::LockFile;
+use crate::object::commit::Signature;
+use crate::object::Hash;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub mod revision;
+
+const HAMACHI_DIR: &str = ".hamachi";
+
+/// What HEAD currently points to
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Head {
+    /// HEAD is a symbolic ref to a branch (the full ref name, e.g. `refs/heads/master`),
+    /// which may not exist yet if no commit was made on it
+    Branch(String),
+    Detached(Hash),
+}
+
+pub(crate) fn read_head() -> std::io::Result<Head> {
+    let content = fs::read_to_string(ref_path("HEAD"))?;
+    let content = content.trim();
+
+    match content.strip_prefix("ref:") {
+        Some(name) => Ok(Head::Branch(name.trim().to_string())),
+        None => Hash::from_str(content)
+            .map(Head::Detached)
+            .map_err(|_| std::io::Error::other("HEAD is corrupted")),
+    }
+}
+
+pub(crate) fn write_head(head: &Head) -> std::io::Result<()> {
+    let content = match head {
+        Head::Branch(name) => format!("ref: {name}\n"),
+        Head::Detached(hash) => format!("{hash}\n"),
+    };
+
+    let mut lock = LockFile::acquire(ref_path("HEAD"))?;
+    lock.write_all(content.as_bytes())?;
+    lock.commit()
+}
+
+/// Makes a ref point to another ref rather than to a commit, the way `refs/remotes/origin/HEAD`
+/// designates the default branch of a remote
+pub(crate) fn write_symbolic_ref(name: &str, target: &str) -> std::io::Result<()> {
+    let mut lock = LockFile::acquire(ref_path(name))?;
+    lock.write_all(format!("ref: {target}\n").as_bytes())?;
+    lock.commit()
+}
+
+/// The commit HEAD resolves to, or `None` on an unborn branch
+pub(crate) fn head_commit() -> std::io::Result<Option<Hash>> {
+    read_ref("HEAD")
+}
+
+/// Resolves a full ref name (e.g. `HEAD` or `refs/heads/master`) to a hash, following symbolic
+/// refs and falling back to the packed-refs file
+pub(crate) fn read_ref(name: &str) -> std::io::Result<Option<Hash>> {
+    let mut name = name.to_string();
+
+    // Bounded to avoid looping forever on symbolic ref cycles
+    for _ in 0..5 {
+        let path = ref_path(&name);
+        if path.is_file() {
+            let content = fs::read_to_string(path)?;
+            let content = content.trim();
+
+            match content.strip_prefix("ref:") {
+                Some(target) => name = target.trim().to_string(),
+                None => {
+                    return Hash::from_str(content)
+                        .map(Some)
+                        .map_err(|_| std::io::Error::other(format!("Ref {name} is corrupted")))
+                }
+            }
+        } else {
+            return Ok(read_packed_refs()?
+                .into_iter()
+                .find(|(packed_name, _)| *packed_name == name)
+                .map(|(_, hash)| hash));
+        }
+    }
+
+    Err(std::io::Error::other(format!(
+        "Too many levels of symbolic refs for {name}"
+    )))
+}
+
+pub(crate) fn ref_exists(name: &str) -> std::io::Result<bool> {
+    Ok(read_ref(name)?.is_some())
+}
+
+/// Points a ref to the specified hash, atomically replacing its previous value
+pub(crate) fn update_ref(name: &str, hash: &Hash) -> std::io::Result<()> {
+    let name = symbolic_target(name)?;
+
+    let mut lock = LockFile::acquire(ref_path(&name))?;
+    lock.write_all(format!("{hash}\n").as_bytes())?;
+    lock.commit()
+}
+
+/// Points a ref to a new hash provided it still points to `old`, `None` meaning that it must not
+/// exist yet, and records the update in its reflog, as well as in the reflog of HEAD when HEAD
+/// points to it
+pub(crate) fn update_ref_with_log(
+    name: &str,
+    old: Option<&Hash>,
+    new: &Hash,
+    message: &str,
+) -> std::io::Result<()> {
+    let target = symbolic_target(name)?;
+    let committer = Signature::reflog_committer();
+
+    let mut lock = LockFile::acquire(ref_path(&target))?;
+    let current = read_ref(&target)?;
+    if current.as_ref() != old {
+        let current = current.map_or(String::from("nothing"), |hash| hash.to_string());
+        let expected = old.map_or(String::from("nothing"), Hash::to_string);
+        return Err(std::io::Error::other(format!(
+            "cannot lock ref '{target}': is at {current} but expected {expected}"
+        )));
+    }
+    lock.write_all(format!("{new}\n").as_bytes())?;
+    lock.commit()?;
+
+    append_reflog(&target, old, new, &committer, message)?;
+    if target != "HEAD" && symbolic_target("HEAD")? == target {
+        append_reflog("HEAD", old, new, &committer, message)?;
+    }
+
+    Ok(())
+}
+
+/// Appends an entry to the log of the values a ref had
+/// https://git-scm.com/docs/git-reflog
+pub(crate) fn append_reflog(
+    name: &str,
+    old: Option<&Hash>,
+    new: &Hash,
+    committer: &Signature,
+    message: &str,
+) -> std::io::Result<()> {
+    let path = PathBuf::from(HAMACHI_DIR).join("logs").join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let old = old.map_or("0".repeat(40), Hash::to_string);
+    let message = message.lines().next().unwrap_or_default();
+
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    log.write_all(format!("{old} {new} {committer}\t{message}\n").as_bytes())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ReflogEntry {
+    /// The previous value of the ref, `None` when it was created
+    pub(crate) old: Option<Hash>,
+    pub(crate) new: Hash,
+    pub(crate) message: String,
+}
+
+/// The entries of the log of a ref, oldest first, none if it has no log
+pub(crate) fn read_reflog(name: &str) -> std::io::Result<Vec<ReflogEntry>> {
+    let path = PathBuf::from(HAMACHI_DIR).join("logs").join(name);
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(old), Some(new)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let message = line.split_once('\t').map_or("", |(_, message)| message);
+        let (Ok(old), Ok(new)) = (Hash::from_str(old), Hash::from_str(new)) else {
+            continue;
+        };
+
+        entries.push(ReflogEntry {
+            old: (old.to_string() != "0".repeat(40)).then_some(old),
+            new,
+            message: message.to_string(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Removes the entry of a reflog recorded `n` updates ago, joining its neighbours so that the
+/// log stays continuous, and points the ref to the value of the newest remaining entry. The ref
+/// is deleted along with its log once no entry remains, as `git stash drop` does.
+pub(crate) fn drop_reflog_entry(name: &str, n: usize) -> std::io::Result<()> {
+    let path = PathBuf::from(HAMACHI_DIR).join("logs").join(name);
+    let content = fs::read_to_string(&path)?;
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    let Some(index) = lines.len().checked_sub(n + 1) else {
+        return Err(std::io::Error::other(format!(
+            "log for '{name}' only has {} entries",
+            lines.len()
+        )));
+    };
+
+    let removed = lines.remove(index);
+    if let Some(next) = lines.get_mut(index) {
+        // The following entry now starts from the value preceding the removed one
+        let old = removed.split(' ').next().unwrap_or_default();
+        *next = format!("{old}{}", &next[old.len()..]);
+    }
+
+    let newest = lines
+        .last()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|hash| Hash::from_str(hash).ok());
+    match newest {
+        Some(newest) => {
+            let mut log = lines.join("\n");
+            log.push('\n');
+            fs::write(&path, log)?;
+            update_ref(name, &newest)
+        }
+        None => delete_ref(name, None),
+    }
+}
+
+/// Returns the ref a symbolic ref ultimately points to, or the name itself for regular refs
+pub(crate) fn symbolic_target(name: &str) -> std::io::Result<String> {
+    let mut name = name.to_string();
+    for _ in 0..5 {
+        let path = ref_path(&name);
+        if !path.is_file() {
+            return Ok(name);
+        }
+
+        let content = fs::read_to_string(path)?;
+        match content.trim().strip_prefix("ref:") {
+            Some(target) => name = target.trim().to_string(),
+            None => return Ok(name),
+        }
+    }
+
+    Err(std::io::Error::other(format!(
+        "Too many levels of symbolic refs for {name}"
+    )))
+}
+
+/// Every ref whose name starts with the prefix (e.g. `refs/heads/`), loose and packed, sorted by
+/// name. Symbolic refs are resolved.
+pub(crate) fn list_refs(prefix: &str) -> std::io::Result<Vec<(String, Hash)>> {
+    let mut refs = BTreeMap::new();
+    for (name, hash) in read_packed_refs()? {
+        if name.starts_with(prefix) {
+            refs.insert(name, hash);
+        }
+    }
+
+    let mut loose = Vec::new();
+    collect_loose_refs(&ref_path("refs"), "refs", &mut loose)?;
+    for name in loose {
+        if name.starts_with(prefix) {
+            if let Some(hash) = read_ref(&name)? {
+                refs.insert(name, hash);
+            }
+        }
+    }
+
+    Ok(refs.into_iter().collect())
+}
+
+fn collect_loose_refs(
+    directory: &Path,
+    prefix: &str,
+    refs: &mut Vec<String>,
+) -> std::io::Result<()> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = format!("{prefix}/{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_loose_refs(&entry.path(), &name, refs)?;
+        } else if !name.ends_with(".lock") {
+            refs.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes a ref, loose and packed, along with its reflog, provided it still points to `old`
+pub(crate) fn delete_ref(name: &str, old: Option<&Hash>) -> std::io::Result<()> {
+    let path = ref_path(name);
+    let lock = LockFile::acquire(&path)?;
+    if let Some(old) = old {
+        if read_ref(name)?.as_ref() != Some(old) {
+            return Err(std::io::Error::other(format!(
+                "cannot lock ref '{name}': is not at {old}"
+            )));
+        }
+    }
+
+    if read_packed_refs()?
+        .iter()
+        .any(|(packed_name, _)| packed_name == name)
+    {
+        let mut packed_lock = LockFile::acquire(ref_path("packed-refs"))?;
+        let content = fs::read_to_string(ref_path("packed-refs"))?;
+
+        // Peeled lines starting with `^` belong to the ref above them
+        let mut skipping = false;
+        let mut kept = String::new();
+        for line in content.lines() {
+            if line.starts_with('^') && skipping {
+                continue;
+            }
+            skipping = line
+                .split_once(' ')
+                .is_some_and(|(_, packed_name)| packed_name == name);
+            if !skipping {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        packed_lock.write_all(kept.as_bytes())?;
+        packed_lock.commit()?;
+    }
+
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    drop(lock);
+    prune_empty_ref_directories(&path);
+
+    let log_path = PathBuf::from(HAMACHI_DIR).join("logs").join(name);
+    if log_path.is_file() {
+        fs::remove_file(&log_path)?;
+        prune_empty_ref_directories(&log_path);
+    }
+
+    Ok(())
+}
+
+/// Removes the directories left empty below `refs/` or `logs/` once a ref is deleted, keeping the
+/// ones `init` creates
+fn prune_empty_ref_directories(path: &Path) {
+    let kept = ["refs", "refs/heads", "refs/tags", "logs"].map(ref_path);
+
+    let mut directory = path.parent();
+    while let Some(current) = directory {
+        if kept.iter().any(|kept| kept == current) || fs::remove_dir(current).is_err() {
+            break;
+        }
+        directory = current.parent();
+    }
+}
+
+/// Whether a ref name is valid, following the rules of `git check-ref-format`
+/// https://git-scm.com/docs/git-check-ref-format
+pub(crate) fn check_ref_format(name: &str) -> bool {
+    let has_forbidden_characters = name.chars().any(|c| {
+        c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+    });
+    let has_invalid_components = name.split('/').any(|component| {
+        component.is_empty() || component.starts_with('.') || component.ends_with(".lock")
+    });
+
+    !name.is_empty()
+        && name != "@"
+        && !has_forbidden_characters
+        && !has_invalid_components
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with('.')
+}
+
+/// The branch a local branch tracks, as configured by `branch.<name>.remote` and
+/// `branch.<name>.merge`: its short name (e.g. `origin/master`) and its full ref name
+/// (e.g. `refs/remotes/origin/master`)
+pub(crate) fn upstream_of(branch: &str) -> std::io::Result<Option<(String, String)>> {
+    let branch = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+    let config = Config::load()?;
+    let (Some(remote), Some(merge)) = (
+        config.get(&format!("branch.{branch}.remote")),
+        config.get(&format!("branch.{branch}.merge")),
+    ) else {
+        return Ok(None);
+    };
+
+    let merge_branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+    if remote == "." {
+        Ok(Some((merge_branch.to_string(), merge.clone())))
+    } else {
+        Ok(Some((
+            format!("{remote}/{merge_branch}"),
+            format!("refs/remotes/{remote}/{merge_branch}"),
+        )))
+    }
+}
+
+pub(crate) fn ref_path(name: &str) -> PathBuf {
+    PathBuf::from(HAMACHI_DIR).join(name)
+}
+
+fn read_packed_refs() -> std::io::Result<Vec<(String, Hash)>> {
+    let path = PathBuf::from(HAMACHI_DIR).join("packed-refs");
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(path)?;
+    let refs = content
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .filter_map(|(hash, name)| Some((name.to_string(), Hash::from_str(hash).ok()?)))
+        .collect();
+
+    Ok(refs)
+}
@@ -0,0 +1,197 @@
+use crate::graph::CommitGraph;
+use crate::object::commit::Commit;
+use crate::object::{Hash, Object, ObjectType};
+use crate::refs::{self, read_ref};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Resolves a revision such as `HEAD~2`, `master^`, a ref name or a (possibly abbreviated) hash
+/// to an object hash
+/// https://git-scm.com/docs/gitrevisions
+pub(crate) fn resolve(spec: &str) -> std::io::Result<Hash> {
+    let base_end = spec.find(['~', '^']).unwrap_or(spec.len());
+    let (base, mut suffix) = spec.split_at(base_end);
+
+    let mut hash = resolve_base(base)?;
+
+    while !suffix.is_empty() {
+        let operator = suffix.as_bytes()[0];
+        suffix = &suffix[1..];
+
+        if operator == b'^' && suffix.starts_with('{') {
+            let end = suffix.find('}').ok_or_else(|| invalid_revision(spec))?;
+            hash = match &suffix[1..end] {
+                "tree" => peel_to_tree(&hash)?,
+                "commit" | "" => {
+                    peel_to_commit(&hash)?;
+                    hash
+                }
+                _ => return Err(invalid_revision(spec)),
+            };
+            suffix = &suffix[end + 1..];
+            continue;
+        }
+
+        let digits_end = suffix
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(suffix.len());
+        let count = match &suffix[..digits_end] {
+            "" => 1,
+            digits => digits
+                .parse::<usize>()
+                .map_err(|_| invalid_revision(spec))?,
+        };
+        suffix = &suffix[digits_end..];
+
+        hash = match operator {
+            // `~n` follows the first parent n times
+            b'~' => {
+                for _ in 0..count {
+                    hash = nth_parent(&hash, 1).ok_or_else(|| invalid_revision(spec))?;
+                }
+                hash
+            }
+            // `^n` selects the n-th parent, `^0` the commit itself
+            _ if count == 0 => hash,
+            _ => nth_parent(&hash, count).ok_or_else(|| invalid_revision(spec))?,
+        };
+    }
+
+    Ok(hash)
+}
+
+/// Resolves a revision and peels it to the tree it designates
+pub(crate) fn resolve_tree(spec: &str) -> std::io::Result<Hash> {
+    peel_to_tree(&resolve(spec)?)
+}
+
+/// Resolves a revision that must designate a commit
+pub(crate) fn resolve_commit(spec: &str) -> std::io::Result<Hash> {
+    let hash = resolve(spec)?;
+    peel_to_commit(&hash)?;
+
+    Ok(hash)
+}
+
+pub(crate) fn peel_to_tree(hash: &Hash) -> std::io::Result<Hash> {
+    let (object_type, _) = Object::read(hash)?;
+    match object_type {
+        ObjectType::TREE => Ok(hash.clone()),
+        ObjectType::COMMIT => Ok(Commit::from_hash(hash).tree_hash),
+        _ => Err(std::io::Error::other(format!("{hash} is not a tree-ish"))),
+    }
+}
+
+fn peel_to_commit(hash: &Hash) -> std::io::Result<()> {
+    let (object_type, _) = Object::read(hash)?;
+    if object_type != ObjectType::COMMIT {
+        return Err(std::io::Error::other(format!("{hash} is not a commit")));
+    }
+
+    Ok(())
+}
+
+fn nth_parent(hash: &Hash, n: usize) -> Option<Hash> {
+    CommitGraph::new().parents(hash).into_iter().nth(n - 1)
+}
+
+/// The full name of the ref a short name such as `master` or `origin/master` designates
+pub(crate) fn dwim_ref(name: &str) -> std::io::Result<Option<String>> {
+    let name = if name == "@" { "HEAD" } else { name };
+
+    // Full names first, then the usual ref namespaces in the order git searches them
+    let candidates = [
+        name.to_string(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ];
+    for candidate in candidates {
+        let is_valid_ref = candidate.starts_with("refs/")
+            || candidate
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == '_');
+        if is_valid_ref && read_ref(&candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Resolves `name@{n}`, the value the ref had n updates ago according to its reflog
+fn resolve_reflog_entry(name: &str, selector: &str) -> std::io::Result<Hash> {
+    let n = selector
+        .parse::<usize>()
+        .map_err(|_| invalid_revision(&format!("{name}@{{{selector}}}")))?;
+    let name = if name.is_empty() { "HEAD" } else { name };
+    let ref_name = dwim_ref(name)?.ok_or_else(|| invalid_revision(name))?;
+
+    let entries = refs::read_reflog(&ref_name)?;
+    match entries.len().checked_sub(n + 1) {
+        Some(index) => Ok(entries[index].new.clone()),
+        None => Err(std::io::Error::other(format!(
+            "log for '{name}' only has {} entries",
+            entries.len()
+        ))),
+    }
+}
+
+fn resolve_base(name: &str) -> std::io::Result<Hash> {
+    if let Some((ref_name, selector)) = name
+        .strip_suffix('}')
+        .and_then(|name| name.split_once("@{"))
+    {
+        return resolve_reflog_entry(ref_name, selector);
+    }
+
+    if let Some(ref_name) = dwim_ref(name)? {
+        if let Some(hash) = read_ref(&ref_name)? {
+            return Ok(hash);
+        }
+    }
+
+    if let Ok(hash) = Hash::from_str(name) {
+        return Ok(hash);
+    }
+
+    resolve_abbreviated_hash(name)?.ok_or_else(|| invalid_revision(name))
+}
+
+fn resolve_abbreviated_hash(prefix: &str) -> std::io::Result<Option<Hash>> {
+    if prefix.len() < 4 || prefix.len() > 40 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+
+    let prefix = prefix.to_ascii_lowercase();
+    let directory = PathBuf::from(".hamachi/objects").join(&prefix[..2]);
+    if !directory.is_dir() {
+        return Ok(None);
+    }
+
+    let mut matches = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        if file_name.starts_with(&prefix[2..]) {
+            matches.push(format!("{}{}", &prefix[..2], file_name));
+        }
+    }
+
+    match matches.as_slice() {
+        [] => Ok(None),
+        [hash] => Ok(Hash::from_str(hash).ok()),
+        _ => Err(std::io::Error::other(format!(
+            "short object ID {prefix} is ambiguous"
+        ))),
+    }
+}
+
+fn invalid_revision(spec: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("invalid reference: {spec}"),
+    )
+}
@@ -0,0 +1,960 @@
+use crate::config::Config;
+use crate::object::packfile::writer::{write_pack, DeltaSearch};
+use crate::object::packfile::PackFile;
+use crate::object::{Hash, Object};
+use crate::remote::negotiator::Negotiator;
+use crate::remote::pkt_line::{PktLineReader, PktLineWriter};
+use crate::remote::sideband::SidebandReader;
+use crate::remote::transport::{self, Service, Transport};
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The number of haves in the first round of a negotiation, doubling every round up to
+/// `LARGE_FLUSH`
+const INITIAL_FLUSH: usize = 16;
+const LARGE_FLUSH: usize = 16384;
+/// How many haves may go unacknowledged after the first common commit before we stop looking
+/// for more
+const MAX_IN_VAIN: usize = 256;
+
+/// Talks to the upload-pack and receive-pack services of a remote over a stateless transport
+/// https://git-scm.com/docs/pack-protocol
+pub struct RemoteClient {
+    transport: Box<dyn Transport>,
+}
+
+impl RemoteClient {
+    /// Connects to the remote at the specified URL or path
+    pub fn connect(url: &str) -> std::io::Result<RemoteClient> {
+        Ok(RemoteClient {
+            transport: transport::connect(url)?,
+        })
+    }
+
+    /// The URL of the remote, local paths being made absolute
+    pub(crate) fn url(&self) -> &str {
+        self.transport.url()
+    }
+
+    /// The git directory of a remote on the local filesystem
+    pub(crate) fn local_repository(&self) -> Option<&Path> {
+        self.transport.local_repository()
+    }
+
+    /// Asks the remote for its capabilities and refs, in the protocol version set by
+    /// `protocol.version`, version 2 by default. Over version 2 the refs are listed by a separate
+    /// `ls-refs` command, only those under one of `ref_prefixes` and none when it is empty, as
+    /// requests such as lazy fetches only need the capabilities. Servers that do not speak
+    /// version 2 answer in version 0 or 1 instead, which always advertise every ref.
+    /// https://git-scm.com/docs/protocol-v2
+    pub fn discover_refs(&self, ref_prefixes: &[String]) -> std::io::Result<DiscoverRefsResponse> {
+        let mut discover_refs_response =
+            self.advertise(Service::UploadPack, requested_protocol_version()?)?;
+        if discover_refs_response.version == ProtocolVersion::V2 && !ref_prefixes.is_empty() {
+            discover_refs_response.refs = self.ls_refs(&discover_refs_response, ref_prefixes)?;
+        }
+
+        Ok(discover_refs_response)
+    }
+
+    /// Asks the remote for the refs it lets us update and its capabilities. Pushing has no
+    /// version 2, and asking for version 1 would not tell more, so the original version is
+    /// spoken whatever `protocol.version` says.
+    /// https://git-scm.com/docs/pack-protocol#_pushing_data_to_a_server
+    pub fn discover_push_refs(&self) -> std::io::Result<DiscoverRefsResponse> {
+        self.advertise(Service::ReceivePack, ProtocolVersion::V0)
+    }
+
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<DiscoverRefsResponse> {
+        let response = self.transport.advertise(service, version)?;
+        parse_discover_refs_response(&mut PktLineReader::new(response), service)
+    }
+
+    /// Lists the refs under the specified prefixes, along with the target of the symbolic ones
+    /// https://git-scm.com/docs/protocol-v2#_ls_refs
+    fn ls_refs(
+        &self,
+        discover_refs_response: &DiscoverRefsResponse,
+        ref_prefixes: &[String],
+    ) -> std::io::Result<Vec<Ref>> {
+        let mut body = command_request("ls-refs", discover_refs_response)?;
+        body.write_line("symrefs")?;
+        for prefix in ref_prefixes {
+            body.write_line(&format!("ref-prefix {prefix}"))?;
+        }
+        body.write_flush()?;
+
+        let mut response = self.post_upload_pack(body.into_inner(), ProtocolVersion::V2)?;
+        parse_ls_refs_response(&mut response)
+    }
+
+    /// Asks the remote for a pack of the wanted commits and everything they reference, minus what
+    /// is reachable from the commits we have, and writes its objects to the object database.
+    ///
+    /// Over a stateless transport every round of the negotiation is a request of its own, repeating the
+    /// wants and the commits acknowledged so far, followed by a growing batch of new haves. Once
+    /// the remote is ready to send a pack or we run out of commits to offer, a last request
+    /// sends `done` and receives the pack. Over version 2, a remote that is ready sends the pack
+    /// right away in the response to the last round.
+    pub fn fetch_pack(
+        &self,
+        discover_refs_response: &DiscoverRefsResponse,
+        request: &FetchRequest,
+    ) -> std::io::Result<FetchResponse> {
+        let version = discover_refs_response.version;
+        let mut negotiator = Negotiator::new(&request.haves);
+
+        if version == ProtocolVersion::V2 {
+            let mut ready_response = None;
+            negotiate(&mut negotiator, |haves| {
+                let body = generate_fetch_command(discover_refs_response, request, haves, false)?;
+                let mut response = self.post_upload_pack(body, version)?;
+
+                let (acknowledgments, ready) = read_acknowledgments_section(&mut response)?;
+                if ready {
+                    ready_response = Some(response);
+                }
+                Ok((acknowledgments, ready))
+            })?;
+
+            let response = match ready_response {
+                Some(response) => response,
+                None => {
+                    let haves = negotiator.acknowledged();
+                    let body =
+                        generate_fetch_command(discover_refs_response, request, haves, true)?;
+                    self.post_upload_pack(body, version)?
+                }
+            };
+
+            return parse_fetch_response(response, request);
+        }
+
+        let haves = if discover_refs_response.has_capability("multi_ack_detailed") {
+            negotiate(&mut negotiator, |haves| {
+                let body = generate_pack(discover_refs_response, request, haves, false)?;
+                let mut response = self.post_upload_pack(body, version)?;
+
+                if request.is_shallow() {
+                    read_shallow_info(&mut response)?;
+                }
+                let acknowledgments = read_acknowledgments(&mut response)?;
+                let ready = acknowledgments.iter().any(|(_, status)| status == "ready");
+                Ok((
+                    acknowledgments.into_iter().map(|(hash, _)| hash).collect(),
+                    ready,
+                ))
+            })?;
+
+            negotiator.acknowledged().to_vec()
+        } else {
+            // Without multi_ack_detailed the remote acknowledges a single commit, so only the
+            // tips are offered
+            negotiator.next_haves(request.haves.len())
+        };
+
+        let body = generate_pack(discover_refs_response, request, &haves, true)?;
+        let response = self.post_upload_pack(body, version)?;
+
+        parse_upload_pack_response(response, discover_refs_response, request)
+    }
+
+    /// Asks the remote to update its refs: one command per ref with its old and new value, the
+    /// first one carrying the capabilities we use, the push options, and then a pack of the
+    /// objects the remote lacks unless every command is a deletion. The remote reports whether
+    /// it could unpack the objects and the outcome of every command.
+    /// https://git-scm.com/docs/pack-protocol#_reference_update_request_and_packfile_transfer
+    pub(crate) fn push_pack(
+        &self,
+        discover_refs_response: &DiscoverRefsResponse,
+        request: &PushRequest,
+    ) -> std::io::Result<PushReport> {
+        let body = generate_push(discover_refs_response, request)?;
+        let response = self.post(Service::ReceivePack, body, ProtocolVersion::V0)?;
+
+        match discover_refs_response.has_capability("side-band-64k") {
+            true => {
+                let report = SidebandReader::new(response, std::io::stderr());
+                parse_report_status(&mut PktLineReader::new(report))
+            }
+            false => parse_report_status(&mut PktLineReader::new(response.into_inner())),
+        }
+    }
+
+    fn post_upload_pack(
+        &self,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<PktLineReader<Box<dyn Read>>> {
+        self.post(Service::UploadPack, body, version)
+    }
+
+    fn post(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<PktLineReader<Box<dyn Read>>> {
+        let response = self.transport.request(service, body, version)?;
+        Ok(PktLineReader::new(response))
+    }
+}
+
+/// Whether the remote should report its progress, which like ours is only shown when stderr is
+/// a terminal
+fn show_progress() -> bool {
+    std::io::stderr().is_terminal()
+}
+
+/// Runs the rounds of a negotiation, offering a growing batch of new haves every round.
+/// `round` sends the specified haves and returns the commits the remote acknowledged, and
+/// whether it is ready to send a pack.
+fn negotiate(
+    negotiator: &mut Negotiator,
+    mut round: impl FnMut(&[Hash]) -> std::io::Result<(Vec<Hash>, bool)>,
+) -> std::io::Result<()> {
+    let mut count = INITIAL_FLUSH;
+    let mut in_vain = 0;
+    loop {
+        let batch = negotiator.next_haves(count);
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut haves = negotiator.acknowledged().to_vec();
+        haves.extend(batch.iter().cloned());
+        let (acknowledgments, ready) = round(&haves)?;
+
+        let mut acknowledged = false;
+        for hash in acknowledgments {
+            acknowledged |= !negotiator.acknowledged().contains(&hash);
+            negotiator.acknowledge(&hash);
+        }
+
+        in_vain = if acknowledged {
+            0
+        } else {
+            in_vain + batch.len()
+        };
+        let gave_up = !negotiator.acknowledged().is_empty() && in_vain >= MAX_IN_VAIN;
+        if ready || gave_up {
+            return Ok(());
+        }
+        count = (count * 2).min(LARGE_FLUSH);
+    }
+}
+
+/// The protocol version set by `protocol.version`, version 2 when unset
+/// https://git-scm.com/docs/git-config#Documentation/git-config.txt-protocolversion
+fn requested_protocol_version() -> std::io::Result<ProtocolVersion> {
+    match Config::load()?.get("protocol.version").as_deref() {
+        Some("0") => Ok(ProtocolVersion::V0),
+        Some("1") => Ok(ProtocolVersion::V1),
+        Some("2") | None => Ok(ProtocolVersion::V2),
+        Some(version) => Err(std::io::Error::other(format!(
+            "unknown value for config 'protocol.version': {version}"
+        ))),
+    }
+}
+
+/// The `Git-Protocol` header, or `GIT_PROTOCOL` variable for local services, asking the server
+/// for a protocol version other than the original
+/// https://git-scm.com/docs/http-protocol#_smart_clients
+pub(crate) fn git_protocol_header(version: ProtocolVersion) -> Option<&'static str> {
+    match version {
+        ProtocolVersion::V0 => None,
+        ProtocolVersion::V1 => Some("version=1"),
+        ProtocolVersion::V2 => Some("version=2"),
+    }
+}
+
+/// Parses the response to the discovery of the refs, which over HTTP opens with a line announcing
+/// the service and a flush. A version 2 server only lists its capabilities, one per line.
+/// Otherwise, after the version line of version 1, comes one line per ref, the first one also
+/// carrying the capabilities of the server after a NUL byte.
+fn parse_discover_refs_response(
+    reader: &mut PktLineReader<impl Read>,
+    service: Service,
+) -> std::io::Result<DiscoverRefsResponse> {
+    let invalid = || std::io::Error::other("protocol error: invalid ref advertisement");
+
+    let mut first_line = reader.read_text()?;
+    if first_line.as_deref() == Some(&format!("# service={}", service.name())) {
+        if reader.read_line()?.is_some() {
+            return Err(invalid());
+        }
+        first_line = reader.read_text()?;
+    }
+    // Such as git daemon refusing to serve a repository
+    if let Some(message) = first_line
+        .as_deref()
+        .and_then(|line| line.strip_prefix("ERR "))
+    {
+        return Err(std::io::Error::other(format!("remote error: {message}")));
+    }
+    if first_line.as_deref() == Some("version 2") {
+        return Ok(DiscoverRefsResponse {
+            version: ProtocolVersion::V2,
+            refs: Vec::new(),
+            capabilities: reader.read_section()?,
+        });
+    }
+
+    let mut version = ProtocolVersion::V0;
+    let mut refs = Vec::new();
+    let mut capabilities = Vec::new();
+    let mut next_line = first_line;
+    while let Some(line) = next_line {
+        next_line = reader.read_text()?;
+        if line == "version 1" && version == ProtocolVersion::V0 && refs.is_empty() {
+            version = ProtocolVersion::V1;
+            continue;
+        }
+
+        let (line, advertised_capabilities) = line.split_once('\0').unwrap_or((&line, ""));
+        if !advertised_capabilities.is_empty() {
+            capabilities = advertised_capabilities
+                .split(' ')
+                .map(str::to_string)
+                .collect();
+        }
+
+        let (hash, name) = line.split_once(' ').ok_or_else(invalid)?;
+        // An empty repository advertises its capabilities on a placeholder ref
+        if name == "capabilities^{}" {
+            continue;
+        }
+
+        let hash = Hash::from_str(hash).map_err(|_| invalid())?;
+        refs.push(Ref {
+            hash,
+            name: name.to_string(),
+            symref: None,
+        });
+    }
+
+    // The targets of the symbolic refs are advertised as `symref=<name>:<target>` capabilities
+    for capability in &capabilities {
+        if let Some((name, target)) = capability
+            .strip_prefix("symref=")
+            .and_then(|symref| symref.split_once(':'))
+        {
+            if let Some(r) = refs.iter_mut().find(|r| r.name == name) {
+                r.symref = Some(target.to_string());
+            }
+        }
+    }
+
+    Ok(DiscoverRefsResponse {
+        version,
+        refs,
+        capabilities,
+    })
+}
+
+/// Parses the response to `ls-refs`: one line per ref, with its hash and name followed by
+/// attributes such as `symref-target:<target>`
+fn parse_ls_refs_response(reader: &mut PktLineReader<impl Read>) -> std::io::Result<Vec<Ref>> {
+    let mut refs = Vec::new();
+
+    for line in reader.read_section()? {
+        let mut fields = line.split(' ');
+        let (Some(hash), Some(name)) = (fields.next(), fields.next()) else {
+            return Err(unexpected_line(&line));
+        };
+        let hash = Hash::from_str(hash).map_err(|_| unexpected_line(&line))?;
+        let symref = fields
+            .find_map(|attribute| attribute.strip_prefix("symref-target:"))
+            .map(str::to_string);
+
+        refs.push(Ref {
+            hash,
+            name: name.to_string(),
+            symref,
+        });
+    }
+
+    Ok(refs)
+}
+
+/// Starts a version 2 command request: the command, the capabilities it relies on, then a
+/// delimiter before the arguments
+/// https://git-scm.com/docs/protocol-v2#_command_request
+fn command_request(
+    command: &str,
+    discover_refs_response: &DiscoverRefsResponse,
+) -> std::io::Result<PktLineWriter<Vec<u8>>> {
+    let mut request = PktLineWriter::new(Vec::new());
+    request.write_line(&format!("command={command}"))?;
+    if discover_refs_response.has_capability("object-format") {
+        request.write_line("object-format=sha1")?;
+    }
+    request.write_delimiter()?;
+
+    Ok(request)
+}
+
+/// A request to upload-pack: the wanted commits, the first one carrying the capabilities we
+/// use, the shallow boundary and how to deepen it, the object filter, then the commits we have
+/// and either `done` or a flush asking for acknowledgments
+/// https://git-scm.com/docs/pack-protocol#_packfile_negotiation
+fn generate_pack(
+    discover_refs_response: &DiscoverRefsResponse,
+    request: &FetchRequest,
+    haves: &[Hash],
+    done: bool,
+) -> std::io::Result<Vec<u8>> {
+    let unsupported =
+        |option: &str| std::io::Error::other(format!("Server does not support {option}"));
+
+    let mut capabilities = Vec::new();
+    for capability in ["multi_ack_detailed", "side-band-64k", "ofs-delta"] {
+        if discover_refs_response.has_capability(capability) {
+            capabilities.push(capability);
+        }
+    }
+    if !show_progress() {
+        capabilities.push("no-progress");
+    }
+    if request.is_shallow() {
+        if !discover_refs_response.has_capability("shallow") {
+            return Err(unsupported("shallow clients"));
+        }
+        capabilities.push("shallow");
+    }
+    if request.deepen_relative {
+        if !discover_refs_response.has_capability("deepen-relative") {
+            return Err(unsupported("--deepen"));
+        }
+        capabilities.push("deepen-relative");
+    }
+    if request.deepen_since.is_some() {
+        if !discover_refs_response.has_capability("deepen-since") {
+            return Err(unsupported("--shallow-since"));
+        }
+        capabilities.push("deepen-since");
+    }
+    if !request.deepen_not.is_empty() {
+        if !discover_refs_response.has_capability("deepen-not") {
+            return Err(unsupported("--shallow-exclude"));
+        }
+        capabilities.push("deepen-not");
+    }
+    let filter = match &request.filter {
+        Some(filter) if discover_refs_response.has_capability("filter") => {
+            capabilities.push("filter");
+            Some(filter)
+        }
+        Some(_) => {
+            eprintln!("warning: filtering not recognized by server, ignoring");
+            None
+        }
+        None => None,
+    };
+
+    let mut pack = PktLineWriter::new(Vec::new());
+    for (i, want) in request.wants.iter().enumerate() {
+        match i {
+            0 if !capabilities.is_empty() => {
+                pack.write_line(&format!("want {want} {}", capabilities.join(" ")))?
+            }
+            _ => pack.write_line(&format!("want {want}"))?,
+        }
+    }
+
+    for shallow in &request.shallow {
+        pack.write_line(&format!("shallow {shallow}"))?;
+    }
+    if let Some(depth) = request.depth {
+        pack.write_line(&format!("deepen {depth}"))?;
+    }
+    if let Some(since) = request.deepen_since {
+        pack.write_line(&format!("deepen-since {since}"))?;
+    }
+    for exclude in &request.deepen_not {
+        pack.write_line(&format!("deepen-not {exclude}"))?;
+    }
+    if let Some(filter) = filter {
+        pack.write_line(&format!("filter {filter}"))?;
+    }
+    pack.write_flush()?;
+
+    for have in haves {
+        pack.write_line(&format!("have {have}"))?;
+    }
+    match done {
+        true => pack.write_line("done")?,
+        false => pack.write_flush()?,
+    }
+
+    Ok(pack.into_inner())
+}
+
+/// A version 2 `fetch` command: the wanted commits, the shallow boundary and how to deepen it,
+/// the object filter, then the commits we have and `done` once negotiation is over
+/// https://git-scm.com/docs/protocol-v2#_fetch
+fn generate_fetch_command(
+    discover_refs_response: &DiscoverRefsResponse,
+    request: &FetchRequest,
+    haves: &[Hash],
+    done: bool,
+) -> std::io::Result<Vec<u8>> {
+    let mut command = command_request("fetch", discover_refs_response)?;
+    command.write_line("ofs-delta")?;
+    if !show_progress() {
+        command.write_line("no-progress")?;
+    }
+    for want in &request.wants {
+        command.write_line(&format!("want {want}"))?;
+    }
+
+    if request.is_shallow() {
+        if !discover_refs_response.has_feature("fetch", "shallow") {
+            return Err(std::io::Error::other(
+                "Server does not support shallow requests",
+            ));
+        }
+        for shallow in &request.shallow {
+            command.write_line(&format!("shallow {shallow}"))?;
+        }
+        if let Some(depth) = request.depth {
+            command.write_line(&format!("deepen {depth}"))?;
+        }
+        if request.deepen_relative {
+            command.write_line("deepen-relative")?;
+        }
+        if let Some(since) = request.deepen_since {
+            command.write_line(&format!("deepen-since {since}"))?;
+        }
+        for exclude in &request.deepen_not {
+            command.write_line(&format!("deepen-not {exclude}"))?;
+        }
+    }
+    if let Some(filter) = &request.filter {
+        match discover_refs_response.has_feature("fetch", "filter") {
+            true => command.write_line(&format!("filter {filter}"))?,
+            false => eprintln!("warning: filtering not recognized by server, ignoring"),
+        }
+    }
+
+    for have in haves {
+        command.write_line(&format!("have {have}"))?;
+    }
+    if done {
+        command.write_line("done")?;
+    }
+    command.write_flush()?;
+
+    Ok(command.into_inner())
+}
+
+/// A request to receive-pack: the update commands, the first one carrying the capabilities we
+/// use, the push options when there are any, then a pack unless every command is a deletion
+/// https://git-scm.com/docs/pack-protocol#_reference_update_request_and_packfile_transfer
+fn generate_push(
+    discover_refs_response: &DiscoverRefsResponse,
+    request: &PushRequest,
+) -> std::io::Result<Vec<u8>> {
+    let mut capabilities = Vec::new();
+    if discover_refs_response.has_capability("report-status-v2") {
+        capabilities.push("report-status-v2");
+    } else if discover_refs_response.has_capability("report-status") {
+        capabilities.push("report-status");
+    }
+    if discover_refs_response.has_capability("side-band-64k") {
+        capabilities.push("side-band-64k");
+    }
+    if !show_progress() && discover_refs_response.has_capability("quiet") {
+        capabilities.push("quiet");
+    }
+    if request.atomic {
+        if !discover_refs_response.has_capability("atomic") {
+            return Err(std::io::Error::other(
+                "the receiving end does not support --atomic push",
+            ));
+        }
+        capabilities.push("atomic");
+    }
+    if !request.push_options.is_empty() {
+        if !discover_refs_response.has_capability("push-options") {
+            return Err(std::io::Error::other(
+                "the receiving end does not support push options",
+            ));
+        }
+        capabilities.push("push-options");
+    }
+    if discover_refs_response.has_capability("object-format") {
+        capabilities.push("object-format=sha1");
+    }
+
+    let mut push = PktLineWriter::new(Vec::new());
+    for (i, command) in request.commands.iter().enumerate() {
+        let line = format!(
+            "{} {} {}",
+            hash_or_zero(command.old.as_ref()),
+            hash_or_zero(command.new.as_ref()),
+            command.name
+        );
+        match i {
+            0 => push.write_line(&format!("{line}\0{}", capabilities.join(" ")))?,
+            _ => push.write_line(&line)?,
+        }
+    }
+    push.write_flush()?;
+
+    if !request.push_options.is_empty() {
+        for option in &request.push_options {
+            push.write_line(option)?;
+        }
+        push.write_flush()?;
+    }
+
+    let mut body = push.into_inner();
+    if request.commands.iter().any(|command| command.new.is_some()) {
+        let ofs_delta = discover_refs_response.has_capability("ofs-delta");
+        (body, _) = write_pack(body, &request.objects, DeltaSearch::default(), ofs_delta)?;
+    }
+
+    Ok(body)
+}
+
+/// The hash of an object, or the hash made of zeros standing for a ref that does not exist
+fn hash_or_zero(hash: Option<&Hash>) -> String {
+    hash.map_or_else(|| "0".repeat(40), Hash::to_string)
+}
+
+/// Parses the response to a version 2 `fetch` command once the remote is ready: sections
+/// starting with their name, the changes to the shallow boundary, the refs asked for by name,
+/// and the pack, multiplexed on band 1 with progress on band 2 and errors on band 3
+/// https://git-scm.com/docs/protocol-v2#_fetch
+fn parse_fetch_response(
+    mut reader: PktLineReader<impl Read>,
+    request: &FetchRequest,
+) -> std::io::Result<FetchResponse> {
+    let mut shallow = Vec::new();
+    let mut unshallow = Vec::new();
+
+    loop {
+        let header = reader
+            .read_text()?
+            .ok_or_else(|| std::io::Error::other("protocol error: expected packfile"))?;
+
+        match header.as_str() {
+            "shallow-info" => (shallow, unshallow) = read_shallow_info(&mut reader)?,
+            // We never ask for refs by name, but the section is skipped all the same
+            "wanted-refs" => {
+                reader.read_section()?;
+            }
+            "packfile" => break,
+            _ => match header.strip_prefix("ERR ") {
+                Some(message) => {
+                    return Err(std::io::Error::other(format!("remote error: {message}")))
+                }
+                None => return Err(unexpected_line(&header)),
+            },
+        }
+    }
+
+    let pack = SidebandReader::new(reader, std::io::stderr());
+    receive_pack(pack, request, shallow, unshallow)
+}
+
+/// Parses the response of upload-pack: the changes to the shallow boundary when the request
+/// involves one, the acknowledgement of the commits we have, and then the pack itself,
+/// multiplexed with the progress of the remote when `side-band-64k` is supported
+fn parse_upload_pack_response(
+    mut reader: PktLineReader<impl Read>,
+    discover_refs_response: &DiscoverRefsResponse,
+    request: &FetchRequest,
+) -> std::io::Result<FetchResponse> {
+    let (shallow, unshallow) = match request.is_shallow() {
+        true => read_shallow_info(&mut reader)?,
+        false => (Vec::new(), Vec::new()),
+    };
+    read_acknowledgments(&mut reader)?;
+
+    match discover_refs_response.has_capability("side-band-64k") {
+        true => {
+            let pack = SidebandReader::new(reader, std::io::stderr());
+            receive_pack(pack, request, shallow, unshallow)
+        }
+        false => receive_pack(reader.into_inner(), request, shallow, unshallow),
+    }
+}
+
+/// Writes the objects of a received pack, checking that it holds every wanted object we lack
+fn receive_pack(
+    data: impl Read,
+    request: &FetchRequest,
+    shallow: Vec<Hash>,
+    unshallow: Vec<Hash>,
+) -> std::io::Result<FetchResponse> {
+    let pack = PackFile::receive(data, true)?;
+    let complete = request
+        .wants
+        .iter()
+        .all(|want| pack.contains(want) || Object::exists(want));
+    if !complete {
+        return Err(std::io::Error::other(
+            "remote did not send all necessary objects",
+        ));
+    }
+
+    Ok(FetchResponse {
+        pack,
+        shallow,
+        unshallow,
+    })
+}
+
+fn unexpected_line(line: &str) -> std::io::Error {
+    std::io::Error::other(format!("protocol error: unexpected '{line}'"))
+}
+
+/// Reads the `shallow` and `unshallow` lines up to the end of the section, which tell how the
+/// shallow boundary moves
+fn read_shallow_info(
+    reader: &mut PktLineReader<impl Read>,
+) -> std::io::Result<(Vec<Hash>, Vec<Hash>)> {
+    let mut shallow = Vec::new();
+    let mut unshallow = Vec::new();
+
+    for line in reader.read_section()? {
+        let (kind, hash) = line.split_once(' ').ok_or_else(|| unexpected_line(&line))?;
+        let hash = Hash::from_str(hash).map_err(|_| unexpected_line(&line))?;
+
+        match kind {
+            "shallow" => shallow.push(hash),
+            "unshallow" => unshallow.push(hash),
+            _ => return Err(unexpected_line(&line)),
+        }
+    }
+
+    Ok((shallow, unshallow))
+}
+
+/// Reads the version 2 `acknowledgments` section: `ACK <hash>` for the commits the remote has,
+/// or `NAK` when it has none of them, then `ready` when it is ready to send the pack
+fn read_acknowledgments_section(
+    reader: &mut PktLineReader<impl Read>,
+) -> std::io::Result<(Vec<Hash>, bool)> {
+    let header = reader
+        .read_text()?
+        .ok_or_else(|| std::io::Error::other("protocol error: expected acknowledgments"))?;
+    if header != "acknowledgments" {
+        return Err(unexpected_line(&header));
+    }
+
+    let mut acknowledgments = Vec::new();
+    let mut ready = false;
+    for line in reader.read_section()? {
+        match line.as_str() {
+            "NAK" => {}
+            "ready" => ready = true,
+            _ => {
+                let hash = line
+                    .strip_prefix("ACK ")
+                    .and_then(|hash| Hash::from_str(hash).ok())
+                    .ok_or_else(|| unexpected_line(&line))?;
+                acknowledgments.push(hash);
+            }
+        }
+    }
+
+    Ok((acknowledgments, ready))
+}
+
+/// Reads the acknowledgments of the commits we have: `ACK <hash> common` or `ACK <hash> ready`
+/// lines ended by `NAK`, or by a final `ACK <hash>` once we sent `done`
+fn read_acknowledgments(
+    reader: &mut PktLineReader<impl Read>,
+) -> std::io::Result<Vec<(Hash, String)>> {
+    let mut acknowledgments = Vec::new();
+
+    loop {
+        let line = reader
+            .read_text()?
+            .ok_or_else(|| std::io::Error::other("protocol error: expected ACK/NAK"))?;
+
+        if line == "NAK" {
+            break;
+        }
+        if let Some(message) = line.strip_prefix("ERR ") {
+            return Err(std::io::Error::other(format!("remote error: {message}")));
+        }
+
+        let acknowledgment = line
+            .strip_prefix("ACK ")
+            .ok_or_else(|| unexpected_line(&line))?;
+        let (hash, status) = acknowledgment
+            .split_once(' ')
+            .unwrap_or((acknowledgment, ""));
+        let hash = Hash::from_str(hash).map_err(|_| unexpected_line(&line))?;
+        acknowledgments.push((hash, status.to_string()));
+
+        if status.is_empty() {
+            break;
+        }
+    }
+
+    Ok(acknowledgments)
+}
+
+/// Reads the report of receive-pack: `unpack ok` or the reason it could not unpack the pack,
+/// then `ok <ref>` or `ng <ref> <reason>` for every command. Version 2 of the report adds
+/// `option` lines after an `ok` when a hook changed the update, which we have no use for.
+/// https://git-scm.com/docs/pack-protocol#_report_status
+fn parse_report_status(reader: &mut PktLineReader<impl Read>) -> std::io::Result<PushReport> {
+    let unpack = reader
+        .read_text()?
+        .ok_or_else(|| std::io::Error::other("protocol error: expected unpack status"))?;
+    let unpack_error = match unpack.strip_prefix("unpack ") {
+        Some("ok") => None,
+        Some(error) => Some(error.to_string()),
+        None => return Err(unexpected_line(&unpack)),
+    };
+
+    let mut rejected = Vec::new();
+    for line in reader.read_section()? {
+        if line.starts_with("ok ") || line.starts_with("option ") {
+            continue;
+        }
+        let (name, reason) = line
+            .strip_prefix("ng ")
+            .and_then(|rejection| rejection.split_once(' '))
+            .ok_or_else(|| unexpected_line(&line))?;
+        rejected.push((name.to_string(), reason.to_string()));
+    }
+
+    Ok(PushReport {
+        unpack_error,
+        rejected,
+    })
+}
+
+/// The version of the wire protocol spoken with a remote. Version 1 is version 0 with a line
+/// announcing the version, and version 2 is made of commands with a ref advertisement of their own.
+/// https://git-scm.com/docs/gitprotocol-v2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ProtocolVersion {
+    V0,
+    V1,
+    V2,
+}
+
+#[derive(Debug)]
+pub struct Ref {
+    pub(crate) hash: Hash,
+    pub(crate) name: String,
+    /// The ref a symbolic ref such as `HEAD` points to, when the remote tells
+    pub(crate) symref: Option<String>,
+}
+
+pub struct DiscoverRefsResponse {
+    pub(crate) version: ProtocolVersion,
+    pub(crate) refs: Vec<Ref>,
+    capabilities: Vec<String>,
+}
+
+impl DiscoverRefsResponse {
+    /// Whether the remote advertises a capability, ignoring its value
+    fn has_capability(&self, name: &str) -> bool {
+        self.capabilities.iter().any(|capability| {
+            capability == name || capability.split_once('=').map(|(key, _)| key) == Some(name)
+        })
+    }
+
+    /// Whether a version 2 command supports a feature, listed in the value of its capability such
+    /// as `fetch=shallow filter`
+    fn has_feature(&self, command: &str, feature: &str) -> bool {
+        self.capabilities.iter().any(|capability| {
+            capability
+                .strip_prefix(command)
+                .and_then(|features| features.strip_prefix('='))
+                .is_some_and(|features| features.split(' ').any(|f| f == feature))
+        })
+    }
+
+    /// The branch the remote HEAD points to, when advertised, or else the first branch advertised
+    /// with the same hash as HEAD
+    pub(crate) fn default_branch(&self) -> Option<String> {
+        let head = self.refs.iter().find(|r| r.name == "HEAD")?;
+        if let Some(target) = &head.symref {
+            return Some(target.clone());
+        }
+
+        self.refs
+            .iter()
+            .find(|r| r.name.starts_with("refs/heads/") && r.hash == head.hash)
+            .map(|r| r.name.clone())
+    }
+}
+
+/// What to ask upload-pack for
+#[derive(Debug, Default)]
+pub(crate) struct FetchRequest {
+    pub(crate) wants: Vec<Hash>,
+    /// The local commits from which to look for the history shared with the remote
+    pub(crate) haves: Vec<Hash>,
+    /// The current shallow boundary of the local repository
+    pub(crate) shallow: Vec<Hash>,
+    /// How many commits of history to fetch from the wanted commits
+    pub(crate) depth: Option<u32>,
+    /// Whether the depth counts from the current shallow boundary rather than from the tips
+    pub(crate) deepen_relative: bool,
+    /// Only fetch the commits more recent than this timestamp
+    pub(crate) deepen_since: Option<u64>,
+    /// Only fetch the commits not reachable from these refs
+    pub(crate) deepen_not: Vec<String>,
+    /// Leave out the objects this filter excludes, such as `blob:none`, for a partial clone
+    pub(crate) filter: Option<String>,
+}
+
+impl FetchRequest {
+    fn is_shallow(&self) -> bool {
+        !self.shallow.is_empty()
+            || self.depth.is_some()
+            || self.deepen_since.is_some()
+            || !self.deepen_not.is_empty()
+    }
+}
+
+pub(crate) struct FetchResponse {
+    pub(crate) pack: PackFile,
+    /// The commits that became part of the shallow boundary
+    pub(crate) shallow: Vec<Hash>,
+    /// The commits of the shallow boundary whose parents were fetched
+    pub(crate) unshallow: Vec<Hash>,
+}
+
+/// What to ask receive-pack for
+#[derive(Debug, Default)]
+pub(crate) struct PushRequest {
+    pub(crate) commands: Vec<PushCommand>,
+    /// The objects the remote lacks, sent in a pack along with the commands
+    pub(crate) objects: Vec<(Hash, String)>,
+    /// Whether the remote must reject every command when it rejects one
+    pub(crate) atomic: bool,
+    /// Strings passed on to the hooks of the remote
+    pub(crate) push_options: Vec<String>,
+}
+
+/// An update of a remote ref, `None` meaning that the ref does not exist before or after
+#[derive(Debug)]
+pub(crate) struct PushCommand {
+    pub(crate) name: String,
+    pub(crate) old: Option<Hash>,
+    pub(crate) new: Option<Hash>,
+}
+
+pub(crate) struct PushReport {
+    /// Why the remote could not unpack the pack, when it could not
+    pub(crate) unpack_error: Option<String>,
+    /// The refs the remote did not update, with its reason
+    pub(crate) rejected: Vec<(String, String)>,
+}
@@ -1 +1,9 @@
-pub mod http_client;
+pub mod client;
+pub(crate) mod negotiator;
+pub(crate) mod pkt_line;
+pub(crate) mod promisor;
+pub(crate) mod receive_pack;
+pub(crate) mod refspec;
+pub(crate) mod sideband;
+pub(crate) mod transport;
+pub(crate) mod upload_pack;
@@ -0,0 +1,156 @@
+use crate::graph::CommitGraph;
+use crate::object::Hash;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Chooses the `have` lines telling a remote which commits we already have, so that it only
+/// sends what we lack. Local history is offered most recent first, and the ancestors of the
+/// commits the remote acknowledges having are known to be shared and no longer offered.
+/// https://git-scm.com/docs/pack-protocol#_packfile_negotiation
+pub(crate) struct Negotiator {
+    graph: CommitGraph,
+    queue: BinaryHeap<(u64, Hash)>,
+    seen: HashSet<Hash>,
+    popped: HashSet<Hash>,
+    common: HashSet<Hash>,
+    /// How many queued commits are not known to be common, nothing being left to offer once
+    /// there are none
+    non_common: usize,
+    /// The commits acknowledged by the remote, which the final request offers again
+    acknowledged: Vec<Hash>,
+}
+
+impl Negotiator {
+    /// Starts from the tips of the local refs, which must be commits
+    pub(crate) fn new(tips: &[Hash]) -> Self {
+        let mut negotiator = Negotiator {
+            graph: CommitGraph::new(),
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            popped: HashSet::new(),
+            common: HashSet::new(),
+            non_common: 0,
+            acknowledged: Vec::new(),
+        };
+        for tip in tips {
+            negotiator.push(tip.clone());
+        }
+
+        negotiator
+    }
+
+    fn push(&mut self, hash: Hash) {
+        if self.seen.insert(hash.clone()) {
+            if !self.common.contains(&hash) {
+                self.non_common += 1;
+            }
+            let date = self.graph.date(&hash);
+            self.queue.push((date, hash));
+        }
+    }
+
+    /// The next commits to offer, at most `count`, skipping those known to be common. The
+    /// parents of common commits are common too, and are marked so as they are queued.
+    pub(crate) fn next_haves(&mut self, count: usize) -> Vec<Hash> {
+        let mut haves = Vec::new();
+        while haves.len() < count && self.non_common > 0 {
+            let Some((_, hash)) = self.queue.pop() else {
+                break;
+            };
+            self.popped.insert(hash.clone());
+            let common = self.common.contains(&hash);
+            if !common {
+                self.non_common -= 1;
+            }
+
+            for parent in self.graph.parents(&hash) {
+                if common {
+                    self.mark_common(&parent);
+                }
+                self.push(parent);
+            }
+            if !common {
+                haves.push(hash);
+            }
+        }
+
+        haves
+    }
+
+    /// Records that the remote has a commit, and so every ancestor of it
+    pub(crate) fn acknowledge(&mut self, hash: &Hash) {
+        if self.common.contains(hash) {
+            return;
+        }
+        self.acknowledged.push(hash.clone());
+        self.mark_common(hash);
+    }
+
+    /// Marks a commit as common along with the ancestors already visited, those still to be
+    /// visited passing the mark on to their parents when they are
+    fn mark_common(&mut self, hash: &Hash) {
+        let mut stack = vec![hash.clone()];
+        while let Some(hash) = stack.pop() {
+            if !self.common.insert(hash.clone()) {
+                continue;
+            }
+            if !self.popped.contains(&hash) {
+                if self.seen.contains(&hash) {
+                    self.non_common -= 1;
+                }
+                continue;
+            }
+            for parent in self.graph.parents(&hash) {
+                if self.seen.contains(&parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn acknowledged(&self) -> &[Hash] {
+        &self.acknowledged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::Hash;
+    use crate::remote::negotiator::Negotiator;
+    use crate::test_utils::{hamachi_git, setup_test_environment, teardown};
+    use rusty_fork::rusty_fork_test;
+    use std::str::FromStr;
+
+    fn commit(message: &str, date: u32) -> Hash {
+        std::env::set_var("GIT_AUTHOR_DATE", format!("{date} +0100"));
+        std::env::set_var("GIT_COMMITTER_DATE", format!("{date} +0100"));
+        hamachi_git(&["commit", "--allow-empty", "-m", message]);
+        Hash::from_str(&hamachi_git(&["rev-parse", "HEAD"])).unwrap()
+    }
+
+    rusty_fork_test! {
+        #[test]
+        fn negotiator_test() {
+            // Setup
+            let repo = setup_test_environment().unwrap();
+            commit("root", 1700000000);
+            let fork = commit("fork", 1700000100);
+            commit("older", 1700000200);
+            let newer = commit("newer", 1700000900);
+            hamachi_git(&["checkout", "-b", "side", &fork.to_string()]);
+            let side = commit("side", 1700000800);
+
+            // Test
+            let mut negotiator = Negotiator::new(&[newer.clone(), side.clone()]);
+            assert_eq!(negotiator.next_haves(1), std::slice::from_ref(&newer));
+
+            // The fork point is only reached after the acknowledgement, through the side branch,
+            // and is known to be common all the same
+            negotiator.acknowledge(&newer);
+            assert_eq!(negotiator.next_haves(10), [side]);
+            assert_eq!(negotiator.acknowledged(), [newer]);
+            assert!(negotiator.next_haves(10).is_empty());
+
+            teardown(repo).unwrap();
+        }
+    }
+}
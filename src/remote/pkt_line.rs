@@ -0,0 +1,224 @@
+use std::io::{Read, Write};
+
+/// The size of the 4 hexadecimal digits length that starts every pkt-line
+const LENGTH_SIZE: usize = 4;
+/// The largest pkt-line, its length included
+pub(crate) const MAX_PKT_LINE_LENGTH: usize = 65520;
+/// The most data a single pkt-line carries
+pub(crate) const MAX_PKT_DATA_LENGTH: usize = MAX_PKT_LINE_LENGTH - LENGTH_SIZE;
+
+/// A pkt-line: a 4 hexadecimal digits length including itself, followed by the data, or one of
+/// the special packets whose length is too short to hold any
+/// https://git-scm.com/docs/protocol-common#_pkt_line_format
+#[derive(Debug, PartialEq)]
+pub(crate) enum Packet {
+    /// `0000`, which ends a message
+    Flush,
+    /// `0001`, which separates the sections of a version 2 message
+    Delimiter,
+    /// `0002`, which ends the response to a version 2 command
+    ResponseEnd,
+    Data(Vec<u8>),
+}
+
+/// Reads pkt-lines from a stream, one packet at a time, so that whatever follows the pkt-lines,
+/// such as a pack, can still be read from the stream
+pub(crate) struct PktLineReader<R> {
+    inner: R,
+}
+
+impl<R: Read> PktLineReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        PktLineReader { inner }
+    }
+
+    pub(crate) fn read_packet(&mut self) -> std::io::Result<Packet> {
+        let mut length = [0; LENGTH_SIZE];
+        self.read_exact(&mut length)?;
+
+        let bad_length = || {
+            std::io::Error::other(format!(
+                "protocol error: bad line length character: {}",
+                String::from_utf8_lossy(&length)
+            ))
+        };
+        if !length.iter().all(u8::is_ascii_hexdigit) {
+            return Err(bad_length());
+        }
+        let length = std::str::from_utf8(&length)
+            .ok()
+            .and_then(|length| usize::from_str_radix(length, 16).ok())
+            .ok_or_else(bad_length)?;
+
+        match length {
+            0 => Ok(Packet::Flush),
+            1 => Ok(Packet::Delimiter),
+            2 => Ok(Packet::ResponseEnd),
+            3 => Err(std::io::Error::other("protocol error: bad line length 3")),
+            length if length > MAX_PKT_LINE_LENGTH => Err(std::io::Error::other(format!(
+                "protocol error: bad line length {length}"
+            ))),
+            length => {
+                let mut data = vec![0; length - LENGTH_SIZE];
+                self.read_exact(&mut data)?;
+                Ok(Packet::Data(data))
+            }
+        }
+    }
+
+    /// Reads a line of data, or `None` at a flush packet
+    pub(crate) fn read_line(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        match self.read_packet()? {
+            Packet::Data(line) => Ok(Some(line)),
+            Packet::Flush => Ok(None),
+            packet => Err(std::io::Error::other(format!(
+                "protocol error: unexpected {packet:?} packet"
+            ))),
+        }
+    }
+
+    /// Reads a line of text without its line feed, or `None` at a flush packet
+    pub(crate) fn read_text(&mut self) -> std::io::Result<Option<String>> {
+        self.read_line()?.map(into_text).transpose()
+    }
+
+    /// Reads the lines of text of a section, up to the flush, delimiter or response end packet
+    /// that ends it
+    pub(crate) fn read_section(&mut self) -> std::io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        while let Packet::Data(line) = self.read_packet()? {
+            lines.push(into_text(line)?);
+        }
+
+        Ok(lines)
+    }
+
+    /// The stream, positioned right after the last packet read
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> std::io::Result<()> {
+        self.inner.read_exact(buffer).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => {
+                std::io::Error::other("the remote end hung up unexpectedly")
+            }
+            _ => e,
+        })
+    }
+}
+
+/// The text of a line, whose trailing line feed is optional
+fn into_text(line: Vec<u8>) -> std::io::Result<String> {
+    let mut text = String::from_utf8(line).map_err(|e| {
+        std::io::Error::other(format!(
+            "protocol error: unexpected '{}'",
+            String::from_utf8_lossy(e.as_bytes())
+        ))
+    })?;
+    if text.ends_with('\n') {
+        text.pop();
+    }
+
+    Ok(text)
+}
+
+/// Writes pkt-lines to a stream
+pub(crate) struct PktLineWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> PktLineWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        PktLineWriter { inner }
+    }
+
+    pub(crate) fn write_data(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if data.len() > MAX_PKT_DATA_LENGTH {
+            return Err(std::io::Error::other(
+                "protocol error: impossibly long line",
+            ));
+        }
+
+        write!(self.inner, "{:04x}", data.len() + LENGTH_SIZE)?;
+        self.inner.write_all(data)
+    }
+
+    /// Writes a line of text, ended by a line feed
+    pub(crate) fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let mut data = Vec::with_capacity(line.len() + 1);
+        data.extend_from_slice(line.as_bytes());
+        data.push(b'\n');
+        self.write_data(&data)
+    }
+
+    pub(crate) fn write_flush(&mut self) -> std::io::Result<()> {
+        self.inner.write_all(b"0000")
+    }
+
+    pub(crate) fn write_delimiter(&mut self) -> std::io::Result<()> {
+        self.inner.write_all(b"0001")
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Packet, PktLineReader, PktLineWriter, MAX_PKT_DATA_LENGTH};
+
+    /// A version 2 `ls-refs` request and its response
+    const LS_REFS_REQUEST: &[u8] = b"0014command=ls-refs\n0017object-format=sha1\n00010009peel\n000csymrefs\n0014ref-prefix HEAD\n001bref-prefix refs/heads/\n0000";
+    const LS_REFS_RESPONSE: &[u8] = b"0052ef8ca3fab2ebbd8ff5c0b2e6bd3ff53e1a5dbd13 HEAD symref-target:refs/heads/master\n003fef8ca3fab2ebbd8ff5c0b2e6bd3ff53e1a5dbd13 refs/heads/master\n0000";
+
+    #[test]
+    fn pkt_line_transcript_test() {
+        let mut writer = PktLineWriter::new(Vec::new());
+        writer.write_line("command=ls-refs").unwrap();
+        writer.write_line("object-format=sha1").unwrap();
+        writer.write_delimiter().unwrap();
+        for argument in [
+            "peel",
+            "symrefs",
+            "ref-prefix HEAD",
+            "ref-prefix refs/heads/",
+        ] {
+            writer.write_line(argument).unwrap();
+        }
+        writer.write_flush().unwrap();
+        assert_eq!(writer.into_inner(), LS_REFS_REQUEST);
+
+        let mut reader = PktLineReader::new(LS_REFS_REQUEST);
+        assert_eq!(reader.read_text().unwrap().unwrap(), "command=ls-refs");
+        assert_eq!(reader.read_section().unwrap(), ["object-format=sha1"]);
+        assert_eq!(reader.read_section().unwrap().len(), 4);
+        assert!(reader.into_inner().is_empty());
+
+        let mut reader = PktLineReader::new(LS_REFS_RESPONSE);
+        let refs = reader.read_section().unwrap();
+        assert_eq!(
+            refs,
+            [
+                "ef8ca3fab2ebbd8ff5c0b2e6bd3ff53e1a5dbd13 HEAD symref-target:refs/heads/master",
+                "ef8ca3fab2ebbd8ff5c0b2e6bd3ff53e1a5dbd13 refs/heads/master"
+            ]
+        );
+
+        // Special packets, binary data and a line without its line feed
+        let mut reader = PktLineReader::new(&b"000100020006\x01\xff0007abc"[..]);
+        assert_eq!(reader.read_packet().unwrap(), Packet::Delimiter);
+        assert_eq!(reader.read_packet().unwrap(), Packet::ResponseEnd);
+        assert_eq!(reader.read_packet().unwrap(), Packet::Data(vec![1, 0xff]));
+        assert_eq!(reader.read_text().unwrap().unwrap(), "abc");
+        assert!(reader.read_packet().is_err());
+
+        for invalid in [&b"0003"[..], b"fff1", b"+00a", b"00 8data", b"000adata"] {
+            assert!(PktLineReader::new(invalid).read_packet().is_err());
+        }
+        let mut writer = PktLineWriter::new(Vec::new());
+        assert!(writer.write_data(&[0; MAX_PKT_DATA_LENGTH]).is_ok());
+        assert!(writer.write_data(&[0; MAX_PKT_DATA_LENGTH + 1]).is_err());
+    }
+}
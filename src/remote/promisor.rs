@@ -0,0 +1,81 @@
+use crate::config::Config;
+use crate::object::{Hash, Object};
+use crate::remote::client::{FetchRequest, RemoteClient};
+use std::collections::HashSet;
+
+/// The filter used to fetch missing objects on demand: the objects asked for are always sent,
+/// so this only leaves out the blobs of the trees being fetched
+const LAZY_FETCH_FILTER: &str = "blob:none";
+
+/// Checks that an object filter is one of the forms we support: `blob:none`, `blob:limit=<n>`
+/// with an optional `k`, `m` or `g` unit, and `tree:<depth>`
+/// https://git-scm.com/docs/git-rev-list#Documentation/git-rev-list.txt---filterltfilter-specgt
+pub(crate) fn validate_filter(filter: &str) -> std::io::Result<()> {
+    let valid = match filter.split_once(':') {
+        Some(("blob", "none")) => true,
+        Some(("blob", limit)) => limit.strip_prefix("limit=").is_some_and(|limit| {
+            let digits = limit.trim_end_matches(['k', 'm', 'g', 'K', 'M', 'G']);
+            limit.len() - digits.len() <= 1 && digits.parse::<u64>().is_ok()
+        }),
+        Some(("tree", depth)) => depth.parse::<u64>().is_ok(),
+        _ => false,
+    };
+
+    if !valid {
+        return Err(std::io::Error::other(format!(
+            "invalid filter-spec '{filter}'"
+        )));
+    }
+
+    Ok(())
+}
+
+/// The remote a partial clone was made from, which promised to send the objects it left out:
+/// the one named by `extensions.partialClone`, or else the first with `remote.<name>.promisor`
+/// https://git-scm.com/docs/partial-clone
+pub(crate) fn promisor_remote() -> std::io::Result<Option<String>> {
+    let config = Config::load()?;
+    if let Some(remote) = config.get("extensions.partialClone") {
+        return Ok(Some(remote));
+    }
+
+    Ok(config.entries().into_iter().find_map(|(key, value)| {
+        let remote = key.strip_prefix("remote.")?.strip_suffix(".promisor")?;
+        (value == "true").then(|| remote.to_string())
+    }))
+}
+
+/// Fetches the specified objects that are missing from the promisor remote in a single request.
+/// Outside of partial clones there is no remote to ask and nothing is done.
+pub(crate) fn fetch_missing(hashes: &[Hash]) -> std::io::Result<()> {
+    let Some(remote) = promisor_remote()? else {
+        return Ok(());
+    };
+
+    let mut seen = HashSet::new();
+    let wants = hashes
+        .iter()
+        .filter(|hash| !Object::exists(hash) && seen.insert(*hash))
+        .cloned()
+        .collect::<Vec<_>>();
+    if wants.is_empty() {
+        return Ok(());
+    }
+
+    let url = Config::load()?
+        .get(&format!("remote.{remote}.url"))
+        .ok_or_else(|| std::io::Error::other(format!("promisor remote '{remote}' has no url")))?;
+    let client = RemoteClient::connect(&url)?;
+    // Only the capabilities of the remote are needed, as the objects are asked for by hash
+    let discover_refs_response = client.discover_refs(&[])?;
+    let request = FetchRequest {
+        wants,
+        filter: Some(String::from(LAZY_FETCH_FILTER)),
+        ..Default::default()
+    };
+
+    client
+        .fetch_pack(&discover_refs_response, &request)?
+        .pack
+        .mark_promisor()
+}
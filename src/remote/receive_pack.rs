@@ -0,0 +1,200 @@
+use crate::config::Config;
+use crate::object::packfile::PackFile;
+use crate::object::{Hash, Object};
+use crate::refs;
+use crate::remote::pkt_line::{PktLineReader, PktLineWriter};
+use crate::remote::sideband::SidebandWriter;
+use crate::remote::upload_pack::{parse_hash, AGENT};
+use std::io::{Read, Write};
+
+/// The capabilities advertised with the refs
+const CAPABILITIES: [&str; 9] = [
+    "report-status",
+    "report-status-v2",
+    "delete-refs",
+    "side-band-64k",
+    "quiet",
+    "atomic",
+    "ofs-delta",
+    "push-options",
+    "object-format=sha1",
+];
+
+/// A ref to update, `None` standing for a ref that does not exist, before or after
+struct Command {
+    name: String,
+    old: Option<Hash>,
+    new: Option<Hash>,
+}
+
+/// Writes the refs receive-pack lets clients update and its capabilities. Without any ref, the
+/// capabilities are advertised on a line of their own.
+/// https://git-scm.com/docs/pack-protocol#_reference_discovery
+pub(crate) fn advertise(output: impl Write) -> std::io::Result<()> {
+    let capabilities = format!("{} agent={AGENT}", CAPABILITIES.join(" "));
+    let mut refs = refs::list_refs("refs/")?
+        .into_iter()
+        .map(|(name, hash)| (hash.to_string(), name))
+        .collect::<Vec<_>>();
+    if refs.is_empty() {
+        refs.push(("0".repeat(40), String::from("capabilities^{}")));
+    }
+
+    let mut writer = PktLineWriter::new(output);
+    for (i, (hash, name)) in refs.iter().enumerate() {
+        match i {
+            0 => writer.write_data(format!("{hash} {name}\0{capabilities}\n").as_bytes())?,
+            _ => writer.write_line(&format!("{hash} {name}"))?,
+        }
+    }
+    writer.write_flush()
+}
+
+/// Answers a request to receive-pack: the update commands, the first one carrying the
+/// capabilities the client uses, the push options, then a pack unless every command is a
+/// deletion. The objects are unpacked, then the refs whose update is allowed are updated, all
+/// of them or none in an atomic push, and the outcome of every command is reported.
+/// https://git-scm.com/docs/pack-protocol#_reference_update_request_and_packfile_transfer
+pub(crate) fn receive_pack(request: impl Read, mut output: impl Write) -> std::io::Result<()> {
+    let mut reader = PktLineReader::new(request);
+    let mut commands = Vec::new();
+    let mut capabilities = Vec::new();
+    while let Some(line) = reader.read_text()? {
+        let (line, advertised) = line.split_once('\0').unwrap_or((&line, ""));
+        if commands.is_empty() {
+            capabilities = advertised.split(' ').map(str::to_string).collect();
+        }
+        let mut fields = line.splitn(3, ' ');
+        let (Some(old), Some(new), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(std::io::Error::other(format!(
+                "protocol error: expected old/new/ref, got '{line}'"
+            )));
+        };
+        commands.push(Command {
+            name: name.to_string(),
+            old: parse_optional_hash(old)?,
+            new: parse_optional_hash(new)?,
+        });
+    }
+    if commands.is_empty() {
+        return Ok(());
+    }
+    let has_capability = |name: &str| capabilities.iter().any(|capability| capability == name);
+
+    // Push options are only meant for hooks, which we do not run
+    if has_capability("push-options") {
+        while reader.read_text()?.is_some() {}
+    }
+    let unpacked = match commands.iter().any(|command| command.new.is_some()) {
+        true => PackFile::receive(reader.into_inner(), true).map(|_| ()),
+        false => Ok(()),
+    };
+
+    let mut statuses = match &unpacked {
+        Ok(()) => {
+            let config = Config::load()?;
+            let current_branch = match config.get("core.bare").as_deref() {
+                Some("true") => None,
+                _ => Some(refs::symbolic_target("HEAD")?),
+            };
+            commands
+                .iter()
+                .map(|command| check_command(command, current_branch.as_deref(), &config))
+                .collect::<std::io::Result<Vec<_>>>()?
+        }
+        Err(_) => vec![Err(String::from("unpacker error")); commands.len()],
+    };
+    if has_capability("atomic") && statuses.iter().any(Result::is_err) {
+        for status in statuses.iter_mut().filter(|status| status.is_ok()) {
+            *status = Err(String::from("atomic transaction failed"));
+        }
+    }
+
+    for (command, status) in commands.iter().zip(statuses.iter_mut()) {
+        if status.is_err() {
+            continue;
+        }
+        let updated = match &command.new {
+            Some(new) => {
+                refs::update_ref_with_log(&command.name, command.old.as_ref(), new, "push")
+            }
+            None => refs::delete_ref(&command.name, command.old.as_ref()),
+        };
+        if updated.is_err() {
+            *status = Err(String::from("failed to update ref"));
+        }
+    }
+
+    if !has_capability("report-status") && !has_capability("report-status-v2") {
+        return Ok(());
+    }
+    let mut report = PktLineWriter::new(Vec::new());
+    match &unpacked {
+        Ok(()) => report.write_line("unpack ok")?,
+        Err(e) => report.write_line(&format!("unpack {e}"))?,
+    }
+    for (command, status) in commands.iter().zip(&statuses) {
+        match status {
+            Ok(()) => report.write_line(&format!("ok {}", command.name))?,
+            Err(reason) => report.write_line(&format!("ng {} {reason}", command.name))?,
+        }
+    }
+    report.write_flush()?;
+
+    match has_capability("side-band-64k") {
+        true => {
+            let mut writer = SidebandWriter::new(output);
+            writer.write_all(&report.into_inner())?;
+            writer.finish().map(|_| ())
+        }
+        false => output.write_all(&report.into_inner()),
+    }
+}
+
+/// Whether a command may be carried out, or why not. Like git, the branch checked out in a
+/// repository with a working tree is neither updated nor deleted, unless
+/// `receive.denyCurrentBranch` or `receive.denyDeleteCurrent` say otherwise.
+/// https://git-scm.com/docs/git-config#Documentation/git-config.txt-receivedenyCurrentBranch
+fn check_command(
+    command: &Command,
+    current_branch: Option<&str>,
+    config: &Config,
+) -> std::io::Result<Result<(), String>> {
+    let denied = |key: &str| {
+        !matches!(
+            config.get(key).map(|value| value.to_lowercase()).as_deref(),
+            Some("ignore" | "warn" | "false" | "no" | "off" | "0")
+        )
+    };
+
+    let reason = if !command.name.starts_with("refs/") || !refs::check_ref_format(&command.name) {
+        "funny refname"
+    } else if command.new.as_ref().is_some_and(|new| !Object::exists(new)) {
+        "missing necessary objects"
+    } else if current_branch == Some(command.name.as_str())
+        && command.new.is_some()
+        && denied("receive.denyCurrentBranch")
+    {
+        "branch is currently checked out"
+    } else if current_branch == Some(command.name.as_str())
+        && command.new.is_none()
+        && denied("receive.denyDeleteCurrent")
+    {
+        "deletion of the current branch prohibited"
+    } else if refs::read_ref(&command.name)? != command.old {
+        "failed to update ref"
+    } else {
+        return Ok(Ok(()));
+    };
+
+    Ok(Err(String::from(reason)))
+}
+
+/// A hash of a command, where zeros stand for a ref that does not exist
+fn parse_optional_hash(hash: &str) -> std::io::Result<Option<Hash>> {
+    match hash.bytes().all(|byte| byte == b'0') {
+        true => Ok(None),
+        false => parse_hash(hash).map(Some),
+    }
+}
@@ -0,0 +1,82 @@
+use crate::refs;
+
+/// A mapping between remote and local refs such as `+refs/heads/*:refs/remotes/origin/*`, where
+/// a `*` in the source matches the rest of a ref name, substituted for the `*` in the
+/// destination. A leading `+` allows updates that are not fast-forwards.
+/// https://git-scm.com/docs/git-fetch#_configured_remote_tracking_branches
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Refspec {
+    pub(crate) force: bool,
+    source: String,
+    destination: Option<String>,
+}
+
+impl Refspec {
+    pub(crate) fn parse(refspec: &str) -> std::io::Result<Self> {
+        let invalid = || std::io::Error::other(format!("invalid refspec '{refspec}'"));
+
+        let (force, rest) = match refspec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, refspec),
+        };
+        let (source, destination) = match rest.split_once(':') {
+            Some((source, destination)) => (source, Some(destination)),
+            None => (rest, None),
+        };
+
+        let destination = destination.filter(|destination| !destination.is_empty());
+        let wildcards = |name: &str| name.matches('*').count();
+        let valid = match destination {
+            Some(destination) => {
+                wildcards(source) <= 1 && wildcards(source) == wildcards(destination)
+            }
+            None => wildcards(source) <= 1,
+        };
+        let well_formed = [Some(source), destination]
+            .into_iter()
+            .flatten()
+            .all(|name| refs::check_ref_format(&name.replace('*', "wildcard")));
+        if source.is_empty() || !valid || !well_formed {
+            return Err(invalid());
+        }
+
+        Ok(Refspec {
+            force,
+            source: source.to_string(),
+            destination: destination.map(str::to_string),
+        })
+    }
+
+    /// The local ref the specified remote ref is fetched into, if the refspec matches it
+    pub(crate) fn map(&self, name: &str) -> Option<String> {
+        let destination = self.destination.as_deref()?;
+        substitute(&self.source, destination, name)
+    }
+
+    /// The remote ref the specified local ref is fetched from, if the refspec produces it
+    pub(crate) fn reverse_map(&self, name: &str) -> Option<String> {
+        let destination = self.destination.as_deref()?;
+        substitute(destination, &self.source, name)
+    }
+
+    /// The prefix of the remote refs this refspec matches, its whole source when it has no `*`
+    pub(crate) fn source_prefix(&self) -> &str {
+        self.source.split('*').next().unwrap_or_default()
+    }
+
+    /// The prefix of the local refs this refspec writes, `None` when it writes a single ref
+    pub(crate) fn destination_prefix(&self) -> Option<&str> {
+        self.destination.as_deref()?.strip_suffix('*')
+    }
+}
+
+/// Matches a name against a pattern and substitutes what the `*` matched into the replacement
+fn substitute(pattern: &str, replacement: &str, name: &str) -> Option<String> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(replacement.replacen('*', matched, 1))
+        }
+        None => (pattern == name).then(|| replacement.to_string()),
+    }
+}
@@ -0,0 +1,197 @@
+use crate::remote::pkt_line::{Packet, PktLineReader, PktLineWriter, MAX_PKT_DATA_LENGTH};
+use std::io::{Read, Write};
+
+/// The band carrying the pack
+const BAND_DATA: u8 = 1;
+/// The band carrying the progress messages of the remote
+const BAND_PROGRESS: u8 = 2;
+/// The band carrying a fatal error of the remote
+const BAND_ERROR: u8 = 3;
+
+/// Reads the pack out of a response multiplexed in pkt-lines whose first byte tells the band.
+/// Progress messages are forwarded to `progress` prefixed with `remote: `, and an error message
+/// fails the read. The pack ends at a flush packet.
+/// https://git-scm.com/docs/protocol-capabilities#_side_band_side_band_64k
+pub(crate) struct SidebandReader<R, W> {
+    reader: PktLineReader<R>,
+    progress: W,
+    /// The band 1 data of the last packet not read yet
+    data: Vec<u8>,
+    position: usize,
+    /// The end of a progress message whose line is not over yet
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read, W: Write> SidebandReader<R, W> {
+    pub(crate) fn new(reader: PktLineReader<R>, progress: W) -> Self {
+        SidebandReader {
+            reader,
+            progress,
+            data: Vec::new(),
+            position: 0,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Forwards the complete lines of progress, which end with a line feed or with a carriage
+    /// return for a meter redrawn in place
+    fn forward_progress(&mut self, message: &[u8]) -> std::io::Result<()> {
+        self.pending.extend_from_slice(message);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n' || b == b'\r') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            self.progress.write_all(b"remote: ")?;
+            self.progress.write_all(&line)?;
+        }
+
+        self.progress.flush()
+    }
+}
+
+impl<R: Read, W: Write> Read for SidebandReader<R, W> {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.data.len() && !self.done {
+            let packet = match self.reader.read_packet()? {
+                Packet::Data(packet) => packet,
+                Packet::Flush | Packet::ResponseEnd => {
+                    if !self.pending.is_empty() {
+                        self.forward_progress(b"\n")?;
+                    }
+                    self.done = true;
+                    break;
+                }
+                Packet::Delimiter => {
+                    return Err(std::io::Error::other(
+                        "protocol error: unexpected delimiter in the pack",
+                    ))
+                }
+            };
+
+            match packet.split_first() {
+                Some((&BAND_DATA, data)) => {
+                    self.data = data.to_vec();
+                    self.position = 0;
+                }
+                Some((&BAND_PROGRESS, message)) => self.forward_progress(message)?,
+                Some((&BAND_ERROR, message)) => {
+                    return Err(std::io::Error::other(format!(
+                        "remote error: {}",
+                        String::from_utf8_lossy(message).trim_end()
+                    )))
+                }
+                _ => {
+                    return Err(std::io::Error::other(format!(
+                        "protocol error: bad band #{}",
+                        packet.first().copied().unwrap_or_default()
+                    )))
+                }
+            }
+        }
+
+        let count = buffer.len().min(self.data.len() - self.position);
+        buffer[..count].copy_from_slice(&self.data[self.position..self.position + count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+/// Multiplexes data in band 1 pkt-lines, the way a server sends a pack to a client that asked
+/// for side-band-64k. Data is gathered into packets as large as they can be, the last one being
+/// written on flush.
+pub(crate) struct SidebandWriter<W> {
+    writer: PktLineWriter<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SidebandWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        SidebandWriter {
+            writer: PktLineWriter::new(inner),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Writes what is left of the data, and the flush packet ending it
+    pub(crate) fn finish(mut self) -> std::io::Result<W> {
+        self.flush()?;
+        self.writer.write_flush()?;
+
+        Ok(self.writer.into_inner())
+    }
+
+    fn write_packet(&mut self, length: usize) -> std::io::Result<()> {
+        let mut packet = Vec::with_capacity(length + 1);
+        packet.push(BAND_DATA);
+        packet.extend(self.buffer.drain(..length));
+        self.writer.write_data(&packet)
+    }
+}
+
+impl<W: Write> Write for SidebandWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        while self.buffer.len() >= MAX_PKT_DATA_LENGTH - 1 {
+            self.write_packet(MAX_PKT_DATA_LENGTH - 1)?;
+        }
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.write_packet(self.buffer.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SidebandReader, SidebandWriter};
+    use crate::remote::pkt_line::{PktLineReader, MAX_PKT_DATA_LENGTH};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn sideband_test() {
+        // A pack split over two packets, with progress in between and after
+        let response = b"0009\x01PACK0022\x02Counting objects:  50% (1/2)\r0022\x02Counting objects: 100% (2/2)\r0008\x01abc000b\x02done.\n0000";
+        let mut progress = Vec::new();
+        let mut pack = Vec::new();
+        SidebandReader::new(PktLineReader::new(&response[..]), &mut progress)
+            .read_to_end(&mut pack)
+            .unwrap();
+        assert_eq!(pack, b"PACKabc");
+        assert_eq!(
+            String::from_utf8(progress).unwrap(),
+            "remote: Counting objects:  50% (1/2)\rremote: Counting objects: 100% (2/2)\rremote: done.\n"
+        );
+
+        let response = b"0009\x01PACK0013\x03access denied\n0000";
+        let mut reader = SidebandReader::new(PktLineReader::new(&response[..]), Vec::new());
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "remote error: access denied");
+    }
+    #[test]
+    fn sideband_writer_test() {
+        // Split into packets as large as they can be, and read back whole
+        let data = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut writer = SidebandWriter::new(Vec::new());
+        writer.write_all(&data[..10]).unwrap();
+        writer.write_all(&data[10..]).unwrap();
+        let response = writer.finish().unwrap();
+        assert_eq!(
+            &response[..5],
+            format!("{:04x}\x01", MAX_PKT_DATA_LENGTH + 4).as_bytes()
+        );
+        assert!(response.ends_with(b"0000"));
+
+        let mut read = Vec::new();
+        SidebandReader::new(PktLineReader::new(&response[..]), Vec::new())
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, data);
+    }
+}
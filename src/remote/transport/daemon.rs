@@ -0,0 +1,136 @@
+use crate::remote::client::{git_protocol_header, ProtocolVersion};
+use crate::remote::transport::{skip_advertisement, split_path, split_port, Service, Transport};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+/// The port git daemon listens on by default
+const DEFAULT_PORT: u16 = 9418;
+
+/// Reaches a remote served by git daemon over TCP, where a connection starts with a request
+/// line naming the service and the repository, followed by the pkt-line protocol. A connection
+/// is opened for each request, as over ssh.
+/// https://git-scm.com/docs/pack-protocol#_git_transport
+pub(crate) struct DaemonTransport {
+    url: String,
+    /// The host as given in the URL, port included, which the request line names
+    authority: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl DaemonTransport {
+    /// Parses a `git://host[:port]/path` URL
+    pub(crate) fn new(url: &str) -> std::io::Result<Self> {
+        let invalid = || std::io::Error::other(format!("invalid url '{url}'"));
+        let (authority, path) = url
+            .strip_prefix("git://")
+            .and_then(split_path)
+            .ok_or_else(invalid)?;
+        let (host, port) = split_port(authority);
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None => DEFAULT_PORT,
+        };
+        if host.is_empty() || host.contains('@') || path.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(DaemonTransport {
+            url: url.to_string(),
+            authority: authority.to_string(),
+            host,
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Connects to the daemon and sends the request line, then the specified input from another
+    /// thread, so that neither side waits on the other. A version of the protocol other than the
+    /// original is asked for in the extra parameters, after a second NUL byte.
+    fn connect(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+        input: Vec<u8>,
+    ) -> std::io::Result<TcpStream> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).map_err(|e| {
+            std::io::Error::other(format!("unable to connect to {}: {e}", self.host))
+        })?;
+
+        let mut request = format!(
+            "{} {}\0host={}\0",
+            service.name(),
+            self.path,
+            self.authority
+        );
+        if let Some(parameter) = git_protocol_header(version) {
+            request.push_str(&format!("\0{parameter}\0"));
+        }
+        let mut writer = stream.try_clone()?;
+        writer.write_all(format!("{:04x}{request}", request.len() + 4).as_bytes())?;
+
+        thread::spawn(move || {
+            // Ending our side lets the service end once it has answered
+            writer.write_all(&input)?;
+            writer.shutdown(Shutdown::Write)
+        });
+
+        Ok(stream)
+    }
+}
+
+impl Transport for DaemonTransport {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        // A flush right after the advertisement tells the service there is no request to follow
+        Ok(Box::new(self.connect(
+            service,
+            version,
+            b"0000".to_vec(),
+        )?))
+    }
+
+    fn request(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        let response = self.connect(service, version, body)?;
+
+        Ok(Box::new(skip_advertisement(response)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DaemonTransport;
+
+    #[test]
+    fn parse_git_url_test() {
+        let transport = DaemonTransport::new("git://example.com/~user/repo.git").unwrap();
+        assert_eq!(transport.authority, "example.com");
+        assert_eq!(
+            (transport.host.as_str(), transport.port),
+            ("example.com", 9418)
+        );
+        assert_eq!(transport.path, "~user/repo.git");
+
+        let transport = DaemonTransport::new("git://[::1]:1234/repo").unwrap();
+        assert_eq!(transport.authority, "[::1]:1234");
+        assert_eq!((transport.host.as_str(), transport.port), ("::1", 1234));
+        assert_eq!(transport.path, "/repo");
+
+        assert!(DaemonTransport::new("git://example.com").is_err());
+        assert!(DaemonTransport::new("git://example.com:port/repo").is_err());
+    }
+}
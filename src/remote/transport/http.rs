@@ -0,0 +1,85 @@
+use crate::remote::client::{git_protocol_header, ProtocolVersion};
+use crate::remote::transport::{Service, Transport};
+use reqwest::Url;
+use std::io::Read;
+
+/// Reaches a remote over git's smart HTTP protocol, a GET request fetching the advertisement of
+/// a service and POST requests carrying the requests to it
+/// https://git-scm.com/docs/http-protocol
+pub(crate) struct HttpTransport {
+    url: Url,
+    reqwest_client: reqwest::blocking::Client,
+}
+
+impl HttpTransport {
+    pub(crate) fn new(url: Url) -> Self {
+        HttpTransport {
+            url,
+            reqwest_client: reqwest::blocking::Client::new(),
+        }
+    }
+
+    fn unable_to_access(&self, error: reqwest::Error) -> std::io::Error {
+        std::io::Error::other(format!("unable to access '{}': {error}", self.url))
+    }
+}
+
+impl Transport for HttpTransport {
+    fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        let url = format!("{}/info/refs?service={}", self.url, service.name());
+        let mut get = self.reqwest_client.get(url);
+        if let Some(header) = git_protocol_header(version) {
+            get = get.header("Git-Protocol", header);
+        }
+        let response = get.send().map_err(|e| self.unable_to_access(e))?;
+
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "repository '{}' not found",
+                self.url
+            )));
+        }
+
+        Ok(Box::new(response))
+    }
+
+    fn request(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        let mut post = self
+            .reqwest_client
+            .post(format!("{}/{}", self.url, service.name()))
+            .header(
+                "Content-Type",
+                format!("application/x-{}-request", service.name()),
+            );
+        if let Some(header) = git_protocol_header(version) {
+            post = post.header("Git-Protocol", header);
+        }
+        let response = post
+            .body(body)
+            .send()
+            .map_err(|e| self.unable_to_access(e))?;
+
+        if !response.status().is_success() {
+            return Err(std::io::Error::other(format!(
+                "unable to access '{}': the requested URL returned error: {}",
+                self.url,
+                response.status().as_u16()
+            )));
+        }
+
+        Ok(Box::new(response))
+    }
+}
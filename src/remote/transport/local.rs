@@ -0,0 +1,147 @@
+use crate::remote::client::{git_protocol_header, ProtocolVersion};
+use crate::remote::transport::process::{ServiceOutput, LOCAL_REPOSITORY_VARIABLES};
+use crate::remote::transport::{Service, Transport};
+use crate::remote::{receive_pack, upload_pack};
+use std::ffi::OsString;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Reaches a repository on the local filesystem, once per request as over smart HTTP: our own
+/// repositories are served by our upload-pack and receive-pack, and git repositories by git's,
+/// run in their stateless mode
+/// https://git-scm.com/docs/git-upload-pack
+pub(crate) struct LocalTransport {
+    url: String,
+    git_dir: PathBuf,
+    /// Whether the repository is one of ours, served by our own services
+    hamachi: bool,
+}
+
+impl LocalTransport {
+    /// Opens the repository at the specified path, designated by `url`. Paths are made absolute,
+    /// as they must keep pointing to the repository from other directories.
+    pub(crate) fn new(url: &str, path: &Path) -> std::io::Result<Self> {
+        let not_a_repository = || {
+            std::io::Error::other(format!(
+                "'{}' does not appear to be a git repository",
+                path.display()
+            ))
+        };
+        let path = path.canonicalize().map_err(|_| not_a_repository())?;
+        let git_dir = find_git_dir(&path).ok_or_else(not_a_repository)?;
+        let url = match url.contains("://") {
+            true => url.to_string(),
+            false => path.to_string_lossy().to_string(),
+        };
+
+        Ok(LocalTransport {
+            url,
+            hamachi: git_dir.ends_with(".hamachi"),
+            git_dir,
+        })
+    }
+
+    fn command(&self, service: Service, version: ProtocolVersion) -> Command {
+        let mut command = Command::new("git");
+        let subcommand = service.name().trim_start_matches("git-");
+        command.args([subcommand, "--stateless-rpc"]);
+        for variable in LOCAL_REPOSITORY_VARIABLES {
+            command.env_remove(variable);
+        }
+        if let Some(header) = git_protocol_header(version) {
+            command.env("GIT_PROTOCOL", header);
+        }
+
+        command
+    }
+
+    /// Runs one of our services in the working tree of the repository, where it finds the
+    /// repository as hamachi always does. The response is produced whole before coming back to
+    /// the current directory, which our side of the exchange works in.
+    fn serve(
+        &self,
+        service: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+    ) -> std::io::Result<Box<dyn Read>> {
+        let current_dir = std::env::current_dir()?;
+        let work_tree = self.git_dir.parent().unwrap_or(&self.git_dir);
+        std::env::set_current_dir(work_tree)?;
+        let mut response = Vec::new();
+        let served = service(&mut response);
+        std::env::set_current_dir(current_dir)?;
+        served?;
+
+        Ok(Box::new(Cursor::new(response)))
+    }
+}
+
+impl Transport for LocalTransport {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        if self.hamachi {
+            return self.serve(|response| match service {
+                Service::UploadPack => upload_pack::advertise(version, response),
+                Service::ReceivePack => receive_pack::advertise(response),
+            });
+        }
+
+        let mut command = self.command(service, version);
+        command.arg("--advertise-refs").arg(&self.git_dir);
+
+        Ok(Box::new(ServiceOutput::spawn(&mut command, None, false)?))
+    }
+
+    fn request(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        if self.hamachi {
+            return self.serve(|response| match service {
+                Service::UploadPack => upload_pack::upload_pack(body.as_slice(), version, response),
+                Service::ReceivePack => receive_pack::receive_pack(body.as_slice(), response),
+            });
+        }
+
+        let mut command = self.command(service, version);
+        command.arg(&self.git_dir);
+
+        Ok(Box::new(ServiceOutput::spawn(
+            &mut command,
+            Some(body),
+            false,
+        )?))
+    }
+
+    fn local_repository(&self) -> Option<&Path> {
+        Some(&self.git_dir)
+    }
+}
+
+/// The git directory of the repository at a path: the `.hamachi` or `.git` directory of a
+/// working tree, or else the path itself or the path with a `.git` suffix, for bare repositories
+fn find_git_dir(path: &Path) -> Option<PathBuf> {
+    let mut with_suffix = OsString::from(path);
+    with_suffix.push(".git");
+
+    [
+        path.join(".hamachi"),
+        path.join(".git"),
+        path.to_path_buf(),
+        PathBuf::from(with_suffix),
+    ]
+    .into_iter()
+    .find(|candidate| {
+        candidate.join("HEAD").is_file()
+            && candidate.join("objects").is_dir()
+            && candidate.join("refs").is_dir()
+    })
+}
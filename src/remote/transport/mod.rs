@@ -0,0 +1,166 @@
+mod daemon;
+mod http;
+mod local;
+mod process;
+mod ssh;
+
+use crate::remote::client::ProtocolVersion;
+use crate::remote::pkt_line::{Packet, PktLineReader};
+use daemon::DaemonTransport;
+use http::HttpTransport;
+use local::LocalTransport;
+use reqwest::Url;
+use ssh::SshTransport;
+use std::io::Read;
+use std::path::Path;
+
+/// A service of a remote repository
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Service {
+    UploadPack,
+    ReceivePack,
+}
+
+impl Service {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "git-upload-pack" => Some(Service::UploadPack),
+            "git-receive-pack" => Some(Service::ReceivePack),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Service::UploadPack => "git-upload-pack",
+            Service::ReceivePack => "git-receive-pack",
+        }
+    }
+}
+
+/// The way the services of a remote repository are reached. Requests are stateless, as over
+/// HTTP: a request carries everything the service needs to answer it, and the response ends
+/// with the answer.
+pub(crate) trait Transport {
+    /// The URL of the remote, local paths being made absolute
+    fn url(&self) -> &str;
+
+    /// Asks a service for its advertisement of refs and capabilities, in the specified version
+    /// of the protocol
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>>;
+
+    /// Sends a request to a service and returns its response
+    fn request(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>>;
+
+    /// The git directory of a remote on the local filesystem, whose files can be read directly
+    fn local_repository(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Opens a transport to the remote at the specified URL: `http://` and `https://` URLs are
+/// reached over smart HTTP, `ssh://` and scp-like `host:path` URLs over ssh, `git://` URLs
+/// through git daemon, while `file://` URLs and paths are served by upload-pack and
+/// receive-pack run locally
+/// https://git-scm.com/docs/git-clone#_git_urls
+pub(crate) fn connect(url: &str) -> std::io::Result<Box<dyn Transport>> {
+    if !url.contains("://") {
+        return match ssh::parse_scp_like(url) {
+            Some(_) => Ok(Box::new(SshTransport::new(url)?)),
+            None => Ok(Box::new(LocalTransport::new(url, Path::new(url))?)),
+        };
+    }
+
+    let parsed =
+        Url::parse(url).map_err(|e| std::io::Error::other(format!("invalid url '{url}': {e}")))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(Box::new(HttpTransport::new(parsed))),
+        "ssh" | "git+ssh" | "ssh+git" => Ok(Box::new(SshTransport::new(url)?)),
+        "git" => Ok(Box::new(DaemonTransport::new(url)?)),
+        "file" => {
+            let path = parsed
+                .to_file_path()
+                .map_err(|_| std::io::Error::other(format!("invalid url '{url}'")))?;
+            Ok(Box::new(LocalTransport::new(url, &path)?))
+        }
+        scheme => Err(std::io::Error::other(format!(
+            "Unable to find remote helper for '{scheme}'"
+        ))),
+    }
+}
+
+/// Whether a remote is designated by a URL rather than by a path, scp-like URLs included
+pub(crate) fn is_url(url: &str) -> bool {
+    url.contains("://") || ssh::parse_scp_like(url).is_some()
+}
+
+/// Splits what follows the scheme of a URL into the authority and the path, where a path
+/// starting with `/~` is relative to a home directory and loses its slash
+fn split_path(url: &str) -> Option<(&str, &str)> {
+    let slash = url.find('/')?;
+    let path = &url[slash..];
+    let path = path
+        .strip_prefix('/')
+        .filter(|path| path.starts_with('~'))
+        .unwrap_or(path);
+
+    Some((&url[..slash], path))
+}
+
+/// Splits the port from a `[user@]host[:port]` authority. Brackets let a host have colons,
+/// around an IPv6 address, or around the whole authority in scp-like URLs.
+fn split_port(authority: &str) -> (String, Option<&str>) {
+    let authority = authority
+        .strip_prefix('[')
+        .and_then(|authority| authority.strip_suffix(']'))
+        .unwrap_or(authority);
+    let (user, host) = match authority.rfind('@') {
+        Some(at) => authority.split_at(at + 1),
+        None => ("", authority),
+    };
+    let (host, port) = match host.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, rest)) => (host, rest.strip_prefix(':')),
+            None => (bracketed, None),
+        },
+        // An IPv6 address without brackets has several colons, and no port
+        None => match host.split_once(':') {
+            Some((name, port)) if !port.contains(':') => (name, Some(port)),
+            _ => (host, None),
+        },
+    };
+
+    (
+        format!("{user}{host}"),
+        port.filter(|port| !port.is_empty()),
+    )
+}
+
+/// The response of a service over a connection of its own, past the advertisement that starts
+/// it, which an earlier connection already read
+fn skip_advertisement<R: Read>(response: R) -> std::io::Result<R> {
+    let mut response = PktLineReader::new(response);
+    loop {
+        match response.read_packet()? {
+            Packet::Flush => return Ok(response.into_inner()),
+            Packet::Data(line) => {
+                if let Some(message) = line.strip_prefix(b"ERR ") {
+                    return Err(std::io::Error::other(format!(
+                        "remote error: {}",
+                        String::from_utf8_lossy(message).trim_end()
+                    )));
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+/// The variables that point git at a repository, which must not leak from ours into the one of
+/// the remote
+pub(super) const LOCAL_REPOSITORY_VARIABLES: [&str; 5] = [
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "GIT_INDEX_FILE",
+    "GIT_OBJECT_DIRECTORY",
+    "GIT_ALTERNATE_OBJECT_DIRECTORIES",
+];
+
+/// The output of a service run as a child process, fed its request from another thread so that
+/// neither side waits on the other. The output ends with an error when the process failed.
+pub(super) struct ServiceOutput {
+    child: Child,
+    stdout: Option<ChildStdout>,
+    input: Option<JoinHandle<std::io::Result<()>>>,
+    /// What the process writes to stderr, when it is kept to explain a failure
+    errors: Option<JoinHandle<String>>,
+}
+
+impl ServiceOutput {
+    /// Runs a command with the specified input. With `capture_errors`, what it writes to stderr
+    /// is only shown as the error it fails with, as a service whose connection we close once we
+    /// have read what we wanted complains that we hung up.
+    pub(super) fn spawn(
+        command: &mut Command,
+        input: Option<Vec<u8>>,
+        capture_errors: bool,
+    ) -> std::io::Result<Self> {
+        let stdin = match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        };
+        let stderr = match capture_errors {
+            true => Stdio::piped(),
+            false => Stdio::inherit(),
+        };
+        let program = command.get_program().to_string_lossy().to_string();
+        let mut child = command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| std::io::Error::other(format!("cannot run {program}: {e}")))?;
+
+        let stdout = child.stdout.take();
+        let input = match (child.stdin.take(), input) {
+            (Some(mut stdin), Some(input)) => Some(thread::spawn(move || stdin.write_all(&input))),
+            _ => None,
+        };
+        let errors = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut errors = String::new();
+                let _ = stderr.read_to_string(&mut errors);
+                errors
+            })
+        });
+
+        Ok(ServiceOutput {
+            child,
+            stdout,
+            input,
+            errors,
+        })
+    }
+
+    /// The error a failed process ends its output with: the last thing it said, if it kept its
+    /// stderr for us
+    fn failure(&mut self) -> std::io::Error {
+        let errors = self
+            .errors
+            .take()
+            .and_then(|errors| errors.join().ok())
+            .unwrap_or_default();
+        let message = errors
+            .lines()
+            .rfind(|line| !line.trim().is_empty())
+            .map(|line| line.trim_start_matches("fatal: ").trim().to_string())
+            .unwrap_or_else(|| String::from("the remote end hung up unexpectedly"));
+
+        std::io::Error::other(message)
+    }
+}
+
+impl Read for ServiceOutput {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let Some(stdout) = self.stdout.as_mut() else {
+            return Ok(0);
+        };
+        let count = stdout.read(buffer)?;
+        if count == 0 && !buffer.is_empty() {
+            self.stdout = None;
+            if !self.child.wait()?.success() {
+                return Err(self.failure());
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+impl Drop for ServiceOutput {
+    fn drop(&mut self) {
+        // Closing the output first lets a service that still has something to say end
+        self.stdout = None;
+        if let Some(input) = self.input.take() {
+            let _ = input.join();
+        }
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,221 @@
+use crate::config::Config;
+use crate::remote::client::{git_protocol_header, ProtocolVersion};
+use crate::remote::transport::process::{ServiceOutput, LOCAL_REPOSITORY_VARIABLES};
+use crate::remote::transport::{skip_advertisement, split_path, split_port, Service, Transport};
+use std::io::Read;
+use std::process::Command;
+
+/// Reaches a remote over ssh, by running git's upload-pack and receive-pack on the remote host
+/// and speaking the pkt-line protocol over the standard input and output of the ssh command.
+/// A connection is opened for each request, as the services answer in the same way a stateless
+/// request they read up to its end.
+/// https://git-scm.com/docs/pack-protocol#_ssh_transport
+pub(crate) struct SshTransport {
+    url: String,
+    user_and_host: String,
+    port: Option<String>,
+    path: String,
+}
+
+impl SshTransport {
+    /// Parses an `ssh://[user@]host[:port]/path` URL, also spelled `git+ssh://` or `ssh+git://`,
+    /// or the scp-like `[user@]host:path`, where a path starting with `~` is relative to a home
+    /// directory
+    pub(crate) fn new(url: &str) -> std::io::Result<Self> {
+        let invalid = || std::io::Error::other(format!("invalid url '{url}'"));
+        let (authority, path) = match ["ssh://", "git+ssh://", "ssh+git://"]
+            .iter()
+            .find_map(|scheme| url.strip_prefix(scheme))
+        {
+            Some(rest) => split_path(rest).ok_or_else(invalid)?,
+            None => parse_scp_like(url).ok_or_else(invalid)?,
+        };
+
+        let (user_and_host, port) = split_port(authority);
+        if user_and_host.is_empty() || user_and_host.ends_with('@') || path.is_empty() {
+            return Err(invalid());
+        }
+        if user_and_host.starts_with('-') || path.starts_with('-') {
+            return Err(std::io::Error::other(format!(
+                "strange hostname or pathname '{url}' blocked"
+            )));
+        }
+
+        Ok(SshTransport {
+            url: url.to_string(),
+            user_and_host: user_and_host.to_string(),
+            port: port.map(str::to_string),
+            path: path.to_string(),
+        })
+    }
+
+    /// The ssh command, whose last argument is the command running the service on the remote
+    /// host. `$HAMACHI_SSH` and `core.sshCommand` are run by the shell, with the arguments
+    /// appended.
+    /// https://git-scm.com/docs/git-config#Documentation/git-config.txt-coresshCommand
+    fn command(&self, service: Service, version: ProtocolVersion) -> std::io::Result<Command> {
+        let ssh_command = match std::env::var("HAMACHI_SSH") {
+            Ok(ssh_command) if !ssh_command.is_empty() => Some(ssh_command),
+            _ => Config::load()?.get("core.sshCommand"),
+        };
+        let mut command = match ssh_command {
+            Some(ssh_command) => {
+                let mut command = Command::new("sh");
+                command
+                    .arg("-c")
+                    .arg(format!("{ssh_command} \"$@\""))
+                    .arg(ssh_command);
+                command
+            }
+            None => Command::new("ssh"),
+        };
+
+        for variable in LOCAL_REPOSITORY_VARIABLES {
+            command.env_remove(variable);
+        }
+        if let Some(header) = git_protocol_header(version) {
+            command.args(["-o", "SendEnv=GIT_PROTOCOL"]);
+            command.env("GIT_PROTOCOL", header);
+        }
+        if let Some(port) = &self.port {
+            command.args(["-p", port]);
+        }
+        command.arg(&self.user_and_host).arg(format!(
+            "{} {}",
+            service.name(),
+            shell_quote(&self.path)
+        ));
+
+        Ok(command)
+    }
+}
+
+impl Transport for SshTransport {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn advertise(
+        &self,
+        service: Service,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        // The services always start with their advertisement, and a flush right away tells them
+        // there is no request to follow, so that they end cleanly
+        let mut command = self.command(service, version)?;
+
+        Ok(Box::new(ServiceOutput::spawn(
+            &mut command,
+            Some(b"0000".to_vec()),
+            true,
+        )?))
+    }
+
+    fn request(
+        &self,
+        service: Service,
+        body: Vec<u8>,
+        version: ProtocolVersion,
+    ) -> std::io::Result<Box<dyn Read>> {
+        let mut command = self.command(service, version)?;
+        let response = ServiceOutput::spawn(&mut command, Some(body), true)?;
+
+        Ok(Box::new(skip_advertisement(response)?))
+    }
+}
+
+/// Splits the scp-like `[user@]host:path` syntax, which is only recognized when a colon comes
+/// before any slash, so that paths with colons can still be given as `./path`. A host with
+/// colons, such as an IPv6 address or one with a port, is written within brackets.
+/// https://git-scm.com/docs/git-clone#_git_urls
+pub(crate) fn parse_scp_like(url: &str) -> Option<(&str, &str)> {
+    let host_end = match url.find('[') {
+        Some(start) if !url[..start].contains(['/', ':']) => start + url[start..].find(']')? + 1,
+        _ => 0,
+    };
+    let colon = host_end + url[host_end..].find(':')?;
+    if colon == 0 || url[..colon].contains('/') {
+        return None;
+    }
+
+    Some((&url[..colon], &url[colon + 1..]))
+}
+
+/// Quotes an argument for the remote shell, within single quotes where only single quotes and
+/// exclamation marks need escaping
+fn shell_quote(argument: &str) -> String {
+    let mut quoted = String::from("'");
+    for character in argument.chars() {
+        match character {
+            '\'' | '!' => {
+                quoted.push('\'');
+                quoted.push('\\');
+                quoted.push(character);
+                quoted.push('\'');
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('\'');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_scp_like, shell_quote, SshTransport};
+
+    fn parse(url: &str) -> (String, Option<String>, String) {
+        let transport = SshTransport::new(url).unwrap();
+        (transport.user_and_host, transport.port, transport.path)
+    }
+
+    #[test]
+    fn parse_ssh_url_test() {
+        let expected = |host: &str, port: Option<&str>, path: &str| {
+            (host.to_string(), port.map(str::to_string), path.to_string())
+        };
+        assert_eq!(
+            parse("ssh://git@example.com/org/repo.git"),
+            expected("git@example.com", None, "/org/repo.git")
+        );
+        assert_eq!(
+            parse("git+ssh://example.com:2222/repo"),
+            expected("example.com", Some("2222"), "/repo")
+        );
+        assert_eq!(
+            parse("ssh+git://user@[::1]:22/~user/repo"),
+            expected("user@::1", Some("22"), "~user/repo")
+        );
+        assert_eq!(
+            parse("git@example.com:org/repo.git"),
+            expected("git@example.com", None, "org/repo.git")
+        );
+        assert_eq!(
+            parse("[example.com:2222]:~/repo"),
+            expected("example.com", Some("2222"), "~/repo")
+        );
+        assert_eq!(
+            parse("[user@::1]:/srv/repo"),
+            expected("user@::1", None, "/srv/repo")
+        );
+
+        assert!(SshTransport::new("ssh://example.com").is_err());
+        assert!(SshTransport::new("ssh://-oProxyCommand=evil/repo").is_err());
+    }
+
+    #[test]
+    fn parse_scp_like_test() {
+        assert_eq!(parse_scp_like("host:repo"), Some(("host", "repo")));
+        assert_eq!(parse_scp_like("user@host:a:b"), Some(("user@host", "a:b")));
+        assert_eq!(parse_scp_like("./host:repo"), None);
+        assert_eq!(parse_scp_like("dir/file"), None);
+        assert_eq!(parse_scp_like(":repo"), None);
+    }
+
+    #[test]
+    fn shell_quote_test() {
+        assert_eq!(shell_quote("/srv/repo.git"), "'/srv/repo.git'");
+        assert_eq!(shell_quote("it's!"), "'it'\\''s'\\!''");
+    }
+}
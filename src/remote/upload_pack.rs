@@ -0,0 +1,389 @@
+use crate::graph::objects::{list_objects, peel};
+use crate::object::packfile::writer::{write_pack, DeltaSearch};
+use crate::object::{Hash, Object};
+use crate::refs::{self, Head};
+use crate::remote::client::ProtocolVersion;
+use crate::remote::pkt_line::{Packet, PktLineReader, PktLineWriter};
+use crate::remote::sideband::SidebandWriter;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// How we introduce ourselves to clients
+pub(crate) const AGENT: &str = concat!("hamachi/", env!("CARGO_PKG_VERSION"));
+
+/// The capabilities advertised with the refs in the original protocol. Shallow fetches are not
+/// supported, and packs are never thin.
+const CAPABILITIES: [&str; 7] = [
+    "multi_ack_detailed",
+    "side-band-64k",
+    "thin-pack",
+    "ofs-delta",
+    "no-progress",
+    "include-tag",
+    "object-format=sha1",
+];
+
+/// A ref as upload-pack advertises it
+struct AdvertisedRef {
+    name: String,
+    hash: Hash,
+    /// The ref a symbolic ref such as HEAD points to
+    symref_target: Option<String>,
+    /// The object an annotated tag ultimately points to
+    peeled: Option<Hash>,
+}
+
+/// Writes what upload-pack starts with: the refs and capabilities in the original protocol,
+/// after a `version 1` line in version 1, and only the capabilities in version 2, whose refs
+/// are listed by the `ls-refs` command
+/// https://git-scm.com/docs/pack-protocol#_reference_discovery
+pub(crate) fn advertise(version: ProtocolVersion, output: impl Write) -> std::io::Result<()> {
+    let mut writer = PktLineWriter::new(output);
+    match version {
+        ProtocolVersion::V2 => {
+            writer.write_line("version 2")?;
+            writer.write_line(&format!("agent={AGENT}"))?;
+            for capability in ["ls-refs", "fetch", "server-option", "object-format=sha1"] {
+                writer.write_line(capability)?;
+            }
+            return writer.write_flush();
+        }
+        ProtocolVersion::V1 => writer.write_line("version 1")?,
+        ProtocolVersion::V0 => {}
+    }
+
+    let advertised = advertised_refs()?;
+    let mut capabilities = CAPABILITIES.join(" ");
+    if let Some(target) = advertised
+        .first()
+        .and_then(|head| head.symref_target.as_ref())
+    {
+        capabilities.push_str(&format!(" symref=HEAD:{target}"));
+    }
+    capabilities.push_str(&format!(" agent={AGENT}"));
+
+    // An empty repository advertises nothing at all
+    for (i, advertised) in advertised.iter().enumerate() {
+        match i {
+            0 => writer.write_data(
+                format!("{} {}\0{capabilities}\n", advertised.hash, advertised.name).as_bytes(),
+            )?,
+            _ => writer.write_line(&format!("{} {}", advertised.hash, advertised.name))?,
+        }
+        if let Some(peeled) = &advertised.peeled {
+            writer.write_line(&format!("{peeled} {}^{{}}", advertised.name))?;
+        }
+    }
+    writer.write_flush()
+}
+
+/// Answers a request to upload-pack, which like over HTTP carries a whole round of the
+/// negotiation: a version 2 command, or in the original protocol the wanted commits and the
+/// ones the client has, followed by a pack once the client is done
+pub(crate) fn upload_pack(
+    request: impl Read,
+    version: ProtocolVersion,
+    output: impl Write,
+) -> std::io::Result<()> {
+    let mut reader = PktLineReader::new(request);
+    match version {
+        ProtocolVersion::V2 => serve_command(&mut reader, output),
+        ProtocolVersion::V0 | ProtocolVersion::V1 => serve_negotiation(&mut reader, output),
+    }
+}
+
+/// HEAD when it points to a commit, then every ref, sorted by name
+fn advertised_refs() -> std::io::Result<Vec<AdvertisedRef>> {
+    let mut advertised = Vec::new();
+    if let Some(hash) = refs::read_ref("HEAD")? {
+        let symref_target = match refs::read_head()? {
+            Head::Branch(branch) => Some(branch),
+            Head::Detached(_) => None,
+        };
+        advertised.push(AdvertisedRef {
+            name: String::from("HEAD"),
+            hash,
+            symref_target,
+            peeled: None,
+        });
+    }
+
+    for (name, hash) in refs::list_refs("refs/")? {
+        let mut tags = Vec::new();
+        let (_, target) = peel(&hash, &mut tags)?;
+        advertised.push(AdvertisedRef {
+            name,
+            hash,
+            symref_target: None,
+            peeled: (!tags.is_empty()).then_some(target),
+        });
+    }
+
+    Ok(advertised)
+}
+
+/// Answers a version 2 command: its name, the capabilities the client uses up to a delimiter,
+/// and its arguments
+/// https://git-scm.com/docs/protocol-v2#_command_request
+fn serve_command(reader: &mut PktLineReader<impl Read>, output: impl Write) -> std::io::Result<()> {
+    let command = reader
+        .read_text()?
+        .and_then(|line| line.strip_prefix("command=").map(str::to_string))
+        .ok_or_else(|| std::io::Error::other("protocol error: expected a command"))?;
+
+    // The agent, object format and server options of the client make no difference to us
+    let mut arguments = Vec::new();
+    loop {
+        match reader.read_packet()? {
+            Packet::Data(_) => {}
+            Packet::Delimiter => {
+                arguments = reader.read_section()?;
+                break;
+            }
+            Packet::Flush => break,
+            Packet::ResponseEnd => {
+                return Err(std::io::Error::other(
+                    "protocol error: unexpected response end packet",
+                ))
+            }
+        }
+    }
+
+    match command.as_str() {
+        "ls-refs" => ls_refs(&arguments, output),
+        "fetch" => fetch(&arguments, output),
+        _ => Err(std::io::Error::other(format!(
+            "upload-pack: unknown command '{command}'"
+        ))),
+    }
+}
+
+/// Lists the refs under the prefixes asked for, all of them when there are none
+/// https://git-scm.com/docs/protocol-v2#_ls_refs
+fn ls_refs(arguments: &[String], output: impl Write) -> std::io::Result<()> {
+    let symrefs = arguments.iter().any(|argument| argument == "symrefs");
+    let peeled = arguments.iter().any(|argument| argument == "peel");
+    let prefixes = arguments
+        .iter()
+        .filter_map(|argument| argument.strip_prefix("ref-prefix "))
+        .collect::<Vec<_>>();
+
+    let mut writer = PktLineWriter::new(output);
+    for advertised in advertised_refs()? {
+        if !prefixes.is_empty()
+            && !prefixes
+                .iter()
+                .any(|prefix| advertised.name.starts_with(prefix))
+        {
+            continue;
+        }
+
+        let mut line = format!("{} {}", advertised.hash, advertised.name);
+        if let Some(target) = advertised.symref_target.filter(|_| symrefs) {
+            line.push_str(&format!(" symref-target:{target}"));
+        }
+        if let Some(target) = advertised.peeled.filter(|_| peeled) {
+            line.push_str(&format!(" peeled:{target}"));
+        }
+        writer.write_line(&line)?;
+    }
+    writer.write_flush()
+}
+
+/// What the client asked to be sent, and how
+#[derive(Default)]
+struct PackRequest {
+    wants: Vec<Hash>,
+    /// The commits the client has that we have too
+    common: Vec<Hash>,
+    ofs_delta: bool,
+    include_tag: bool,
+    side_band: bool,
+}
+
+/// Acknowledges the commits the client has that we have too, and sends the pack once the
+/// client is done or as soon as one of them is common, as what it reaches needs not be sent
+/// https://git-scm.com/docs/protocol-v2#_fetch
+fn fetch(arguments: &[String], output: impl Write) -> std::io::Result<()> {
+    let mut request = PackRequest {
+        side_band: true,
+        ..Default::default()
+    };
+    let mut done = false;
+    for argument in arguments {
+        match argument.split_once(' ') {
+            Some(("want", hash)) => request.wants.push(parse_hash(hash)?),
+            Some(("have", hash)) => {
+                let hash = parse_hash(hash)?;
+                if Object::exists(&hash) {
+                    request.common.push(hash);
+                }
+            }
+            _ => match argument.as_str() {
+                "done" => done = true,
+                "ofs-delta" => request.ofs_delta = true,
+                "include-tag" => request.include_tag = true,
+                "thin-pack" | "no-progress" | "wait-for-done" => {}
+                _ => {
+                    return Err(std::io::Error::other(format!(
+                        "upload-pack: unexpected line: '{argument}'"
+                    )))
+                }
+            },
+        }
+    }
+    check_wants(&request.wants)?;
+
+    let mut writer = PktLineWriter::new(output);
+    if !done {
+        writer.write_line("acknowledgments")?;
+        if request.common.is_empty() {
+            writer.write_line("NAK")?;
+            return writer.write_flush();
+        }
+        for common in &request.common {
+            writer.write_line(&format!("ACK {common}"))?;
+        }
+        writer.write_line("ready")?;
+        writer.write_delimiter()?;
+    }
+
+    writer.write_line("packfile")?;
+    send_pack(writer.into_inner(), &request)
+}
+
+/// Answers a request of the original protocol: the wanted commits, the first one carrying the
+/// capabilities the client uses, then the commits it has and either `done` or a flush asking
+/// for acknowledgments. With multi_ack_detailed every common commit is acknowledged, and we
+/// are ready as soon as there is one.
+/// https://git-scm.com/docs/pack-protocol#_packfile_negotiation
+fn serve_negotiation(
+    reader: &mut PktLineReader<impl Read>,
+    output: impl Write,
+) -> std::io::Result<()> {
+    let mut request = PackRequest::default();
+    let mut capabilities = Vec::new();
+    while let Some(line) = reader.read_text()? {
+        let want = line
+            .strip_prefix("want ")
+            .ok_or_else(|| unexpected_line(&line))?;
+        let (hash, advertised) = want.split_once(' ').unwrap_or((want, ""));
+        if request.wants.is_empty() {
+            capabilities = advertised.split(' ').map(str::to_string).collect();
+        }
+        request.wants.push(parse_hash(hash)?);
+    }
+    if request.wants.is_empty() {
+        return Ok(());
+    }
+    check_wants(&request.wants)?;
+
+    let has_capability = |name: &str| capabilities.iter().any(|capability| capability == name);
+    let multi_ack = has_capability("multi_ack_detailed") || has_capability("multi_ack");
+    request.ofs_delta = has_capability("ofs-delta");
+    request.include_tag = has_capability("include-tag");
+    request.side_band = has_capability("side-band-64k");
+
+    let mut writer = PktLineWriter::new(output);
+    let mut done = false;
+    while let Some(line) = reader.read_text()? {
+        if line == "done" {
+            done = true;
+            break;
+        }
+        let have = line
+            .strip_prefix("have ")
+            .ok_or_else(|| unexpected_line(&line))?;
+        let have = parse_hash(have)?;
+        if !Object::exists(&have) {
+            continue;
+        }
+
+        if has_capability("multi_ack_detailed") {
+            writer.write_line(&format!("ACK {have} common"))?;
+        } else if multi_ack {
+            writer.write_line(&format!("ACK {have} continue"))?;
+        } else if request.common.is_empty() {
+            writer.write_line(&format!("ACK {have}"))?;
+        }
+        request.common.push(have);
+    }
+
+    let last_common = request.common.last();
+    if !done {
+        if let Some(common) = last_common.filter(|_| has_capability("multi_ack_detailed")) {
+            writer.write_line(&format!("ACK {common} ready"))?;
+        }
+        if last_common.is_none() || multi_ack {
+            writer.write_line("NAK")?;
+        }
+        return Ok(());
+    }
+
+    match last_common {
+        Some(common) if multi_ack => writer.write_line(&format!("ACK {common}"))?,
+        Some(_) => {}
+        None => writer.write_line("NAK")?,
+    }
+    send_pack(writer.into_inner(), &request)
+}
+
+/// Sends a pack of what the wanted objects reach but the common commits do not, along with the
+/// annotated tags pointing into it when asked for, multiplexed on band 1 if asked for
+fn send_pack(output: impl Write, request: &PackRequest) -> std::io::Result<()> {
+    let mut objects = list_objects(&request.wants, &request.common)?;
+    if request.include_tag {
+        let mut sent = objects
+            .iter()
+            .map(|(hash, _)| hash.clone())
+            .collect::<HashSet<_>>();
+        for (_, hash) in refs::list_refs("refs/tags/")? {
+            let mut tags = Vec::new();
+            let (_, target) = peel(&hash, &mut tags)?;
+            if sent.contains(&target) {
+                for tag in tags {
+                    if sent.insert(tag.clone()) {
+                        objects.push((tag, String::new()));
+                    }
+                }
+            }
+        }
+    }
+
+    let search = DeltaSearch::default();
+    match request.side_band {
+        true => {
+            let (writer, _) = write_pack(
+                SidebandWriter::new(output),
+                &objects,
+                search,
+                request.ofs_delta,
+            )?;
+            writer.finish().map(|_| ())
+        }
+        false => write_pack(output, &objects, search, request.ofs_delta).map(|_| ()),
+    }
+}
+
+/// Checks that we have every wanted object
+fn check_wants(wants: &[Hash]) -> std::io::Result<()> {
+    match wants.iter().find(|want| !Object::exists(want)) {
+        Some(want) => Err(std::io::Error::other(format!(
+            "upload-pack: not our ref {want}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn parse_hash(hash: &str) -> std::io::Result<Hash> {
+    Hash::from_str(hash).map_err(|_| {
+        std::io::Error::other(format!(
+            "protocol error: expected an object ID, got '{hash}'"
+        ))
+    })
+}
+
+fn unexpected_line(line: &str) -> std::io::Error {
+    std::io::Error::other(format!("protocol error: unexpected '{line}'"))
+}
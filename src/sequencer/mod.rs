@@ -0,0 +1,604 @@
+pub(crate) mod rebase;
+
+use crate::command::commit::{cleanup_message, commit, print_summary, CommitOptions};
+use crate::diff::FileMap;
+use crate::graph::CommitGraph;
+use crate::index::Index;
+use crate::merge::text::ConflictStyle;
+use crate::merge::{self, checkout_merge, merge_trees, MergeLabels, TreeMerge, MERGE_HEAD_PATH};
+use crate::object::commit::{Commit, Signature};
+use crate::object::tree::Tree;
+use crate::object::Hash;
+use crate::refs::revision;
+use crate::{refs, worktree};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const SEQUENCER_DIR: &str = ".hamachi/sequencer";
+pub(crate) const CHERRY_PICK_HEAD_PATH: &str = ".hamachi/CHERRY_PICK_HEAD";
+pub(crate) const REVERT_HEAD_PATH: &str = ".hamachi/REVERT_HEAD";
+
+/// Marks the origin of a cherry-picked commit in its message with `-x`
+const CHERRY_PICKED_PREFIX: &str = "(cherry picked from commit ";
+
+/// What the sequencer does with every commit of its todo list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    /// Applies the changes a commit introduced, as `cherry-pick` does
+    Pick,
+    /// Undoes the changes a commit introduced, as `revert` does
+    Revert,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Pick => "cherry-pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    /// The command of the todo list lines
+    fn command(self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    /// The file recording the commit being applied while the sequencer is stopped
+    fn head_path(self) -> &'static str {
+        match self {
+            Action::Pick => CHERRY_PICK_HEAD_PATH,
+            Action::Revert => REVERT_HEAD_PATH,
+        }
+    }
+}
+
+/// Options kept in `.hamachi/sequencer/opts` so they still apply after `--continue`
+#[derive(Debug, Default)]
+pub(crate) struct SequencerOptions {
+    /// Append a line recording the picked commit to the message, with `-x`
+    pub(crate) record_origin: bool,
+    /// Append a `Signed-off-by` trailer with the committer to the message
+    pub(crate) signoff: bool,
+    /// The parent, counting from 1, that merge commits are compared to
+    pub(crate) mainline: Option<usize>,
+}
+
+impl SequencerOptions {
+    fn write(&self) -> std::io::Result<()> {
+        let mut opts = String::from("[options]\n");
+        if self.record_origin {
+            opts.push_str("\trecord-origin = true\n");
+        }
+        if self.signoff {
+            opts.push_str("\tsignoff = true\n");
+        }
+        if let Some(mainline) = self.mainline {
+            opts.push_str(&format!("\tmainline = {mainline}\n"));
+        }
+
+        fs::write(Path::new(SEQUENCER_DIR).join("opts"), opts)
+    }
+
+    fn read() -> std::io::Result<Self> {
+        let mut options = SequencerOptions::default();
+        let opts = fs::read_to_string(Path::new(SEQUENCER_DIR).join("opts"))?;
+
+        for (key, value) in opts.lines().filter_map(|line| line.split_once('=')) {
+            match (key.trim(), value.trim()) {
+                ("record-origin", value) => options.record_origin = value == "true",
+                ("signoff", value) => options.signoff = value == "true",
+                ("mainline", value) => options.mainline = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Applies or undoes the specified commits one after the other on top of HEAD, committing each
+/// result. Ranges like `A..B` stand for the commits of `B` missing from `A`, oldest first when
+/// picking and most recent first when reverting. The sequencer stops at the first commit that
+/// does not apply cleanly and leaves its state in `.hamachi/sequencer` for `resume`, `skip` and
+/// `abort`.
+pub(crate) fn start(
+    action: Action,
+    revisions: &[String],
+    options: SequencerOptions,
+) -> std::io::Result<()> {
+    if Path::new(SEQUENCER_DIR).exists()
+        || Path::new(CHERRY_PICK_HEAD_PATH).exists()
+        || Path::new(REVERT_HEAD_PATH).exists()
+    {
+        return Err(std::io::Error::other(format!(
+            "{} is already in progress\nhint: try \"hamachi {} (--continue | --skip | --abort)\"",
+            action.name(),
+            action.name()
+        )));
+    }
+    if Path::new(MERGE_HEAD_PATH).exists() {
+        return Err(std::io::Error::other(format!(
+            "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you {}.",
+            action.name()
+        )));
+    }
+
+    let Some(head) = refs::head_commit()? else {
+        return Err(std::io::Error::other(format!(
+            "cannot {} onto an unborn branch",
+            action.name()
+        )));
+    };
+
+    let commits = resolve_commits(action, revisions)?;
+    if commits.is_empty() {
+        return Err(std::io::Error::other("empty commit set passed"));
+    }
+
+    fs::create_dir_all(SEQUENCER_DIR)?;
+    fs::write(Path::new(SEQUENCER_DIR).join("head"), format!("{head}\n"))?;
+    fs::write(
+        Path::new(SEQUENCER_DIR).join("abort-safety"),
+        format!("{head}\n"),
+    )?;
+    options.write()?;
+    write_todo(action, &commits)?;
+
+    let result = run(action, &options);
+    // A failure before anything was applied or stopped at leaves nothing to resume
+    if result.is_err()
+        && refs::head_commit()?.as_ref() == Some(&head)
+        && !Path::new(action.head_path()).exists()
+    {
+        fs::remove_dir_all(SEQUENCER_DIR)?;
+    }
+
+    result
+}
+
+/// Concludes the commit the sequencer stopped at, with the changes staged in the index, and goes
+/// on with the rest of the todo list. Commits concluded with `commit` in the meantime are not
+/// committed again.
+pub(crate) fn resume(action: Action) -> std::io::Result<()> {
+    let options = SequencerOptions::read().map_err(|_| no_sequence())?;
+
+    if Path::new(action.head_path()).exists() {
+        if Index::load()?.has_conflicts() {
+            return Err(std::io::Error::other(format!(
+                "Committing is not possible because you have unmerged files.\nhint: Fix them up in the work tree, and then use 'hamachi add/rm <file>'\nhint: as appropriate to mark resolution and make a commit.\nfatal: {} failed",
+                action.name()
+            )));
+        }
+        commit(CommitOptions::default())?;
+        drop_first_todo(action)?;
+    } else if refs::head_commit()? != read_state_hash("abort-safety")? {
+        drop_first_todo(action)?;
+    }
+
+    run(action, &options)
+}
+
+/// Forgets the commit the sequencer stopped at, restoring HEAD in the index and working tree,
+/// and goes on with the rest of the todo list
+pub(crate) fn skip(action: Action) -> std::io::Result<()> {
+    let options = SequencerOptions::read().map_err(|_| no_sequence())?;
+
+    if let Some(head) = refs::head_commit()? {
+        let head_tree = Commit::from_hash(&head).tree_hash;
+        worktree::checkout_tree(Some(&head_tree), &head_tree, true)?;
+    }
+    merge::clear_merge_state()?;
+    clear_pick_heads()?;
+    drop_first_todo(action)?;
+
+    run(action, &options)
+}
+
+/// Ends the sequence, bringing the branch, index and working tree back to where they were before
+/// it started, unless HEAD was moved elsewhere in the meantime
+pub(crate) fn abort() -> std::io::Result<()> {
+    let original = read_state_hash("head")
+        .ok()
+        .flatten()
+        .ok_or_else(no_sequence)?;
+    let head = refs::head_commit()?;
+
+    if head != read_state_hash("abort-safety")? {
+        clear_state()?;
+        return Err(std::io::Error::other(
+            "You seem to have moved HEAD. Not rewinding, check your HEAD!",
+        ));
+    }
+
+    let original_tree = Commit::from_hash(&original).tree_hash;
+    let head_tree = head.as_ref().map(|head| Commit::from_hash(head).tree_hash);
+    worktree::checkout_tree(head_tree.as_ref(), &original_tree, true)?;
+    refs::update_ref_with_log(
+        "HEAD",
+        head.as_ref(),
+        &original,
+        &format!("reset: moving to {original}"),
+    )?;
+
+    clear_state()
+}
+
+/// The commit being cherry-picked while the sequencer is stopped, whose author the commit
+/// concluding it keeps
+pub(crate) fn cherry_pick_head() -> std::io::Result<Option<Hash>> {
+    match fs::read_to_string(CHERRY_PICK_HEAD_PATH) {
+        Ok(content) => Hash::from_str(content.trim())
+            .map(Some)
+            .map_err(|_| std::io::Error::other("could not parse CHERRY_PICK_HEAD")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Forgets the commit being cherry-picked or reverted, once concluded
+pub(crate) fn clear_pick_heads() -> std::io::Result<()> {
+    for path in [CHERRY_PICK_HEAD_PATH, REVERT_HEAD_PATH] {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Works through the todo list, removing every commit once applied, and removes the sequencer
+/// state once the list is done
+fn run(action: Action, options: &SequencerOptions) -> std::io::Result<()> {
+    while let Some(commit) = read_todo()?.first() {
+        apply(action, commit, options)?;
+
+        let head = refs::head_commit()?.unwrap();
+        fs::write(
+            Path::new(SEQUENCER_DIR).join("abort-safety"),
+            format!("{head}\n"),
+        )?;
+        drop_first_todo(action)?;
+    }
+
+    clear_state()
+}
+
+/// Merges the changes a commit introduced, or their inverse when reverting, into HEAD and commits
+/// the result. When the merge has conflicts or leaves nothing to commit, the commit is recorded
+/// in `CHERRY_PICK_HEAD` or `REVERT_HEAD` along with the prepared message, and an error stops
+/// the sequence.
+fn apply(action: Action, hash: &Hash, options: &SequencerOptions) -> std::io::Result<()> {
+    let head = refs::head_commit()?.unwrap();
+    let head_tree = Commit::from_hash(&head).tree_hash;
+    check_index(action.name(), &head_tree)?;
+
+    let picked = Commit::from_hash(hash);
+    let parent = mainline_parent(hash, &picked, options.mainline)?;
+    let result = merge_into_head(action, hash, &picked, parent.as_ref())?;
+
+    let mut message = commit_message(action, hash, &picked, parent.as_ref(), options)?;
+
+    if !result.is_clean() {
+        message.push_str(&conflicts_comment(&result));
+        stop(action, hash, &message)?;
+
+        let verb = match action {
+            Action::Pick => "apply",
+            Action::Revert => "revert",
+        };
+        return Err(std::io::Error::other(format!(
+            "could not {verb} {}... {}\nhint: After resolving the conflicts, mark them with\nhint: \"hamachi add/rm <pathspec>\", then run\nhint: \"hamachi {hint_action} --continue\".\nhint: You can instead skip this commit with \"hamachi {hint_action} --skip\".\nhint: To abort and get back to the state before \"hamachi {hint_action}\",\nhint: run \"hamachi {hint_action} --abort\".",
+            hash.to_short_string(),
+            picked.subject(),
+            hint_action = action.name()
+        )));
+    }
+
+    let tree = Index::load()?.write_tree()?;
+    if tree == head_tree {
+        stop(action, hash, &message)?;
+        return Err(std::io::Error::other(format!(
+            "The previous {} is now empty, possibly due to conflict resolution.\nIf you wish to commit it anyway, use:\n\n    hamachi commit --allow-empty\n\nOtherwise, please use 'hamachi {} --skip'",
+            action.name(),
+            action.name()
+        )));
+    }
+
+    let author = match action {
+        Action::Pick => picked.author(),
+        Action::Revert => Signature::author()?,
+    };
+    let commit = Commit::new(
+        tree,
+        vec![head.clone()],
+        author,
+        Signature::committer()?,
+        message,
+    );
+    let new_hash = commit.write()?;
+    refs::update_ref_with_log(
+        "HEAD",
+        Some(&head),
+        &new_hash,
+        &format!("{}: {}", action.name(), commit.subject()),
+    )?;
+
+    print_summary(&new_hash, &commit)
+}
+
+/// Refuses to apply changes over an index with unmerged entries or changes staged on top of
+/// HEAD, which the commit created next would include
+pub(crate) fn check_index(action: &str, head_tree: &Hash) -> std::io::Result<()> {
+    let index = Index::load()?;
+    if index.has_conflicts() {
+        return Err(std::io::Error::other(format!(
+            "{} is not possible because you have unmerged files.",
+            capitalize(action)
+        )));
+    }
+    if index.write_tree()? != *head_tree {
+        return Err(std::io::Error::other(format!(
+            "your local changes would be overwritten by {action}.\nhint: commit your changes or stash them to proceed."
+        )));
+    }
+
+    Ok(())
+}
+
+/// Merges the changes a commit introduced relative to `parent`, or their inverse when reverting,
+/// into HEAD, updating the index and working tree. Conflicts are left in both for the user to
+/// resolve.
+pub(crate) fn merge_into_head(
+    action: Action,
+    hash: &Hash,
+    picked: &Commit,
+    parent: Option<&Hash>,
+) -> std::io::Result<TreeMerge> {
+    let head = refs::head_commit()?.unwrap();
+    let ours = Tree::flatten(&Commit::from_hash(&head).tree_hash)?;
+    let picked_files = Tree::flatten(&picked.tree_hash)?;
+    let parent_files = match parent {
+        Some(parent) => Tree::flatten(&Commit::from_hash(parent).tree_hash)?,
+        None => FileMap::new(),
+    };
+
+    let label = format!("{} ({})", hash.to_short_string(), picked.subject());
+    let parent_label = format!("parent of {label}");
+    let (base, theirs, labels) = match action {
+        Action::Pick => (
+            parent_files,
+            picked_files,
+            MergeLabels {
+                base: parent_label,
+                ours: String::from("HEAD"),
+                theirs: label,
+            },
+        ),
+        Action::Revert => (
+            picked_files,
+            parent_files,
+            MergeLabels {
+                base: label,
+                ours: String::from("HEAD"),
+                theirs: parent_label,
+            },
+        ),
+    };
+
+    let result = merge_trees(
+        &base,
+        &ours,
+        &theirs,
+        &labels,
+        ConflictStyle::from_config()?,
+    )?;
+    checkout_merge(&ours, &result)?;
+    for message in &result.messages {
+        println!("{message}");
+    }
+
+    Ok(result)
+}
+
+/// The list of conflicting paths git appends to the prepared message as comments
+pub(crate) fn conflicts_comment(result: &TreeMerge) -> String {
+    let mut comment = String::from("\n# Conflicts:\n");
+    for path in result.conflicts.keys() {
+        comment.push_str(&format!("#\t{path}\n"));
+    }
+    comment
+}
+
+/// The parent the changes of a commit are taken against, which must be chosen with `-m` for
+/// merge commits
+fn mainline_parent(
+    hash: &Hash,
+    commit: &Commit,
+    mainline: Option<usize>,
+) -> std::io::Result<Option<Hash>> {
+    let parents = &commit.parents;
+    match mainline {
+        None if parents.len() > 1 => Err(std::io::Error::other(format!(
+            "commit {hash} is a merge but no -m option was given."
+        ))),
+        None => Ok(parents.first().map(|parent| parent.parent_hash.clone())),
+        Some(mainline) if parents.len() > 1 => match mainline.checked_sub(1) {
+            Some(index) if index < parents.len() => Ok(Some(parents[index].parent_hash.clone())),
+            _ => Err(std::io::Error::other(format!(
+                "commit {hash} does not have parent {mainline}"
+            ))),
+        },
+        Some(_) => Err(std::io::Error::other(format!(
+            "mainline was specified but commit {hash} is not a merge."
+        ))),
+    }
+}
+
+/// The message of the commit applying or undoing a commit, with the trailers asked for
+fn commit_message(
+    action: Action,
+    hash: &Hash,
+    picked: &Commit,
+    parent: Option<&Hash>,
+    options: &SequencerOptions,
+) -> std::io::Result<String> {
+    let mut message = match action {
+        Action::Pick => {
+            let mut message = picked.commit_message.clone();
+            if options.record_origin {
+                append_trailer(&mut message, &format!("{CHERRY_PICKED_PREFIX}{hash})"));
+            }
+            message
+        }
+        Action::Revert => match parent.filter(|_| picked.parents.len() > 1) {
+            Some(parent) => format!(
+                "Revert \"{}\"\n\nThis reverts commit {hash}, reversing\nchanges made to {parent}.\n",
+                picked.subject()
+            ),
+            None => format!(
+                "Revert \"{}\"\n\nThis reverts commit {hash}.\n",
+                picked.subject()
+            ),
+        },
+    };
+
+    if options.signoff {
+        let committer = Signature::committer()?;
+        let signoff = format!("Signed-off-by: {} <{}>", committer.name, committer.email);
+        if message.lines().last() != Some(signoff.as_str()) {
+            append_trailer(&mut message, &signoff);
+        }
+    }
+
+    Ok(message)
+}
+
+/// Appends a trailer line to the message, separated by a blank line unless the last paragraph
+/// already is a trailer block. Like git, a paragraph other than the subject is a trailer block
+/// when all its lines are trailers, or at least a quarter of them with one generated by git.
+fn append_trailer(message: &mut String, trailer: &str) {
+    let cleaned = cleanup_message(message, false);
+    let paragraphs = cleaned.trim_end().split("\n\n").collect::<Vec<_>>();
+    let has_trailers = match paragraphs.as_slice() {
+        [_, .., last] => {
+            let lines = last.lines().collect::<Vec<_>>();
+            let trailers = lines.iter().filter(|line| is_trailer(line)).count();
+            let generated = lines.iter().any(|line| {
+                line.starts_with(CHERRY_PICKED_PREFIX) || line.starts_with("Signed-off-by: ")
+            });
+            trailers == lines.len() || (generated && trailers * 4 >= lines.len())
+        }
+        _ => false,
+    };
+
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+    if !has_trailers {
+        message.push('\n');
+    }
+    message.push_str(trailer);
+    message.push('\n');
+}
+
+/// Whether a message line is a `Token: value` trailer, or the line added by `-x`
+fn is_trailer(line: &str) -> bool {
+    line.starts_with(CHERRY_PICKED_PREFIX)
+        || line.split_once(": ").is_some_and(|(token, _)| {
+            !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Records the commit the sequence stopped at and the message prepared for it
+fn stop(action: Action, hash: &Hash, message: &str) -> std::io::Result<()> {
+    fs::write(action.head_path(), format!("{hash}\n"))?;
+    fs::write(merge::MERGE_MSG_PATH, message)
+}
+
+/// The commits named on the command line, expanding `A..B` ranges
+fn resolve_commits(action: Action, revisions: &[String]) -> std::io::Result<Vec<Hash>> {
+    let mut graph = CommitGraph::new();
+    let mut commits = Vec::new();
+
+    for revision in revisions {
+        match revision.split_once("..") {
+            Some((exclude, include)) => {
+                let exclude =
+                    revision::resolve_commit(if exclude.is_empty() { "HEAD" } else { exclude })?;
+                let include =
+                    revision::resolve_commit(if include.is_empty() { "HEAD" } else { include })?;
+                let mut range = graph.range(&exclude, &include);
+                if action == Action::Pick {
+                    range.reverse();
+                }
+                commits.extend(range);
+            }
+            None => commits.push(revision::resolve_commit(revision)?),
+        }
+    }
+
+    Ok(commits)
+}
+
+fn read_todo() -> std::io::Result<Vec<Hash>> {
+    let todo = fs::read_to_string(Path::new(SEQUENCER_DIR).join("todo"))?;
+
+    todo.lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(revision::resolve_commit)
+        .collect()
+}
+
+fn write_todo(action: Action, commits: &[Hash]) -> std::io::Result<()> {
+    let todo = commits
+        .iter()
+        .map(|hash| {
+            format!(
+                "{} {} {}\n",
+                action.command(),
+                hash.to_short_string(),
+                Commit::from_hash(hash).subject()
+            )
+        })
+        .collect::<String>();
+
+    fs::write(Path::new(SEQUENCER_DIR).join("todo"), todo)
+}
+
+fn drop_first_todo(action: Action) -> std::io::Result<()> {
+    let todo = read_todo()?;
+    write_todo(action, todo.get(1..).unwrap_or_default())
+}
+
+fn read_state_hash(name: &str) -> std::io::Result<Option<Hash>> {
+    let content = fs::read_to_string(Path::new(SEQUENCER_DIR).join(name))?;
+    Ok(Hash::from_str(content.trim()).ok())
+}
+
+fn clear_state() -> std::io::Result<()> {
+    match fs::remove_dir_all(SEQUENCER_DIR) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    merge::clear_merge_state()?;
+    clear_pick_heads()
+}
+
+fn no_sequence() -> std::io::Error {
+    std::io::Error::other("no cherry-pick or revert in progress")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,994 @@
+use crate::command::commit::{cleanup_message, edit_message, editor, launch_editor};
+use crate::command::switch::switch;
+use crate::config::Config;
+use crate::graph::CommitGraph;
+use crate::index::Index;
+use crate::merge::{self, MERGE_MSG_PATH};
+use crate::object::commit::{Commit, Signature};
+use crate::object::Hash;
+use crate::refs::revision;
+use crate::refs::Head;
+use crate::sequencer::{check_index, conflicts_comment, merge_into_head, Action};
+use crate::{refs, worktree};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+const REBASE_DIR: &str = ".hamachi/rebase-merge";
+pub(crate) const REBASE_HEAD_PATH: &str = ".hamachi/REBASE_HEAD";
+
+const TODO_HELP: &str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\" but keep only the previous
+#                    commit's log message
+# x, exec <command> = run command (the rest of the line) using shell
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+";
+
+/// What a line of the todo list does
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TodoCommand {
+    /// Applies the commit as it is
+    Pick,
+    /// Applies the commit, editing its message
+    Reword,
+    /// Applies the commit, then stops so it can be amended
+    Edit,
+    /// Melds the commit into the previous one, editing the combined message
+    Squash,
+    /// Melds the commit into the previous one, keeping the message of the previous one
+    Fixup,
+    /// Runs a shell command, stopping if it fails
+    Exec,
+    /// Leaves the commit out
+    Drop,
+}
+
+impl TodoCommand {
+    fn name(self) -> &'static str {
+        match self {
+            TodoCommand::Pick => "pick",
+            TodoCommand::Reword => "reword",
+            TodoCommand::Edit => "edit",
+            TodoCommand::Squash => "squash",
+            TodoCommand::Fixup => "fixup",
+            TodoCommand::Exec => "exec",
+            TodoCommand::Drop => "drop",
+        }
+    }
+
+    fn is_fixup(self) -> bool {
+        matches!(self, TodoCommand::Squash | TodoCommand::Fixup)
+    }
+}
+
+impl FromStr for TodoCommand {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p" | "pick" => Ok(TodoCommand::Pick),
+            "r" | "reword" => Ok(TodoCommand::Reword),
+            "e" | "edit" => Ok(TodoCommand::Edit),
+            "s" | "squash" => Ok(TodoCommand::Squash),
+            "f" | "fixup" => Ok(TodoCommand::Fixup),
+            "x" | "exec" => Ok(TodoCommand::Exec),
+            "d" | "drop" => Ok(TodoCommand::Drop),
+            _ => Err(std::io::Error::other(format!("invalid command '{s}'"))),
+        }
+    }
+}
+
+/// A line of the todo list: a command with its commit, or with a shell command for `exec`
+#[derive(Debug, Clone)]
+struct TodoItem {
+    command: TodoCommand,
+    commit: Option<Hash>,
+    /// The subject of the commit, or the shell command to run
+    argument: String,
+}
+
+impl TodoItem {
+    fn pick(commit: Hash) -> Self {
+        let argument = Commit::from_hash(&commit).subject().to_string();
+        TodoItem {
+            command: TodoCommand::Pick,
+            commit: Some(commit),
+            argument,
+        }
+    }
+
+    /// Parses a line of the todo list, `None` for blank lines and comments
+    fn parse(line: &str) -> std::io::Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let command = command.parse::<TodoCommand>()?;
+        if command == TodoCommand::Exec {
+            return Ok(Some(TodoItem {
+                command,
+                commit: None,
+                argument: rest.trim().to_string(),
+            }));
+        }
+
+        let (commit, subject) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+        let commit = revision::resolve_commit(commit)
+            .map_err(|_| std::io::Error::other(format!("invalid line: {line}")))?;
+
+        Ok(Some(TodoItem {
+            command,
+            commit: Some(commit),
+            argument: subject.to_string(),
+        }))
+    }
+
+    fn to_line(&self, abbreviate: bool) -> String {
+        match &self.commit {
+            Some(commit) if abbreviate => format!(
+                "{} {} {}\n",
+                self.command.name(),
+                commit.to_short_string(),
+                self.argument
+            ),
+            Some(commit) => format!("{} {commit} {}\n", self.command.name(), self.argument),
+            None => format!("{} {}\n", self.command.name(), self.argument),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RebaseOptions {
+    /// The commit the branch is compared to, the upstream of the branch by default
+    pub(crate) upstream: Option<String>,
+    /// Where the commits are replayed, `upstream` by default
+    pub(crate) onto: Option<String>,
+    /// The branch to switch to and rebase, instead of the current one
+    pub(crate) branch: Option<String>,
+    /// Let the user edit the todo list before it runs
+    pub(crate) interactive: bool,
+    /// Move `fixup!` and `squash!` commits after the commits they amend
+    pub(crate) autosquash: bool,
+    /// Shell commands to run after every commit
+    pub(crate) exec: Vec<String>,
+}
+
+/// Replays the commits of the current branch missing from the upstream on top of `onto`, one at
+/// a time as listed in the todo list of `.hamachi/rebase-merge`, and points the branch to the
+/// result. The original tip is saved as ORIG_HEAD and every step is recorded in the reflog of
+/// HEAD, so that the rebase can be undone.
+/// https://git-scm.com/docs/git-rebase
+pub(crate) fn start(options: RebaseOptions) -> std::io::Result<()> {
+    if Path::new(REBASE_DIR).exists() {
+        return Err(std::io::Error::other(
+            "It seems that there is already a rebase-merge directory, and\nI wonder if you are in the middle of another rebase.  If that is the\ncase, please try\n\thamachi rebase (--continue | --abort | --skip)\nIf that is not the case, please\n\trm -fr \".hamachi/rebase-merge\"\nand run me again.  I am stopping in case you still have something\nvaluable there.",
+        ));
+    }
+
+    if let Some(branch) = options.branch {
+        if refs::read_head()? != Head::Branch(format!("refs/heads/{branch}")) {
+            switch(Some(branch), false, false, None, false)?;
+        }
+    }
+    let head_name = refs::read_head()?;
+    let Some(head) = refs::head_commit()? else {
+        return Err(std::io::Error::other(
+            "cannot rebase: the current branch has no commits yet",
+        ));
+    };
+    let head_tree = Commit::from_hash(&head).tree_hash;
+    check_clean_worktree(&head_tree)?;
+
+    let upstream_name = match (options.upstream, &head_name) {
+        (Some(upstream), _) => upstream,
+        (None, Head::Branch(branch)) => match refs::upstream_of(branch)? {
+            Some((name, _)) => name,
+            None => {
+                return Err(std::io::Error::other(
+                    "There is no tracking information for the current branch.\nPlease specify which branch you want to rebase against.",
+                ))
+            }
+        },
+        (None, Head::Detached(_)) => {
+            return Err(std::io::Error::other(
+                "You are not currently on a branch.\nPlease specify which branch you want to rebase against.",
+            ))
+        }
+    };
+    let upstream = revision::resolve_commit(&upstream_name)
+        .map_err(|_| std::io::Error::other(format!("invalid upstream '{upstream_name}'")))?;
+    let onto_name = options.onto.unwrap_or(upstream_name);
+    let onto = revision::resolve_commit(&onto_name).map_err(|_| {
+        std::io::Error::other(format!("Does not point to a valid commit '{onto_name}'"))
+    })?;
+
+    // Merge commits are left out, their changes being those of the commits they join
+    let mut graph = CommitGraph::new();
+    let mut commits = graph.range(&upstream, &head);
+    commits.retain(|commit| graph.parents(commit).len() < 2);
+    commits.reverse();
+
+    let branch_name = match &head_name {
+        Head::Branch(branch) => branch.strip_prefix("refs/heads/").unwrap_or(branch),
+        Head::Detached(_) => "HEAD",
+    };
+    let is_up_to_date = commits.last().unwrap_or(&onto) == &head
+        && commits
+            .iter()
+            .try_fold(&onto, |previous, commit| {
+                (graph.parents(commit).first() == Some(previous)).then_some(commit)
+            })
+            .is_some();
+    if is_up_to_date && !options.interactive && options.exec.is_empty() {
+        println!("Current branch {branch_name} is up to date.");
+        return Ok(());
+    }
+
+    let mut todo = commits.into_iter().map(TodoItem::pick).collect::<Vec<_>>();
+    let autosquash = options.autosquash
+        || (options.interactive
+            && Config::load()?
+                .get("rebase.autoSquash")
+                .is_some_and(|value| value == "true"));
+    if autosquash {
+        todo = rearrange_squashes(todo);
+    }
+    if !options.exec.is_empty() {
+        todo = insert_execs(todo, &options.exec);
+    }
+
+    fs::create_dir_all(REBASE_DIR)?;
+    let head_name_content = match &head_name {
+        Head::Branch(branch) => branch.clone(),
+        Head::Detached(_) => String::from("detached HEAD"),
+    };
+    write_state("head-name", &head_name_content)?;
+    write_state("onto", &onto.to_string())?;
+    write_state("orig-head", &head.to_string())?;
+    write_state("msgnum", "0")?;
+    write_state("end", &todo.len().to_string())?;
+    fs::write(state_path("done"), "")?;
+    if options.interactive {
+        write_state("interactive", "")?;
+        todo = edit_todo(&todo, &upstream, &head, &onto)?;
+        if todo.is_empty() {
+            fs::remove_dir_all(REBASE_DIR)?;
+            return Err(std::io::Error::other("Nothing to do"));
+        }
+        write_state("end", &todo.len().to_string())?;
+    }
+
+    // Commits already on top of `onto` do not need to be replayed
+    let mut start = onto.clone();
+    while let Some(item) = todo.first() {
+        let Some(commit) = item
+            .commit
+            .as_ref()
+            .filter(|_| item.command == TodoCommand::Pick)
+        else {
+            break;
+        };
+        if graph.parents(commit).first() != Some(&start) {
+            break;
+        }
+        start = commit.clone();
+        mark_done(&todo.remove(0))?;
+    }
+    write_todo(&todo)?;
+
+    refs::update_ref("ORIG_HEAD", &head)?;
+    worktree::checkout_tree(
+        Some(&head_tree),
+        &Commit::from_hash(&start).tree_hash,
+        false,
+    )?;
+    move_head(
+        &Head::Detached(start.clone()),
+        &head,
+        &start,
+        &format!("rebase (start): checkout {onto_name}"),
+    )?;
+
+    run()
+}
+
+/// Concludes the step the rebase stopped at, with the changes staged in the index, and goes on
+/// with the rest of the todo list
+pub(crate) fn resume() -> std::io::Result<()> {
+    if !Path::new(REBASE_DIR).exists() {
+        return Err(no_rebase());
+    }
+    let index = Index::load()?;
+    if index.has_conflicts() {
+        return Err(std::io::Error::other(
+            "You must edit all merge conflicts and then\nmark them as resolved using hamachi add",
+        ));
+    }
+
+    let head = refs::head_commit()?.unwrap();
+    let head_commit = Commit::from_hash(&head);
+    let tree = index.write_tree()?;
+
+    if let Some(stopped) = read_state_hash("stopped-sha")? {
+        let command = last_done()?.map_or(TodoCommand::Pick, |item| item.command);
+        if command.is_fixup() {
+            let chain = read_fixups()?;
+            let next_is_fixup = next_command()?.is_some_and(TodoCommand::is_fixup);
+            let message = squash_message(&chain, next_is_fixup)?;
+            amend_head(&head, tree, message, command)?;
+        } else if tree != head_commit.tree_hash {
+            let initial = merge::merge_message()?.unwrap_or_default();
+            let message = cleanup_message(&edit_message(&initial)?, true);
+            let author =
+                read_author_script()?.unwrap_or_else(|| Commit::from_hash(&stopped).author());
+            let commit = Commit::new(
+                tree,
+                vec![head.clone()],
+                author,
+                Signature::committer()?,
+                message,
+            );
+            let hash = commit.write()?;
+            refs::update_ref_with_log(
+                "HEAD",
+                Some(&head),
+                &hash,
+                &format!("rebase (continue): {}", commit.subject()),
+            )?;
+        }
+    } else if read_state_hash("amend")?.as_ref() == Some(&head) && tree != head_commit.tree_hash {
+        // Changes staged after stopping to edit a commit are amended into it
+        amend_head(
+            &head,
+            tree,
+            head_commit.commit_message.clone(),
+            TodoCommand::Edit,
+        )?;
+    }
+    clear_stop()?;
+
+    run()
+}
+
+/// Drops the commit the rebase stopped at, restoring HEAD in the index and working tree, and
+/// goes on with the rest of the todo list
+pub(crate) fn skip() -> std::io::Result<()> {
+    if !Path::new(REBASE_DIR).exists() {
+        return Err(no_rebase());
+    }
+
+    let head = refs::head_commit()?.unwrap();
+    let head_tree = Commit::from_hash(&head).tree_hash;
+    worktree::checkout_tree(Some(&head_tree), &head_tree, true)?;
+    clear_stop()?;
+
+    run()
+}
+
+/// Ends the rebase, checking out the original branch as it was before the rebase started
+pub(crate) fn abort() -> std::io::Result<()> {
+    let Some(orig_head) = read_state_hash("orig-head").ok().flatten() else {
+        return Err(no_rebase());
+    };
+    let head_name = read_state("head-name")?;
+    let head = refs::head_commit()?.unwrap();
+
+    worktree::checkout_tree(
+        Some(&Commit::from_hash(&head).tree_hash),
+        &Commit::from_hash(&orig_head).tree_hash,
+        true,
+    )?;
+    let (target, name) = if head_name.starts_with("refs/") {
+        (Head::Branch(head_name.clone()), head_name)
+    } else {
+        (Head::Detached(orig_head.clone()), orig_head.to_string())
+    };
+    move_head(
+        &target,
+        &head,
+        &orig_head,
+        &format!("rebase (abort): returning to {name}"),
+    )?;
+
+    clear_stop()?;
+    fs::remove_dir_all(REBASE_DIR)
+}
+
+/// Works through the todo list, moving every line to `done` before running it, and finishes the
+/// rebase once the list is empty. Returns early when a step stops the rebase on purpose.
+fn run() -> std::io::Result<()> {
+    loop {
+        let mut todo = read_todo()?;
+        if todo.is_empty() {
+            return finish();
+        }
+        let item = todo.remove(0);
+        write_todo(&todo)?;
+        mark_done(&item)?;
+        let next_is_fixup = todo.first().is_some_and(|next| next.command.is_fixup());
+
+        let stopped = match item.command {
+            TodoCommand::Pick | TodoCommand::Reword | TodoCommand::Edit => pick(&item)?,
+            TodoCommand::Squash | TodoCommand::Fixup => {
+                fixup(&item, next_is_fixup)?;
+                false
+            }
+            TodoCommand::Exec => {
+                exec(&item.argument)?;
+                false
+            }
+            TodoCommand::Drop => false,
+        };
+        if stopped {
+            return Ok(());
+        }
+    }
+}
+
+/// Replays a commit on top of HEAD. Returns whether the rebase stops to let the commit be edited.
+fn pick(item: &TodoItem) -> std::io::Result<bool> {
+    let hash = item.commit.as_ref().unwrap();
+    let picked = Commit::from_hash(hash);
+    let parent = picked
+        .parents
+        .first()
+        .map(|parent| parent.parent_hash.clone());
+    let head = refs::head_commit()?.unwrap();
+    let head_tree = Commit::from_hash(&head).tree_hash;
+    clear_fixups()?;
+
+    // A commit whose parent is HEAD already is where it belongs
+    let new_hash = if parent.as_ref() == Some(&head) && item.command != TodoCommand::Reword {
+        check_index("rebase", &head_tree)?;
+        worktree::checkout_tree(Some(&head_tree), &picked.tree_hash, false)?;
+        refs::update_ref_with_log("HEAD", Some(&head), hash, "rebase: fast-forward")?;
+        hash.clone()
+    } else {
+        check_index("rebase", &head_tree)?;
+        let result = merge_into_head(Action::Pick, hash, &picked, parent.as_ref())?;
+        if !result.is_clean() {
+            let message = format!("{}{}", picked.commit_message, conflicts_comment(&result));
+            return Err(stop_on_conflict(hash, &picked, &message));
+        }
+
+        let tree = Index::load()?.write_tree()?;
+        if tree == head_tree {
+            // The changes are already upstream
+            return Ok(false);
+        }
+
+        let message = match item.command {
+            TodoCommand::Reword => cleanup_message(&edit_message(&picked.commit_message)?, true),
+            _ => picked.commit_message.clone(),
+        };
+        let commit = Commit::new(
+            tree,
+            vec![head.clone()],
+            picked.author(),
+            Signature::committer()?,
+            message,
+        );
+        let new_hash = commit.write()?;
+        refs::update_ref_with_log(
+            "HEAD",
+            Some(&head),
+            &new_hash,
+            &format!("rebase ({}): {}", item.command.name(), commit.subject()),
+        )?;
+        new_hash
+    };
+
+    if item.command == TodoCommand::Edit {
+        write_state("amend", &new_hash.to_string())?;
+        println!(
+            "Stopped at {}...  {}\nYou can amend the commit now, with\n\n  hamachi commit --amend \n\nOnce you are satisfied with your changes, run\n\n  hamachi rebase --continue",
+            hash.to_short_string(),
+            picked.subject()
+        );
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Melds a commit into HEAD. The message is settled once the last of consecutive fixups and
+/// squashes is applied, opening the editor on the combined messages if any of them is a squash.
+fn fixup(item: &TodoItem, next_is_fixup: bool) -> std::io::Result<()> {
+    let hash = item.commit.as_ref().unwrap();
+    let picked = Commit::from_hash(hash);
+    let parent = picked
+        .parents
+        .first()
+        .map(|parent| parent.parent_hash.clone());
+    let head = refs::head_commit()?.unwrap();
+    let head_commit = Commit::from_hash(&head);
+    check_index("rebase", &head_commit.tree_hash)?;
+
+    let mut chain = read_fixups()?;
+    if chain.is_empty() {
+        fs::write(state_path("message-squash"), &head_commit.commit_message)?;
+    }
+    chain.push((item.command, hash.clone()));
+    let fixups = chain
+        .iter()
+        .map(|(command, hash)| format!("{} {hash}\n", command.name()))
+        .collect::<String>();
+    fs::write(state_path("current-fixups"), fixups)?;
+
+    let result = merge_into_head(Action::Pick, hash, &picked, parent.as_ref())?;
+    if !result.is_clean() {
+        let message = format!(
+            "{}{}",
+            combined_message(&chain)?,
+            conflicts_comment(&result)
+        );
+        return Err(stop_on_conflict(hash, &picked, &message));
+    }
+
+    let tree = Index::load()?.write_tree()?;
+    let message = squash_message(&chain, next_is_fixup)?;
+    amend_head(&head, tree, message, item.command)?;
+    if !next_is_fixup {
+        clear_fixups()?;
+    }
+
+    Ok(())
+}
+
+/// Runs a shell command of the todo list, stopping the rebase when it fails
+fn exec(command: &str) -> std::io::Result<()> {
+    println!("Executing: {command}");
+    let status = Command::new("sh").arg("-c").arg(command).status()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "Execution failed: {command}\nYou can fix the problem, and then run\n\n  hamachi rebase --continue\n"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Points the branch being rebased to the result and checks it out again
+fn finish() -> std::io::Result<()> {
+    let head_name = read_state("head-name")?;
+    let orig_head = read_state_hash("orig-head")?;
+    let onto = read_state("onto")?;
+    let head = refs::head_commit()?.unwrap();
+
+    if head_name.starts_with("refs/") {
+        refs::update_ref_with_log(
+            &head_name,
+            orig_head.as_ref(),
+            &head,
+            &format!("rebase (finish): {head_name} onto {onto}"),
+        )?;
+        move_head(
+            &Head::Branch(head_name.clone()),
+            &head,
+            &head,
+            &format!("rebase (finish): returning to {head_name}"),
+        )?;
+    }
+
+    clear_stop()?;
+    fs::remove_dir_all(REBASE_DIR)?;
+    println!("Successfully rebased and updated {head_name}.");
+
+    Ok(())
+}
+
+/// Moves `fixup!` and `squash!` commits right after the commit their subject refers to, by
+/// subject or hash, turning them into fixups and squashes
+fn rearrange_squashes(todo: Vec<TodoItem>) -> Vec<TodoItem> {
+    let mut rearranged: Vec<TodoItem> = Vec::new();
+    let mut fixups: Vec<Vec<TodoItem>> = Vec::new();
+
+    for mut item in todo {
+        let target = ["fixup! ", "squash! "].iter().find_map(|prefix| {
+            let mut subject = item.argument.strip_prefix(prefix)?;
+            // Fixups of fixups refer to the same commit
+            while let Some(rest) = ["fixup! ", "squash! "]
+                .iter()
+                .find_map(|prefix| subject.strip_prefix(prefix))
+            {
+                subject = rest;
+            }
+            let position = rearranged.iter().position(|other| {
+                other.argument == subject
+                    || other
+                        .commit
+                        .as_ref()
+                        .is_some_and(|hash| hash.to_string().starts_with(subject))
+            })?;
+            Some((prefix, position))
+        });
+
+        match target {
+            Some((prefix, position)) => {
+                item.command = if *prefix == "fixup! " {
+                    TodoCommand::Fixup
+                } else {
+                    TodoCommand::Squash
+                };
+                fixups[position].push(item);
+            }
+            None => {
+                rearranged.push(item);
+                fixups.push(Vec::new());
+            }
+        }
+    }
+
+    rearranged
+        .into_iter()
+        .zip(fixups)
+        .flat_map(|(item, fixups)| std::iter::once(item).chain(fixups))
+        .collect()
+}
+
+/// Adds the commands after every commit, after its fixups when it has some
+fn insert_execs(todo: Vec<TodoItem>, commands: &[String]) -> Vec<TodoItem> {
+    let mut result = Vec::new();
+    let mut items = todo.into_iter().peekable();
+
+    while let Some(item) = items.next() {
+        result.push(item);
+        if items.peek().is_some_and(|next| next.command.is_fixup()) {
+            continue;
+        }
+        result.extend(commands.iter().map(|command| TodoItem {
+            command: TodoCommand::Exec,
+            commit: None,
+            argument: command.clone(),
+        }));
+    }
+
+    result
+}
+
+/// Lets the user or a script rearrange the todo list in the sequence editor
+fn edit_todo(
+    todo: &[TodoItem],
+    upstream: &Hash,
+    head: &Hash,
+    onto: &Hash,
+) -> std::io::Result<Vec<TodoItem>> {
+    let mut content = todo
+        .iter()
+        .map(|item| item.to_line(true))
+        .collect::<String>();
+    content.push_str(&format!(
+        "\n# Rebase {}..{} onto {} ({} command{})\n#",
+        upstream.to_short_string(),
+        head.to_short_string(),
+        onto.to_short_string(),
+        todo.len(),
+        if todo.len() == 1 { "" } else { "s" }
+    ));
+    content.push_str(TODO_HELP);
+
+    let path = state_path("git-rebase-todo");
+    fs::write(&path, content)?;
+    fs::copy(&path, state_path("git-rebase-todo.backup"))?;
+
+    let editor = std::env::var("GIT_SEQUENCE_EDITOR")
+        .ok()
+        .or_else(|| Config::load().ok()?.get("sequence.editor"))
+        .unwrap_or_else(editor);
+    launch_editor(&editor, &path.to_string_lossy())?;
+
+    let todo = fs::read_to_string(&path)?
+        .lines()
+        .filter_map(|line| TodoItem::parse(line).transpose())
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if todo.first().is_some_and(|item| item.command.is_fixup()) {
+        return Err(std::io::Error::other(format!(
+            "cannot '{}' without a previous commit",
+            todo[0].command.name()
+        )));
+    }
+
+    Ok(todo)
+}
+
+/// Records the commit that did not apply cleanly, then builds the error stopping the rebase
+fn stop_on_conflict(hash: &Hash, picked: &Commit, message: &str) -> std::io::Error {
+    let recorded = (|| {
+        fs::write(REBASE_HEAD_PATH, format!("{hash}\n"))?;
+        write_state("stopped-sha", &hash.to_string())?;
+        fs::write(state_path("message"), &picked.commit_message)?;
+        write_author_script(&picked.author())?;
+        fs::write(MERGE_MSG_PATH, message)
+    })();
+    if let Err(e) = recorded {
+        return e;
+    }
+
+    std::io::Error::other(format!(
+        "could not apply {}... {}\nhint: Resolve all conflicts manually, mark them as resolved with\nhint: \"hamachi add/rm <conflicted_files>\", then run \"hamachi rebase --continue\".\nhint: You can instead skip this commit: run \"hamachi rebase --skip\".\nhint: To abort and get back to the state before \"hamachi rebase\", run \"hamachi rebase --abort\".",
+        hash.to_short_string(),
+        picked.subject()
+    ))
+}
+
+/// Replaces HEAD with a commit of the specified tree and message, keeping its author and parents
+fn amend_head(
+    head: &Hash,
+    tree: Hash,
+    message: String,
+    command: TodoCommand,
+) -> std::io::Result<()> {
+    let head_commit = Commit::from_hash(head);
+    let parents = head_commit
+        .parents
+        .iter()
+        .map(|parent| parent.parent_hash.clone())
+        .collect();
+    let commit = Commit::new(
+        tree,
+        parents,
+        head_commit.author(),
+        Signature::committer()?,
+        message,
+    );
+    let hash = commit.write()?;
+
+    refs::update_ref_with_log(
+        "HEAD",
+        Some(head),
+        &hash,
+        &format!("rebase ({}): {}", command.name(), commit.subject()),
+    )
+}
+
+/// The message of the commit a chain of fixups and squashes is melded into: the original message
+/// while only fixups were applied, the combined messages otherwise, edited by the user once the
+/// chain is complete
+fn squash_message(chain: &[(TodoCommand, Hash)], next_is_fixup: bool) -> std::io::Result<String> {
+    if chain
+        .iter()
+        .all(|(command, _)| *command == TodoCommand::Fixup)
+    {
+        return fs::read_to_string(state_path("message-squash"));
+    }
+
+    let combined = combined_message(chain)?;
+    if next_is_fixup {
+        Ok(cleanup_message(&combined, true))
+    } else {
+        Ok(cleanup_message(&edit_message(&combined)?, true))
+    }
+}
+
+/// The messages of the commits melded together, with those of fixups and the `squash!` subjects
+/// commented out
+fn combined_message(chain: &[(TodoCommand, Hash)]) -> std::io::Result<String> {
+    let mut message = format!(
+        "# This is a combination of {} commits.\n# This is the 1st commit message:\n\n{}",
+        chain.len() + 1,
+        fs::read_to_string(state_path("message-squash"))?
+    );
+
+    for (i, (command, hash)) in chain.iter().enumerate() {
+        let number = i + 2;
+        let body = Commit::from_hash(hash).commit_message;
+        if *command == TodoCommand::Fixup {
+            message.push_str(&format!(
+                "\n# The commit message #{number} will be skipped:\n\n"
+            ));
+            for line in body.lines() {
+                message.push_str(&format!("# {line}\n"));
+            }
+            continue;
+        }
+
+        message.push_str(&format!("\n# This is the commit message #{number}:\n\n"));
+        for (j, line) in body.lines().enumerate() {
+            if j == 0 && (line.starts_with("squash! ") || line.starts_with("fixup! ")) {
+                message.push_str("# ");
+            }
+            message.push_str(line);
+            message.push('\n');
+        }
+    }
+
+    Ok(message)
+}
+
+/// Points HEAD to a branch or commit, recording the move in its reflog
+fn move_head(target: &Head, old: &Hash, new: &Hash, message: &str) -> std::io::Result<()> {
+    refs::write_head(target)?;
+    refs::append_reflog("HEAD", Some(old), new, &Signature::committer()?, message)
+}
+
+/// Refuses to rebase over uncommitted changes to tracked files
+fn check_clean_worktree(head_tree: &Hash) -> std::io::Result<()> {
+    let index = Index::load()?;
+    if index.has_conflicts() || index.write_tree()? != *head_tree {
+        return Err(std::io::Error::other(
+            "cannot rebase: Your index contains uncommitted changes.\nPlease commit or stash them.",
+        ));
+    }
+
+    for entry in &index.entries {
+        if fs::symlink_metadata(&entry.path).is_err() || worktree::is_modified(&index, entry)? {
+            return Err(std::io::Error::other(
+                "cannot rebase: You have unstaged changes.\nPlease commit or stash them.",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_todo() -> std::io::Result<Vec<TodoItem>> {
+    fs::read_to_string(state_path("git-rebase-todo"))?
+        .lines()
+        .filter_map(|line| TodoItem::parse(line).transpose())
+        .collect()
+}
+
+fn write_todo(todo: &[TodoItem]) -> std::io::Result<()> {
+    let content = todo
+        .iter()
+        .map(|item| item.to_line(false))
+        .collect::<String>();
+    fs::write(state_path("git-rebase-todo"), content)
+}
+
+fn next_command() -> std::io::Result<Option<TodoCommand>> {
+    Ok(read_todo()?.first().map(|item| item.command))
+}
+
+/// Appends a line to the `done` list and counts it as the current step
+fn mark_done(item: &TodoItem) -> std::io::Result<()> {
+    let mut done = fs::read_to_string(state_path("done")).unwrap_or_default();
+    done.push_str(&item.to_line(false));
+    fs::write(state_path("done"), done)?;
+
+    let msgnum = read_state("msgnum")?.parse::<usize>().unwrap_or(0);
+    write_state("msgnum", &(msgnum + 1).to_string())
+}
+
+fn last_done() -> std::io::Result<Option<TodoItem>> {
+    let done = fs::read_to_string(state_path("done"))?;
+    match done.lines().last() {
+        Some(line) => TodoItem::parse(line),
+        None => Ok(None),
+    }
+}
+
+fn read_fixups() -> std::io::Result<Vec<(TodoCommand, Hash)>> {
+    let content = match fs::read_to_string(state_path("current-fixups")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    content
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(command, hash)| {
+            let hash = Hash::from_str(hash)
+                .map_err(|_| std::io::Error::other("could not parse current-fixups"))?;
+            Ok((command.parse()?, hash))
+        })
+        .collect()
+}
+
+fn clear_fixups() -> std::io::Result<()> {
+    remove_if_exists(&state_path("current-fixups"))?;
+    remove_if_exists(&state_path("message-squash"))
+}
+
+/// The author of the commit the rebase stopped at, in the shell syntax git uses
+fn write_author_script(author: &Signature) -> std::io::Result<()> {
+    write_state(
+        "author-script",
+        &format!(
+            "GIT_AUTHOR_NAME='{}'\nGIT_AUTHOR_EMAIL='{}'\nGIT_AUTHOR_DATE='@{} {}'",
+            author.name, author.email, author.date, author.timezone
+        ),
+    )
+}
+
+fn read_author_script() -> std::io::Result<Option<Signature>> {
+    let Ok(script) = read_state("author-script") else {
+        return Ok(None);
+    };
+
+    let value = |name: &str| {
+        script.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim_matches('\'').to_string())
+        })
+    };
+    let (Some(name), Some(email), Some(date)) = (
+        value("GIT_AUTHOR_NAME"),
+        value("GIT_AUTHOR_EMAIL"),
+        value("GIT_AUTHOR_DATE"),
+    ) else {
+        return Ok(None);
+    };
+    let Some((date, timezone)) = date.trim_start_matches('@').split_once(' ') else {
+        return Ok(None);
+    };
+
+    Ok(Some(Signature {
+        name,
+        email,
+        date: date
+            .parse()
+            .map_err(|_| std::io::Error::other("could not parse author-script"))?,
+        timezone: timezone.to_string(),
+    }))
+}
+
+/// Forgets the step the rebase stopped at
+fn clear_stop() -> std::io::Result<()> {
+    for path in [
+        PathBuf::from(REBASE_HEAD_PATH),
+        PathBuf::from(MERGE_MSG_PATH),
+        state_path("stopped-sha"),
+        state_path("message"),
+        state_path("author-script"),
+        state_path("amend"),
+    ] {
+        remove_if_exists(&path)?;
+    }
+
+    Ok(())
+}
+
+fn state_path(name: &str) -> PathBuf {
+    Path::new(REBASE_DIR).join(name)
+}
+
+fn read_state(name: &str) -> std::io::Result<String> {
+    let content = fs::read_to_string(state_path(name))?;
+    Ok(content.strip_suffix('\n').unwrap_or(&content).to_string())
+}
+
+fn write_state(name: &str, value: &str) -> std::io::Result<()> {
+    let content = if value.is_empty() || value.ends_with('\n') {
+        value.to_string()
+    } else {
+        format!("{value}\n")
+    };
+    fs::write(state_path(name), content)
+}
+
+fn read_state_hash(name: &str) -> std::io::Result<Option<Hash>> {
+    match read_state(name) {
+        Ok(content) => Ok(Hash::from_str(content.trim()).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn no_rebase() -> std::io::Error {
+    std::io::Error::other("No rebase in progress?")
+}
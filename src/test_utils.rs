@@ -1,16 +1,21 @@
+use crate::config::Config;
 use crate::init;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Child, Command};
-use std::{env, fs};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::{env, fs, thread};
 
 /// Creates and sets working directory in a temporary directory and initializes a git and hamachi repo in it
 pub fn setup_test_environment() -> std::io::Result<PathBuf> {
     // Create repo directory
     let temp_dir = env::temp_dir();
     let repo_name = format!("hamachi-{}", srfng::Generator::new().generate());
-    let repo_path = PathBuf::from(temp_dir).join(repo_name);
+    let repo_path = temp_dir.join(repo_name);
 
     fs::create_dir(&repo_path)?;
 
@@ -41,10 +46,14 @@ pub fn setup_test_environment() -> std::io::Result<PathBuf> {
         .expect("Failed to set user name");
 
     let mut gitignore = File::create(".gitignore")?;
-    gitignore.write(".hamachi".as_bytes())?;
+    gitignore.write_all(".hamachi".as_bytes())?;
 
     // Create hamachi repo
     init().expect("Failed to initialize hamachi repo");
+    let mut config = Config::load()?;
+    config.set("user.email", "osamu.dazai@gmail.com")?;
+    config.set("user.name", "Osamu Dazai")?;
+    config.write()?;
 
     Ok(repo_path)
 }
@@ -56,6 +65,17 @@ pub fn run_git_command(command: &mut Command) -> std::io::Result<String> {
     Ok(captured_stdout.trim().to_string())
 }
 
+/// Runs git with the specified arguments, returning what it prints
+pub fn git(args: &[&str]) -> String {
+    run_git_command(Command::new("git").args(args)).unwrap()
+}
+
+/// Runs git on the hamachi repository of the current directory, which git reads as its own
+pub fn hamachi_git(args: &[&str]) -> String {
+    run_git_command(Command::new("git").env("GIT_DIR", ".hamachi").args(args)).unwrap()
+}
+
+#[allow(dead_code)]
 pub fn run_git_command_piped_input(mut command: Child, input: String) -> std::io::Result<String> {
     if let Some(mut stdin) = command.stdin.take() {
         stdin.write_all(input.as_bytes())?;
@@ -78,7 +98,7 @@ pub(crate) fn copy_git_object_file(hash: &str) -> std::io::Result<()> {
         .join(subdirectory)
         .join(file_name);
 
-    let subdirectory = PathBuf::from(".hamachi/objects").join(&subdirectory);
+    let subdirectory = PathBuf::from(".hamachi/objects").join(subdirectory);
     if !fs::exists(&subdirectory)? {
         fs::create_dir(&subdirectory)?;
     }
@@ -88,6 +108,222 @@ pub(crate) fn copy_git_object_file(hash: &str) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Copies every loose object of the git repository into the hamachi object database
+pub(crate) fn copy_git_objects() -> std::io::Result<()> {
+    for subdirectory in fs::read_dir(".git/objects")? {
+        let subdirectory = subdirectory?;
+        let subdirectory_name = subdirectory.file_name().to_string_lossy().to_string();
+        if subdirectory_name.len() != 2 {
+            continue;
+        }
+
+        for object in fs::read_dir(subdirectory.path())? {
+            let object_name = object?.file_name().to_string_lossy().to_string();
+            let to = PathBuf::from(".hamachi/objects").join(&subdirectory_name);
+            if !to.join(&object_name).exists() {
+                copy_git_object_file(&format!("{subdirectory_name}{object_name}"))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies the objects, refs, reflogs, HEAD and index of the git repository into the hamachi
+/// repository, so hamachi sees the exact state git left
+pub(crate) fn copy_git_repository() -> std::io::Result<()> {
+    copy_git_objects()?;
+    copy_directory(&PathBuf::from(".git/refs"), &PathBuf::from(".hamachi/refs"))?;
+    if PathBuf::from(".git/logs").is_dir() {
+        copy_directory(&PathBuf::from(".git/logs"), &PathBuf::from(".hamachi/logs"))?;
+    }
+
+    for file in ["HEAD", "index", "packed-refs"] {
+        let from = PathBuf::from(".git").join(file);
+        if from.exists() {
+            fs::copy(from, PathBuf::from(".hamachi").join(file))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn copy_directory(from: &PathBuf, to: &PathBuf) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serves the git repositories below `root` over smart HTTP by running `git http-backend` for
+/// every request, until the test process exits. Returns the base URL of the server.
+pub(crate) fn serve_git_http(root: &Path) -> std::io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let root = root.canonicalize()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let root = root.clone();
+            thread::spawn(move || handle_http_request(stream, &root));
+        }
+    });
+
+    Ok(format!("http://{address}"))
+}
+
+/// Serves the git repositories below `root` with `git daemon`, run for every connection, until
+/// the test process exits. Pushes are accepted. Returns the base URL of the server.
+pub(crate) fn serve_git_daemon(root: &Path) -> std::io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let root = root.canonicalize()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let Ok(input) = stream.try_clone() else {
+                continue;
+            };
+            let _ = Command::new("git")
+                .arg("daemon")
+                .arg("--inetd")
+                .arg("--export-all")
+                .arg("--informative-errors")
+                .arg("--enable=receive-pack")
+                .arg(format!("--base-path={}", root.display()))
+                .stdin(OwnedFd::from(input))
+                .stdout(OwnedFd::from(stream))
+                .stderr(Stdio::null())
+                .spawn();
+        }
+    });
+
+    Ok(format!("git://{address}"))
+}
+
+fn handle_http_request(mut stream: TcpStream, root: &Path) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+            }
+            None => break,
+        }
+    }
+
+    let mut body = Vec::new();
+    if let Some(length) = headers.get("content-length") {
+        body.resize(length.parse().unwrap_or(0), 0);
+        reader.read_exact(&mut body)?;
+    } else if headers.get("transfer-encoding").map(String::as_str) == Some("chunked") {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size)?;
+            let size = usize::from_str_radix(size.trim(), 16).unwrap_or(0);
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk)?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let mut command = Command::new("git");
+    command
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("REQUEST_METHOD", &method)
+        .env("PATH_INFO", path)
+        .env("QUERY_STRING", query)
+        .env("CONTENT_LENGTH", body.len().to_string())
+        .env("REMOTE_ADDR", "127.0.0.1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+    for (header, variable) in [
+        ("content-type", "CONTENT_TYPE"),
+        ("content-encoding", "HTTP_CONTENT_ENCODING"),
+        ("git-protocol", "GIT_PROTOCOL"),
+    ] {
+        if let Some(value) = headers.get(header) {
+            command.env(variable, value);
+        }
+    }
+
+    let mut backend = command.spawn()?;
+    let mut stdin = backend.stdin.take().expect("stdin is piped");
+    let writer = thread::spawn(move || stdin.write_all(&body));
+    let output = backend.wait_with_output()?;
+    let _ = writer.join();
+
+    // The backend answers with CGI headers, including the status, followed by the body
+    let header_end = output
+        .stdout
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap_or(output.stdout.len());
+    let cgi_headers = String::from_utf8_lossy(&output.stdout[..header_end]).to_string();
+    let response_body = output.stdout.get(header_end + 4..).unwrap_or_default();
+
+    let mut status = String::from("200 OK");
+    let mut response = String::new();
+    for line in cgi_headers.lines() {
+        match line.strip_prefix("Status:") {
+            Some(value) => status = value.trim().to_string(),
+            None => response.push_str(&format!("{line}\r\n")),
+        }
+    }
+
+    stream.write_all(
+        format!(
+            "HTTP/1.1 {status}\r\n{response}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            response_body.len()
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(response_body)?;
+    stream.flush()
+}
+
+/// Points `$HAMACHI_SSH` at a script standing in for ssh, which runs the command meant for the
+/// remote host locally, and logs its arguments to `ssh.log` in `directory`
+pub(crate) fn fake_ssh(directory: &Path) -> std::io::Result<()> {
+    let directory = directory.canonicalize()?;
+    let script = directory.join("fake-ssh");
+    fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\nshift $(($# - 1))\nexec sh -c \"$1\"\n",
+            directory.join("ssh.log").display()
+        ),
+    )?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+    env::set_var("HAMACHI_SSH", script);
+
+    Ok(())
+}
+
 pub fn teardown(repo: PathBuf) -> std::io::Result<()> {
     env::set_current_dir("..")?;
     fs::remove_dir_all(&repo)?;
@@ -0,0 +1,503 @@
+use crate::ignore::IgnoreRules;
+use crate::index::{Index, IndexEntry};
+use crate::object::tree::{Mode, Tree};
+use crate::object::{Hash, Object, ObjectType};
+use crate::remote::promisor;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::fs::Metadata;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::Path;
+
+/// Hashes the working tree file at the specified path as a blob, writing it to the object
+/// database if requested. Symbolic links are hashed as their target path, the way git stores them.
+pub(crate) fn hash_file(path: &str, write: bool) -> std::io::Result<(Mode, Hash, Metadata)> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mode = Mode::from_metadata(&metadata);
+
+    let content = if mode == Mode::SYMBOLIC {
+        fs::read_link(path)?
+            .to_string_lossy()
+            .to_string()
+            .into_bytes()
+    } else {
+        fs::read(path)?
+    };
+
+    let hash = if write {
+        Object::write(ObjectType::BLOB, &content)?
+    } else {
+        Object::hash(ObjectType::BLOB, &content)
+    };
+
+    Ok((mode, hash, metadata))
+}
+
+/// Whether the working tree file differs from its index entry, relying on the recorded stat
+/// information when possible and only rehashing the content when it is inconclusive
+pub(crate) fn is_modified(index: &Index, entry: &IndexEntry) -> std::io::Result<bool> {
+    let metadata = match fs::symlink_metadata(&entry.path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    if metadata.is_dir() {
+        return Ok(true);
+    }
+
+    if entry.matches_stat(&metadata) && !is_racily_clean(index, entry) {
+        return Ok(false);
+    }
+
+    if Mode::from_metadata(&metadata) != entry.mode
+        || metadata.len() as u32 != entry.size && entry.size != 0
+    {
+        return Ok(true);
+    }
+
+    let (_, hash, _) = hash_file(&entry.path, false)?;
+
+    Ok(hash != entry.hash)
+}
+
+/// A file modified in the same timestamp granule the index was written in may have changed
+/// without its stat information changing, so it has to be compared by content
+fn is_racily_clean(index: &Index, entry: &IndexEntry) -> bool {
+    match index.timestamp {
+        Some(timestamp) => timestamp <= (entry.mtime_seconds, entry.mtime_nanoseconds),
+        None => true,
+    }
+}
+
+/// Writes a blob from the object database to the working tree, creating the parent directories,
+/// setting the executable bit and creating symbolic links as dictated by the mode
+pub(crate) fn write_file(path: &str, mode: Mode, hash: &Hash) -> std::io::Result<Metadata> {
+    let (object_type, content) = Object::read(hash)?;
+    if object_type != ObjectType::BLOB {
+        return Err(std::io::Error::other(format!("{hash} is not a blob")));
+    }
+
+    let path = Path::new(path);
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    match mode {
+        Mode::SYMBOLIC => {
+            let target = String::from_utf8_lossy(&content).to_string();
+            symlink(target, path)?;
+        }
+        Mode::EXECUTABLE => {
+            fs::write(path, &content)?;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        }
+        _ => {
+            fs::write(path, &content)?;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o644))?;
+        }
+    }
+
+    fs::symlink_metadata(path)
+}
+
+/// Removes a file from the working tree along with the parent directories it leaves empty
+pub(crate) fn remove_file(path: &str) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut directory = Path::new(path).parent();
+    while let Some(current) = directory {
+        if current.as_os_str().is_empty() || fs::remove_dir(current).is_err() {
+            break;
+        }
+        directory = current.parent();
+    }
+
+    Ok(())
+}
+
+/// Whether a repository relative path is selected by any of the specified pathspecs,
+/// `.` selecting everything and directories selecting everything below them
+pub(crate) fn matches_pathspec(path: &str, pathspecs: &[String]) -> bool {
+    pathspecs.iter().any(|spec| {
+        let spec = normalize_path(spec);
+        spec.is_empty()
+            || path == spec
+            || path
+                .strip_prefix(spec.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Turns a user supplied path into the slash separated, repository relative form used in the index
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Moves the index and working tree from the `current` tree to the `target` tree, the way
+/// `git read-tree -m -u` does when switching branches.
+///
+/// Paths that are identical in both trees keep their local modifications. Paths that differ
+/// are only updated if they have no staged or unstaged changes and if doing so does not
+/// overwrite an untracked file, unless `force` is set, in which case the index and working tree
+/// are made to match the target tree exactly.
+pub(crate) fn checkout_tree(
+    current: Option<&Hash>,
+    target: &Hash,
+    force: bool,
+) -> std::io::Result<()> {
+    let mut index = Index::load()?;
+    if index.has_conflicts() && !force {
+        return Err(std::io::Error::other(
+            "you need to resolve your current index first",
+        ));
+    }
+
+    let current_files = match current {
+        Some(hash) => Tree::flatten(hash)?,
+        None => BTreeMap::new(),
+    };
+    let target_files = Tree::flatten(target)?;
+
+    let paths = current_files
+        .keys()
+        .chain(target_files.keys())
+        .chain(index.entries.iter().map(|entry| &entry.path))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut updates = Vec::new();
+    let mut local_changes = Vec::new();
+    let mut untracked = Vec::new();
+
+    for path in paths {
+        let head_entry = current_files.get(&path);
+        let target_entry = target_files.get(&path);
+        let index_entry = index
+            .get(&path)
+            .map(|entry| (entry.mode, entry.hash.clone()));
+
+        if force {
+            let up_to_date = match index.get(&path) {
+                Some(entry) => index_entry.as_ref() == target_entry && !is_modified(&index, entry)?,
+                // Paths with only conflict stages are resolved to the target as well
+                None => target_entry.is_none() && !index.contains(&path),
+            };
+            if !up_to_date {
+                updates.push((path, target_entry.cloned()));
+            }
+            continue;
+        }
+
+        if head_entry == target_entry || index_entry.as_ref() == target_entry {
+            continue;
+        }
+
+        if index_entry.as_ref() != head_entry {
+            local_changes.push(path);
+            continue;
+        }
+
+        match index.get(&path) {
+            Some(entry) if is_modified(&index, entry)? => local_changes.push(path),
+            // An untracked file already identical to the target loses nothing when overwritten
+            None if fs::symlink_metadata(&path).is_ok() => {
+                let (mode, hash, _) = hash_file(&path, false)?;
+                if target_entry != Some(&(mode, hash)) {
+                    untracked.push(path);
+                } else {
+                    updates.push((path, target_entry.cloned()));
+                }
+            }
+            _ => updates.push((path, target_entry.cloned())),
+        }
+    }
+
+    if !local_changes.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "Your local changes to the following files would be overwritten by checkout:\n\t{}\nPlease commit your changes or stash them before you switch branches.",
+            local_changes.join("\n\t")
+        )));
+    }
+    if !untracked.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "The following untracked working tree files would be overwritten by checkout:\n\t{}\nPlease move or remove them before you switch branches.",
+            untracked.join("\n\t")
+        )));
+    }
+
+    let blobs = updates
+        .iter()
+        .filter_map(|(_, target_entry)| target_entry.as_ref().map(|(_, hash)| hash.clone()))
+        .collect::<Vec<_>>();
+    promisor::fetch_missing(&blobs)?;
+
+    // Removals go first so files can replace directories and the other way around
+    for (path, target_entry) in &updates {
+        if target_entry.is_none() {
+            remove_file(path)?;
+            index.remove(path);
+        }
+    }
+    for (path, target_entry) in updates {
+        if let Some((mode, hash)) = target_entry {
+            let metadata = write_file(&path, mode, &hash)?;
+            index.add(IndexEntry::from_metadata(path, mode, hash, &metadata));
+        }
+    }
+
+    index.write()
+}
+
+/// Overwrites the working tree files selected by the pathspecs with their content in the
+/// specified tree, or in the index if no tree is given. Unlike switching branches this
+/// intentionally discards local modifications. Outside of overlay mode, tracked files that the
+/// source does not have are removed as well, as `git restore` does. With `stage`, the files taken
+/// from the tree are staged too, as `git checkout <tree-ish> -- <paths>` does.
+pub(crate) fn checkout_paths(
+    source: Option<&Hash>,
+    pathspecs: &[String],
+    overlay: bool,
+    stage: bool,
+) -> std::io::Result<()> {
+    let mut index = Index::load()?;
+    // Restoring from the index refreshes the stat information of its entries
+    let update_index = source.is_none() || stage;
+
+    let source_files = match source {
+        Some(hash) => Tree::flatten(hash)?,
+        None => index
+            .entries
+            .iter()
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| (entry.path.clone(), (entry.mode, entry.hash.clone())))
+            .collect(),
+    };
+
+    let mut matched = false;
+    if !overlay {
+        let missing = index
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .filter(|path| matches_pathspec(path, pathspecs) && !source_files.contains_key(path))
+            .collect::<BTreeSet<_>>();
+        for path in missing {
+            matched = true;
+            remove_file(&path)?;
+            if stage {
+                index.remove(&path);
+            }
+        }
+    }
+
+    let source_files = source_files
+        .into_iter()
+        .filter(|(path, _)| matches_pathspec(path, pathspecs))
+        .collect::<Vec<_>>();
+    let blobs = source_files
+        .iter()
+        .map(|(_, (_, hash))| hash.clone())
+        .collect::<Vec<_>>();
+    promisor::fetch_missing(&blobs)?;
+
+    for (path, (mode, hash)) in source_files {
+        matched = true;
+
+        let metadata = write_file(&path, mode, &hash)?;
+        if update_index {
+            index.add(IndexEntry::from_metadata(path, mode, hash, &metadata));
+        }
+    }
+
+    if !matched {
+        return Err(std::io::Error::other(format!(
+            "pathspec '{}' did not match any file(s) known to hamachi",
+            pathspecs.join(" ")
+        )));
+    }
+
+    if update_index {
+        index.write()?;
+    }
+
+    Ok(())
+}
+
+/// Resets the index entries selected by the pathspecs to their version in the specified tree,
+/// removing those the tree does not have, `None` standing for the empty tree of an unborn
+/// branch. The working tree is left alone, as with `git reset -- <paths>`.
+pub(crate) fn reset_paths(source: Option<&Hash>, pathspecs: &[String]) -> std::io::Result<()> {
+    let mut index = Index::load()?;
+    let source_files = match source {
+        Some(hash) => Tree::flatten(hash)?,
+        None => BTreeMap::new(),
+    };
+
+    let tracked = index
+        .entries
+        .iter()
+        .map(|entry| entry.path.clone())
+        .filter(|path| matches_pathspec(path, pathspecs))
+        .collect::<BTreeSet<_>>();
+    for path in tracked {
+        if !source_files.contains_key(&path) {
+            index.remove(&path);
+        }
+    }
+
+    for (path, (mode, hash)) in source_files {
+        if !matches_pathspec(&path, pathspecs) {
+            continue;
+        }
+        // Entries already matching the tree keep their stat information
+        let unchanged = index
+            .get(&path)
+            .is_some_and(|entry| entry.mode == mode && entry.hash == hash);
+        if !unchanged {
+            index.add(IndexEntry::new(path, mode, hash));
+        }
+    }
+
+    index.write()
+}
+
+/// Lists the working tree files that are neither tracked in the index nor ignored, sorted by
+/// path. Like `git status`, a directory containing no tracked file is reported once as
+/// `directory/` instead of file by file.
+pub(crate) fn untracked_files(
+    index: &Index,
+    ignore: &mut IgnoreRules,
+) -> std::io::Result<Vec<String>> {
+    let tracked_directories = tracked_directories(index);
+
+    let mut untracked = Vec::new();
+    collect_untracked(index, ignore, &tracked_directories, "", &mut untracked)?;
+    untracked.sort();
+
+    Ok(untracked)
+}
+
+/// Lists every working tree file that is tracked in the index or not ignored, sorted by path
+pub(crate) fn worktree_files(
+    index: &Index,
+    ignore: &mut IgnoreRules,
+) -> std::io::Result<Vec<String>> {
+    let tracked_directories = tracked_directories(index);
+
+    let mut files = Vec::new();
+    let mut directories = vec![String::new()];
+    while let Some(directory) = directories.pop() {
+        for (path, metadata) in read_directory(&directory)? {
+            let is_directory = metadata.is_dir();
+            let tracked = if is_directory {
+                tracked_directories.contains(&path)
+            } else {
+                index.contains(&path)
+            };
+            if !tracked && ignore.is_ignored(&path, is_directory)? {
+                continue;
+            }
+
+            if is_directory {
+                directories.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Every directory containing a file tracked in the index
+pub(crate) fn tracked_directories(index: &Index) -> HashSet<String> {
+    let mut tracked_directories = HashSet::new();
+    for entry in &index.entries {
+        let mut path = entry.path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            if !tracked_directories.insert(parent.to_string()) {
+                break;
+            }
+            path = parent;
+        }
+    }
+
+    tracked_directories
+}
+
+fn collect_untracked(
+    index: &Index,
+    ignore: &mut IgnoreRules,
+    tracked_directories: &HashSet<String>,
+    directory: &str,
+    untracked: &mut Vec<String>,
+) -> std::io::Result<()> {
+    for (path, metadata) in read_directory(directory)? {
+        if metadata.is_dir() {
+            if tracked_directories.contains(&path) {
+                collect_untracked(index, ignore, tracked_directories, &path, untracked)?;
+            } else if !ignore.is_ignored(&path, true)? && contains_files(ignore, &path)? {
+                untracked.push(format!("{path}/"));
+            }
+        } else if !index.contains(&path) && !ignore.is_ignored(&path, false)? {
+            untracked.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a directory contains any file that is not ignored
+fn contains_files(ignore: &mut IgnoreRules, directory: &str) -> std::io::Result<bool> {
+    for (path, metadata) in read_directory(directory)? {
+        if ignore.is_ignored(&path, metadata.is_dir())? {
+            continue;
+        }
+        if !metadata.is_dir() || contains_files(ignore, &path)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// The entries of a working tree directory as repository relative paths, sorted by name,
+/// leaving out the repository directories themselves
+pub(crate) fn read_directory(directory: &str) -> std::io::Result<Vec<(String, Metadata)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(if directory.is_empty() { "." } else { directory })? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if directory.is_empty() && (name == ".git" || name == ".hamachi") {
+            continue;
+        }
+
+        let path = if directory.is_empty() {
+            name
+        } else {
+            format!("{directory}/{name}")
+        };
+        entries.push((path, fs::symlink_metadata(entry.path())?));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(entries)
+}